@@ -2,6 +2,9 @@
 mod log_settings;
 mod subcommands;
 
+use std::io::IsTerminal;
+use std::path::Path;
+
 use anyhow::{bail, Result};
 use clap::Parser;
 use env_logger::{Env, Target};
@@ -12,6 +15,9 @@ use modda_lib::args::{ Cli, Commands, ConfigArgs };
 use modda_lib::cache::Cache;
 use modda_lib::canon_path::CanonPath;
 use modda_lib::chitin::ensure_chitin_key;
+use modda_lib::credentials::Credentials;
+use modda_lib::effective_config::EffectiveConfig;
+use modda_lib::game_dir::{ask_game_dir, enter_game_dir};
 use modda_lib::modda_context::WeiduContext;
 use modda_lib::run_weidu::check_weidu_exe;
 use modda_lib::config::{global_conf_dir, Settings};
@@ -19,11 +25,14 @@ use modda_lib::sub::append_mod::append_mod;
 use modda_lib::sub::extract_manifest::extract_manifest;
 use modda_lib::sub::install::install;
 use modda_lib::sub::invalidate::invalidate;
+use modda_lib::sub::verify::verify_install;
 use subcommands::config_show::open_global_config_dir;
 use subcommands::config_edit::edit_global_config_dir;
 use subcommands::discover::discover;
 use subcommands::introspect::introspect;
 use subcommands::list_components::sub_list_components;
+use subcommands::list_modules::list_modules;
+use subcommands::prune_local::prune_local;
 use subcommands::reset::reset;
 use subcommands::search::search;
 
@@ -42,17 +51,31 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    if let Some(game_dir) = &cli.game_dir {
+        enter_game_dir(Path::new(game_dir))?;
+    } else if cli.command.wants_chitin_key() && !Path::new("chitin.key").exists() && !Path::new("game/chitin.key").exists() {
+        let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+        if let Some(game_dir) = ask_game_dir(interactive)? {
+            enter_game_dir(&game_dir)?;
+        }
+    }
+
     let current_dir = std::env::current_dir()?;
     let current_dir = CanonPath::new(current_dir)?;
 
-    if cli.command.wants_chitin_key() {
+    if cli.command.wants_chitin_key() && !cli.print_config {
         if ensure_chitin_key().is_err() {
-            bail!("Must be run from the game directory (where chitin.key is)");
+            bail!("Must be run from the game directory (where chitin.key is) or with --game-dir");
         } else {
             debug!("chitin.key found");
         }
     }
     let settings = Settings::read_settings(&current_dir)?;
+    if cli.print_config {
+        let effective = EffectiveConfig::new(&settings, &current_dir, cli.command.cli_jobs(), &Credentials::read()?)?;
+        print!("{}", effective.to_yaml()?);
+        return Ok(());
+    }
     let config = &settings.combined;
     let weidu_context = WeiduContext{ config: &config, current_dir: &current_dir };
     check_weidu_exe(&weidu_context)?;
@@ -62,7 +85,7 @@ fn main() -> Result<()> {
         Commands::Install(ref install_opts) => install(install_opts, &config, &current_dir, &cache),
         Commands::Search(ref search_opts) => search(search_opts),
         Commands::ListComponents(ref params) => sub_list_components(params, &weidu_context),
-        Commands::Invalidate(ref params) => invalidate(params, &cache),
+        Commands::Invalidate(ref params) => invalidate(params, &current_dir, &cache, config),
         Commands::Reverse(ref params) => extract_manifest(params, &current_dir),
         Commands::AppendMod(ref params) => append_mod(params, &weidu_context),
         Commands::Reset(ref reset_args) => reset(reset_args, &weidu_context),
@@ -70,6 +93,9 @@ fn main() -> Result<()> {
         Commands::Introspect(ref params) => introspect(params, &settings, &current_dir,
                                                                     &global_conf_dir(),
                                                                     &log_settings),
+        Commands::Verify(ref params) => verify_install(params, &current_dir, config),
+        Commands::ListModules(ref params) => list_modules(params, &current_dir, config),
+        Commands::PruneLocal(ref params) => prune_local(params, &current_dir, config),
         Commands::GlobalConfig(sub) => match sub {
             ConfigArgs::Show(_) => open_global_config_dir(),
             ConfigArgs::Edit(_) => edit_global_config_dir(&config),
@@ -0,0 +1,17 @@
+
+use anyhow::Result;
+
+use modda_lib::args::{manifest_root_from_path, ListModules};
+use modda_lib::canon_path::CanonPath;
+use modda_lib::config::Config;
+use modda_lib::module::manifest::Manifest;
+use modda_lib::module::module_listing::ModuleFilter;
+
+
+pub fn list_modules(params: &ListModules, current_dir: &CanonPath, config: &Config) -> Result<()> {
+    let manifest_root = manifest_root_from_path(&params.manifest_path, current_dir);
+    let manifest = Manifest::assemble_from_path(&params.manifest_path, &manifest_root, config)?;
+    let filter = ModuleFilter { only: params.only.clone(), skip: params.skip.clone() };
+    print!("{}", manifest.list_modules(&filter));
+    Ok(())
+}
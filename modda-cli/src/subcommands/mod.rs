@@ -4,5 +4,7 @@ pub mod config_show;
 pub mod discover;
 pub mod introspect;
 pub mod list_components;
+pub mod list_modules;
+pub mod prune_local;
 pub mod reset;
 pub mod search;
@@ -0,0 +1,22 @@
+
+use anyhow::Result;
+
+use modda_lib::args::{manifest_root_from_path, PruneLocal};
+use modda_lib::canon_path::CanonPath;
+use modda_lib::config::Config;
+use modda_lib::module::manifest::Manifest;
+use modda_lib::prune_local::prune_local_mods;
+
+
+pub fn prune_local(params: &PruneLocal, current_dir: &CanonPath, config: &Config) -> Result<()> {
+    let manifest_root = manifest_root_from_path(&params.manifest_path, current_dir);
+    let manifest = Manifest::assemble_from_path(&params.manifest_path, &manifest_root, config)?;
+    let unused = prune_local_mods(&manifest, manifest_root.path(), !params.delete)?;
+    if unused.is_empty() {
+        println!("nothing unused in local_mods/local_files");
+    }
+    for path in &unused {
+        println!("{}{:?}", if params.delete { "removed " } else { "" }, path);
+    }
+    Ok(())
+}
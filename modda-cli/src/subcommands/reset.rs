@@ -1,9 +1,12 @@
 
+use std::path::Path;
+
 use anyhow::{Result, bail};
 use itertools::Itertools;
 use log::info;
 
 use modda_lib::args::Reset;
+use modda_lib::install_journal::InstallJournal;
 use modda_lib::modda_context::WeiduContext;
 use modda_lib::module::components::Components;
 use modda_lib::module::manifest::Manifest;
@@ -15,7 +18,11 @@ use modda_lib::tp2::find_tp2_str;
 
 pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
     let installed = extract_bare_mods()?;
-    let manifest = Manifest::read_path(&args.manifest_path,)?;
+    let mut manifest = Manifest::read_path(&args.manifest_path,)?;
+    let manifest_root = Path::new(&args.manifest_path).parent().unwrap_or(Path::new("."));
+    manifest.load_component_files(manifest_root)?;
+    // indexes are in install order, like for `install`
+    manifest.sort_by_order();
 
     let reset_index = args.to_index;
 
@@ -27,6 +34,7 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
     let weidu_mod = match target_module {
         Module::Mod { weidu_mod } => weidu_mod.clone(),
         Module::Generated { gen } => gen.as_weidu(),
+        Module::File { .. } => bail!("Can't reset to a file module, it doesn't install components"),
     };
     let components = match weidu_mod.components {
         Components::None => bail!("Can't reset to a module fragment which doesn't install components (`components: none`)"),
@@ -34,6 +42,7 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
         Components::All => bail!("Can't reset to a module fragment which doesn't specify components explicitly (`components: all`)"),
         Components::List(list) if list.is_empty() => bail!("Can't reset to a module fragment which doesn't install components (`components list is empty`)"),
         Components::List(ref list) => list,
+        Components::File { .. } => bail!("Components file was not loaded"),
     };
     let name_matches = installed.iter().enumerate()
         .filter(|(_, module)| module.name == weidu_mod.name)
@@ -43,7 +52,7 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
         return Ok(());
     }
     let component_matches = name_matches.iter().filter(|(_, module)|
-        components.iter().all(|comp| module.components.iter().any(|item| item.index == comp.index()))
+        components.iter().all(|comp| module.components.iter().any(|item| Some(item.index) == comp.index()))
     ).collect::<Vec<_>>();
 
     let (index, _) = match component_matches.as_slice() {
@@ -58,6 +67,10 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
             let tp2 = find_tp2_str(weidu_context.current_dir, &fragment.name)?;
             run_weidu_uninstall(&tp2, fragment, args, weidu_context)?;
         }
+        if !args.dry_run {
+            // journal indexes count from one
+            InstallJournal::open(weidu_context.current_dir.path())?.forget_from(reset_index + 1)?;
+        }
         Ok(())
     } else {
         info!("Aborted");
@@ -4,7 +4,7 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Result};
 use log::{debug, info, warn};
-use patch::{Patch, Line};
+use patch::{Hunk, Line, Patch};
 
 use crate::args::Install;
 use crate::canon_path::CanonPath;
@@ -27,10 +27,11 @@ pub async fn patch_module(game_dir: &CanonPath, module_name: &LwcString, patch:
         }
         PatchSource::Inline { inline } => Cow::Borrowed(inline),
     };
-    patch_module_with_content(game_dir, module_name, &*patch_content, patch.encoding)
+    patch_module_with_content(game_dir, module_name, &*patch_content, patch.encoding, usize::from(patch.fuzz))
 }
 
-fn patch_module_with_content(game_dir: &CanonPath, module_name: &LwcString, patch: &str, encoding: PatchEncoding) -> Result<()> {
+fn patch_module_with_content(game_dir: &CanonPath, module_name: &LwcString, patch: &str, encoding: PatchEncoding,
+                                fuzz: usize) -> Result<()> {
     let diff = match Patch::from_multiple(&patch) {
         Ok(diff) => diff,
         Err(error) => bail!("Couldn't parse patch for module {}\n -> {:?}", module_name, error),
@@ -51,7 +52,7 @@ fn patch_module_with_content(game_dir: &CanonPath, module_name: &LwcString, patc
                                             new_path, module_name, error),
         };
         check_path(game_dir, &new)?;
-        if let Err(error) = patch_files(&old, &new, &patch, encoding) {
+        if let Err(error) = patch_files(&old, &new, &patch, encoding, fuzz) {
             bail!("Failed to patch file {:?} for mod {}\n -> {:?}", old, module_name, error);
         }
     }
@@ -79,12 +80,12 @@ fn decode_file(path:&CanonPath, encoding: PatchEncoding) -> Result<String> {
     Ok(decoded.0.into_owned())
 }
 
-fn patch_files(old: &CanonPath, new: &CanonPath, diff: &Patch, encoding: PatchEncoding) -> Result<()> {
+fn patch_files(old: &CanonPath, new: &CanonPath, diff: &Patch, encoding: PatchEncoding, fuzz: usize) -> Result<()> {
     let old_content = get_old_content(old, encoding)?;
 
     let old_lines: Vec<String> = old_content.split("\n").map(From::from).collect();
 
-    let new_lines = match apply_patch(&old_lines, diff) {
+    let new_lines = match apply_patch(&old_lines, diff, fuzz) {
         Err(error) => bail!("Error patching file {:?}\n -> {:?}", old, error),
         Ok(new_lines) => new_lines,
     };
@@ -113,41 +114,56 @@ fn get_old_content(old: &CanonPath, encoding: PatchEncoding) -> Result<String> {
     }
 }
 
-fn apply_patch<'a>(old_lines: &'a[String], diff: &'a Patch) -> Result<Vec<&'a str>> {
+/// Applies the hunks of `diff`; with `fuzz`, a hunk is searched around the line given by the patch (closest first)
+/// and up to `fuzz` context lines at its start and end are ignored, the fewest possible.<br>
+/// All the hunks are tried so that the error lists every hunk that doesn't apply.
+fn apply_patch<'a>(old_lines: &'a[String], diff: &'a Patch, fuzz: usize) -> Result<Vec<&'a str>> {
     let mut new_lines = vec![];
     let mut old_line = 0;
+    // shift of the previous hunks, the next ones are searched with the same shift first
+    let mut offset: i64 = 0;
+    let mut failed = vec![];
     for (idx, hunk) in diff.hunks.iter().enumerate() {
         info!("apply hunk {} of {}", idx + 1, diff.hunks.len());
         debug!("hunk {}", hunk);
-        while old_line + 1 < hunk.old_range.start {
-            new_lines.push(old_lines[old_line as usize].as_str());
+        let patch_position = hunk.old_range.start.max(1) as i64 - 1;
+        let expected = (patch_position + offset).max(0) as usize;
+        let (position, used_fuzz) = match find_hunk(old_lines, hunk, expected, old_line, fuzz) {
+            Some(found) => found,
+            None => {
+                failed.push(format!("hunk {} (line {}) doesn't apply{}:\n{}", idx + 1, hunk.old_range.start,
+                                    mismatch_description(old_lines, hunk, expected), hunk));
+                continue;
+            }
+        };
+        offset = position as i64 - patch_position;
+        if offset != 0 || used_fuzz > 0 {
+            info!("hunk {} applied with offset {} and fuzz {}", idx + 1, offset, used_fuzz);
+        }
+        while old_line < position {
+            new_lines.push(old_lines[old_line].as_str());
             old_line += 1;
         }
         for line in &hunk.lines {
             match line {
                 Line::Add(s)  => new_lines.push(s),
                 Line::Context(s) => {
-                    let context_line = &old_lines[old_line as usize];
-                    if !str_equals_ignore_cr(context_line, s) {
-                        bail!("patch hunk doesn't apply (actual context line '{:?}'):\n{}",
-                                context_line.as_bytes(), hunk);
-                    }
-                    new_lines.push(s);
-                    old_line += 1;
-                }
-                Line::Remove(s) => {
-                    let context_line = &old_lines[old_line as usize];
-                    if !str_equals_ignore_cr(context_line, s) {
-                        bail!("patch hunk doesn't apply (actual deleted line '{:?}', expected '{:?}'):\n{}",
-                                context_line.as_bytes(), s.as_bytes(), hunk);
-                    }
+                    // a context line ignored thanks to the fuzz keeps the content of the file
+                    let context_line = &old_lines[old_line];
+                    new_lines.push(if str_equals_ignore_cr(context_line, s) { s } else { context_line });
                     old_line += 1;
                 }
+                Line::Remove(_) => old_line += 1,
             }
         }
         debug!("at the end of the hunk, old_line is {}", old_line);
     }
-    for line in old_lines.get((old_line as usize)..).unwrap_or(&[]) {
+    if !failed.is_empty() {
+        let hint = if fuzz == 0 { " (set `fuzz` in the patch to allow some differences)" } else { "" };
+        bail!("{} of {} patch hunks don't apply with fuzz {}{}\n{}",
+                failed.len(), diff.hunks.len(), fuzz, hint, failed.join("\n"));
+    }
+    for line in old_lines.get(old_line..).unwrap_or(&[]) {
         new_lines.push(line);
     }
     match old_lines.last() {
@@ -157,6 +173,53 @@ fn apply_patch<'a>(old_lines: &'a[String], diff: &'a Patch) -> Result<Vec<&'a st
     Ok(new_lines)
 }
 
+/// Position (index in `old_lines`, not before `min`) and fuzz of the best match of the hunk.<br>
+/// Without fuzz, the hunk must apply at `expected`.
+fn find_hunk(old_lines: &[String], hunk: &Hunk, expected: usize, min: usize, fuzz: usize) -> Option<(usize, usize)> {
+    let max_distance = if fuzz == 0 { 0 } else { old_lines.len() };
+    for used_fuzz in 0..=fuzz {
+        for distance in 0..=max_distance {
+            let candidates = [Some(expected + distance), expected.checked_sub(distance).filter(|_| distance > 0)];
+            for position in candidates.into_iter().flatten().filter(|position| *position >= min) {
+                if hunk_matches(old_lines, hunk, position, used_fuzz) {
+                    return Some((position, used_fuzz));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The context and removed lines of the hunk (`true` for context).
+fn old_side<'a>(hunk: &'a Hunk) -> Vec<(bool, &'a str)> {
+    hunk.lines.iter().filter_map(|line| match line {
+        Line::Context(s) => Some((true, *s)),
+        Line::Remove(s) => Some((false, *s)),
+        Line::Add(_) => None,
+    }).collect()
+}
+
+fn hunk_matches(old_lines: &[String], hunk: &Hunk, position: usize, fuzz: usize) -> bool {
+    let old_side = old_side(hunk);
+    if position + old_side.len() > old_lines.len() {
+        return false;
+    }
+    let leading = old_side.iter().take_while(|(context, _)| *context).count().min(fuzz);
+    let trailing = old_side.iter().rev().take_while(|(context, _)| *context).count().min(fuzz);
+    old_side.iter().enumerate()
+        .filter(|(index, _)| *index >= leading && *index + trailing < old_side.len())
+        .all(|(index, (_, s))| str_equals_ignore_cr(&old_lines[position + index], s))
+}
+
+/// The first line of the hunk that differs from the file at `position`.
+fn mismatch_description(old_lines: &[String], hunk: &Hunk, position: usize) -> String {
+    old_side(hunk).iter().enumerate()
+        .find(|(index, (_, s))| !old_lines.get(position + index).map(|line| str_equals_ignore_cr(line, s)).unwrap_or(false))
+        .map(|(index, (context, s))| format!(" (actual {} line {:?}, expected {:?})",
+                                            if *context { "context" } else { "deleted" },
+                                            old_lines.get(position + index).map(String::as_str).unwrap_or("<end of file>"), s))
+        .unwrap_or_default()
+}
 
 fn read_patch_relative(relative: &str, game_dir: &CanonPath, opts: &Install,
                         global: &Global, encoding: PatchEncoding) -> Result<String> {
@@ -164,28 +227,36 @@ fn read_patch_relative(relative: &str, game_dir: &CanonPath, opts: &Install,
     if !relative_path.is_relative() {
         bail!("path is not relative: {:?}", relative);
     }
-    let manifest_loc = match PathBuf::from(&opts.manifest_path).parent() {
-        Some(path) => match CanonPath::new(path) {
-                Ok(parent) => parent,
-                Err(error) => bail!("failed to canonalize manifest parent\n -> {:?}", error),
-            },
-        None => game_dir.to_owned(),
-    };
-    let local_patches_loc = match &global.local_patches {
-        Some(path) => match manifest_loc.join(path) {
-            Err(err) => bail!("Could not canonicalize local patches path\n{err}"),
-            Ok(path) => path
-        },
-        None => manifest_loc
+    let manifest_loc = opts.get_manifest_root(game_dir)?;
+    let local_patches_locs = match &global.local_patches {
+        Some(paths) => {
+            let mut locations = vec![];
+            for path in paths.paths() {
+                match manifest_loc.join(path) {
+                    Err(err) => bail!("Could not canonicalize local patches path {path}\n{err}"),
+                    Ok(path) => locations.push(path),
+                }
+            }
+            locations
+        }
+        None => vec![manifest_loc],
     };
-    if !local_patches_loc.path().exists() {
-        bail!("Local patches path {local_patches_loc:?} doesn't exist");
-    }
 
-    match read_patch_from(relative_path.as_path(), &local_patches_loc, encoding) {
-        Ok(diff) => Ok(diff),
-        Err(error) => bail!("Couldn't find relative patch file {}\n{error}", relative),
+    let mut searched = vec![];
+    for local_patches_loc in &local_patches_locs {
+        if !local_patches_loc.path().exists() {
+            searched.push(format!("{:?} (doesn't exist)", local_patches_loc.path()));
+            continue;
+        }
+        if local_patches_loc.join(&relative_path).map(|path| path.path().is_file()).unwrap_or(false) {
+            return match read_patch_from(relative_path.as_path(), local_patches_loc, encoding) {
+                Ok(diff) => Ok(diff),
+                Err(error) => bail!("Couldn't read relative patch file {} in {:?}\n{error}", relative, local_patches_loc.path()),
+            };
+        }
+        searched.push(format!("{:?}", local_patches_loc.path()));
     }
+    bail!("Couldn't find relative patch file {}, searched in\n  - {}", relative, searched.join("\n  - "))
 }
 
 fn read_patch_from(relative: &Path, base: &CanonPath, encoding: PatchEncoding) -> Result<String> {
@@ -215,6 +286,7 @@ mod apply_patch_tests {
     use std::path::Path;
     use indoc::indoc;
     use patch::Patch;
+    use crate::test_utils::test_dir;
 
     use crate::apply_patch::apply_patch;
     use crate::lowercase::lwc;
@@ -313,6 +385,20 @@ mod apply_patch_tests {
                  ~english~
     "#);
 
+    /// SIMPLEST_PATCH made for an upstream version with two more lines at the top and another SUPPORT url.
+    const PATCH_WITH_SHIFTED_CONTEXT: &str = indoc!(r#"
+        --- modulename.tp2
+        +++ modulename.tp2
+        @@ -3,6 +3,6 @@
+         BACKUP ~weidu_external/backup/modulename~
+         SUPPORT ~http://somewhere.iflucky.com~
+        -VERSION ~1.0~
+        +VERSION ~2.0~
+         //languages
+         LANGUAGE ~English~
+                 ~english~
+    "#);
+
     const PATCH_WITH_NEW_FILE: &str = indoc!(r#"
         --- /dev/null
         +++ french.tra
@@ -332,7 +418,7 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(SIMPLEST_PATCH).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         let patched_origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename_patched.tp2");
         let expected = read_all(&patched_origin).unwrap();
@@ -345,7 +431,7 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(PATCH_WITH_DELETE).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         let patched_origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename_delete.tp2");
         let expected = read_all(&patched_origin).unwrap();
@@ -358,7 +444,7 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(PATCH_WITH_ADD).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         let patched_origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename_add.tp2");
         let expected = read_all(&patched_origin).unwrap();
@@ -371,7 +457,7 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(PATCH_WITH_UNMODIFIED_EMPTY_LINE).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         let patched_origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename_patched.tp2");
         let expected = read_all(&patched_origin).unwrap();
@@ -384,7 +470,7 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(PATCH_WITH_INCORRECT_CONTEXT_LINE).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         println!("{:?}", result);
 
@@ -396,13 +482,40 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(PATCH_WITH_INCORRECT_DELETED_LINE).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         println!("{:?}", result);
 
         assert!(result.is_err());
     }
 
+    #[test]
+    fn shifted_context_needs_fuzz() {
+        let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
+        let old = read_all(&origin).unwrap();
+        let patch = Patch::from_single(PATCH_WITH_SHIFTED_CONTEXT).unwrap();
+
+        let error = apply_patch(&old, &patch, 0).unwrap_err().to_string();
+        assert!(error.contains("1 of 1 patch hunks don't apply with fuzz 0"), "{}", error);
+        assert!(error.contains("hunk 1 (line 3) doesn't apply"), "{}", error);
+        // only the first context line is ignored with fuzz 1
+        assert!(apply_patch(&old, &patch, 1).is_err());
+    }
+
+    #[test]
+    fn shifted_context_applies_with_fuzz() {
+        let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
+        let old = read_all(&origin).unwrap();
+        let patch = Patch::from_single(PATCH_WITH_SHIFTED_CONTEXT).unwrap();
+        let result = apply_patch(&old, &patch, 2);
+
+        // the SUPPORT line of the file is kept
+        let patched_origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename_patched.tp2");
+        let expected = read_all(&patched_origin).unwrap();
+
+        assert_eq!(result.unwrap(), expected);
+    }
+
     #[test]
     fn simple_patch_on_files() {
         let (_tempdir, game_dir) = setup_test_game_dir();
@@ -410,7 +523,7 @@ mod apply_patch_tests {
         std::fs::copy(&origin, &game_dir.join_path("modulename.tp2")).unwrap();
 
         super::patch_module_with_content(&game_dir, &lwc!("modulename"), SIMPLEST_PATCH,
-                                    crate::patch_source::PatchEncoding::UTF8).unwrap();
+                                    crate::patch_source::PatchEncoding::UTF8, 0).unwrap();
 
         // file modulename.tp2.old must exist and contain OLD content
         let dot_old_file = game_dir.join_path("modulename.tp2.old");
@@ -426,11 +539,57 @@ mod apply_patch_tests {
         assert_eq!(new_content, expected);
     }
 
+    /// Patches directories `a`, `b` and `c` (not created) in a temporary manifest root.
+    fn read_patch_in_dirs(files: &[(&str, &str)], relative: &str) -> anyhow::Result<String> {
+        use crate::args::Install;
+        use crate::canon_path::CanonPath;
+        use crate::global::{Global, PathList};
+        use crate::patch_source::PatchEncoding;
+
+        let temp = test_dir();
+        for dir in ["patches/a", "patches/b"] {
+            std::fs::create_dir_all(temp.path().join(dir)).unwrap();
+        }
+        for (path, content) in files {
+            std::fs::write(temp.path().join("patches").join(path), content).unwrap();
+        }
+        let game_dir = CanonPath::new(temp.path()).unwrap();
+        let opts = Install { manifest_path: temp.path().join("manifest.yml").to_string_lossy().to_string(), ..Default::default() };
+        let global = Global {
+            local_patches: Some(PathList::List(vec!["patches/a".to_string(), "patches/b".to_string(), "patches/c".to_string()])),
+            ..Default::default()
+        };
+        super::read_patch_relative(relative, &game_dir, &opts, &global, PatchEncoding::UTF8)
+    }
+
+    #[test]
+    fn relative_patch_in_several_directories() {
+        assert_eq!(read_patch_in_dirs(&[("b/fix.diff", "from b")], "fix.diff").unwrap(), "from b");
+        // the first directory wins
+        assert_eq!(read_patch_in_dirs(&[("a/fix.diff", "from a"), ("b/fix.diff", "from b")], "fix.diff").unwrap(), "from a");
+
+        let error = read_patch_in_dirs(&[("b/other.diff", "from b")], "fix.diff").unwrap_err().to_string();
+        assert!(error.contains("Couldn't find relative patch file fix.diff"), "{}", error);
+        for dir in ["patches/a\"", "patches/b\"", "patches/c\" (doesn't exist)"] {
+            assert!(error.contains(dir), "{}", error);
+        }
+    }
+
+    #[test]
+    fn local_patches_single_or_list() {
+        use crate::global::PathList;
+
+        let single: PathList = serde_yaml::from_str("patches").unwrap();
+        assert_eq!(single.paths(), vec!["patches"]);
+        let list: PathList = serde_yaml::from_str("[patches, shared/patches]").unwrap();
+        assert_eq!(list.paths(), vec!["patches", "shared/patches"]);
+    }
+
     #[test]
     fn apply_add_patch_create_file() {
         let old = vec![];
         let patch = Patch::from_single(PATCH_WITH_NEW_FILE).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         assert_eq!(result.unwrap(), vec!["@1 = ~héhé~"]);
     }
@@ -440,7 +599,7 @@ mod apply_patch_tests {
         let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
         let old = read_all(&origin).unwrap();
         let patch = Patch::from_single(PATCH_WITH_A_B_PREFIXES).unwrap();
-        let result = apply_patch(&old, &patch);
+        let result = apply_patch(&old, &patch, 0);
 
         let patched_origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename_add.tp2");
         let expected = read_all(&patched_origin).unwrap();
@@ -1,28 +1,95 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Component, PathBuf};
 use std::process::{Stdio, Command};
+use std::time::{Duration, Instant};
 use std::{path::Path, collections::HashSet};
 
 use std::fs::File;
-use std::io::{BufReader, self};
+use std::io::{BufReader, Read, Seek, self};
 
 use globwalk::GlobWalkerBuilder;
 use log::{debug, info};
 use anyhow::{bail, Result, anyhow};
 use tempfile::TempDir;
 use zip::ZipArchive;
-use zip::result::{ZipResult, ZipError};
+use zip::result::{InvalidPassword, ZipResult, ZipError};
 
+use crate::archive_layout::GlobDesc;
+use crate::archive_name_encoding::{entry_relative_path, ArchiveNameEncoding};
 use crate::canon_path::CanonPath;
+use crate::case_collisions::{describe_collisions, find_case_collisions, remove_case_duplicates, CaseCollisions};
+use crate::iemod::{read_iemod_metadata, IemodMetadata};
+use crate::jobs::Pool;
 use crate::lowercase::{LwcString, lwc};
-use crate::module::location::location::ConcreteLocation;
+use crate::module::location::location::{ConcreteLocation, MergeExisting};
 use crate::module::pre_copy_command::PrecopyCommand;
+use crate::progname::PROGNAME;
 use crate::config::{Config, ExtractorCommand};
+use crate::extractor_hint::missing_extractor_hint;
+use crate::run_warnings::{report_warning, WarningCollector};
+use crate::split_archive::{find_split_archive, SplitArchive, VolumeReader};
+use crate::tar_symlinks::{unpack_tar, TarSymlinks};
+use crate::utils::pathext::is_hidden_under;
+use crate::utils::permissions::normalize_permissions;
 
 
 #[cfg_attr(test, faux::create)]
 pub struct Extractor<'a> {
     game_dir: &'a CanonPath,
     config: &'a Config,
+    warnings: Option<&'a WarningCollector>,
+    /// Workers for the entries of zip archives; without a pool, they are extracted one after the other.
+    pool: Option<&'a Pool>,
+}
+
+/// How `Extractor::extract_files` obtained the mod content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractionMethod {
+    /// The location is a directory, used in place.
+    Directory,
+    /// The location is a directory, copied to a temporary location (for a `precopy` command or a `layout`).
+    DirectoryCopy,
+    Zip,
+    Tgz,
+    /// Built-in `.rar` extraction (`rar` feature).
+    Rar,
+    /// Configured external extractor (`extractors` in the config).
+    External,
+}
+
+/// Archive formats that can be extracted while they are read (`stream_extract` config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Zip,
+    Tgz,
+}
+
+impl StreamFormat {
+    /// From the archive file name, `None` for the formats that need the whole file.
+    pub fn from_name(archive: &Path) -> Option<Self> {
+        let name = archive.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") || name.ends_with(".iemod") {
+            Some(StreamFormat::Zip)
+        } else if name.ends_with(".tgz") || name.ends_with(".tar.gz") {
+            Some(StreamFormat::Tgz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returned by `Extractor::extract_files`, used for profiling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractionStats {
+    pub method: ExtractionMethod,
+    /// Number of files in the extracted content (before the `layout` selection).
+    pub entries: usize,
+    /// Total size of these files.
+    pub bytes: u64,
+    /// Wall-clock duration, including moving the files to the game directory.
+    pub duration: Duration,
+    /// Metadata of an `.iemod` package, used for the values the manifest leaves out.
+    pub iemod: Option<IemodMetadata>,
 }
 
 #[cfg_attr(test, faux::methods)]
@@ -32,27 +99,148 @@ impl <'a> Extractor<'a> {
         Self {
             game_dir,
             config,
+            warnings: None,
+            pool: None,
         }
     }
 
-    pub fn extract_files(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation,) -> Result<()> {
+    /// Zip archives (read from a file) have their entries extracted by the workers of `pool`.
+    pub fn with_pool(self, pool: &'a Pool) -> Self {
+        Self { pool: Some(pool), ..self }
+    }
+
+    /// Warnings (iemod mismatch, patterns matching nothing...) go to `warnings` instead of only being logged.
+    pub fn with_warnings(self, warnings: &'a WarningCollector) -> Self {
+        Self { warnings: Some(warnings), ..self }
+    }
+
+    /// Extracts the mod content and moves it to the game directory.<br>
+    /// Returns statistics about the extracted content (callers may ignore them).
+    pub fn extract_files(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation,) -> Result<ExtractionStats> {
         debug!("extract_files from archive {:?} for {}", archive, module_name);
+        let start = Instant::now();
+        let iemod = self.iemod_metadata(archive, module_name);
         let result = self.extract_files_to_temp(archive, module_name, location);
         debug!("done extracting files, ended in {}", result.as_ref().map(|_| "success".to_owned()).unwrap_or_else(|_| "failure".to_owned()));
 
-        let temp_dir = result?;
+        let (temp_dir, method) = result?;
+        let stats = self.complete_extraction(temp_dir, method, start, Some(archive), module_name, location)?;
+        Ok(ExtractionStats { iemod, ..stats })
+    }
+
+    /// Extracts an archive while it is read from `reader` (a download), then moves the mod content
+    /// to the game directory like `extract_files`.<br>
+    /// Encrypted zip entries and zip archives that don't have the entry sizes in their local headers are not supported.
+    pub fn extract_stream(&self, reader: &mut dyn Read, format: StreamFormat, module_name: &LwcString,
+                            location: &ConcreteLocation) -> Result<ExtractionStats> {
+        debug!("extract {:?} stream for {}", format, module_name);
+        let start = Instant::now();
+        let temp_dir = match self.create_temp_dir(location, module_name) {
+            Ok(dir) => dir,
+            Err(error) => bail!("Extraction of streamed mod {} failed\n -> {:?}", module_name, error),
+        };
+        let (result, method) = match format {
+            StreamFormat::Zip => (extract_zip_stream(reader, temp_dir.path(), self.archive_name_encoding(location))
+                                    .map_err(anyhow::Error::from), ExtractionMethod::Zip),
+            StreamFormat::Tgz => (unpack_tar(&mut tar::Archive::new(flate2::read::GzDecoder::new(reader)), temp_dir.path(),
+                                                self.tar_symlinks(location)), ExtractionMethod::Tgz),
+        };
+        if let Err(error) = result {
+            bail!("Streamed {:?} extraction failed for mod {}\n -> {:?}", format, module_name, error);
+        }
+        self.complete_extraction(ExtractLocation::Temp(temp_dir), method, start, None, module_name, location)
+    }
+
+    /// Resolves case collisions, runs `precopy` on the extracted content and moves it to the game directory.<br>
+    /// `archive` is `None` for streamed archives.
+    fn complete_extraction(&self, temp_dir: ExtractLocation, method: ExtractionMethod, start: Instant, archive: Option<&Path>,
+                            module_name: &LwcString, location: &ConcreteLocation) -> Result<ExtractionStats> {
+        if let ExtractLocation::Temp(dir) = &temp_dir {
+            self.resolve_case_collisions(dir.path(), archive, module_name, location)?;
+        }
+        let (entries, bytes) = match content_size(temp_dir.as_path_buf()) {
+            Ok(size) => size,
+            Err(error) => bail!("Could not compute size of extracted content for mod {}\n -> {:?}", module_name, error),
+        };
         if let Some(command) = &location.precopy {
             if let Err(error) = self.run_precopy_command(&temp_dir.as_path_buf(), command) {
-                bail!("Couldn't run precopy command for mod {}, command={} with args {:?}\n{:?}",
-                        module_name, command.command, command.args, error);
+                bail!("Couldn't run precopy command for mod {}, command={:?}\n{:?}",
+                        module_name, command, error);
             }
         }
 
         self.move_content_to_game_dir(&temp_dir, module_name, location)?;
 
+        let stats = ExtractionStats { method, entries, bytes, duration: start.elapsed(), iemod: None };
+        debug!("extraction stats for {}: {:?}", module_name, stats);
+        Ok(stats)
+    }
+
+    /// Files that only differ by case can't all be in the game directory on a case-insensitive filesystem
+    /// (and WeiDU or the game would only see one of them anyway).<br>
+    /// The order of the archive entries decides which one is first; without it (streamed archive,
+    /// unlistable archive or directory copy), the name order is used.
+    fn resolve_case_collisions(&self, temp_dir: &Path, archive: Option<&Path>, module_name: &LwcString,
+                                location: &ConcreteLocation) -> Result<()> {
+        let collisions = find_case_collisions(temp_dir, &[])?;
+        if collisions.is_empty() {
+            return Ok(());
+        }
+        let order = match archive.filter(|archive| archive.is_file()).map(|archive| self.list_entries(archive)) {
+            Some(Ok(entries)) => entries,
+            Some(Err(error)) => {
+                debug!("could not list archive entries for mod {}, name order is used\n -> {:?}", module_name, error);
+                vec![]
+            }
+            None => vec![],
+        };
+        let collisions = find_case_collisions(temp_dir, &order)?;
+        let resolution = location.case_collisions.or(self.config.case_collisions).unwrap_or_default();
+        match resolution {
+            CaseCollisions::Error =>
+                bail!("Mod {} has files that only differ by case\n -> {}", module_name, describe_collisions(&collisions)),
+            CaseCollisions::Warn =>
+                report_warning(self.warnings, Some(module_name), "extract",
+                                &format!("files that only differ by case (set case_collisions to choose one): {}",
+                                        describe_collisions(&collisions))),
+            CaseCollisions::KeepFirst | CaseCollisions::KeepLast => {
+                info!("mod {} has files that only differ by case, keeping {:?}: {}",
+                        module_name, resolution, describe_collisions(&collisions));
+                remove_case_duplicates(temp_dir, &collisions, resolution)?;
+            }
+        }
         Ok(())
     }
 
+    /// `.iemod` packages may embed metadata; it only provides defaults, so reading it never fails the extraction.
+    fn iemod_metadata(&self, archive: &Path, module_name: &LwcString) -> Option<IemodMetadata> {
+        let is_iemod = archive.extension().map(|ext| ext.eq_ignore_ascii_case("iemod")).unwrap_or(false);
+        if !is_iemod || !archive.is_file() {
+            return None;
+        }
+        match read_iemod_metadata(archive) {
+            Err(error) => {
+                report_warning(self.warnings, Some(module_name), "extract",
+                                &format!("Could not read iemod metadata from {:?}\n -> {:?}", archive, error));
+                None
+            }
+            Ok(None) => {
+                debug!("no metadata in iemod archive {:?}", archive);
+                None
+            }
+            Ok(Some(metadata)) => {
+                info!("iemod package for {}: {}", module_name, metadata.describe());
+                match &metadata.mod_dir {
+                    Some(mod_dir) if mod_dir != module_name =>
+                        report_warning(self.warnings, Some(module_name), "extract",
+                                        &format!("iemod archive {:?} contains mod {} but the manifest expects {}", archive, mod_dir, module_name)),
+                    _ => {}
+                }
+                Some(metadata)
+            }
+        }
+    }
+
     fn move_content_to_game_dir(&self, temp_dir: &ExtractLocation,  module_name: &LwcString, location: &ConcreteLocation) -> Result<()> {
         match temp_dir {
             ExtractLocation::Temp(temp_dir) => {
@@ -76,98 +264,238 @@ impl <'a> Extractor<'a> {
 
     /// Extracts (if needed) the archive to a temporary location.
     /// Returns the path to the extracted content.
-    fn extract_files_to_temp(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<ExtractLocation> {
+    fn extract_files_to_temp(&self, archive: &Path, module_name: &LwcString,
+                                location: &ConcreteLocation) -> Result<(ExtractLocation, ExtractionMethod)> {
+        if !archive.exists() {
+            bail!("Mod location {:?} does not exist (neither an archive nor a directory)", archive);
+        }
         if archive.is_dir() {
-            if location.precopy.is_some() {
-                // precopy could modify the content so make a temp copy to preserve original
-                let temp_dir_attempt = self.create_temp_dir();
+            if location.precopy.is_some() || location.layout.is_some() {
+                // precopy could modify the content so make a temp copy to preserve original;
+                // with a layout, only part of the content is moved (like for an archive)
+                let temp_dir_attempt = self.create_temp_dir(location, module_name);
                 let temp_dir = match temp_dir_attempt {
                     Ok(dir) => dir,
                     Err(error) => bail!("Creation of temp copy of mod {} failed\n -> {:?}", module_name, error),
                 };
                 self.copy_to_temp_dir(archive, temp_dir.as_ref())?;
-                debug!("Directory content was copied to {:?} for precopy command", temp_dir);
-                Ok(ExtractLocation::Temp(temp_dir))
+                debug!("Directory content was copied to {:?} for precopy command or layout", temp_dir);
+                Ok((ExtractLocation::Temp(temp_dir), ExtractionMethod::DirectoryCopy))
             } else {
                 // will not change the source directory, no need to create a temporary copy
-                Ok(ExtractLocation::Regular(archive.to_owned()))
+                Ok((ExtractLocation::Regular(archive.to_owned()), ExtractionMethod::Directory))
             }
+        } else if let Some(split) = find_split_archive(archive)? {
+            let (tmp_dir, method) = self.extract_split(&split, module_name, location)?;
+            Ok((ExtractLocation::Temp(tmp_dir), method))
         } else {
-            let tmp_dir = match archive.extension() {
+            let (tmp_dir, method) = match archive.extension() {
                 Some(ext) =>  match ext.to_str() {
                     None => bail!("Couldn't determine archive type for file {:?}", archive),
-                    Some("zip") | Some("iemod") => self.extract_zip(archive, module_name),
-                    Some("tgz") => self.extract_tgz(archive, module_name),
-                    Some("gz") => self.extract_gz(archive, module_name),
-                    Some(ext) => self.extract_external(archive, module_name, ext),
+                    Some("zip") | Some("iemod") => (self.extract_zip(archive, module_name, location), ExtractionMethod::Zip),
+                    Some("tgz") => (self.extract_tgz(archive, module_name, location), ExtractionMethod::Tgz),
+                    Some("gz") => (self.extract_gz(archive, module_name, location), ExtractionMethod::Tgz),
+                    Some("rar") if cfg!(feature = "rar") => (self.extract_rar(archive, module_name, location), ExtractionMethod::Rar),
+                    Some(ext) => (self.extract_external(archive, module_name, ext, location), ExtractionMethod::External),
                 }
                 None => bail!("archive file has no extension {:?}", archive),
             };
-            tmp_dir.map(|dir| ExtractLocation::Temp(dir))
+            tmp_dir.map(|dir| (ExtractLocation::Temp(dir), method))
         }
     }
 
-    fn extract_gz(&self, archive: &Path, module_name: &LwcString) -> Result<TempDir> {
-        let stem = archive.file_stem();
-        match stem {
-            Some(stem) => {
-                let stem_path = PathBuf::from(stem);
-                let sub_ext = stem_path.extension();
-                match sub_ext {
-                    None => bail!("unsupported .gz file for archive {:?}", archive),
-                    Some(sub_ext) => match sub_ext.to_str() {
-                        Some("tar") => self.extract_tgz(archive, module_name),
-                        _ =>  bail!("unsupported .gz file for archive {:?}", archive),
-                    }
-                }
+    /// Zip and tar.gz volumes are read as one file; other formats go to the external extractor of the archive
+    /// extension, with the first volume (7z finds the next ones by itself).
+    fn extract_split(&self, split: &SplitArchive, module_name: &LwcString,
+                        location: &ConcreteLocation) -> Result<(TempDir, ExtractionMethod)> {
+        let first = &split.volumes[0];
+        debug!("split archive {:?} has {} volumes", first, split.volumes.len());
+        match split.extension.as_str() {
+            "zip" | "iemod" => {
+                let mut reader = BufReader::new(VolumeReader::open(&split.volumes)?);
+                Ok((self.extract_zip_reader(&mut reader, first, module_name, location)?, ExtractionMethod::Zip))
+            }
+            _ if is_split_tgz(split) => {
+                let mut reader = BufReader::new(VolumeReader::open(&split.volumes)?);
+                Ok((self.extract_tgz_reader(&mut reader, first, module_name, location)?, ExtractionMethod::Tgz))
+            }
+            extension => Ok((self.extract_external(first, module_name, extension, location)?, ExtractionMethod::External)),
+        }
+    }
+
+    fn extract_gz(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<TempDir> {
+        if is_tar_gz(archive) {
+            self.extract_tgz(archive, module_name, location)
+        } else {
+            bail!("unsupported .gz file for archive {:?}", archive)
+        }
+    }
+
+    /// Lists the paths of the entries of the archive, without extracting anything.<br>
+    /// Archives that are not zip or tar.gz are listed with the external extractor `list_args`
+    /// when it's configured.
+    pub fn list_entries(&self, archive: &Path) -> Result<Vec<PathBuf>> {
+        if let Some(split) = find_split_archive(archive)? {
+            return self.list_split_entries(&split);
+        }
+        match archive.extension() {
+            Some(ext) =>  match ext.to_str() {
+                None => bail!("Couldn't determine archive type for file {:?}", archive),
+                Some("zip") | Some("iemod") => list_zip_entries(archive),
+                Some("tgz") => list_tgz_entries(archive),
+                Some("gz") if is_tar_gz(archive) => list_tgz_entries(archive),
+                Some("gz") => bail!("unsupported .gz file for archive {:?}", archive),
+                Some(ext) => self.list_external(archive, ext),
             }
-            None => bail!("unsupported .gz file for archive {:?}", archive)
+            None => bail!("archive file has no extension {:?}", archive),
+        }
+    }
+
+    fn list_split_entries(&self, split: &SplitArchive) -> Result<Vec<PathBuf>> {
+        let first = &split.volumes[0];
+        match split.extension.as_str() {
+            "zip" | "iemod" => list_zip_reader(BufReader::new(VolumeReader::open(&split.volumes)?), first),
+            _ if is_split_tgz(split) =>
+                list_tgz_reader(BufReader::new(VolumeReader::open(&split.volumes)?)),
+            extension => self.list_external(first, extension),
+        }
+    }
+
+    fn list_external(&self, archive: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+        let extractor_command = self.extractor_command(extension)?;
+        let list_args = match &extractor_command.list_args {
+            None => bail!("No list_args configured for {extension} extractor, can't list archive content"),
+            Some(list_args) => list_args,
+        };
+        let input = match archive.to_str() {
+            None => bail!("Error extracting archive path"),
+            Some(input) => input,
+        };
+        let mut command = Command::new(&extractor_command.command);
+        command.args(list_args.iter().map(|arg| arg.replace("${input}", input)));
+        debug!("list command: {:?}", command);
+        let output = match command.output() {
+            Ok(output) => output,
+            Err(error) => bail!("failure running list command for {:?}\n{:?}", archive, error),
+        };
+        if !output.status.success() {
+            bail!("list command failed for {:?} with status\n{:?}", archive, output.status.code());
         }
+        Ok(String::from_utf8_lossy(&output.stdout).lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect())
     }
 
-    fn extract_zip(&self, archive: &Path,  module_name: &LwcString) -> Result<TempDir> {
+    fn extract_zip(&self, archive: &Path,  module_name: &LwcString, location: &ConcreteLocation) -> Result<TempDir> {
         let file = match File::open(archive) {
             Ok(file) => file,
             Err(error) => bail!("Could not open archive {:?} - {:?}", archive, error)
         };
-        let reader = BufReader::new(file);
+        let pool = match self.pool {
+            None => return self.extract_zip_reader(&mut BufReader::new(file), archive, module_name, location),
+            Some(pool) => pool,
+        };
+        let entries = match zip::ZipArchive::new(BufReader::new(file)) {
+            Ok(zip_archive) => zip_archive.len(),
+            Err(error) => bail!("Cold not open zip archive at {:?}\n -> {:?}", archive, error),
+        };
+        let temp_dir = match self.create_temp_dir(location, module_name) {
+            Ok(dir) => dir,
+            Err(error) => bail!("Extraction of zip mod {} failed\n -> {:?}", module_name, error),
+        };
+        let password = self.archive_password(module_name, location)?;
+        let name_encoding = self.archive_name_encoding(location);
+        // each worker opens the archive and extracts its share of the entries
+        let workers = pool.limit().min(entries).max(1);
+        let shares = (0..workers).map(|worker| (worker..entries).step_by(workers).collect::<Vec<_>>()).collect();
+        debug!("zip extraction starting ({} workers)", workers);
+        let results = pool.map(shares, |indexes: Vec<usize>| {
+            let mut zip_archive = ZipArchive::new(BufReader::new(File::open(archive)?))?;
+            extract_zip_entries(&mut zip_archive, indexes, temp_dir.path(), password.as_deref().map(str::as_bytes), name_encoding)
+        });
+        if let Some(error) = results.into_iter().find_map(Result::err) {
+            return Err(zip_extraction_error(error, archive, module_name));
+        }
+        debug!("zip extraction done");
+        Ok(temp_dir)
+    }
+
+    /// `archive` is only used in messages.
+    fn extract_zip_reader(&self, reader: &mut dyn ReadSeek, archive: &Path, module_name: &LwcString,
+                            location: &ConcreteLocation) -> Result<TempDir> {
         let mut zip_archive = match zip::ZipArchive::new(reader) {
             Ok(archive) => archive,
             Err(error) => bail!("Cold not open zip archive at {:?}\n -> {:?}", archive, error),
         };
-        let temp_dir_attempt = self.create_temp_dir();
+        let temp_dir_attempt = self.create_temp_dir(location, module_name);
         let temp_dir = match temp_dir_attempt {
             Ok(dir) => dir,
             Err(error) => bail!("Extraction of zip mod {} failed\n -> {:?}", module_name, error),
         };
+        let password = self.archive_password(module_name, location)?;
         debug!("zip extraction starting");
-        if let Err(error) = extract_zip_archive(&mut zip_archive, &temp_dir) {
-            bail!("Zip extraction failed for {:?}\n-> {:?}", archive, error);
+        let name_encoding = self.archive_name_encoding(location);
+        let entries = 0..zip_archive.len();
+        if let Err(error) = extract_zip_entries(&mut zip_archive, entries, temp_dir.path(),
+                                                password.as_deref().map(str::as_bytes), name_encoding) {
+            return Err(zip_extraction_error(error, archive, module_name));
         }
         debug!("zip extraction done");
 
         Ok(temp_dir)
     }
 
-    fn extract_tgz(&self, archive: &Path, module_name: &LwcString) -> Result<TempDir> {
-        let tar_gz = File::open(archive)?;
-        let tar = flate2::read::GzDecoder::new(tar_gz);
+    /// The location `password`, with environment variables expanded.
+    fn archive_password(&self, module_name: &LwcString, location: &ConcreteLocation) -> Result<Option<String>> {
+        match &location.password {
+            None => Ok(None),
+            Some(password) => match shellexpand::env(password) {
+                Err(error) => bail!("Archive password expansion failed for mod {}\n  {error}", module_name),
+                Ok(expanded) => Ok(Some(expanded.to_string())),
+            },
+        }
+    }
+
+    fn extract_rar(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<TempDir> {
+        let temp_dir = match self.create_temp_dir(location, module_name) {
+            Ok(dir) => dir,
+            Err(error) => bail!("Extraction of rar mod {} failed\n -> {:?}", module_name, error),
+        };
+        let password = self.archive_password(module_name, location)?;
+        if let Err(error) = extract_rar_archive(archive, temp_dir.path(), password.as_deref()) {
+            bail!("Rar extraction failed for {:?} (mod {})\n -> {:?}", archive, module_name, error);
+        }
+        Ok(temp_dir)
+    }
+
+    fn extract_tgz(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<TempDir> {
+        let mut tar_gz = File::open(archive)?;
+        self.extract_tgz_reader(&mut tar_gz, archive, module_name, location)
+    }
+
+    /// `archive` is only used in messages.
+    fn extract_tgz_reader(&self, reader: &mut dyn Read, archive: &Path, module_name: &LwcString,
+                            location: &ConcreteLocation) -> Result<TempDir> {
+        let tar = flate2::read::GzDecoder::new(reader);
         let mut tar_archive = tar::Archive::new(tar);
 
-        let temp_dir_attempt = self.create_temp_dir();
+        let temp_dir_attempt = self.create_temp_dir(location, module_name);
         let temp_dir = match temp_dir_attempt {
             Ok(dir) => dir,
             Err(error) => bail!("Extraction of tgz mod {} failed\n -> {:?}", module_name, error),
         };
-        if let Err(error) = tar_archive.unpack(&temp_dir) {
+        if let Err(error) = unpack_tar(&mut tar_archive, temp_dir.path(), self.tar_symlinks(location)) {
             bail!("Tgz extraction failed for {:?} - {:?}", archive, error);
         }
 
         Ok(temp_dir)
     }
 
-    fn extract_external(&self, archive: &Path, module_name: &LwcString, extension: &str) -> Result<TempDir> {
-        let temp_dir_attempt = self.create_temp_dir();
+    fn extract_external(&self, archive: &Path, module_name: &LwcString, extension: &str,
+                        location: &ConcreteLocation) -> Result<TempDir> {
+        let temp_dir_attempt = self.create_temp_dir(location, module_name);
         let temp_dir = match temp_dir_attempt {
             Ok(dir) => dir,
             Err(error) => bail!("Extraction of '{}' mod {} failed\n -> {:?}", extension, module_name, error),
@@ -180,9 +508,12 @@ impl <'a> Extractor<'a> {
         Ok(temp_dir)
     }
 
-    fn create_temp_dir(&self) -> Result<tempfile::TempDir> {
-        let temp_dir_attempt = match &self.config.extract_location {
-            None => tempfile::tempdir(),
+    /// The module location `extract_location` (if set) wins over the one from the config.<br>
+    /// With `debug_extract_dirs`, the directory name comes from the module name (see `create_debug_dir`).
+    fn create_temp_dir(&self, location: &ConcreteLocation, module_name: &LwcString) -> Result<tempfile::TempDir> {
+        let extract_location = location.extract_location.as_ref().or(self.config.extract_location.as_ref());
+        let base = match extract_location {
+            None => None,
             Some(location) => {
                 let expanded = match shellexpand::full(location) {
                     Err(error) => bail!("Temporary dir expansion failed\n  {error}"),
@@ -190,11 +521,16 @@ impl <'a> Extractor<'a> {
                 };
                 debug!("using {:?} for extraction location", expanded);
                 if let Err(error) = std::fs::create_dir_all(&*expanded) {
-                    bail!("Error creating extraction location from config: {}\n -> {:?}", expanded, error);
+                    bail!("Error creating extraction location {}\n -> {:?}", expanded, error);
                 }
-                tempfile::tempdir_in(&*expanded)
+                Some(PathBuf::from(expanded))
             }
         };
+        let temp_dir_attempt = match (self.config.debug_extract_dirs.unwrap_or(false), base) {
+            (true, base) => create_debug_dir(&base.unwrap_or_else(std::env::temp_dir), module_name),
+            (false, None) => tempfile::tempdir(),
+            (false, Some(base)) => tempfile::tempdir_in(base),
+        };
         match temp_dir_attempt {
             Ok(dir) => Ok(dir),
             Err(error) => bail!("Could not create temp dir for archive extraction\n -> {:?}", error),
@@ -228,46 +564,145 @@ impl <'a> Extractor<'a> {
     }
 
     fn move_from_temp_dir(&self, temp_dir: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<()> {
+        let temp_dir = &layout_root(temp_dir, location)?;
+        // relocated files are moved first so that the layout doesn't move them too
+        self.relocate_files(temp_dir, module_name, location)?;
         let items = match self.files_to_move(temp_dir, module_name, location) {
             Ok(items) => items,
             Err(error) => bail!("Failed to prepare list of files to move\n -> {:?}", error),
         };
+        if items.is_empty() {
+            let glob_descs = location.layout().to_glob(module_name, &location.source);
+            report_warning(self.warnings, Some(module_name), "extract",
+                            &format!("the layout patterns matched no file in the archive\n -> {}\n -> hint: {}",
+                                    layout_context(module_name, temp_dir, &glob_descs),
+                                    no_match_hint(temp_dir, &glob_descs, self.glob_case_sensitive(location))));
+        }
         let copy_options = fs_extra::dir::CopyOptions {
             copy_inside: true,
             ..Default::default()
         };
-        let _result = fs_extra::move_items(&items.iter().collect::<Vec<_>>(), &self.game_dir.path(), &copy_options)?;
-        // this is ne number of moved items ; I don't care
+        // the result of move_items is the number of moved items ; I don't care
+        if let Some(merge) = location.merge {
+            merge_items(&items, self.game_dir.path(), module_name, merge)?;
+        } else if let Err(error) = fs_extra::move_items(&items.iter().collect::<Vec<_>>(), &self.game_dir.path(), &copy_options) {
+            return Err(move_error(error, &items, self.game_dir.path()));
+        }
+
+        if self.must_normalize_permissions(location) {
+            debug!("normalizing permissions of moved files");
+            for item in &items {
+                if let Some(name) = item.file_name() {
+                    normalize_permissions(&self.game_dir.path().join(name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the files matched by the `relocate` globs to their destination in the mod directory.<br>
+    /// Fails if two files would land at the same place.
+    fn relocate_files(&self, temp_dir: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<()> {
+        let relocate = match &location.relocate {
+            None => return Ok(()),
+            Some(relocate) => relocate,
+        };
+        let mod_dir = self.game_dir.path().join(module_name.as_ref());
+        let mut moves: HashMap<PathBuf, PathBuf> = HashMap::new();
+        for (pattern, dest) in relocate {
+            if Path::new(dest).components().any(|part| !matches!(part, Component::Normal(_) | Component::CurDir)) {
+                bail!("Invalid `relocate` destination {} for module {}\n -> must be relative to the mod directory", dest, module_name);
+            }
+            let glob = GlobWalkerBuilder::from_patterns(temp_dir, &[pattern])
+                .case_insensitive(!self.glob_case_sensitive(location))
+                .build();
+            let glob = match glob {
+                Err(error) => bail!("Invalid `relocate` pattern {} for module {}\n -> {:?}", pattern, module_name, error),
+                Ok(glob) => glob,
+            };
+            let sources = glob.into_iter().filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.into_path())
+                .collect::<Vec<_>>();
+            if sources.is_empty() {
+                report_warning(self.warnings, Some(module_name), "extract",
+                                &format!("`relocate` pattern {} matched no file in the archive", pattern));
+            }
+            for source in sources {
+                let target = match (dest.ends_with('/'), source.file_name()) {
+                    (true, Some(name)) => mod_dir.join(dest).join(name),
+                    _ => mod_dir.join(dest),
+                };
+                if let Some(previous) = moves.get(&target) {
+                    bail!("Two files of module {} are relocated to {:?}\n -> {:?}\n -> {:?}", module_name, target, previous, source);
+                }
+                moves.insert(target, source);
+            }
+        }
+        for (target, source) in moves {
+            debug!("relocate {:?} to {:?}", source, target);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if let Err(error) = fs_extra::file::move_file(&source, &target, &fs_extra::file::CopyOptions::new()) {
+                bail!("Could not relocate {:?} to {:?}\n -> {:?}", source, target, error);
+            }
+        }
         Ok(())
     }
 
+    fn glob_case_sensitive(&self, location: &ConcreteLocation) -> bool {
+        location.layout().case_sensitive.or(self.config.glob_case_sensitive).unwrap_or(false)
+    }
+
+    fn archive_name_encoding(&self, location: &ConcreteLocation) -> ArchiveNameEncoding {
+        location.archive_name_encoding.or(self.config.archive_name_encoding).unwrap_or_default()
+    }
+
+    fn tar_symlinks(&self, location: &ConcreteLocation) -> TarSymlinks {
+        location.tar_symlinks.or(self.config.tar_symlinks).unwrap_or_default()
+    }
+
+    fn must_normalize_permissions(&self, location: &ConcreteLocation) -> bool {
+        location.normalize_permissions.or(self.config.normalize_permissions).unwrap_or(false)
+    }
+
     fn files_to_move(&self, base: &Path, module_name: &LwcString, location:&ConcreteLocation) -> Result<HashSet<PathBuf>> {
         let mut items = HashSet::new();
         debug!("files_to_move temp dir={:?}", base);
 
-        let glob_descs = location.layout.to_glob(module_name, &location.source);
+        let layout = location.layout();
+        let glob_descs = layout.to_glob(module_name, &location.source);
         if glob_descs.patterns.is_empty() || glob_descs.patterns.iter().all(|entry| entry.trim().is_empty()) {
             bail!("No file patterns to copy from archive for module {}", module_name);
         }
+        if layout.literal.unwrap_or(false) {
+            let paths = layout.content_paths(module_name, &location.source);
+            return literal_items(base, &paths, layout.subdir.as_deref());
+        }
         debug!("Copy files from patterns: {:?}", glob_descs);
         let glob_builder = GlobWalkerBuilder::from_patterns(base, &glob_descs.patterns)
-                .case_insensitive(true)
+                .case_insensitive(!self.glob_case_sensitive(location))
                 .min_depth(glob_descs.strip)
                 .max_depth(glob_descs.strip + 1);
         let glob = match glob_builder.build() {
-            Err(error) => bail!("Could not evaluate patterns {:?}\n -> {:?}", glob_descs, error),
+            Err(error) => bail!("Could not evaluate patterns\n -> {}\n -> {:?}", layout_context(module_name, base, &glob_descs), error),
             Ok(glob) => glob,
         };
+        let include_hidden = self.config.glob_include_hidden.unwrap_or(true);
         for item in glob.into_iter().filter_map(Result::ok) {
-            items.insert(item.into_path());
+            if include_hidden || !is_hidden_under(item.path(), base) {
+                items.insert(item.into_path());
+            }
         }
         Ok(items)
     }
 
     fn run_precopy_command(&self, from: &Path, precopy: &PrecopyCommand) -> Result<()> {
+        let (program, args) = precopy.for_current_os()?;
         info!("Running precopy command `{}` with args {:?} from path `{:?}` in subdir {:?}",
-                precopy.command, precopy.args, from, precopy.subdir);
-        let mut command = Command::new(&precopy.command);
+                program, args, from, precopy.subdir);
+        let mut command = Command::new(program);
         let work_dir = match &precopy.subdir {
             None => from.to_path_buf(),
             Some(subdir) => from.join(subdir),
@@ -275,10 +710,8 @@ impl <'a> Extractor<'a> {
         command.current_dir(work_dir)
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit());
-        if let Some(args) = &precopy.args {
-            command.args(args);
-        }
+            .stderr(Stdio::inherit())
+            .args(args);
         debug!("command: {:?}", command);
         return match command.status() {
             Ok(status) => {
@@ -332,38 +765,386 @@ impl <'a> Extractor<'a> {
     fn extractor_command(&self, extension: &str) -> Result<&ExtractorCommand> {
         match self.config.extractors.get(&lwc!(extension)) {
             Some(extractor) => Ok(extractor),
-            None => bail!("No extractor configured for {extension}"),
+            None if extension.eq_ignore_ascii_case("rar") =>
+                bail!("No extractor configured for rar\n -> use a modda built with the `rar` feature, or {}",
+                        missing_extractor_hint(extension, std::env::var_os("PATH").as_deref())),
+            None => bail!("No extractor configured for {extension}\n -> {}",
+                            missing_extractor_hint(extension, std::env::var_os("PATH").as_deref())),
         }
     }
 }
 
+fn is_tar_gz(archive: &Path) -> bool {
+    archive.file_stem()
+        .map(PathBuf::from)
+        .and_then(|stem| stem.extension().map(|ext| ext.to_os_string()))
+        .map(|sub_ext| sub_ext == "tar")
+        .unwrap_or(false)
+}
+
+fn is_split_tgz(split: &SplitArchive) -> bool {
+    split.extension == "tgz" || (split.extension == "gz" && is_tar_gz(&split.volumes[0].with_extension("")))
+}
+
+/// Total size of the archive content once extracted, read from the zip central directory
+/// or from the tar headers; `None` for other archive formats.
+pub fn uncompressed_size(archive: &Path) -> Result<Option<u64>> {
+    let extension = archive.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("zip") | Some("iemod") => zip_uncompressed_size(archive).map(Some),
+        Some("tgz") => tgz_uncompressed_size(archive).map(Some),
+        Some("gz") if is_tar_gz(archive) => tgz_uncompressed_size(archive).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn zip_uncompressed_size(archive: &Path) -> Result<u64> {
+    let file = match File::open(archive) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open archive {:?} - {:?}", archive, error)
+    };
+    let mut zip_archive = match zip::ZipArchive::new(BufReader::new(file)) {
+        Ok(archive) => archive,
+        Err(error) => bail!("Cold not open zip archive at {:?}\n -> {:?}", archive, error),
+    };
+    let mut total = 0;
+    for index in 0..zip_archive.len() {
+        // raw: no decryption needed for encrypted archives
+        total += zip_archive.by_index_raw(index)?.size();
+    }
+    Ok(total)
+}
+
+fn tgz_uncompressed_size(archive: &Path) -> Result<u64> {
+    let tar_gz = match File::open(archive) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open archive {:?} - {:?}", archive, error)
+    };
+    let mut tar_archive = tar::Archive::new(flate2::read::GzDecoder::new(tar_gz));
+    let mut total = 0;
+    for entry in tar_archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            total += entry.header().size()?;
+        }
+    }
+    Ok(total)
+}
+
+/// Both `Read` and `Seek`, for the readers of zip archives.
+trait ReadSeek: Read + Seek {}
+
+impl <T: Read + Seek> ReadSeek for T {}
+
+fn list_zip_entries(archive: &Path) -> Result<Vec<PathBuf>> {
+    let file = match File::open(archive) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open archive {:?} - {:?}", archive, error)
+    };
+    list_zip_reader(BufReader::new(file), archive)
+}
+
+/// `archive` is only used in messages.
+fn list_zip_reader<R: Read + Seek>(reader: R, archive: &Path) -> Result<Vec<PathBuf>> {
+    let mut zip_archive = match zip::ZipArchive::new(reader) {
+        Ok(archive) => archive,
+        Err(error) => bail!("Cold not open zip archive at {:?}\n -> {:?}", archive, error),
+    };
+    let mut entries = vec![];
+    for index in 0..zip_archive.len() {
+        let file = zip_archive.by_index(index)?;
+        match file.enclosed_name() {
+            None => bail!("Invalid file path {:?} in archive {:?}", file.name(), archive),
+            Some(path) => entries.push(path.to_path_buf()),
+        }
+    }
+    Ok(entries)
+}
+
+fn list_tgz_entries(archive: &Path) -> Result<Vec<PathBuf>> {
+    let tar_gz = match File::open(archive) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open archive {:?} - {:?}", archive, error)
+    };
+    list_tgz_reader(tar_gz)
+}
+
+fn list_tgz_reader<R: Read>(reader: R) -> Result<Vec<PathBuf>> {
+    let mut tar_archive = tar::Archive::new(flate2::read::GzDecoder::new(reader));
+    let mut entries = vec![];
+    for entry in tar_archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_pax_global_extensions() {
+            continue;
+        }
+        entries.push(entry.path()?.into_owned());
+    }
+    Ok(entries)
+}
+
 // duplicated from zip-rs source
-fn extract_zip_archive<P: AsRef<Path>>(zip_archive: &mut ZipArchive<BufReader<File>>, directory: P) -> ZipResult<()> {
-    use std::fs;
+const INVALID_PASSWORD: &str = "Invalid password";
+
+/// The layout entries taken as paths (`literal` layout): they are looked up in each directory found
+/// at the stripped depth (whatever its name), under `subdir` if there is one.
+fn literal_items(base: &Path, paths: &GlobDesc, subdir: Option<&str>) -> Result<HashSet<PathBuf>> {
+    let mut roots = vec![base.to_path_buf()];
+    for _ in 0..paths.strip {
+        let mut children = vec![];
+        for root in &roots {
+            let entries = match std::fs::read_dir(root) {
+                Err(error) => bail!("Could not read directory {:?}\n -> {:?}", root, error),
+                Ok(entries) => entries,
+            };
+            children.extend(entries.filter_map(Result::ok).map(|entry| entry.path()).filter(|path| path.is_dir()));
+        }
+        roots = children;
+    }
+    let mut items = HashSet::new();
+    for root in roots {
+        let root = match subdir {
+            None => root,
+            Some(subdir) => root.join(subdir.trim_matches('/')),
+        };
+        for path in &paths.patterns {
+            let item = root.join(path);
+            if item.exists() {
+                items.insert(item);
+            }
+        }
+    }
+    Ok(items)
+}
 
-    for i in 0..zip_archive.len() {
-        let mut file = zip_archive.by_index(i)?;
-        let filepath = file
-            .enclosed_name()
-            .ok_or(ZipError::InvalidArchive("Invalid file path"))?;
+/// Where and how the layout patterns are evaluated, for error messages.
+fn layout_context(module_name: &LwcString, base: &Path, glob_descs: &GlobDesc) -> String {
+    format!("module {}, patterns {:?} in {:?}, strip_leading {} (depth {} to {})",
+            module_name, glob_descs.patterns, base, glob_descs.strip, glob_descs.strip, glob_descs.strip + 1)
+}
+
+/// Depth down to which `no_match_hint` looks for the patterns.
+const HINT_MAX_DEPTH: usize = 5;
 
-        let outpath = directory.as_ref().join(filepath);
+/// When the layout patterns match nothing, tells whether they match at another depth
+/// (with the `strip_leading` value that would work) or lists what the archive contains.
+fn no_match_hint(base: &Path, glob_descs: &GlobDesc, case_sensitive: bool) -> String {
+    let walker = GlobWalkerBuilder::from_patterns(base, &glob_descs.patterns)
+        .case_insensitive(!case_sensitive)
+        .max_depth(HINT_MAX_DEPTH)
+        .build();
+    let depth = walker.ok()
+        .and_then(|walker| walker.into_iter().filter_map(Result::ok).map(|entry| entry.depth()).min());
+    match depth {
+        Some(depth) if depth > 0 => format!("the patterns match at another level, try `strip_leading: {}`", depth - 1),
+        _ => {
+            let mut top_level = std::fs::read_dir(base).into_iter().flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+            top_level.sort();
+            format!("the patterns match nothing in the archive, its top level contains {:?}", top_level)
+        }
+    }
+}
+
+/// With `auto_strip_single_root`, the only directory at the top of the extracted content (if it's alone),
+/// else `temp_dir`.
+fn layout_root(temp_dir: &Path, location: &ConcreteLocation) -> Result<PathBuf> {
+    if !location.layout().auto_strip_single_root.unwrap_or(false) {
+        return Ok(temp_dir.to_path_buf());
+    }
+    let entries = match std::fs::read_dir(temp_dir) {
+        Err(error) => bail!("Could not read extracted content {:?}\n -> {:?}", temp_dir, error),
+        Ok(entries) => entries.collect::<std::io::Result<Vec<_>>>()?,
+    };
+    match entries.as_slice() {
+        [single] if single.path().is_dir() => {
+            debug!("single root directory {:?} is stripped", single.file_name());
+            Ok(single.path())
+        }
+        _ => Ok(temp_dir.to_path_buf()),
+    }
+}
 
-        if file.name().ends_with('/') {
-            fs::create_dir_all(&outpath)?;
+#[cfg(feature = "rar")]
+fn extract_rar_archive(archive: &Path, directory: &Path, password: Option<&str>) -> Result<()> {
+    let rar = match password {
+        None => unrar::Archive::new(archive),
+        Some(password) => unrar::Archive::with_password(archive, password),
+    };
+    let mut rar = rar.open_for_processing()?;
+    while let Some(header) = rar.read_header()? {
+        debug!("rar entry {:?}", header.entry().filename);
+        rar = if header.entry().is_file() {
+            header.extract_with_base(directory)?
         } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p)?;
-                }
+            header.skip()?
+        };
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "rar"))]
+fn extract_rar_archive(archive: &Path, _directory: &Path, _password: Option<&str>) -> Result<()> {
+    bail!("Can't extract {:?}, modda was built without the `rar` feature", archive)
+}
+
+/// Reads the entries from their local headers (the central directory at the end of the archive is not used).<br>
+/// The zip crate refuses encrypted entries and entries with a data descriptor.
+fn extract_zip_stream(reader: &mut dyn Read, directory: &Path, name_encoding: ArchiveNameEncoding) -> ZipResult<()> {
+    let mut reader = reader;
+    while let Some(mut file) = zip::read::read_zipfile_from_stream(&mut reader)? {
+        write_zip_entry(&mut file, directory, name_encoding)?;
+    }
+    Ok(())
+}
+
+/// Extracts the entries at `indexes`.<br>
+/// `password` is only used for encrypted entries, `name_encoding` for entry names that are not valid UTF-8.
+fn extract_zip_entries<R: Read + Seek>(zip_archive: &mut ZipArchive<R>, indexes: impl IntoIterator<Item = usize>,
+                                        directory: &Path, password: Option<&[u8]>, name_encoding: ArchiveNameEncoding) -> ZipResult<()> {
+    for i in indexes {
+        let mut file = match password {
+            None => zip_archive.by_index(i)?,
+            Some(password) => match zip_archive.by_index_decrypt(i, password)? {
+                Ok(file) => file,
+                Err(InvalidPassword) => return Err(ZipError::InvalidArchive(INVALID_PASSWORD)),
+            },
+        };
+        write_zip_entry(&mut file, directory, name_encoding)?;
+    }
+    Ok(())
+}
+
+fn zip_extraction_error(error: ZipError, archive: &Path, module_name: &LwcString) -> anyhow::Error {
+    match error {
+        ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) =>
+            anyhow!("Zip archive {:?} for mod {} is password protected\n -> set the `password` property of the location", archive, module_name),
+        ZipError::InvalidArchive(INVALID_PASSWORD) =>
+            anyhow!("Wrong password for zip archive {:?} (mod {})", archive, module_name),
+        error => anyhow!("Zip extraction failed for {:?}\n-> {:?}", archive, error),
+    }
+}
+
+fn write_zip_entry(file: &mut zip::read::ZipFile, directory: &Path, name_encoding: ArchiveNameEncoding) -> ZipResult<()> {
+    use std::fs;
+
+    // the zip crate reads names without the UTF-8 flag as CP437, which mangles UTF-8 names
+    let name = name_encoding.decode(file.name_raw());
+    let filepath = entry_relative_path(&name)
+        .ok_or(ZipError::InvalidArchive("Invalid file path"))?;
+
+    let outpath = directory.join(filepath);
+
+    if name.ends_with('/') {
+        fs::create_dir_all(&outpath)?;
+    } else {
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                fs::create_dir_all(p)?;
             }
-            let mut outfile = fs::File::create(&outpath)?;
-            io::copy(&mut file, &mut outfile)?;
+        }
+        let mut outfile = fs::File::create(&outpath)?;
+        io::copy(file, &mut outfile)?;
+    }
+    Ok(())
+}
+
+/// Moves `items` into `target` file by file, so that the directories that already exist there are merged;
+/// `policy` decides what happens to the files that already exist.
+fn merge_items(items: &HashSet<PathBuf>, target: &Path, module_name: &LwcString, policy: MergeExisting) -> Result<()> {
+    let mut moves = vec![];
+    for item in items {
+        match item.file_name() {
+            None => bail!("Could not determine file or directory name for {:?}", item),
+            Some(name) => list_moves(item, &target.join(name), &mut moves)?,
+        }
+    }
+    let existing = moves.iter()
+        .filter(|(from, to)| !from.is_dir() && to.exists())
+        .map(|(_, to)| to)
+        .collect::<Vec<_>>();
+    if policy == MergeExisting::Fail && !existing.is_empty() {
+        bail!("Mod {} would replace existing files (set `merge` to `overwrite` or `keep_existing` if this is expected)\n -> {:?}",
+                module_name, existing);
+    }
+    let options = fs_extra::file::CopyOptions { overwrite: true, ..Default::default() };
+    for (from, to) in &moves {
+        if from.is_dir() {
+            std::fs::create_dir_all(to)?;
+        } else if policy == MergeExisting::KeepExisting && to.exists() {
+            debug!("keep existing {:?}", to);
+        } else if let Err(error) = fs_extra::file::move_file(from, to, &options) {
+            return Err(move_error(error, items, target));
+        }
+    }
+    Ok(())
+}
+
+/// The (source, destination) pairs of the directories and files under `from`, parents first.
+fn list_moves(from: &Path, to: &Path, moves: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    moves.push((from.to_path_buf(), to.to_path_buf()));
+    if from.is_dir() {
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            list_moves(&entry.path(), &to.join(entry.file_name()), moves)?;
         }
     }
     Ok(())
 }
 
+/// A full disk gets an actionable message: where, and how much the items that were not moved yet need
+/// (the items moved before the error stay in `target`).
+fn move_error(error: fs_extra::error::Error, items: &HashSet<PathBuf>, target: &Path) -> anyhow::Error {
+    let disk_full = matches!(&error.kind, fs_extra::error::ErrorKind::Io(io_error) if io_error.kind() == io::ErrorKind::StorageFull);
+    if !disk_full {
+        return error.into();
+    }
+    let remaining = items.iter().filter(|item| item.exists()).collect::<Vec<_>>();
+    let needed = remaining.iter()
+        .map(|item| if item.is_dir() {
+            content_size(item.to_path_buf()).map(|(_, bytes)| bytes).unwrap_or(0)
+        } else {
+            std::fs::metadata(item).map(|metadata| metadata.len()).unwrap_or(0)
+        })
+        .sum::<u64>();
+    anyhow!("Disk full while moving mod files to {:?}\n -> {} of {} items were not moved, they need {} more\n \
+            -> the moved ones are in the game directory: free some space and install the mod again",
+            target, remaining.len(), items.len(), indicatif::HumanBytes(needed))
+}
+
+/// Counts files (and their total size) under `path`, recursively.
+fn content_size(path: PathBuf) -> Result<(usize, u64)> {
+    let mut entries = 0;
+    let mut bytes = 0;
+    let mut pending = vec![path];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                entries += 1;
+                bytes += metadata.len();
+            }
+        }
+    }
+    Ok((entries, bytes))
+}
+
+/// Directory `<PROGNAME>-extract-<module>` in `base`, emptied if it exists and not removed when dropped.
+fn create_debug_dir(base: &Path, module_name: &LwcString) -> std::io::Result<TempDir> {
+    let name = format!("{}-extract-{}", PROGNAME, module_name.as_ref().replace(['/', '\\'], "_"));
+    let dir = base.join(&name);
+    if dir.exists() {
+        debug!("removing extraction directory {:?} left by a previous run", dir);
+        std::fs::remove_dir_all(&dir)?;
+    }
+    info!("extracting {} in {:?} (debug_extract_dirs, kept afterwards)", module_name, dir);
+    tempfile::Builder::new().prefix(&name).rand_bytes(0).disable_cleanup(true).tempdir_in(base)
+}
+
 enum ExtractLocation {
     Temp(TempDir),
     Regular(PathBuf),
@@ -377,3 +1158,652 @@ impl ExtractLocation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use itertools::Itertools;
+
+    use crate::archive_layout::Layout;
+    use crate::archive_name_encoding::ArchiveNameEncoding;
+    use crate::canon_path::CanonPath;
+    use crate::case_collisions::CaseCollisions;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::module::location::location::{ConcreteLocation, MergeExisting};
+    use crate::run_warnings::WarningCollector;
+    use crate::test_utils::extractor::{extractor_fixture, ExtractorFixture};
+    use crate::test_utils::test_dir;
+
+    use super::{move_error, ExtractionMethod, ExtractionStats, Extractor, StreamFormat};
+
+    fn archive_fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/archives").join(name)
+    }
+
+    fn expected_entries() -> Vec<PathBuf> {
+        vec![
+            PathBuf::from("my_mod-1.0/my_mod/my_mod.tp2"),
+            PathBuf::from("my_mod-1.0/my_mod/tra/english.tra"),
+            PathBuf::from("my_mod-1.0/readme.txt"),
+        ]
+    }
+
+    #[test]
+    fn disk_full_when_moving_files() {
+        let temp = test_dir();
+        let moved = temp.path().join("moved.tp2");
+        let remaining = temp.path().join("my_mod");
+        std::fs::create_dir_all(&remaining).unwrap();
+        std::fs::write(remaining.join("my_mod.tp2"), vec![0u8; 2048]).unwrap();
+        std::fs::write(remaining.join("readme.txt"), vec![0u8; 1024]).unwrap();
+        let items = HashSet::from([moved, remaining]);
+        let disk_full = io::Error::from(io::ErrorKind::StorageFull);
+
+        let error = move_error(fs_extra::error::Error::from(disk_full), &items, Path::new("/game")).to_string();
+
+        assert!(error.contains(r#"Disk full while moving mod files to "/game""#), "{}", error);
+        assert!(error.contains("1 of 2 items were not moved, they need 3.00 KiB more"), "{}", error);
+
+        let other = io::Error::from(io::ErrorKind::PermissionDenied);
+        let error = move_error(fs_extra::error::Error::from(other), &items, Path::new("/game")).to_string();
+        assert!(!error.contains("Disk full"), "{}", error);
+    }
+
+    #[test]
+    fn list_zip_entries() {
+        let config = Config::default();
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+
+        let mut entries = extractor.list_entries(&archive_fixture("my_mod.zip")).unwrap();
+        entries.sort();
+
+        assert_eq!(entries, expected_entries());
+    }
+
+    #[test]
+    fn list_split_zip_entries() {
+        let config = Config::default();
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+
+        let mut entries = extractor.list_entries(&archive_fixture("my_mod_split.zip.001")).unwrap();
+        entries.sort();
+
+        assert_eq!(entries, expected_entries());
+    }
+
+    #[test]
+    fn list_tgz_entries() {
+        let config = Config::default();
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+
+        let mut entries = extractor.list_entries(&archive_fixture("my_mod.tgz")).unwrap();
+        entries.sort();
+
+        assert_eq!(entries, expected_entries());
+    }
+
+    #[test]
+    fn list_unconfigured_external_fails() {
+        let config = Config::default();
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+
+        extractor.list_entries(&archive_fixture("my_mod.rar")).unwrap_err();
+    }
+
+    #[test]
+    fn create_temp_dir_uses_module_extract_location() {
+        let temp = test_dir();
+        let global_location = temp.path().join("global");
+        let module_location = temp.path().join("module");
+        let config = Config {
+            extract_location: Some(global_location.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+        let location = ConcreteLocation {
+            extract_location: Some(module_location.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let temp_dir = extractor.create_temp_dir(&location, &lwc!("my_mod")).unwrap();
+
+        assert_eq!(temp_dir.path().parent().unwrap(), module_location);
+    }
+
+    #[test]
+    fn create_temp_dir_falls_back_to_config_extract_location() {
+        let temp = test_dir();
+        let global_location = temp.path().join("global");
+        let config = Config {
+            extract_location: Some(global_location.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+
+        let temp_dir = extractor.create_temp_dir(&ConcreteLocation::default(), &lwc!("my_mod")).unwrap();
+
+        assert_eq!(temp_dir.path().parent().unwrap(), global_location);
+    }
+
+    #[test]
+    fn create_temp_dir_expands_module_extract_location() {
+        let temp = test_dir();
+        let config = Config::default();
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+        let location = ConcreteLocation {
+            extract_location: Some("${MODDA_TEST_EXTRACT_BASE}/module".to_string()),
+            ..Default::default()
+        };
+
+        let temp_dir = temp_env::with_var("MODDA_TEST_EXTRACT_BASE", Some(temp.path()), || {
+            extractor.create_temp_dir(&location, &lwc!("my_mod")).unwrap()
+        });
+
+        assert_eq!(temp_dir.path().parent().unwrap(), temp.path().join("module"));
+    }
+
+    #[test]
+    fn create_temp_dir_with_stable_name_for_debugging() {
+        let temp = test_dir();
+        let config = Config {
+            extract_location: Some(temp.path().to_string_lossy().to_string()),
+            debug_extract_dirs: Some(true),
+            ..Default::default()
+        };
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+        let expected = temp.path().join("modda-extract-my_mod");
+
+        let temp_dir = extractor.create_temp_dir(&ConcreteLocation::default(), &lwc!("my_mod")).unwrap();
+        assert_eq!(temp_dir.path(), expected);
+        std::fs::write(temp_dir.path().join("left_over.txt"), "").unwrap();
+        drop(temp_dir);
+
+        // kept after use, emptied when the mod is extracted again
+        assert!(expected.join("left_over.txt").is_file());
+        let temp_dir = extractor.create_temp_dir(&ConcreteLocation::default(), &lwc!("my_mod")).unwrap();
+        assert_eq!(temp_dir.path(), expected);
+        assert!(!expected.join("left_over.txt").exists());
+    }
+
+    #[test]
+    fn create_temp_dir_with_random_name_by_default() {
+        let temp = test_dir();
+        let config = Config { extract_location: Some(temp.path().to_string_lossy().to_string()), ..Default::default() };
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, &config);
+
+        let first = extractor.create_temp_dir(&ConcreteLocation::default(), &lwc!("my_mod")).unwrap();
+        let second = extractor.create_temp_dir(&ConcreteLocation::default(), &lwc!("my_mod")).unwrap();
+
+        assert_ne!(first.path(), second.path());
+        assert!(!first.path().ends_with("modda-extract-my_mod"));
+    }
+
+    fn extract_fixture(archive: &Path) -> ExtractionStats {
+        let fixture = extractor_fixture(Config::default());
+        let location = fixture.location(Layout::with_tp2_default_and_strip(1));
+
+        let stats = fixture.extractor().extract_files(archive, &lwc!("my_mod"), &location).unwrap();
+
+        assert!(fixture.game_dir.join_path("my_mod/my_mod.tp2").exists());
+        stats
+    }
+
+    #[cfg(feature = "rar")]
+    #[test]
+    fn extract_rar_stats() {
+        let stats = extract_fixture(&archive_fixture("my_mod.rar"));
+        assert_eq!(stats.method, ExtractionMethod::Rar);
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.bytes, 97);
+    }
+
+    #[cfg(not(feature = "rar"))]
+    #[test]
+    fn rar_without_feature_needs_extractor() {
+        let fixture = extractor_fixture(Config::default());
+        let location = fixture.location(Layout::default());
+
+        let error = fixture.extractor().extract_files(&archive_fixture("my_mod.rar"), &lwc!("my_mod"), &location).unwrap_err();
+
+        assert!(format!("{:?}", error).contains("`rar` feature"), "{:?}", error);
+    }
+
+    #[test]
+    fn extract_iemod_with_metadata() {
+        let iemod = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/iemod/my_mod.iemod");
+        let stats = extract_fixture(&iemod);
+        assert_eq!(stats.method, ExtractionMethod::Zip);
+        assert_eq!(stats.iemod.and_then(|metadata| metadata.module_description()).as_deref(), Some("Does things"));
+
+        assert_eq!(extract_fixture(&archive_fixture("my_mod.zip")).iemod, None);
+    }
+
+    #[test]
+    fn extract_zip_stats() {
+        let stats = extract_fixture(&archive_fixture("my_mod.zip"));
+        assert_eq!(stats.method, ExtractionMethod::Zip);
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.bytes, 97);
+    }
+
+    #[test]
+    fn extract_split_zip_stats() {
+        let stats = extract_fixture(&archive_fixture("my_mod_split.zip.001"));
+        assert_eq!(stats.method, ExtractionMethod::Zip);
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.bytes, 97);
+    }
+
+    #[test]
+    fn extract_split_zip_with_missing_volume() {
+        let fixture = extractor_fixture(Config::default());
+        let archive = fixture.temp.path().join("my_mod_split.zip.001");
+        std::fs::copy(archive_fixture("my_mod_split.zip.001"), &archive).unwrap();
+        std::fs::copy(archive_fixture("my_mod_split.zip.002"), fixture.temp.path().join("my_mod_split.zip.003")).unwrap();
+
+        let error = fixture.extractor().extract_files(&archive, &lwc!("my_mod"), &ConcreteLocation::default()).unwrap_err();
+
+        assert!(error.to_string().contains("my_mod_split.zip.002"), "{:?}", error);
+    }
+
+    fn extract_encrypted_fixture(password: Option<&str>) -> (anyhow::Result<ExtractionStats>, ExtractorFixture) {
+        let fixture = extractor_fixture(Config::default());
+        let location = ConcreteLocation {
+            password: password.map(|password| password.to_string()),
+            ..fixture.location(Layout::with_tp2_default_and_strip(1))
+        };
+        let result = fixture.extractor().extract_files(&archive_fixture("my_mod_encrypted.zip"), &lwc!("my_mod"), &location);
+        (result, fixture)
+    }
+
+    #[test]
+    fn extract_encrypted_zip_with_password() {
+        let (result, fixture) = temp_env::with_var("MODDA_TEST_ZIP_PASSWORD", Some("secret"), || {
+            extract_encrypted_fixture(Some("${MODDA_TEST_ZIP_PASSWORD}"))
+        });
+
+        let stats = result.unwrap();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/tra/english.tra")).unwrap(), "@1 = ~Hello~\n");
+    }
+
+    #[test]
+    fn extract_encrypted_zip_without_password() {
+        let (result, fixture) = extract_encrypted_fixture(None);
+
+        let error = result.unwrap_err();
+        assert!(format!("{:?}", error).contains("password protected"), "unexpected error {:?}", error);
+        assert!(!fixture.game_dir.join_path("my_mod").exists());
+    }
+
+    fn extract_cp437_fixture(config: Config, encoding: Option<ArchiveNameEncoding>) -> Vec<String> {
+        let fixture = extractor_fixture(config);
+        let location = ConcreteLocation {
+            archive_name_encoding: encoding,
+            ..fixture.location(Layout::with_tp2_default_and_strip(0))
+        };
+        fixture.extractor().extract_files(&archive_fixture("old_mod_cp437.zip"), &lwc!("old_mod"), &location).unwrap();
+        std::fs::read_dir(fixture.game_dir.join_path("old_mod/tra")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn extract_zip_with_cp437_names() {
+        let config = Config { archive_name_encoding: Some(ArchiveNameEncoding::CP437), ..Default::default() };
+        let names = extract_cp437_fixture(config.clone(), None);
+        assert_eq!(names, vec!["français.tra".to_string()]);
+
+        // the location setting wins
+        let names = extract_cp437_fixture(config, Some(ArchiveNameEncoding::UTF8));
+        assert_eq!(names, vec!["fran\u{FFFD}ais.tra".to_string()]);
+    }
+
+    /// `case_collision.zip` has `case_mod/file.2da` ("first") then `case_mod/File.2da` ("second").
+    fn extract_case_collision(resolution: Option<CaseCollisions>, warnings: &WarningCollector)
+            -> (anyhow::Result<Vec<(String, String)>>, ExtractorFixture) {
+        let fixture = extractor_fixture(Config { case_collisions: resolution, ..Default::default() });
+        let extractor = fixture.extractor().with_warnings(warnings);
+        let location = fixture.location(Layout::with_tp2_default_and_strip(0));
+        let result = extractor.extract_files(&archive_fixture("case_collision.zip"), &lwc!("case_mod"), &location)
+            .map(|_| std::fs::read_dir(fixture.game_dir.join_path("case_mod")).unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().unwrap() == "2da")
+                .map(|path| (path.file_name().unwrap().to_string_lossy().to_string(), std::fs::read_to_string(&path).unwrap()))
+                .sorted()
+                .collect());
+        (result, fixture)
+    }
+
+    #[test]
+    fn case_collisions_are_kept_with_a_warning() {
+        let warnings = WarningCollector::new();
+        let (files, _fixture) = extract_case_collision(None, &warnings);
+
+        assert_eq!(files.unwrap().len(), 2);
+        let collected = warnings.warnings();
+        assert_eq!(collected.len(), 1);
+        assert!(collected[0].message.contains("only differ by case"), "{}", collected[0].message);
+        assert!(collected[0].message.contains("File.2da"), "{}", collected[0].message);
+    }
+
+    #[test]
+    fn case_collisions_keep_first_or_last() {
+        let warnings = WarningCollector::new();
+
+        let (files, _fixture) = extract_case_collision(Some(CaseCollisions::KeepFirst), &warnings);
+        assert_eq!(files.unwrap(), vec![("file.2da".to_string(), "first".to_string())]);
+
+        let (files, _fixture) = extract_case_collision(Some(CaseCollisions::KeepLast), &warnings);
+        assert_eq!(files.unwrap(), vec![("File.2da".to_string(), "second".to_string())]);
+
+        assert!(warnings.warnings().is_empty());
+    }
+
+    #[test]
+    fn case_collisions_fail_extraction() {
+        let warnings = WarningCollector::new();
+        let (files, fixture) = extract_case_collision(Some(CaseCollisions::Error), &warnings);
+
+        let error = files.unwrap_err().to_string();
+        assert!(error.contains("only differ by case"), "{}", error);
+        assert!(!fixture.game_dir.join_path("case_mod").exists());
+    }
+
+    fn no_match_warning(module_name: &str) -> (String, PathBuf) {
+        let fixture = extractor_fixture(Config::default());
+        let warnings = WarningCollector::new();
+        let extractor = fixture.extractor().with_warnings(&warnings);
+        let location = fixture.location(Layout::with_tp2_default_and_strip(0));
+        extractor.extract_files(&archive_fixture("my_mod.zip"), &lwc!(module_name), &location).unwrap();
+        let collected = warnings.warnings();
+        assert_eq!(collected.len(), 1);
+        (collected[0].message.clone(), fixture.extract_location())
+    }
+
+    #[test]
+    fn no_match_warning_suggests_strip_leading() {
+        let (message, extract_location) = no_match_warning("my_mod");
+
+        assert!(message.contains("matched no file"), "{}", message);
+        assert!(message.contains("module my_mod"), "{}", message);
+        assert!(message.contains(r#"patterns ["my_mod", "setup-my_mod.tp2"]"#), "{}", message);
+        assert!(message.contains(&format!("{:?}", extract_location).trim_end_matches('"')), "{}", message);
+        assert!(message.contains("strip_leading 0 (depth 0 to 1)"), "{}", message);
+        assert!(message.contains("try `strip_leading: 1`"), "{}", message);
+    }
+
+    #[test]
+    fn no_match_warning_lists_archive_content() {
+        let (message, _) = no_match_warning("other_mod");
+
+        assert!(message.contains("module other_mod"), "{}", message);
+        assert!(message.contains(r#"top level contains ["my_mod-1.0"]"#), "{}", message);
+    }
+
+    #[test]
+    fn extract_tgz_stats() {
+        let stats = extract_fixture(&archive_fixture("my_mod.tgz"));
+        assert_eq!(stats.method, ExtractionMethod::Tgz);
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.bytes, 97);
+    }
+
+    fn extract_relocated(relocate: &[(&str, &str)]) -> (anyhow::Result<ExtractionStats>, ExtractorFixture) {
+        let fixture = extractor_fixture(Config::default());
+        let location = ConcreteLocation {
+            relocate: Some(relocate.iter().map(|(glob, dest)| (glob.to_string(), dest.to_string())).collect()),
+            ..fixture.location(Layout::with_tp2_default_and_strip(1))
+        };
+        let result = fixture.extractor().extract_files(&archive_fixture("my_mod.zip"), &lwc!("my_mod"), &location);
+        (result, fixture)
+    }
+
+    #[test]
+    fn relocate_files_to_mapped_destinations() {
+        let (result, fixture) = extract_relocated(&[
+            ("my_mod-1.0/README.txt", "docs/readme.txt"),
+            ("**/*.tra", "lang/english/"),
+        ]);
+
+        result.unwrap();
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/lang/english/english.tra")).unwrap(), "@1 = ~Hello~\n");
+        assert!(fixture.game_dir.join_path("my_mod/docs/readme.txt").is_file());
+        assert!(fixture.game_dir.join_path("my_mod/my_mod.tp2").is_file());
+        assert!(!fixture.game_dir.join_path("my_mod/tra/english.tra").exists());
+    }
+
+    #[test]
+    fn relocate_conflict() {
+        let (result, fixture) = extract_relocated(&[
+            ("**/*.tra", "docs/info.txt"),
+            ("**/readme.txt", "docs/info.txt"),
+        ]);
+
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(error.contains("relocated to"), "{}", error);
+        assert!(error.contains("english.tra") && error.contains("readme.txt"), "{}", error);
+        assert!(!fixture.game_dir.join_path("my_mod").exists());
+    }
+
+    #[test]
+    fn relocate_outside_of_mod_dir() {
+        let (result, _fixture) = extract_relocated(&[("**/readme.txt", "../readme.txt")]);
+
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(error.contains("must be relative to the mod directory"), "{}", error);
+    }
+
+    /// Extracts my_mod.zip over a `my_mod` directory that already has the files of a base mod.
+    fn extract_over_base_mod(merge: MergeExisting) -> (anyhow::Result<ExtractionStats>, ExtractorFixture) {
+        let fixture = extractor_fixture(Config::default());
+        let game_dir = &fixture.game_dir;
+        std::fs::create_dir_all(game_dir.join_path("my_mod/tra")).unwrap();
+        std::fs::write(game_dir.join_path("my_mod/my_mod.tp2"), "BASE\n").unwrap();
+        std::fs::write(game_dir.join_path("my_mod/tra/base.tra"), "@1 = ~Base~\n").unwrap();
+        let location = ConcreteLocation {
+            merge: Some(merge),
+            ..fixture.location(Layout::with_tp2_default_and_strip(1))
+        };
+        let result = fixture.extractor().extract_files(&archive_fixture("my_mod.zip"), &lwc!("my_mod"), &location);
+        (result, fixture)
+    }
+
+    #[test]
+    fn merge_and_overwrite_existing_files() {
+        let (result, fixture) = extract_over_base_mod(MergeExisting::Overwrite);
+
+        result.unwrap();
+        assert_ne!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/my_mod.tp2")).unwrap(), "BASE\n");
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/tra/english.tra")).unwrap(), "@1 = ~Hello~\n");
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/tra/base.tra")).unwrap(), "@1 = ~Base~\n");
+    }
+
+    #[test]
+    fn merge_and_keep_existing_files() {
+        let (result, fixture) = extract_over_base_mod(MergeExisting::KeepExisting);
+
+        result.unwrap();
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/my_mod.tp2")).unwrap(), "BASE\n");
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/tra/english.tra")).unwrap(), "@1 = ~Hello~\n");
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/tra/base.tra")).unwrap(), "@1 = ~Base~\n");
+    }
+
+    #[test]
+    fn merge_fails_before_moving_anything() {
+        let (result, fixture) = extract_over_base_mod(MergeExisting::Fail);
+
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(error.contains("would replace existing files") && error.contains("my_mod.tp2"), "{}", error);
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/my_mod.tp2")).unwrap(), "BASE\n");
+        assert!(!fixture.game_dir.join_path("my_mod/tra/english.tra").exists());
+    }
+
+    fn extract_auto_strip(archive: &Path) -> (anyhow::Result<ExtractionStats>, ExtractorFixture) {
+        let fixture = extractor_fixture(Config::default());
+        let location = fixture.location(Layout { auto_strip_single_root: Some(true), ..Layout::with_tp2_default() });
+        let result = fixture.extractor().extract_files(archive, &lwc!("my_mod"), &location);
+        (result, fixture)
+    }
+
+    #[test]
+    fn auto_strip_single_root_dir() {
+        // the content is in my_mod-1.0/
+        let (result, fixture) = extract_auto_strip(&archive_fixture("my_mod.zip"));
+
+        result.unwrap();
+        assert!(fixture.game_dir.join_path("my_mod/my_mod.tp2").is_file());
+        assert!(fixture.game_dir.join_path("my_mod/tra/english.tra").is_file());
+    }
+
+    #[test]
+    fn auto_strip_multiple_roots() {
+        let source = test_dir();
+        for file in ["my_mod/my_mod.tp2", "setup-my_mod.tp2", "readme.txt"] {
+            let path = source.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let (result, fixture) = extract_auto_strip(source.path());
+
+        result.unwrap();
+        assert!(fixture.game_dir.join_path("my_mod/my_mod.tp2").is_file());
+        assert!(fixture.game_dir.join_path("setup-my_mod.tp2").is_file());
+        assert!(!fixture.game_dir.join_path("readme.txt").exists());
+    }
+
+    fn extract_from_stream(content: Vec<u8>, format: StreamFormat) -> (anyhow::Result<ExtractionStats>, ExtractorFixture) {
+        let fixture = extractor_fixture(Config::default());
+        let location = fixture.location(Layout::with_tp2_default_and_strip(1));
+        let result = fixture.extractor().extract_stream(&mut std::io::Cursor::new(content), format, &lwc!("my_mod"), &location);
+        (result, fixture)
+    }
+
+    #[test]
+    fn extract_zip_from_stream() {
+        let content = std::fs::read(archive_fixture("my_mod.zip")).unwrap();
+
+        let (result, fixture) = extract_from_stream(content, StreamFormat::Zip);
+
+        let stats = result.unwrap();
+        assert_eq!(stats.method, ExtractionMethod::Zip);
+        assert_eq!(stats.entries, 3);
+        assert_eq!(std::fs::read_to_string(fixture.game_dir.join_path("my_mod/tra/english.tra")).unwrap(), "@1 = ~Hello~\n");
+        assert!(fixture.game_dir.join_path("my_mod/my_mod.tp2").is_file());
+    }
+
+    #[test]
+    fn extract_tgz_from_stream() {
+        let content = std::fs::read(archive_fixture("my_mod.tgz")).unwrap();
+
+        let (result, fixture) = extract_from_stream(content, StreamFormat::Tgz);
+
+        assert_eq!(result.unwrap().method, ExtractionMethod::Tgz);
+        assert!(fixture.game_dir.join_path("my_mod/my_mod.tp2").is_file());
+    }
+
+    #[test]
+    fn truncated_stream_is_not_installed() {
+        let mut content = std::fs::read(archive_fixture("my_mod.tgz")).unwrap();
+        content.truncate(content.len() / 2);
+
+        let (result, fixture) = extract_from_stream(content, StreamFormat::Tgz);
+
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(error.contains("Streamed Tgz extraction failed"), "{}", error);
+        assert!(!fixture.game_dir.join_path("my_mod").exists());
+    }
+
+    #[test]
+    fn stream_format_from_archive_name() {
+        assert_eq!(StreamFormat::from_name(Path::new("cache/my_mod-1.0.ZIP")), Some(StreamFormat::Zip));
+        assert_eq!(StreamFormat::from_name(Path::new("my_mod.iemod")), Some(StreamFormat::Zip));
+        assert_eq!(StreamFormat::from_name(Path::new("my_mod.tar.gz")), Some(StreamFormat::Tgz));
+        assert_eq!(StreamFormat::from_name(Path::new("my_mod.tgz")), Some(StreamFormat::Tgz));
+        assert_eq!(StreamFormat::from_name(Path::new("my_mod.7z")), None);
+        assert_eq!(StreamFormat::from_name(Path::new("my_mod.rar")), None);
+    }
+
+    fn files_to_move_case_variants(config: &Config, layout_case_sensitive: Option<bool>) -> Vec<PathBuf> {
+        let base = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/glob_case/archive_content");
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let extractor = Extractor::new(&game_dir, config);
+        let location = ConcreteLocation {
+            layout: Some(Layout { case_sensitive: layout_case_sensitive, ..Layout::single_dir(0) }),
+            ..Default::default()
+        };
+
+        let items = extractor.files_to_move(&base, &lwc!("my_mod"), &location).unwrap();
+
+        items.into_iter().map(|item| item.strip_prefix(&base).unwrap().to_path_buf()).collect()
+    }
+
+    #[test]
+    fn files_to_move_only_from_subdir() {
+        let base = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/compilation/archive_content");
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&game_dir, &config);
+        let location = ConcreteLocation {
+            layout: Some(Layout { subdir: Some("ModA".to_string()), ..Layout::with_tp2_default_and_strip(1) }),
+            ..Default::default()
+        };
+
+        let items = extractor.files_to_move(&base, &lwc!("moda"), &location).unwrap();
+
+        let items = items.into_iter().map(|item| item.strip_prefix(&base).unwrap().to_path_buf()).sorted().collect::<Vec<_>>();
+        assert_eq!(items, vec![PathBuf::from("Pack/ModA/moda"), PathBuf::from("Pack/ModA/setup-moda.tp2")]);
+    }
+
+    fn files_to_move_bracket_names(literal: Option<bool>) -> Vec<PathBuf> {
+        let temp = test_dir();
+        let base = temp.path();
+        for dir in ["my_mod-1.0/mod[1]", "my_mod-1.0/mod1"] {
+            std::fs::create_dir_all(base.join(dir)).unwrap();
+            std::fs::write(base.join(dir).join("setup.tp2"), "").unwrap();
+        }
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&game_dir, &config);
+        let layout = Layout { literal, ..Layout::multi_dir_and_strip(vec!["mod[1]".to_string()], 1) };
+        let location = ConcreteLocation { layout: Some(layout), ..Default::default() };
+
+        let items = extractor.files_to_move(base, &lwc!("my_mod"), &location).unwrap();
+
+        items.into_iter().map(|item| item.strip_prefix(base).unwrap().to_path_buf()).collect()
+    }
+
+    #[test]
+    fn files_to_move_literal_layout() {
+        assert_eq!(files_to_move_bracket_names(None), vec![PathBuf::from("my_mod-1.0/mod1")]);
+        assert_eq!(files_to_move_bracket_names(Some(true)), vec![PathBuf::from("my_mod-1.0/mod[1]")]);
+    }
+
+    #[test]
+    fn files_to_move_case_insensitive_by_default() {
+        assert_eq!(files_to_move_case_variants(&Config::default(), None), vec![PathBuf::from("MY_MOD")]);
+    }
+
+    #[test]
+    fn files_to_move_case_sensitive() {
+        let config = Config { glob_case_sensitive: Some(true), ..Default::default() };
+        assert_eq!(files_to_move_case_variants(&config, None), Vec::<PathBuf>::new());
+        assert_eq!(files_to_move_case_variants(&Config::default(), Some(true)), Vec::<PathBuf>::new());
+        // the layout value wins over the config
+        assert_eq!(files_to_move_case_variants(&config, Some(false)), vec![PathBuf::from("MY_MOD")]);
+    }
+}
@@ -10,6 +10,17 @@ use crate::module::location::source::Source;
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
 pub struct Layout {
     pub strip_leading: Option<usize>,
+    /// When the archive content is a single directory (whatever its name), the layout is applied inside it;
+    /// `strip_leading` then defaults to 0 (and counts the levels below this directory).
+    pub auto_strip_single_root: Option<bool>,
+    /// Whether the layout patterns are matched case-sensitively against the archive content.<br>
+    /// Overrides the `glob_case_sensitive` config value (default: case-insensitive).
+    pub case_sensitive: Option<bool>,
+    /// For archives that bundle several mods: the directory (after `strip_leading`) that contains the mod,
+    /// the layout is applied inside it.
+    pub subdir: Option<String>,
+    /// The layout entries (`dirs`, `tp2`) are exact paths, `[`, `?` or `*` are not special.
+    pub literal: Option<bool>,
     #[serde(default, flatten)]
     pub layout: LayoutContent,
 }
@@ -59,6 +70,15 @@ impl GlobDesc {
 
 impl Layout {
     pub fn to_glob(&self, module_name: &LwcString, location_source: &Source) -> GlobDesc {
+        let glob_desc = self.content_paths(module_name, location_source);
+        match &self.subdir {
+            None => glob_desc,
+            Some(subdir) => in_subdir(glob_desc, subdir),
+        }
+    }
+
+    /// The entries of the layout (relative to the stripped levels and `subdir`).
+    pub fn content_paths(&self, module_name: &LwcString, location_source: &Source) -> GlobDesc {
         use LayoutContent::*;
 
         let strip_level = self.strip_level(location_source);
@@ -77,12 +97,24 @@ impl Layout {
 
     fn strip_level(&self, source: &Source) -> usize {
         match self {
+            Layout { strip_leading: None, auto_strip_single_root: Some(true), .. } => 0,
             Layout { strip_leading: None, .. } => source.default_strip_leading(),
             Layout { strip_leading: Some(v), .. } => *v,
         }
     }
 }
 
+/// Anchors the patterns under `subdir` (after the stripped levels, whatever their names)
+/// and strips `subdir` too.
+fn in_subdir(glob_desc: GlobDesc, subdir: &str) -> GlobDesc {
+    let subdir = subdir.trim_matches('/');
+    let prefix = format!("{}{}/", "*/".repeat(glob_desc.strip), subdir);
+    GlobDesc {
+        patterns: glob_desc.patterns.iter().map(|pattern| format!("{}{}", prefix, pattern)).collect(),
+        strip: glob_desc.strip + subdir.split('/').count(),
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct FilePattern {
     pub pattern: String,
@@ -102,6 +134,7 @@ impl Layout {
         Layout {
             layout: LayoutContent::SingleDir,
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
     pub fn with_tp2(tp2: String) -> Self {
@@ -120,12 +153,14 @@ impl Layout {
         Layout {
             layout: LayoutContent::with_tp2(tp2),
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
     pub fn with_tp2_default_and_strip(strip_lvl: usize) -> Self {
         Layout {
             layout: LayoutContent::with_tp2_default(),
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
     pub fn multi_dir(dirs: Vec<String>) -> Self {
@@ -138,6 +173,7 @@ impl Layout {
         Layout {
             layout: LayoutContent::multi_dir(dirs),
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
 }
@@ -214,6 +250,39 @@ fn test_to_glob() {
     );
 }
 
+#[test]
+fn test_to_glob_in_subdir() {
+    use crate::lowercase::lwc;
+
+    let http_source = Source::http_source();
+    let layout = Layout { subdir: Some("ModA".to_string()), ..Layout::with_tp2_default() };
+    assert_eq!(layout.to_glob(&lwc!("toto"), &http_source), GlobDesc::from(&["ModA/toto", "ModA/setup-toto.tp2"], 1));
+
+    let layout = Layout { subdir: Some("Pack/ModA/".to_string()), ..Layout::single_dir(2) };
+    assert_eq!(layout.to_glob(&lwc!("toto"), &http_source), GlobDesc::single("*/*/Pack/ModA/toto", 4));
+}
+
+#[test]
+fn test_to_glob_auto_strip_single_root() {
+    use crate::lowercase::lwc;
+
+    let auto_strip = Layout { auto_strip_single_root: Some(true), ..Layout::default() };
+    assert_eq!(auto_strip.to_glob(&lwc!("toto"), &Source::gh_branch_source()), GlobDesc::single("toto", 0));
+    let auto_strip = Layout { auto_strip_single_root: Some(true), ..Layout::single_dir(1) };
+    assert_eq!(auto_strip.to_glob(&lwc!("toto"), &Source::gh_branch_source()), GlobDesc::single("toto", 1));
+}
+
+#[test]
+fn deserialize_layout_in_subdir() {
+    let yaml = r#"
+    strip_leading: 1
+    subdir: ModA
+    layout_type: single_dir_plus_tp2
+    "#;
+    let layout: Layout = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(layout, Layout { subdir: Some("ModA".to_string()), ..Layout::with_tp2_default_and_strip(1) });
+}
+
 #[test]
 fn deserialize_layout_single_dir_missing_strip() {
     let yaml = r#"
@@ -0,0 +1,80 @@
+
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Encoding used for the names of archive entries that are not valid UTF-8
+/// (old zip files usually use the DOS codepage).
+#[derive(Deserialize, Serialize, Debug, PartialEq, Copy, Clone, Default)]
+pub enum ArchiveNameEncoding {
+    /// Default value, invalid sequences are replaced with `�`
+    #[default]
+    UTF8,
+    /// Original IBM PC / DOS codepage, the one the zip format specifies when the UTF-8 flag is not set
+    CP437,
+    /// Windows central european
+    WIN1250,
+    /// Windows cyrillic
+    WIN1251,
+    /// Windows western european
+    WIN1252,
+}
+
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+impl ArchiveNameEncoding {
+    /// Decodes a raw entry name; names that are valid UTF-8 are always read as UTF-8.
+    pub fn decode(&self, raw: &[u8]) -> String {
+        if let Ok(name) = std::str::from_utf8(raw) {
+            return name.to_string();
+        }
+        match self {
+            ArchiveNameEncoding::UTF8 => String::from_utf8_lossy(raw).to_string(),
+            ArchiveNameEncoding::CP437 => raw.iter().map(|byte| match byte {
+                0..=127 => *byte as char,
+                _ => CP437_HIGH.chars().nth(*byte as usize - 128).unwrap(),
+            }).collect(),
+            ArchiveNameEncoding::WIN1250 => encoding_rs::WINDOWS_1250.decode(raw).0.to_string(),
+            ArchiveNameEncoding::WIN1251 => encoding_rs::WINDOWS_1251.decode(raw).0.to_string(),
+            ArchiveNameEncoding::WIN1252 => encoding_rs::WINDOWS_1252.decode(raw).0.to_string(),
+        }
+    }
+}
+
+/// Relative path of an archive entry, `None` if it would be outside of the extraction directory.<br>
+/// Backslashes (from DOS/Windows archivers) are read as separators.
+pub fn entry_relative_path(name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let mut result = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => result.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{entry_relative_path, ArchiveNameEncoding};
+
+    #[test]
+    fn decode_names() {
+        let raw = b"tra/fran\x87ais.tra";
+        assert_eq!(ArchiveNameEncoding::CP437.decode(raw), "tra/français.tra");
+        assert_eq!(ArchiveNameEncoding::UTF8.decode(raw), "tra/fran\u{FFFD}ais.tra");
+        assert_eq!(ArchiveNameEncoding::WIN1252.decode(b"tra/fran\xe7ais.tra"), "tra/français.tra");
+        assert_eq!(ArchiveNameEncoding::CP437.decode("tra/español.tra".as_bytes()), "tra/español.tra");
+    }
+
+    #[test]
+    fn entry_paths() {
+        assert_eq!(entry_relative_path("my_mod\\tra/./english.tra"), Some(PathBuf::from("my_mod/tra/english.tra")));
+        assert_eq!(entry_relative_path("my_mod/../../outside"), None);
+        assert_eq!(entry_relative_path("/etc/passwd"), None);
+    }
+}
@@ -1,6 +1,7 @@
 
 use std::path::PathBuf;
 
+use anyhow::{bail, Result};
 use clap_derive::{Parser, Subcommand, Args};
 
 use crate::canon_path::CanonPath;
@@ -15,6 +16,17 @@ use crate::progname::PROGNAME;
 #[command(about = "Weidu install automation", long_about = None)]
 pub struct Cli {
 
+    /// Game directory (where chitin.key is); defaults to the current directory.<br>
+    /// When absent and the current directory is not a game directory, the usual install
+    /// locations are searched and the user is asked to choose (only in an interactive terminal).
+    #[arg(long, global = true)]
+    pub game_dir: Option<String>,
+
+    /// Prints the configuration in effect for the command (config files, environment variables
+    /// and command-line options combined, secrets redacted) and exits without running it.
+    #[arg(long, global = true)]
+    pub print_config: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -40,6 +52,12 @@ pub enum Commands {
     Discover(Discover),
     /// Show configuration/settings information.
     Introspect(Introspect),
+    /// Checks that the modules of the manifest were installed (files are present in the game directory).
+    Verify(Verify),
+    /// Prints the modules of the manifest (name, kind, source type and component count) in installation order.
+    ListModules(ListModules),
+    /// Lists the content of `local_mods` and `local_files` that the manifest doesn't use (deletes it with `--delete`).
+    PruneLocal(PruneLocal),
 
     /// Works with the global configuration
     #[clap(subcommand)]
@@ -47,6 +65,14 @@ pub enum Commands {
 }
 
 impl Commands {
+    /// Value of the `--jobs` option, for the commands that have one.
+    pub fn cli_jobs(&self) -> Option<usize> {
+        match self {
+            Commands::Install(install) => install.jobs,
+            _ => None,
+        }
+    }
+
     pub fn wants_chitin_key(&self) -> bool {
         match self {
             Commands::Install(..) => true,
@@ -58,6 +84,9 @@ impl Commands {
             Commands::Reset(..) => true,
             Commands::Discover(..) => true,
             Commands::Introspect(..) => true,
+            Commands::Verify(..) => true,
+            Commands::ListModules(..) => false,
+            Commands::PruneLocal(..) => false,
             Commands::GlobalConfig(variant) => match variant {
                 ConfigArgs::Edit(..) => false,
                 ConfigArgs::Show(..) => false,
@@ -66,13 +95,24 @@ impl Commands {
     }
 }
 
-#[derive(Args, Debug, Default)]
+#[derive(Args, Debug, Default, Clone)]
 pub struct Install {
 
     /// Path of the YAML manifest file.
-    #[arg(long, short)]
+    #[arg(long, short, required_unless_present = "manifest_url", conflicts_with = "manifest_url", default_value = "")]
     pub manifest_path: String,
 
+    /// URL (http or https) of the manifest, used instead of `--manifest-path`.<br>
+    /// The manifest is downloaded to the archive cache.
+    #[arg(long)]
+    pub manifest_url: Option<String>,
+
+    /// With `--manifest-url`, directory used to resolve paths relative to the manifest
+    /// (`local_mods`, `local_files`, `local_patches`, external location registries...).<br>
+    /// Defaults to the game directory.
+    #[arg(long, requires = "manifest_url")]
+    pub manifest_root: Option<String>,
+
     /// If set to true, will not stop when weidu returns a warning.
     #[arg(long)]
     pub no_stop_on_warn: bool,
@@ -101,6 +141,11 @@ pub struct Install {
     #[arg(long)]
     pub dry_run: bool,
 
+    /// Before installing a mod, checks with weidu (`--list-components-json`) that its tp2 can be read and that
+    /// the requested components exist; with `--dry-run`, validates the manifest without installing anything.
+    #[arg(long)]
+    pub check_components: bool,
+
     /// If set, prints duration information.
     #[arg(long)]
     pub timeline: bool,
@@ -120,15 +165,60 @@ pub struct Install {
     /// Decides what to do if a replace action has a `strict` property that is not obeyed.<br>
     #[arg(long, default_value = "ask")]
     pub check_replace: StrictReplaceAction,
+
+    /// Maximum number of archives downloaded, zip entries extracted and globs walked in parallel.<br>
+    /// Overrides the `jobs` config value; `1` runs everything serially.
+    #[arg(long)]
+    pub jobs: Option<usize>,
+
+    /// Prints how the given module would be obtained and installed (source URL, cache path, layout globs,
+    /// patches...), then stops without installing anything.
+    #[arg(long)]
+    pub explain: Option<LwcString>,
+
+    /// Installs the modules again even if the install journal (`weidu_external/modda/install_journal.txt`)
+    /// says they were completed by a previous run.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Lock file (the manifest as it was last installed with this option): only the modules that are new or
+    /// whose location, components or files changed since then are installed, then the lock is updated.<br>
+    /// Without a lock file, everything is installed and the lock is created.
+    #[arg(long, conflicts_with_all = ["from_index", "to_index", "just_one", "count"])]
+    pub since_lock: Option<String>,
+
+    /// Installs only the module with this name (it must be in the manifest once), without touching the other ones.
+    #[arg(long, conflicts_with_all = ["from_index", "to_index", "just_one", "count", "since_lock"])]
+    pub module: Option<LwcString>,
+
+    /// File modules only copy the source files modified since their previous copy (the time of each copy is kept
+    /// in `weidu_external/modda/sync`), which is faster when iterating on local files.
+    #[arg(long)]
+    pub sync_files: bool,
 }
 
 impl Install {
-    pub fn get_manifest_root(&self, game_dir: &CanonPath) -> CanonPath {
-        let manifest = PathBuf::from(&self.manifest_path);
-        match manifest.parent() {
-            None => game_dir.to_owned(),
-            Some(path) => CanonPath::new(path).unwrap_or_else(|_| game_dir.to_owned()),
+    /// Fails when the `--manifest-root` of a `--manifest-url` can't be resolved to an existing directory.
+    pub fn get_manifest_root(&self, game_dir: &CanonPath) -> Result<CanonPath> {
+        if self.manifest_url.is_some() {
+            return match &self.manifest_root {
+                None => Ok(game_dir.to_owned()),
+                Some(root) => match game_dir.join(root) {
+                    Ok(path) if path.path().is_dir() => Ok(path),
+                    Ok(path) => bail!("Invalid --manifest-root {:?}\n -> {:?} is not a directory", root, path),
+                    Err(error) => bail!("Invalid --manifest-root {:?}\n -> {:?}", root, error),
+                },
+            };
         }
+        Ok(manifest_root_from_path(&self.manifest_path, game_dir))
+    }
+}
+
+/// Directory of the manifest file (where `local_mods` and `local_files` are resolved), `default` if there is none.
+pub fn manifest_root_from_path(manifest_path: &str, default: &CanonPath) -> CanonPath {
+    match PathBuf::from(manifest_path).parent() {
+        None => default.to_owned(),
+        Some(path) => CanonPath::new(path).unwrap_or_else(|_| default.to_owned()),
     }
 }
 
@@ -232,6 +322,42 @@ pub struct Introspect {
     pub show_config: bool,
 }
 
+#[derive(Args, Debug)]
+pub struct Verify {
+
+    /// Path of the YAML manifest file.
+    #[arg(long, short)]
+    pub manifest_path: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ListModules {
+
+    /// Path of the YAML manifest file.
+    #[arg(long, short)]
+    pub manifest_path: String,
+
+    /// Only lists the modules with these names (comma-separated or repeated).
+    #[arg(long, value_delimiter = ',')]
+    pub only: Vec<LwcString>,
+
+    /// Doesn't list the modules with these names (comma-separated or repeated).
+    #[arg(long, value_delimiter = ',')]
+    pub skip: Vec<LwcString>,
+}
+
+#[derive(Args, Debug)]
+pub struct PruneLocal {
+
+    /// Path of the YAML manifest file.
+    #[arg(long, short)]
+    pub manifest_path: String,
+
+    /// Deletes the unused files and directories instead of only listing them.
+    #[arg(long)]
+    pub delete: bool,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum ConfigArgs {
     /// Show the global configuration (opens the directory that contains the global configuration file)
@@ -246,3 +372,36 @@ pub struct ConfigShow {}
 
 #[derive(Args, Debug)]
 pub struct ConfigEdit {}
+
+#[cfg(test)]
+mod tests {
+    use crate::canon_path::CanonPath;
+    use crate::test_utils::test_dir;
+
+    use super::{manifest_root_from_path, Install};
+
+    #[test]
+    fn manifest_root_is_the_manifest_directory() {
+        let game_dir = CanonPath::new("/game").unwrap();
+        assert_eq!(manifest_root_from_path("/home/me/install.yml", &game_dir).path(), std::path::Path::new("/home/me"));
+        assert_eq!(manifest_root_from_path("", &game_dir).path(), game_dir.path());
+    }
+
+    #[test]
+    fn unresolvable_manifest_root_is_an_error() {
+        let dir = test_dir();
+        let game_dir = CanonPath::new(dir.path()).unwrap();
+        std::fs::create_dir(dir.path().join("manifests")).unwrap();
+        let install = |root: &str| Install {
+            manifest_url: Some("http://example.com/manifest.yml".to_string()),
+            manifest_root: Some(root.into()),
+            ..Default::default()
+        };
+
+        let root = install("manifests").get_manifest_root(&game_dir).unwrap();
+        assert_eq!(root.path(), game_dir.path().join("manifests"));
+
+        let error = install("does/not/exist").get_manifest_root(&game_dir).unwrap_err();
+        assert!(error.to_string().contains("does/not/exist"), "{:?}", error);
+    }
+}
@@ -0,0 +1,84 @@
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by all the downloads of a `Downloader`, so that they collectively stay
+/// under `max_bytes_per_sec`.<br>
+/// The bucket starts empty and holds at most a quarter of second of transfer, so that short bursts
+/// can't go much over the limit.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_sec: u64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Can be negative: a chunk larger than what is available is received anyway, the following
+    /// ones wait until this is paid back.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl BandwidthLimiter {
+    /// `None` for `0` (unlimited).
+    pub fn new(bytes_per_sec: u64) -> Option<Self> {
+        if bytes_per_sec == 0 {
+            return None;
+        }
+        Some(BandwidthLimiter {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket { tokens: 0.0, last_refill: Instant::now() }),
+        })
+    }
+
+    /// Waits until `bytes` can be consumed without going over the limit.
+    pub async fn consume(&self, bytes: usize) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes `bytes` from the bucket, returns how long to wait before using them.
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let rate = self.bytes_per_sec as f64;
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate / 4.0);
+        bucket.last_refill = now;
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::BandwidthLimiter;
+
+    #[test]
+    fn reservations_are_shared() {
+        let limiter = BandwidthLimiter::new(1000).unwrap();
+        let start = limiter.bucket.lock().unwrap().last_refill;
+
+        // two downloads get their chunks at the same time: the second one waits for both
+        assert_eq!(limiter.reserve(500, start), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(500, start), Duration::from_secs(1));
+        // nothing was used during one second: the debt is paid back
+        assert_eq!(limiter.reserve(0, start + Duration::from_secs(1)), Duration::ZERO);
+        // a long pause only gives a short burst
+        assert_eq!(limiter.reserve(250, start + Duration::from_secs(60)), Duration::ZERO);
+        assert_eq!(limiter.reserve(100, start + Duration::from_secs(60)), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn zero_is_unlimited() {
+        assert!(BandwidthLimiter::new(0).is_none());
+    }
+}
@@ -1,11 +1,31 @@
 
+use std::fs::File;
 use std::path::{PathBuf, Path};
 
 use anyhow::{bail, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
+use crate::archive_extractor::uncompressed_size;
 use crate::config::Config;
+use crate::lowercase::LwcString;
+use crate::module::location::source::Source;
 
 
+/// What is checked before an archive found in the cache is reused instead of being downloaded.<br>
+/// The checks use the metadata recorded when the archive was downloaded; without it, the file name is enough.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Copy, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheValidation {
+    /// Any file with the expected name is reused.
+    Name,
+    /// The file must have the size it had when it was downloaded.
+    #[default]
+    Size,
+    /// Size and SHA-256 of the content (the whole file is read).
+    Checksum,
+}
 
 pub enum Cache {
     Tmp(tempfile::TempDir),
@@ -37,4 +57,377 @@ impl Cache {
             Cache::Path(base_path) => base_path.join(path),
         }
     }
+
+    /// Where the archive of a module is (or would be) downloaded in the cache: the subdirectory of the source
+    /// (host for http, user/repository for github), the shard directories and its save name.<br>
+    /// `path`/`local` sources are never in the cache.
+    pub fn path_for(&self, source: &Source, module_name: &LwcString, shard_depth: u8) -> Result<PathBuf> {
+        Ok(self.archive_dir(source, module_name, shard_depth)?.join(source.save_name(module_name)?))
+    }
+
+    /// The directory the save name of an archive is relative to: the subdirectory of the source, with
+    /// `shard_depth` levels of `shard_dirs` below it.<br>
+    /// An archive that is only in the unsharded directory (cached before sharding was set) is used from there.
+    pub fn archive_dir(&self, source: &Source, module_name: &LwcString, shard_depth: u8) -> Result<PathBuf> {
+        if let Source::Absolute { .. } | Source::Local { .. } = source {
+            bail!("Module {} has a local source, its archive is not in the cache", module_name);
+        }
+        let subdir = self.join(source.save_subdir()?);
+        if shard_depth == 0 {
+            return Ok(subdir);
+        }
+        let save_name = source.save_name(module_name)?;
+        let sharded = subdir.join(shard_dirs(&save_name, shard_depth));
+        if !sharded.join(&save_name).exists() && subdir.join(&save_name).exists() {
+            debug!("archive {:?} of {} found in the unsharded cache directory", save_name, module_name);
+            return Ok(subdir);
+        }
+        Ok(sharded)
+    }
+
+    fn base(&self) -> &Path {
+        match self {
+            Cache::Tmp(tmpdir) => tmpdir.path(),
+            Cache::Path(base_path) => base_path,
+        }
+    }
+
+    /// Size of the archive content once extracted (`None` for directories and archive formats
+    /// that can't be read directly).<br>
+    /// For archives in the cache, it is computed once and kept in the metadata file next to the archive.
+    pub fn uncompressed_size(&self, archive: &Path) -> Result<Option<u64>> {
+        if !archive.is_file() {
+            return Ok(None);
+        }
+        let archive_size = std::fs::metadata(archive)?.len();
+        let in_cache = archive.starts_with(self.base());
+        if in_cache {
+            match ArchiveMetadata::read(archive) {
+                Some(metadata) if metadata.archive_size == archive_size && metadata.uncompressed_size.is_some() => {
+                    debug!("uncompressed size of {:?} read from its metadata", archive);
+                    return Ok(metadata.uncompressed_size);
+                }
+                _ => {}
+            }
+        }
+        let size = uncompressed_size(archive)?;
+        if in_cache && size.is_some() {
+            // keep the checksum recorded for this exact archive
+            let sha256 = ArchiveMetadata::read(archive)
+                .filter(|metadata| metadata.archive_size == archive_size)
+                .and_then(|metadata| metadata.sha256);
+            let metadata = ArchiveMetadata { archive_size, uncompressed_size: size, sha256 };
+            // the metadata is only an optimization
+            if let Err(error) = metadata.write(archive) {
+                warn!("Could not write archive metadata {:?}\n  {}", archive_metadata_path(archive), error);
+            }
+        }
+        Ok(size)
+    }
+
+    /// Records the size and checksum of an archive that was just downloaded to the cache, so that
+    /// it can be checked when it is reused (see `CacheValidation`).
+    pub fn record_download(&self, archive: &Path, sha256: &str) -> Result<()> {
+        if !archive.starts_with(self.base()) {
+            return Ok(());
+        }
+        let archive_size = std::fs::metadata(archive)?.len();
+        let metadata = ArchiveMetadata { archive_size, uncompressed_size: None, sha256: Some(sha256.to_string()) };
+        metadata.write(archive)
+    }
+
+    /// Why the archive reused from the cache can't be trusted, `None` if it passes the checks
+    /// (or there is nothing recorded to check it against).
+    pub fn check_cached_archive(&self, archive: &Path, validation: CacheValidation) -> Option<String> {
+        if validation == CacheValidation::Name || !archive.is_file() {
+            return None;
+        }
+        let metadata = match ArchiveMetadata::read(archive) {
+            None => {
+                debug!("no metadata for {:?}, reused by name", archive);
+                return None;
+            }
+            Some(metadata) => metadata,
+        };
+        let archive_size = match std::fs::metadata(archive) {
+            Err(error) => return Some(format!("could not read its size ({})", error)),
+            Ok(file_metadata) => file_metadata.len(),
+        };
+        if archive_size != metadata.archive_size {
+            return Some(format!("size is {} bytes, {} bytes were downloaded", archive_size, metadata.archive_size));
+        }
+        match (validation, &metadata.sha256) {
+            (CacheValidation::Checksum, Some(expected)) => match file_sha256(archive) {
+                Err(error) => Some(format!("could not compute its checksum ({})", error)),
+                Ok(sha256) if &sha256 != expected => Some(format!("sha256 is {}, expected {}", sha256, expected)),
+                Ok(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Removes an archive from the cache with its metadata file; archives outside of the cache
+    /// (`local` or `path` locations) are never removed.<br>
+    /// Returns whether the archive was removed.
+    pub fn remove_archive(&self, archive: &Path) -> Result<bool> {
+        if !archive.starts_with(self.base()) || !archive.is_file() {
+            return Ok(false);
+        }
+        if let Err(error) = std::fs::remove_file(archive) {
+            bail!("Could not remove archive {:?} from the cache\n -> {:?}", archive, error);
+        }
+        let metadata = archive_metadata_path(archive);
+        if metadata.exists() {
+            if let Err(error) = std::fs::remove_file(&metadata) {
+                bail!("Could not remove archive metadata {:?}\n -> {:?}", metadata, error);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// What is known about a cached archive, kept in `<archive file name>.meta.json` next to it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ArchiveMetadata {
+    /// Size of the archive file when the metadata was written, the metadata is ignored if it changed.
+    pub archive_size: u64,
+    pub uncompressed_size: Option<u64>,
+    /// SHA-256 (lowercase hex) computed when the archive was downloaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl ArchiveMetadata {
+    /// `None` if there is no metadata file or it can't be read.
+    pub fn read(archive: &Path) -> Option<Self> {
+        let path = archive_metadata_path(archive);
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(metadata) => Some(metadata),
+            Err(error) => {
+                warn!("Ignoring invalid archive metadata {:?}\n  {}", path, error);
+                None
+            }
+        }
+    }
+
+    pub fn write(&self, archive: &Path) -> Result<()> {
+        let path = archive_metadata_path(archive);
+        if let Err(error) = std::fs::write(&path, serde_json::to_string(self)?) {
+            bail!("Could not write archive metadata {:?}\n -> {:?}", path, error);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn file_sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut file = File::open(path)?;
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Directories that spread the archives of a cache subdirectory: one per level, named after the next two
+/// hexadecimal characters of the SHA-256 of the save name (at most 32 levels).
+pub fn shard_dirs(save_name: &Path, depth: u8) -> PathBuf {
+    let hash = format!("{:x}", Sha256::digest(save_name.to_string_lossy().as_bytes()));
+    (0..usize::from(depth.min(32))).map(|level| &hash[level * 2..level * 2 + 2]).collect()
+}
+
+pub fn archive_metadata_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(".meta.json");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::lowercase::lwc;
+    use crate::module::location::github::{GitBranch, Github, GithubDescriptor};
+    use crate::module::location::http::Http;
+    use crate::module::location::source::Source;
+    use crate::module::refresh::RefreshCondition;
+    use crate::test_utils::test_dir;
+
+    use super::{archive_metadata_path, shard_dirs, ArchiveMetadata, Cache, CacheValidation};
+
+    fn cache_with_archive(fixture: &str) -> (Cache, PathBuf) {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let cache = Cache::Tmp(test_dir());
+        let archive = cache.join("http").join(fixture);
+        std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        std::fs::copy(project.join("resources/test/archives").join(fixture), &archive).unwrap();
+        (cache, archive)
+    }
+
+    fn github(descriptor: GithubDescriptor) -> Source {
+        Source::Github(Github {
+            github_user: "some_user".to_string(),
+            repository: "my-mod".to_string(),
+            descriptor,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn path_for_each_source() {
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let name = lwc!("my_mod");
+        let http = |http: &str, rename: Option<&str>| Source::Http(Http {
+            http: http.to_string(),
+            rename: rename.map(|rename| rename.to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(cache.path_for(&http("https://example.com/files/my%20mod.zip", None), &name, 0).unwrap(),
+                    PathBuf::from("/cache_path/http/example.com/my mod.zip"));
+        assert_eq!(cache.path_for(&http("https://example.com/download?id=12", Some("mods/my_mod.7z")), &name, 0).unwrap(),
+                    PathBuf::from("/cache_path/http/example.com/mods/my_mod.7z"));
+        assert_eq!(cache.path_for(&github(GithubDescriptor::Tag { tag: "v1.2".to_string() }), &name, 0).unwrap(),
+                    PathBuf::from("/cache_path/github/some_user/my-mod/my_mod-v1.2.zip"));
+        assert_eq!(cache.path_for(&github(GithubDescriptor::Commit { commit: "abc123".to_string() }), &name, 0).unwrap(),
+                    PathBuf::from("/cache_path/github/some_user/my-mod/my_mod-abc123.zip"));
+        let branch = GithubDescriptor::Branch(GitBranch { branch: "main".to_string(), refresh: RefreshCondition::Never });
+        assert_eq!(cache.path_for(&github(branch), &name, 0).unwrap(),
+                    PathBuf::from("/cache_path/github/some_user/my-mod/my_mod-main.zip"));
+        let release = GithubDescriptor::Release { release: None, asset: "my_mod-1.0.zip".to_string() };
+        assert_eq!(cache.path_for(&github(release), &name, 0).unwrap(),
+                    PathBuf::from("/cache_path/github/some_user/my-mod/my_mod-1.0.zip"));
+        cache.path_for(&Source::Local { local: "my_mod".to_string() }, &name, 0).unwrap_err();
+        cache.path_for(&Source::Absolute { path: "/mods/my_mod".to_string() }, &name, 0).unwrap_err();
+    }
+
+    #[test]
+    fn enterprise_github_path_has_the_host() {
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let enterprise = |base_url: &str| Source::Github(Github {
+            github_user: "some_user".to_string(),
+            repository: "my-mod".to_string(),
+            descriptor: GithubDescriptor::Tag { tag: "v1.2".to_string() },
+            base_url: Some(base_url.to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(cache.path_for(&enterprise("https://github.mycorp.com"), &lwc!("my_mod"), 0).unwrap(),
+                    PathBuf::from("/cache_path/github/github.mycorp.com/some_user/my-mod/my_mod-v1.2.zip"));
+        assert_eq!(cache.path_for(&enterprise("https://git.example.org:8443/"), &lwc!("my_mod"), 0).unwrap(),
+                    PathBuf::from("/cache_path/github/git.example.org_8443/some_user/my-mod/my_mod-v1.2.zip"));
+        cache.path_for(&enterprise("not an url"), &lwc!("my_mod"), 0).unwrap_err();
+    }
+
+    #[test]
+    fn sharded_path() {
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let source = Source::Http(Http { http: "https://example.com/files/my_mod.zip".to_string(), ..Default::default() });
+
+        // sha256("my_mod.zip") starts with 8826
+        assert_eq!(shard_dirs(Path::new("my_mod.zip"), 2), PathBuf::from("88/26"));
+        assert_eq!(cache.path_for(&source, &lwc!("my_mod"), 1).unwrap(),
+                    PathBuf::from("/cache_path/http/example.com/88/my_mod.zip"));
+        assert_eq!(cache.path_for(&source, &lwc!("my_mod"), 2).unwrap(),
+                    PathBuf::from("/cache_path/http/example.com/88/26/my_mod.zip"));
+    }
+
+    #[test]
+    fn unsharded_archive_is_still_found() {
+        let (cache, archive) = cache_with_archive("my_mod.zip");
+        let source = Source::Http(Http { http: "https://example.com/files/my_mod.zip".to_string(), ..Default::default() });
+        let unsharded = cache.join("http/example.com/my_mod.zip");
+        std::fs::create_dir_all(unsharded.parent().unwrap()).unwrap();
+        std::fs::rename(&archive, &unsharded).unwrap();
+
+        assert_eq!(cache.path_for(&source, &lwc!("my_mod"), 2).unwrap(), unsharded);
+
+        // once the archive is in its sharded directory, it is used from there
+        let sharded = cache.join("http/example.com/88/26/my_mod.zip");
+        std::fs::create_dir_all(sharded.parent().unwrap()).unwrap();
+        std::fs::copy(&unsharded, &sharded).unwrap();
+        assert_eq!(cache.path_for(&source, &lwc!("my_mod"), 2).unwrap(), sharded);
+    }
+
+    #[test]
+    fn uncompressed_size_is_stored_in_metadata() {
+        let (cache, archive) = cache_with_archive("my_mod.zip");
+
+        let size = cache.uncompressed_size(&archive).unwrap();
+
+        let metadata = ArchiveMetadata::read(&archive).unwrap();
+        assert_eq!(metadata.archive_size, std::fs::metadata(&archive).unwrap().len());
+        assert_eq!(metadata.uncompressed_size, size);
+        assert!(size.unwrap() > 0);
+        // same content in the tgz version of the fixture
+        let (tgz_cache, tgz_archive) = cache_with_archive("my_mod.tgz");
+        assert_eq!(tgz_cache.uncompressed_size(&tgz_archive).unwrap(), size);
+    }
+
+    #[test]
+    fn uncompressed_size_is_read_from_metadata() {
+        let (cache, archive) = cache_with_archive("my_mod.zip");
+        let archive_size = std::fs::metadata(&archive).unwrap().len();
+        ArchiveMetadata { archive_size, uncompressed_size: Some(1234), sha256: None }.write(&archive).unwrap();
+
+        assert_eq!(cache.uncompressed_size(&archive).unwrap(), Some(1234));
+
+        // the archive was replaced: the metadata is stale
+        ArchiveMetadata { archive_size: archive_size + 1, uncompressed_size: Some(1234), sha256: None }.write(&archive).unwrap();
+        assert_ne!(cache.uncompressed_size(&archive).unwrap(), Some(1234));
+    }
+
+    #[test]
+    fn cached_archive_checked_against_download_metadata() {
+        let (cache, archive) = cache_with_archive("my_mod.zip");
+        // nothing recorded: reused by name
+        assert_eq!(cache.check_cached_archive(&archive, CacheValidation::Checksum), None);
+
+        let wrong_sha256 = "0".repeat(64);
+        cache.record_download(&archive, &wrong_sha256).unwrap();
+        assert_eq!(cache.check_cached_archive(&archive, CacheValidation::Size), None);
+        let reason = cache.check_cached_archive(&archive, CacheValidation::Checksum).unwrap();
+        assert!(reason.contains(&format!("expected {}", wrong_sha256)), "{}", reason);
+
+        // truncated
+        let content = std::fs::read(&archive).unwrap();
+        std::fs::write(&archive, &content[..content.len() / 2]).unwrap();
+        assert!(cache.check_cached_archive(&archive, CacheValidation::Size).is_some());
+        assert_eq!(cache.check_cached_archive(&archive, CacheValidation::Name), None);
+    }
+
+    #[test]
+    fn recorded_checksum_kept_with_uncompressed_size() {
+        let (cache, archive) = cache_with_archive("my_mod.zip");
+        cache.record_download(&archive, "abcd").unwrap();
+
+        cache.uncompressed_size(&archive).unwrap();
+
+        let metadata = ArchiveMetadata::read(&archive).unwrap();
+        assert!(metadata.uncompressed_size.is_some());
+        assert_eq!(metadata.sha256, Some("abcd".to_string()));
+    }
+
+    #[test]
+    fn remove_archive_only_from_cache() {
+        let (cache, archive) = cache_with_archive("my_mod.zip");
+        cache.uncompressed_size(&archive).unwrap();
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let outside = project.join("resources/test/archives/my_mod.zip");
+
+        assert!(!cache.remove_archive(&outside).unwrap());
+        assert!(outside.exists());
+
+        assert!(cache.remove_archive(&archive).unwrap());
+        assert!(!archive.exists());
+        assert!(!archive_metadata_path(&archive).exists());
+    }
+
+    #[test]
+    fn no_metadata_outside_of_cache() {
+        let (cache, _) = cache_with_archive("my_mod.zip");
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let outside = project.join("resources/test/archives/my_mod.zip");
+
+        assert!(cache.uncompressed_size(&outside).unwrap().is_some());
+        assert!(!archive_metadata_path(&outside).exists());
+    }
 }
@@ -0,0 +1,87 @@
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// What to do when extracted files only differ by the case of their path (`File.2da` and `file.2da`);
+/// on Windows (and for the game engine) they are the same file.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseCollisions {
+    /// Default value, all the files are kept and a warning lists them
+    #[default]
+    Warn,
+    /// Keeps the file that comes first in the archive (Windows archivers would have kept the last one)
+    KeepFirst,
+    /// Keeps the file that comes last in the archive, like an extraction on Windows
+    KeepLast,
+    /// Fails the extraction
+    Error,
+}
+
+/// Groups of files under `base` (relative paths) whose paths are equal ignoring case.<br>
+/// Each group is sorted by position in `order` (the archive entries), files that are not in `order`
+/// come last, in name order.
+pub fn find_case_collisions(base: &Path, order: &[PathBuf]) -> Result<Vec<Vec<PathBuf>>> {
+    let mut files = vec![];
+    list_files(base, Path::new(""), &mut files)?;
+    let mut groups = HashMap::<String, Vec<PathBuf>>::new();
+    for file in files {
+        groups.entry(file.to_string_lossy().to_lowercase()).or_default().push(file);
+    }
+    let position = |path: &PathBuf| order.iter().position(|entry| entry == path).unwrap_or(usize::MAX);
+    let mut collisions = groups.into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_by(|left, right| position(left).cmp(&position(right)).then_with(|| left.cmp(right)));
+            group
+        })
+        .collect::<Vec<_>>();
+    collisions.sort();
+    Ok(collisions)
+}
+
+/// Removes (from `base`) the files of each group that are not kept by `resolution`.<br>
+/// Nothing is removed for `Warn` and `Error`.
+pub fn remove_case_duplicates(base: &Path, collisions: &[Vec<PathBuf>], resolution: CaseCollisions) -> Result<()> {
+    for group in collisions {
+        let removed = match resolution {
+            CaseCollisions::Warn | CaseCollisions::Error => continue,
+            CaseCollisions::KeepFirst => &group[1..],
+            CaseCollisions::KeepLast => &group[..group.len() - 1],
+        };
+        for file in removed {
+            if let Err(error) = std::fs::remove_file(base.join(file)) {
+                bail!("Could not remove case-colliding file {:?}\n -> {:?}", file, error);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn describe_collisions(collisions: &[Vec<PathBuf>]) -> String {
+    collisions.iter()
+        .map(|group| group.iter().map(|file| format!("{:?}", file)).collect::<Vec<_>>().join(", "))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn list_files(base: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let dir = base.join(relative);
+    let entries = match std::fs::read_dir(&dir) {
+        Err(error) => bail!("Could not read extracted directory {:?}\n -> {:?}", dir, error),
+        Ok(entries) => entries,
+    };
+    for entry in entries {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(base, &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use log::info;
+
+use crate::canon_path::CanonPath;
+use crate::lowercase::{lwc, LwcString};
+
+/// Removes what a previous (possibly partial or broken) installation of a mod left in the game directory,
+/// so that the mod can be extracted again from scratch:
+/// - the `<mod>` directory
+/// - the `<mod>.tp2` and `setup-<mod>.tp2` files at the top of the game directory
+///
+/// Only direct children of the game directory that belong to the module are removed; a module name
+/// that would designate something else (`..`, path separators...) is refused.<br>
+/// Returns the removed paths.
+pub fn clean_module_files(game_dir: &CanonPath, module_name: &LwcString) -> Result<Vec<PathBuf>> {
+    let mut components = Path::new(module_name.as_ref()).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {}
+        _ => bail!("Refusing to clean module {} - it would delete files outside of the game directory {:?}",
+                    module_name, game_dir),
+    }
+
+    let owned_names = [
+        module_name.clone(),
+        lwc!(&format!("{module_name}.tp2")),
+        lwc!(&format!("setup-{module_name}.tp2")),
+    ];
+    let entries = match std::fs::read_dir(game_dir) {
+        Ok(entries) => entries,
+        Err(error) => bail!("Could not read game directory {:?}\n -> {:?}", game_dir, error),
+    };
+    let mut removed = vec![];
+    for entry in entries {
+        let entry = entry?;
+        let is_owned = entry.file_name().to_str()
+            .map(|name| owned_names.contains(&lwc!(name)))
+            .unwrap_or(false);
+        if !is_owned {
+            continue;
+        }
+        let path = entry.path();
+        if path.parent() != Some(game_dir.path()) {
+            bail!("Refusing to delete {:?} which is not in the game directory {:?}", path, game_dir);
+        }
+        // symlinks are removed, not followed
+        let result = if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        if let Err(error) = result {
+            bail!("Could not delete {:?} for module {}\n -> {:?}", path, module_name, error);
+        }
+        info!("Removed {:?} before reinstalling module {}", path, module_name);
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::archive_layout::Layout;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::test_utils::extractor::extractor_fixture;
+    use crate::test_utils::test_dir;
+
+    use super::clean_module_files;
+
+    #[test]
+    fn clean_then_extract_again() {
+        let fixture = extractor_fixture(Config::default());
+        let game_dir = &fixture.game_dir;
+        std::fs::create_dir_all(game_dir.join_path("My_Mod/tra")).unwrap();
+        std::fs::write(game_dir.join_path("My_Mod/tra/stale.tra"), "stale").unwrap();
+        std::fs::write(game_dir.join_path("setup-my_mod.tp2"), "stale").unwrap();
+        std::fs::create_dir_all(game_dir.join_path("other_mod")).unwrap();
+        std::fs::write(game_dir.join_path("other_mod/other_mod.tp2"), "other").unwrap();
+        std::fs::write(game_dir.join_path("my_mod_readme.txt"), "not owned").unwrap();
+
+        let mut removed = clean_module_files(game_dir, &lwc!("my_mod")).unwrap();
+        removed.sort();
+
+        assert_eq!(removed, vec![game_dir.join_path("My_Mod"), game_dir.join_path("setup-my_mod.tp2")]);
+        assert!(!game_dir.join_path("My_Mod").exists());
+        assert!(game_dir.join_path("other_mod/other_mod.tp2").exists());
+        assert!(game_dir.join_path("my_mod_readme.txt").exists());
+
+        let location = fixture.location(Layout::with_tp2_default_and_strip(1));
+        let archive = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/archives/my_mod.zip");
+        fixture.extractor().extract_files(&archive, &lwc!("my_mod"), &location).unwrap();
+
+        assert!(game_dir.join_path("my_mod/my_mod.tp2").exists());
+        assert!(game_dir.join_path("my_mod/tra/english.tra").exists());
+        assert!(!game_dir.join_path("my_mod/tra/stale.tra").exists());
+    }
+
+    #[test]
+    fn refuse_cleaning_outside_game_dir() {
+        let temp = test_dir();
+        let game_dir = CanonPath::new(temp.path().join("game")).unwrap();
+        std::fs::create_dir_all(game_dir.path()).unwrap();
+        std::fs::create_dir_all(temp.path().join("outside")).unwrap();
+        std::fs::write(temp.path().join("outside/file.txt"), "keep").unwrap();
+
+        clean_module_files(&game_dir, &lwc!("../outside")).unwrap_err();
+        clean_module_files(&game_dir, &lwc!("..")).unwrap_err();
+        clean_module_files(&game_dir, &lwc!("")).unwrap_err();
+
+        assert!(temp.path().join("outside/file.txt").exists());
+        assert!(game_dir.path().exists());
+    }
+}
@@ -10,8 +10,13 @@ use anyhow::{bail, Ok, Result};
 use log::debug;
 use serde::{Deserialize, Serialize};
 
+use crate::archive_name_encoding::ArchiveNameEncoding;
+use crate::case_collisions::CaseCollisions;
+use crate::tar_symlinks::TarSymlinks;
+use crate::cache::CacheValidation;
 use crate::canon_path::CanonPath;
 use crate::lowercase::LwcString;
+use crate::module::manifest::ManifestErrors;
 use crate::progname::PROGNAME;
 
 pub const ARCHIVE_CACHE_ENV_VAR: &'static str = "MODDA_ARCHIVE_CACHE";
@@ -19,6 +24,7 @@ pub const EXTRACT_LOCATION_ENV_VAR: &'static str = "MODDA_EXTRACT_LOCATION";
 pub const WEIDU_PATH_ENV_VAR: &'static str = "MODDA_WEIDU_PATH";
 pub const IGNORE_CURRENT_DIR_WEIDU_ENV_VAR: &'static str = "MODDA_IGNORE_CURRENT_DIR_WEIDU";
 pub const CODE_EDITOR_ENV_VAR: &'static str = "MODDA_CODE_EDITOR";
+pub const JOBS_ENV_VAR: &str = "MODDA_JOBS";
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct Config {
@@ -52,6 +58,10 @@ pub struct Config {
     /// and directly fall back to weidu-on-path
     pub ignore_current_dir_weidu: Option<bool>,
 
+    /// Required weidu version (`249`, `>=247`, `>=247, <250`), checked with `weidu --version`
+    /// before anything else; modda stops if the weidu that would be used doesn't match.
+    pub weidu_version: Option<String>,
+
     /// Sets-up archive extractors by extension.
     /// - the key is the extension (case-insensitive)
     /// - the value contains both a`command` and an `args` properties
@@ -64,6 +74,7 @@ pub struct Config {
     ///   rar:
     ///     command: unrar-nonfree
     ///     args: [ "x", "${input}", "${target}" ]
+    ///     list_args: [ "lb", "${input}" ]
     ///   7z:
     ///     command: 7z
     ///     args: [ "x", "${input}", "-o${target}" ]
@@ -74,12 +85,139 @@ pub struct Config {
     /// Path to the code editor program.<br>
     /// Used with the `config edit` subcommands.
     pub code_editor: Option<String>,
+
+    /// Maximum number of archives downloaded, zip entries extracted and globs walked in parallel.<br>
+    /// Defaults to the number of CPUs; `1` runs everything serially.
+    /// Overridden by the `--jobs` option of the `install` subcommand.
+    pub jobs: Option<usize>,
+
+    /// If true, the permissions of the extracted mod files are made consistent after they are moved
+    /// to the game directory (`0644` for files, `0755` for directories and executables on Unix,
+    /// read-only flag cleared on Windows).<br>
+    /// Can be set for a single location with its `normalize_permissions` property.
+    pub normalize_permissions: Option<bool>,
+
+    /// If true, glob patterns (`layout` of the locations, `files` of generated mods) are matched
+    /// case-sensitively.<br>
+    /// Defaults to false, as mods are often made on Windows where file names case is not reliable.
+    /// Can be set for a single location with the `case_sensitive` property of its `layout`.
+    pub glob_case_sensitive: Option<bool>,
+
+    /// If false, glob patterns (`layout` of the locations, `files` of generated mods) don't select hidden
+    /// (dot-prefixed) files and directories, nor anything inside hidden directories.<br>
+    /// Defaults to true: some mods ship dot-prefixed data files.
+    pub glob_include_hidden: Option<bool>,
+
+    /// Encoding of the zip entry names that are not valid UTF-8 (`UTF8`, `CP437`, `WIN1250`, `WIN1251`, `WIN1252`).<br>
+    /// Defaults to `UTF8` (invalid characters are replaced); very old zips often need `CP437`.
+    /// Can be set for a single location with its `archive_name_encoding` property.
+    pub archive_name_encoding: Option<ArchiveNameEncoding>,
+
+    /// What to do with extracted files whose paths only differ by case (`warn`, `keep_first`, `keep_last`, `error`).<br>
+    /// Defaults to `warn` (all files are kept).
+    /// Can be set for a single location with its `case_collisions` property.
+    pub case_collisions: Option<CaseCollisions>,
+
+    /// What to do with the symlinks of tar archives (`recreate_if_safe`, `copy`, `skip`).<br>
+    /// Defaults to `recreate_if_safe`; a symlink pointing outside of the archive fails the extraction unless
+    /// it is skipped. Can be set for a single location with its `tar_symlinks` property.
+    pub tar_symlinks: Option<TarSymlinks>,
+
+    /// Maximum time (in seconds) spent waiting when the github API answers that a rate limit is exceeded.<br>
+    /// Defaults to 60; `0` fails immediately (with the time when the API can be used again).
+    pub github_rate_limit_max_wait: Option<u64>,
+
+    /// Maximum time (in seconds) spent waiting when a download server answers `429 Too Many Requests`.<br>
+    /// Defaults to 120; `0` fails immediately.
+    pub download_rate_limit_max_wait: Option<u64>,
+
+    /// Maximum total download speed (in bytes per second), shared by all the downloads that run at the same time.<br>
+    /// Absent or `0`: unlimited.
+    pub max_bytes_per_sec: Option<u64>,
+
+    /// Removes a downloaded archive from the cache as soon as its content was extracted to the game directory
+    /// (archives whose extraction failed are kept).<br>
+    /// The archives are downloaded again for each installation. Defaults to `false`.
+    pub delete_archive_after_extract: Option<bool>,
+
+    /// What is checked before an archive found in the cache is reused: `name`, `size` (default) or `checksum`.<br>
+    /// The checks need the size and checksum recorded when the archive was downloaded, older archives are reused by name.
+    pub cache_validation: Option<CacheValidation>,
+
+    /// Number of hash-prefix directory levels (two hexadecimal characters each) inserted between the source
+    /// subdirectory of the cache (`http/<host>`...) and the archives, to avoid huge flat directories.<br>
+    /// Defaults to 0 (no sharding); archives already in the unsharded location are still used.
+    pub cache_shard_depth: Option<u8>,
+
+    /// URL of the GitHub Enterprise server used by the github locations without their own `base_url`.<br>
+    /// github.com if absent.
+    pub github_base_url: Option<String>,
+
+    /// `fail_fast` (default) stops at the first error found in the manifest, `aggregate` reads each module
+    /// on its own and reports all the errors.
+    pub manifest_errors: Option<ManifestErrors>,
+
+    /// When weidu fails a module, how many times the components that failed (and only them) are installed again.<br>
+    /// Defaults to 0 (no retry).
+    pub component_retries: Option<u32>,
+
+    /// If true, zip and tar.gz archives of `http` locations are extracted while they are downloaded,
+    /// without writing them to the cache (other archives and sources still go through the cache).
+    pub stream_extract: Option<bool>,
+
+    /// If true, each mod is extracted in a directory named after it (`modda-extract-<mod>` in `extract_location`,
+    /// or in the system temporary directory) instead of a random one; a directory left by a previous run is removed
+    /// first, and the directory is kept after the extraction so that it can be inspected (for debugging).
+    pub debug_extract_dirs: Option<bool>,
+
+    /// Proxy used for the downloads (`http://proxy.example:3128`), or `none` to ignore the proxies
+    /// of the environment; when not set, the `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY` environment variables are used.<br>
+    /// `http` locations can override it with their own `proxy` or `no_proxy: true`.
+    pub proxy: Option<String>,
+
+    /// Rules applied, in order, to the URL of each download just before it is sent (to go through an internal mirror
+    /// for example); github API calls are not rewritten.
+    /// ```yaml
+    /// url_rewrites:
+    ///   - pattern: ^https://github\.com/
+    ///     replacement: https://ghproxy.internal/github.com/
+    /// ```
+    pub url_rewrites: Option<Vec<UrlRewrite>>,
+
+    /// Program used instead of the built-in client for the downloads of archives (GET requests without extra headers,
+    /// so not the `http` locations with a `form`/`body` or the authenticated github downloads).
+    /// ```yaml
+    /// external_downloader:
+    ///     command: aria2c
+    ///     args: [ "-x", "8", "-d", "${output_dir}", "-o", "${output_name}", "${url}" ]
+    /// ```
+    pub external_downloader: Option<ExternalDownloader>,
+}
+
+/// In `args`, `${url}` is replaced by the URL (after the `url_rewrites`), `${output}` by the path of the file to
+/// create, `${output_dir}` and `${output_name}` by its directory and file name.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub struct ExternalDownloader {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+/// A `url_rewrites` rule: `pattern` is a regular expression, `replacement` can use its groups (`$1`, `${name}`).
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct UrlRewrite {
+    pub pattern: String,
+    pub replacement: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct ExtractorCommand {
     pub command: String,
     pub args: Vec<String>,
+    /// Arguments used to list the archive content (one path per output line), `${input}` is
+    /// replaced by the archive path.<br>
+    /// If absent, the content of this type of archive can't be listed.
+    #[serde(default)]
+    pub list_args: Option<Vec<String>>,
 }
 
 pub fn global_conf_dir() -> Option<PathBuf> {
@@ -193,6 +331,13 @@ impl Settings {
             Result::Ok(s) if s == "false" => Some(false),
             _ => bail!("Incorrect value for {IGNORE_CURRENT_DIR_WEIDU_ENV_VAR} env var")
         };
+        let jobs = match std::env::var(JOBS_ENV_VAR) {
+            Err(_) => None,
+            Result::Ok(s) => match s.parse::<usize>() {
+                Result::Ok(value) => Some(value),
+                Err(_) => bail!("Incorrect value for {JOBS_ENV_VAR} env var"),
+            }
+        };
         Ok(ConfigSource {
             id: "environment".to_string(),
             config: Some(Config {
@@ -200,9 +345,31 @@ impl Settings {
                 extract_location: std::env::var(EXTRACT_LOCATION_ENV_VAR).ok(),
                 weidu_path: std::env::var(WEIDU_PATH_ENV_VAR).ok(),
                 ignore_current_dir_weidu,
+                weidu_version: None,
                 // Setting extractor not supported for now
                 extractors: HashMap::new(),
                 code_editor: std::env::var(CODE_EDITOR_ENV_VAR).ok(),
+                jobs,
+                normalize_permissions: None,
+                glob_case_sensitive: None,
+                glob_include_hidden: None,
+                archive_name_encoding: None,
+                case_collisions: None,
+                tar_symlinks: None,
+                github_rate_limit_max_wait: None,
+                download_rate_limit_max_wait: None,
+                max_bytes_per_sec: None,
+                delete_archive_after_extract: None,
+                cache_validation: None,
+                cache_shard_depth: None,
+                github_base_url: None,
+                manifest_errors: None,
+                component_retries: None,
+                stream_extract: None,
+                debug_extract_dirs: None,
+                proxy: None,
+                url_rewrites: None,
+                external_downloader: None,
             })
         })
     }
@@ -217,9 +384,31 @@ fn combine(global: Option<Config>, local: Option<Config>, env_config: Option<Con
         archive_cache: env_config.archive_cache.or(local.archive_cache).or(global.archive_cache),
         extract_location: env_config.extract_location.or(local.extract_location).or(global.extract_location),
         weidu_path: env_config.weidu_path.or(local.weidu_path).or(global.weidu_path),
+        weidu_version: env_config.weidu_version.or(local.weidu_version).or(global.weidu_version),
         ignore_current_dir_weidu: env_config.ignore_current_dir_weidu.or(local.ignore_current_dir_weidu).or(global.ignore_current_dir_weidu),
         extractors: merge_maps(&global.extractors, &local.extractors, &env_config.extractors),
         code_editor: env_config.code_editor.or(local.code_editor).or(global.code_editor),
+        jobs: env_config.jobs.or(local.jobs).or(global.jobs),
+        normalize_permissions: env_config.normalize_permissions.or(local.normalize_permissions).or(global.normalize_permissions),
+        glob_case_sensitive: env_config.glob_case_sensitive.or(local.glob_case_sensitive).or(global.glob_case_sensitive),
+        glob_include_hidden: env_config.glob_include_hidden.or(local.glob_include_hidden).or(global.glob_include_hidden),
+        archive_name_encoding: env_config.archive_name_encoding.or(local.archive_name_encoding).or(global.archive_name_encoding),
+        case_collisions: env_config.case_collisions.or(local.case_collisions).or(global.case_collisions),
+        tar_symlinks: env_config.tar_symlinks.or(local.tar_symlinks).or(global.tar_symlinks),
+        github_rate_limit_max_wait: env_config.github_rate_limit_max_wait.or(local.github_rate_limit_max_wait).or(global.github_rate_limit_max_wait),
+        download_rate_limit_max_wait: env_config.download_rate_limit_max_wait.or(local.download_rate_limit_max_wait).or(global.download_rate_limit_max_wait),
+        max_bytes_per_sec: env_config.max_bytes_per_sec.or(local.max_bytes_per_sec).or(global.max_bytes_per_sec),
+        delete_archive_after_extract: env_config.delete_archive_after_extract.or(local.delete_archive_after_extract).or(global.delete_archive_after_extract),
+        cache_validation: env_config.cache_validation.or(local.cache_validation).or(global.cache_validation),
+        cache_shard_depth: env_config.cache_shard_depth.or(local.cache_shard_depth).or(global.cache_shard_depth),
+        github_base_url: env_config.github_base_url.or(local.github_base_url).or(global.github_base_url),
+        manifest_errors: env_config.manifest_errors.or(local.manifest_errors).or(global.manifest_errors),
+        component_retries: env_config.component_retries.or(local.component_retries).or(global.component_retries),
+        stream_extract: env_config.stream_extract.or(local.stream_extract).or(global.stream_extract),
+        debug_extract_dirs: env_config.debug_extract_dirs.or(local.debug_extract_dirs).or(global.debug_extract_dirs),
+        proxy: env_config.proxy.or(local.proxy).or(global.proxy),
+        url_rewrites: env_config.url_rewrites.or(local.url_rewrites).or(global.url_rewrites),
+        external_downloader: env_config.external_downloader.or(local.external_downloader).or(global.external_downloader),
     }
 }
 
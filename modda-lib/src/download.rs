@@ -1,37 +1,220 @@
 
+use std::borrow::Cow;
 use std::cmp::min;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Write, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{bail, Result};
 use filetime::FileTime;
+use flate2::write::{GzDecoder, ZlibDecoder};
 use futures_util::stream::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle, ProgressState};
-use log::{debug, info};
-use reqwest::header::{HeaderMap, USER_AGENT};
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_RANGE, RANGE, RETRY_AFTER, TRANSFER_ENCODING, USER_AGENT};
+use regex::Regex;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
 
+use crate::bandwidth::BandwidthLimiter;
+use crate::cache::file_sha256;
+use crate::config::{ExternalDownloader, UrlRewrite};
 use crate::module::refresh::RefreshCondition;
 use crate::progname::PROGNAME;
 
 
 #[cfg_attr(test, faux::create)]
-pub struct Downloader {}
+pub struct Downloader {
+    max_rate_limit_wait: Duration,
+    /// Shared by all downloads, `None` when unlimited.
+    bandwidth: Option<Arc<BandwidthLimiter>>,
+    proxy: ProxySetting,
+    url_rewrites: Arc<UrlRewrites>,
+    external_downloader: Option<Arc<ExternalDownloader>>,
+}
+
+/// How the downloads reach the servers.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ProxySetting {
+    /// Proxies from the environment (`HTTP_PROXY`, `HTTPS_PROXY`, `NO_PROXY`...).
+    #[default]
+    System,
+    /// Direct connection, even if the environment has proxies.
+    Direct,
+    /// Everything goes through this proxy (`http://proxy.example:3128`).
+    Url(String),
+}
+
+impl ProxySetting {
+    /// The `proxy` config value: a proxy URL, or `none` for a direct connection; the system proxies when absent.
+    pub fn from_config(proxy: &Option<String>) -> Self {
+        match proxy.as_deref().map(str::trim) {
+            None | Some("") => ProxySetting::System,
+            Some(value) if value.eq_ignore_ascii_case("none") => ProxySetting::Direct,
+            Some(url) => ProxySetting::Url(url.to_string()),
+        }
+    }
+}
+
+/// The `url_rewrites` of the config, ready to be applied.
+#[derive(Debug, Clone, Default)]
+pub struct UrlRewrites {
+    rules: Vec<(Regex, String)>,
+}
+
+impl UrlRewrites {
+    pub fn new(rules: &[UrlRewrite]) -> Result<Self> {
+        let mut compiled = vec![];
+        for rule in rules {
+            match Regex::new(&rule.pattern) {
+                Err(error) => bail!("Invalid url_rewrites pattern {}\n -> {:?}", rule.pattern, error),
+                Ok(pattern) => compiled.push((pattern, rule.replacement.clone())),
+            }
+        }
+        Ok(UrlRewrites { rules: compiled })
+    }
+
+    /// Applies all the rules in order, each one to the result of the previous ones.
+    pub fn apply<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        let mut result = Cow::Borrowed(url);
+        for (pattern, replacement) in &self.rules {
+            let rewritten = match pattern.replace(&result, replacement.as_str()) {
+                Cow::Borrowed(_) => continue,
+                Cow::Owned(rewritten) => rewritten,
+            };
+            result = Cow::Owned(rewritten);
+        }
+        if result != url {
+            debug!("url {} rewritten to {}", url, result);
+        }
+        result
+    }
+}
+
+/// Used when `download_rate_limit_max_wait` is not set in the config.
+pub const DEFAULT_DOWNLOAD_RATE_LIMIT_WAIT: Duration = Duration::from_secs(120);
+
+/// Wait when a server answers `429 Too Many Requests` without telling how long.
+const TOO_MANY_REQUESTS_DEFAULT_WAIT: Duration = Duration::from_secs(30);
+
+/// Result of `Downloader::download`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadedFile {
+    pub path: PathBuf,
+    /// SHA-256 of the content (lowercase hex), computed while the file was written.<br>
+    /// `None` when the file was reused from the cache (not downloaded).
+    pub sha256: Option<String>,
+}
 
 #[derive(Debug, Clone)]
 pub struct DownloadOpts {
     pub no_cache: bool,
     pub refresh: RefreshCondition,
+    pub request: DownloadRequest,
+}
+
+/// The request sent to obtain the file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum DownloadRequest {
+    #[default]
+    Get,
+    /// Some download portals only give the file in answer to a POST with some form fields.
+    Post(PostBody),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostBody {
+    /// Sent url-encoded (`application/x-www-form-urlencoded`).
+    Form(BTreeMap<String, String>),
+    /// Sent as it is.
+    Raw(String),
+}
+
+/// Part of a file asked with a `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// The last bytes of the file.
+    Suffix(u64),
+    /// From `start` to `end` (included).
+    Span { start: u64, end: u64 },
+}
+
+impl ByteRange {
+    fn header_value(&self) -> String {
+        match self {
+            ByteRange::Suffix(length) => format!("bytes=-{}", length),
+            ByteRange::Span { start, end } => format!("bytes={}-{}", start, end),
+        }
+    }
+}
+
+/// Result of `Downloader::download_range`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangedContent {
+    /// Position of the first byte of `content` in the file.
+    pub offset: u64,
+    pub content: Vec<u8>,
+    /// Size of the whole file, when the server told it.
+    pub total_size: Option<u64>,
+    /// `false` when the server ignored the range and sent the whole file.
+    pub partial: bool,
 }
 
 #[cfg_attr(test, faux::methods)]
 impl Downloader {
     pub fn new() -> Self {
-        Downloader {}
+        Downloader {
+            max_rate_limit_wait: DEFAULT_DOWNLOAD_RATE_LIMIT_WAIT,
+            bandwidth: None,
+            proxy: ProxySetting::System,
+            url_rewrites: Arc::new(UrlRewrites::default()),
+            external_downloader: None,
+        }
+    }
+
+    pub fn with_proxy(self, proxy: ProxySetting) -> Self {
+        Self { proxy, ..self }
+    }
+
+    pub fn with_url_rewrites(self, url_rewrites: UrlRewrites) -> Self {
+        Self { url_rewrites: Arc::new(url_rewrites), ..self }
+    }
+
+    /// Program used by `download` for plain GET requests (see `Config::external_downloader`).
+    pub fn with_external_downloader(self, external_downloader: Option<ExternalDownloader>) -> Self {
+        Self { external_downloader: external_downloader.map(Arc::new), ..self }
+    }
+
+    /// A downloader for a source with its own proxy setting (same rate limit wait, shares the bandwidth limit).
+    pub fn for_proxy(&self, proxy: ProxySetting) -> Self {
+        Downloader {
+            max_rate_limit_wait: self.max_rate_limit_wait,
+            bandwidth: self.bandwidth.clone(),
+            proxy,
+            url_rewrites: self.url_rewrites.clone(),
+            external_downloader: self.external_downloader.clone(),
+        }
+    }
+
+    pub fn proxy(&self) -> &ProxySetting {
+        &self.proxy
+    }
+
+    /// Total time a download can spend waiting for a server that answers `429 Too Many Requests`.
+    pub fn with_max_rate_limit_wait(self, max_rate_limit_wait: Duration) -> Self {
+        Self { max_rate_limit_wait, ..self }
+    }
+
+    /// Limits the total throughput of all downloads (`None` or `0`: unlimited).
+    pub fn with_max_bytes_per_sec(self, max_bytes_per_sec: Option<u64>) -> Self {
+        Self { bandwidth: max_bytes_per_sec.and_then(BandwidthLimiter::new).map(Arc::new), ..self }
     }
 
     pub async fn download(&self, url: &str, dest_dir: &PathBuf, file_name: PathBuf, opts: &DownloadOpts,
-                            headers: &Option<HeaderMap>) -> Result<PathBuf> {
+                            headers: &Option<HeaderMap>) -> Result<DownloadedFile> {
         info!("obtaining {:?}, url is {} (cache={:?})", file_name, url, dest_dir);
 
         // check if archive exists in the cache
@@ -39,50 +222,144 @@ impl Downloader {
 
         if use_from_cache(opts, &file_name)? {
             info!("File already downloaded before, reusing");
-            return Ok(file_name.to_owned());
+            return Ok(DownloadedFile { path: file_name.to_owned(), sha256: None });
         }
 
         debug!("will be located under: '{:?}'", file_name);
 
         let partial_name = get_partial_filename(&file_name)?;
 
-        if let Err(error) = self.download_partial(url, &partial_name, &dest_dir, headers).await {
-            bail!("download_partial failed for {} to {:?}\n  {}", url, partial_name, error);
+        let sha256 = match (&self.external_downloader, &opts.request, headers) {
+            (Some(external), DownloadRequest::Get, None) =>
+                match self.download_external(external, url, &partial_name).await {
+                    Err(error) => bail!("external download failed for {} to {:?}\n  {}", url, partial_name, error),
+                    Ok(sha256) => sha256,
+                },
+            _ => match self.download_partial(url, &opts.request, &partial_name, &dest_dir, headers).await {
+                Err(error) => bail!("download_partial failed for {} to {:?}\n  {}", url, partial_name, error),
+                Ok(sha256) => sha256,
+            },
         };
+        debug!("sha256 of {:?} is {}", file_name, sha256);
 
         if opts.no_cache {
-            Ok(partial_name)
+            Ok(DownloadedFile { path: partial_name, sha256: Some(sha256) })
         } else {
             if let Err(error) = self.rename_partial(&partial_name, &file_name) {
                 bail!("rename_partial failed for {:?} to {:?}\n  {}", partial_name, file_name, error);
             };
-            Ok(file_name)
+            Ok(DownloadedFile { path: file_name, sha256: Some(sha256) })
         }
     }
 
-    pub async fn download_partial(&self, url: &str, partial_name: &PathBuf, dest_dir: &PathBuf,
-                                    headers: &Option<HeaderMap>)  -> Result<()> {
+    /// Downloads to `partial_name` and returns the SHA-256 (hex) of the content, computed
+    /// on the fly (no need to read the file again to check it).
+    pub async fn download_partial(&self, url: &str, request: &DownloadRequest, partial_name: &PathBuf, dest_dir: &PathBuf,
+                                    headers: &Option<HeaderMap>)  -> Result<String> {
         info!("download {} to {:?}", url, dest_dir);
-        std::fs::create_dir_all(dest_dir)?;
-
-        let client = reqwest::Client::new();
+        // the file name may contain sub-directories (`rename` property)
+        std::fs::create_dir_all(partial_name.parent().unwrap_or(dest_dir))?;
 
         let mut partial_file = match File::create(&partial_name) {
             Err(error) => bail!("failed to create file {:?}\n -> {:?}", partial_name, error),
             Ok(file) => file,
         };
-        let mut request = client.get(url)
-            .header(USER_AGENT, PROGNAME);
+        self.download_to_writer(url, request, &mut partial_file, headers).await
+    }
 
-        if let Some(headers) = headers {
-            request = request.headers(headers.to_owned());
+    /// Runs the external downloader to create `partial_name`, returns the SHA-256 (hex) of the file.<br>
+    /// The proxy setting and the bandwidth limit are not applied, the program has its own options for that.
+    async fn download_external(&self, external: &ExternalDownloader, url: &str, partial_name: &Path) -> Result<String> {
+        let url = self.url_rewrites.apply(url);
+        let (output, output_dir, output_name) = match (partial_name.to_str(), partial_name.parent().and_then(Path::to_str),
+                                                        partial_name.file_name().and_then(|name| name.to_str())) {
+            (Some(output), Some(output_dir), Some(output_name)) => (output, output_dir, output_name),
+            _ => bail!("Invalid download path {:?} for the external downloader", partial_name),
+        };
+        std::fs::create_dir_all(output_dir)?;
+        // a leftover from an interrupted download would make some programs resume or rename the output
+        if partial_name.exists() {
+            std::fs::remove_file(partial_name)?;
+        }
+        let args = external.args.iter().map(|arg| arg
+            .replace("${url}", &url)
+            .replace("${output_dir}", output_dir)
+            .replace("${output_name}", output_name)
+            .replace("${output}", output)
+        ).collect::<Vec<_>>();
+        info!("download {} with {} {:?}", url, external.command, args);
+        let status = match tokio::process::Command::new(&external.command).args(&args).status().await {
+            Err(error) => bail!("Could not run external downloader {}\n -> {:?}", external.command, error),
+            Ok(status) => status,
+        };
+        if !status.success() {
+            bail!("External downloader {} failed for {} with status {:?}\n -> args: {:?}",
+                    external.command, url, status.code(), args);
+        }
+        if !partial_name.is_file() {
+            bail!("External downloader {} did not create {:?}\n -> check that its args use ${{output}} or ${{output_dir}}/${{output_name}}",
+                    external.command, partial_name);
         }
+        file_sha256(partial_name)
+    }
+
+    /// Downloads the whole content in memory (for small files).
+    pub async fn download_to_memory(&self, url: &str, headers: &Option<HeaderMap>) -> Result<Vec<u8>> {
+        let mut content = vec![];
+        self.download_to_writer(url, &DownloadRequest::Get, &mut content, headers).await?;
+        Ok(content)
+    }
 
-        let response = match request.send().await {
+    /// Downloads only a part of the file (in memory).<br>
+    /// Servers that don't support ranges answer with the whole file, which is returned as it is
+    /// (with `partial` set to `false`).
+    pub async fn download_range(&self, url: &str, range: ByteRange, headers: &Option<HeaderMap>) -> Result<RangedContent> {
+        let mut range_headers = headers.clone().unwrap_or_default();
+        range_headers.insert(RANGE, HeaderValue::from_str(&range.header_value())?);
+        let client = self.client()?;
+        let response = self.send_with_rate_limit(&client, url, &DownloadRequest::Get, &Some(range_headers)).await?;
+        let response = match response.error_for_status() {
+            Err(ref error) => bail!("Could not download {:?} of {}\n -> {}", range, url, error),
             Ok(response) => response,
-            Err(error) => bail!("HTTP download failed\n -> {:?}", error),
         };
+        let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+        let (offset, total_size) = match (partial, response.headers().get(CONTENT_RANGE)) {
+            (false, _) => (0, response.content_length()),
+            (true, None) => bail!("Partial response without Content-Range for {}", url),
+            (true, Some(value)) => match parse_content_range(value.to_str().unwrap_or_default()) {
+                None => bail!("Invalid Content-Range for {}\n -> {:?}", url, value),
+                Some(parsed) => parsed,
+            },
+        };
+        if !partial {
+            info!("{} doesn't support ranges, the whole file was downloaded", url);
+        }
+        let content = match response.bytes().await {
+            Err(error) => bail!("Error while downloading {:?} of {}\n -> {:?}", range, url, error),
+            Ok(content) => content.to_vec(),
+        };
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.consume(content.len()).await;
+        }
+        debug!("{} bytes received from {} at offset {}", content.len(), url, offset);
+        Ok(RangedContent { offset, content, total_size, partial })
+    }
+
+    /// Writes the response body to `writer` as it is received and returns its SHA-256 (hex).<br>
+    /// Storage-agnostic: `download_partial` uses it with a file, it can also feed a decompressor.<br>
+    /// A body sent with a gzip or deflate content (or transfer) encoding is decoded before being written;
+    /// the SHA-256 is the one of the written bytes, which differ from what was received (and from `Content-Length`).
+    pub async fn download_to_writer(&self, url: &str, request: &DownloadRequest, writer: &mut dyn Write,
+                                    headers: &Option<HeaderMap>) -> Result<String> {
+        let client = self.client()?;
+        let response = self.send_with_rate_limit(&client, url, request, headers).await?;
+        // this is the size on the wire, used for the progress only
         let total_size = response.content_length();
+        let encoding = match body_encoding(response.headers(), url) {
+            Err(error) => bail!("Can't download {}\n -> {}", url, error),
+            Ok(encoding) => encoding,
+        };
+        debug!("body encoding for {} is {:?}", url, encoding);
 
 
         // Indicatif setup
@@ -124,14 +401,19 @@ impl Downloader {
 
         let mut stream = response.bytes_stream();
         let mut downloaded: u64 = 0;
+        let mut stored = StoredContent { writer, hasher: Sha256::new(), size: 0 };
+        let mut decoder = BodyDecoder::new(encoding, &mut stored);
 
         while let Some(item) = stream.next().await {
             let chunk = match item {
                 Err(error) => bail!("Error while downloading file\n -> {:?}", error),
                 Ok(chunk) => chunk,
             };
-            if let Err(error) = partial_file.write(&chunk) {
-                bail!("Error while writing to file\n ->{:?}", error);
+            if let Some(bandwidth) = &self.bandwidth {
+                bandwidth.consume(chunk.len()).await;
+            }
+            if let Err(error) = decoder.write_all(&chunk) {
+                bail!("Error while writing downloaded content\n ->{:?}", error);
             }
             if let Some(total_size) = total_size {
                 let new = min(downloaded + (chunk.len() as u64), total_size);
@@ -143,8 +425,70 @@ impl Downloader {
                 pb.set_position(new);
             }
         }
+        if let Err(error) = decoder.finish().and_then(|_| stored.writer.flush()) {
+            bail!("Error while writing downloaded content\n ->{:?}", error);
+        }
         pb.finish_with_message(format!("Download from {} finished", url));
-        Ok(())
+        debug!("{} bytes received, {} bytes written for {}", downloaded, stored.size, url);
+        Ok(format!("{:x}", stored.hasher.finalize()))
+    }
+
+    /// A client that uses the proxy setting of this downloader.
+    fn client(&self) -> Result<reqwest::Client> {
+        let builder = reqwest::Client::builder();
+        let builder = match &self.proxy {
+            ProxySetting::System => builder,
+            ProxySetting::Direct => builder.no_proxy(),
+            ProxySetting::Url(url) => match reqwest::Proxy::all(url) {
+                Err(error) => bail!("Invalid proxy {}\n -> {:?}", url, error),
+                Ok(proxy) => builder.proxy(proxy),
+            },
+        };
+        match builder.build() {
+            Err(error) => bail!("Could not create the HTTP client\n -> {:?}", error),
+            Ok(client) => Ok(client),
+        }
+    }
+
+    /// Sends the request, waiting and trying again while the server answers `429 Too Many Requests`
+    /// (for the delay given by `Retry-After`), as long as the total wait stays under `max_rate_limit_wait`.<br>
+    /// The request goes to `url` after the `url_rewrites`.
+    async fn send_with_rate_limit(&self, client: &reqwest::Client, url: &str, download_request: &DownloadRequest,
+                                    headers: &Option<HeaderMap>) -> Result<reqwest::Response> {
+        let url = self.url_rewrites.apply(url);
+        let url = url.as_ref();
+        let mut waited = Duration::ZERO;
+        loop {
+            let mut request = match download_request {
+                DownloadRequest::Get => client.get(url),
+                DownloadRequest::Post(PostBody::Form(fields)) => client.post(url).form(fields),
+                DownloadRequest::Post(PostBody::Raw(body)) => client.post(url).body(body.to_owned()),
+            };
+            request = request.header(USER_AGENT, PROGNAME);
+
+            if let Some(headers) = headers {
+                request = request.headers(headers.to_owned());
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(error) => bail!("HTTP download failed\n -> {:?}", error),
+            };
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(response);
+            }
+            let wait = retry_after(response.headers(), SystemTime::now()).unwrap_or(TOO_MANY_REQUESTS_DEFAULT_WAIT);
+            if waited + wait > self.max_rate_limit_wait {
+                bail!("The server is rate-limiting downloads from {url} (HTTP 429 Too Many Requests)\n \
+                        -> it asks to wait {seconds}s, more than `download_rate_limit_max_wait` allows (currently {max}s)\n \
+                        -> reducing `jobs` (fewer downloads at the same time) should help",
+                        seconds = wait.as_secs(), max = self.max_rate_limit_wait.as_secs());
+            }
+            warn!("Too many requests to {}, waiting {}s before trying again", url, wait.as_secs());
+            tokio::time::sleep(wait).await;
+            // never loop without waiting at all
+            waited += wait.max(Duration::from_secs(1));
+        }
     }
 
     pub fn rename_partial(&self, partial_file_name: &PathBuf, final_file_name: &PathBuf) -> Result<()> {
@@ -161,6 +505,13 @@ impl Downloader {
     }
 }
 
+/// Start offset and total size (if known) from `bytes <start>-<end>/<total or *>`.
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
 fn get_partial_filename(file_name: &PathBuf) -> Result<PathBuf> {
     let extension = match file_name.extension() {
         None => bail!("file to download {:?} has no extension", file_name),
@@ -174,6 +525,102 @@ fn get_partial_filename(file_name: &PathBuf) -> Result<PathBuf> {
     Ok(PathBuf::from(partial_name))
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BodyEncoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+/// How the body must be decoded, from the `Content-Encoding` and `Transfer-Encoding` headers
+/// (`chunked` is already handled by the HTTP client).<br>
+/// Like browsers, a gzip-encoded `.gz`/`.tgz` file is kept as it is: many servers announce the encoding
+/// of gzipped archives this way, the file is what was wanted.
+fn body_encoding(headers: &HeaderMap, url: &str) -> Result<BodyEncoding> {
+    let codings = headers.get_all(CONTENT_ENCODING).iter()
+        .chain(headers.get_all(TRANSFER_ENCODING).iter())
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|coding| coding.trim().to_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity" && coding != "chunked")
+        .collect::<Vec<_>>();
+    let encoding = match codings.as_slice() {
+        [] => BodyEncoding::Identity,
+        [coding] if coding == "gzip" || coding == "x-gzip" => BodyEncoding::Gzip,
+        [coding] if coding == "deflate" => BodyEncoding::Deflate,
+        _ => bail!("unsupported content encoding {:?}", codings),
+    };
+    let path = url::Url::parse(url).map(|url| url.path().to_lowercase()).unwrap_or_default();
+    if encoding == BodyEncoding::Gzip && (path.ends_with(".gz") || path.ends_with(".tgz")) {
+        return Ok(BodyEncoding::Identity);
+    }
+    Ok(encoding)
+}
+
+/// What is actually written (after decoding): hashed and counted on the way.
+struct StoredContent<'a> {
+    writer: &'a mut dyn Write,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl Write for StoredContent<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+enum BodyDecoder<W: Write> {
+    Identity(W),
+    Gzip(GzDecoder<W>),
+    Deflate(ZlibDecoder<W>),
+}
+
+impl <W: Write> BodyDecoder<W> {
+    fn new(encoding: BodyEncoding, writer: W) -> Self {
+        match encoding {
+            BodyEncoding::Identity => BodyDecoder::Identity(writer),
+            BodyEncoding::Gzip => BodyDecoder::Gzip(GzDecoder::new(writer)),
+            BodyEncoding::Deflate => BodyDecoder::Deflate(ZlibDecoder::new(writer)),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            BodyDecoder::Identity(writer) => writer.write_all(buf),
+            BodyDecoder::Gzip(decoder) => decoder.write_all(buf),
+            BodyDecoder::Deflate(decoder) => decoder.write_all(buf),
+        }
+    }
+
+    /// Writes what remains (and checks the gzip trailer).
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            BodyDecoder::Identity(_) => Ok(()),
+            BodyDecoder::Gzip(decoder) => decoder.finish().map(|_| ()),
+            BodyDecoder::Deflate(decoder) => decoder.finish().map(|_| ()),
+        }
+    }
+}
+
+/// Value of the `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let now = now.duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0);
+    Some(Duration::from_secs(u64::try_from(date.timestamp()).unwrap_or(0).saturating_sub(now)))
+}
+
 fn use_from_cache(opts: &DownloadOpts, file_name: &PathBuf) -> Result<bool> {
     match opts.refresh {
         RefreshCondition::Always => Ok(false),
@@ -207,16 +654,20 @@ fn use_from_cache(opts: &DownloadOpts, file_name: &PathBuf) -> Result<bool> {
 #[cfg(test)]
 mod test_cache_duration {
     use std::fs::OpenOptions;
+    use std::borrow::Cow;
     use std::path::PathBuf;
-    use std::time::SystemTime;
+    use std::time::{Duration, SystemTime};
 
     use anyhow::{Result, Context};
     use filetime::FileTime;
     use function_name::named;
     use log::{warn, info};
     use crate::module::refresh::RefreshCondition;
+    use crate::test_utils::test_dir;
 
-    use super::DownloadOpts;
+    use super::{DownloadOpts, DownloadRequest, Downloader, PostBody, UrlRewrites};
+    use crate::config::{ExternalDownloader, UrlRewrite};
+    use crate::test_utils::server::{not_found, ok, response, TestServer};
 
     struct Cleanup(String);
     impl Drop for Cleanup {
@@ -237,7 +688,8 @@ mod test_cache_duration {
     fn cached_file_is_expired() -> Result<()> {
         let _cleanup = Cleanup(function_name!().to_string());
 
-        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Duration(humantime::parse_duration("1day")?) };
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Duration(humantime::parse_duration("1day")?),
+                                    request: DownloadRequest::Get };
 
         let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let file_loc = project.join("target").join("test_data").join("caching");
@@ -258,7 +710,8 @@ mod test_cache_duration {
     fn cached_file_is_not_expired() -> Result<()> {
         let _cleanup = Cleanup(function_name!().to_string());
 
-        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Duration(humantime::parse_duration("1day")?) };
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Duration(humantime::parse_duration("1day")?),
+                                    request: DownloadRequest::Get };
 
         let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let file_loc = project.join("target").join("test_data").join("caching");
@@ -279,7 +732,7 @@ mod test_cache_duration {
     fn cached_file_is_always_refreshed() -> Result<()> {
         let _cleanup = Cleanup(function_name!().to_string());
 
-        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always };
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
 
         let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let file_loc = project.join("target").join("test_data").join("caching");
@@ -300,7 +753,7 @@ mod test_cache_duration {
     fn cached_file_is_never_refreshed() -> Result<()> {
         let _cleanup = Cleanup(function_name!().to_string());
 
-        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never };
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never, request: DownloadRequest::Get };
 
         let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         let file_loc = project.join("target").join("test_data").join("caching");
@@ -315,4 +768,325 @@ mod test_cache_duration {
         assert_eq!(super::use_from_cache(&opts, &file_path)?, true);
         Ok(())
     }
+
+    fn too_many_requests(retry_after: &str) -> Vec<u8> {
+        response("429 Too Many Requests", &[("Retry-After", retry_after)], b"")
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_waits_for_retry_after() {
+        let server = TestServer::scripted(vec![too_many_requests("1"), ok(b"content")]);
+        let url = server.url("/some_mod.zip");
+        let dest = test_dir();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+
+        let start = std::time::Instant::now();
+        let downloaded = Downloader::new()
+            .with_max_rate_limit_wait(Duration::from_secs(5))
+            .download(&url, &dest.path().to_path_buf(), PathBuf::from("some_mod.zip"), &opts, &None).await
+            .unwrap();
+
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(std::fs::read_to_string(&downloaded.path).unwrap(), "content");
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_fails_when_wait_is_too_long() {
+        let server = TestServer::scripted(vec![too_many_requests("3600")]);
+        let url = server.url("/some_mod.zip");
+        let dest = test_dir();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+
+        let start = std::time::Instant::now();
+        let error = Downloader::new()
+            .with_max_rate_limit_wait(Duration::from_secs(5))
+            .download(&url, &dest.path().to_path_buf(), PathBuf::from("some_mod.zip"), &opts, &None).await
+            .unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let message = error.to_string();
+        assert!(message.contains("rate-limiting"), "{}", message);
+        assert!(message.contains("wait 3600s"), "{}", message);
+        assert!(message.contains("reducing `jobs`"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn download_to_memory_writer() {
+        use sha2::{Digest, Sha256};
+
+        let body: Vec<u8> = (0..50_000u32).map(|value| (value % 13) as u8).collect();
+        let server = TestServer::scripted(vec![ok(&body)]);
+        let url = server.url("/some_mod.zip");
+
+        let mut written: Vec<u8> = vec![];
+        let sha256 = Downloader::new().download_to_writer(&url, &DownloadRequest::Get, &mut written, &None).await.unwrap();
+
+        assert_eq!(written, body);
+        assert_eq!(sha256, format!("{:x}", Sha256::digest(&body)));
+    }
+
+    fn rewrite(pattern: &str, replacement: &str) -> UrlRewrite {
+        UrlRewrite { pattern: pattern.to_string(), replacement: replacement.to_string() }
+    }
+
+    #[test]
+    fn rewrite_github_urls() {
+        let rewrites = UrlRewrites::new(&[
+            rewrite(r"^https://github\.com/(?P<user>[^/]+)/", "https://ghproxy.internal/github/${user}/"),
+            rewrite(r"^http://", "https://"),
+        ]).unwrap();
+
+        assert_eq!(rewrites.apply("https://github.com/Gibberlings3/iwdification/releases/download/v4.1/iwdification.zip"),
+                    "https://ghproxy.internal/github/Gibberlings3/iwdification/releases/download/v4.1/iwdification.zip");
+        assert_eq!(rewrites.apply("http://github.com/Gibberlings3/iwdification/archive/v4.1.zip"),
+                    "https://github.com/Gibberlings3/iwdification/archive/v4.1.zip");
+        assert!(matches!(rewrites.apply("https://example.com/github.com/my_mod.zip"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn invalid_rewrite_pattern() {
+        let error = UrlRewrites::new(&[rewrite("^https://(github.com/", "")]).unwrap_err();
+
+        assert!(error.to_string().contains("^https://(github.com/"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn download_from_rewritten_url() {
+        let server = TestServer::scripted(vec![ok(b"mirrored content")]);
+        let mirror = format!("{}/", server.address());
+        let rewrites = UrlRewrites::new(&[rewrite(r"^https://github\.com/", &mirror)]).unwrap();
+
+        let content = Downloader::new().with_url_rewrites(rewrites)
+            .download_to_memory("https://github.com/some_user/some_mod/releases/download/v1/some_mod.zip", &None).await.unwrap();
+
+        assert_eq!(content, b"mirrored content");
+    }
+
+    fn shell(script: &str, args: &[&str]) -> ExternalDownloader {
+        ExternalDownloader {
+            command: "sh".to_string(),
+            args: ["-c", script].iter().chain(args).map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn download_with_external_program() {
+        let temp = test_dir();
+        let rewrites = UrlRewrites::new(&[rewrite(r"^https://github\.com/", "https://mirror.example/")]).unwrap();
+        // writes the url it was given in the output file
+        let external = shell(r#"printf '%s' "$0" > "$1""#, &["${url}", "${output}"]);
+        let downloader = Downloader::new().with_url_rewrites(rewrites).with_external_downloader(Some(external));
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+
+        let downloaded = downloader.download("https://github.com/some_user/some_mod.zip", &temp.path().to_path_buf(),
+                                                PathBuf::from("some_mod.zip"), &opts, &None).await.unwrap();
+
+        assert_eq!(downloaded.path, temp.path().join("some_mod.zip"));
+        assert_eq!(std::fs::read_to_string(&downloaded.path).unwrap(), "https://mirror.example/some_user/some_mod.zip");
+        assert!(downloaded.sha256.is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn external_program_failure() {
+        let temp = test_dir();
+        let downloader = Downloader::new().with_external_downloader(Some(shell("exit 3", &["${url}"])));
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+
+        let error = downloader.download("https://example.com/some_mod.zip", &temp.path().to_path_buf(),
+                                            PathBuf::from("some_mod.zip"), &opts, &None).await.unwrap_err();
+
+        assert!(error.to_string().contains("status Some(3)"), "{}", error);
+        assert!(!temp.path().join("some_mod.zip").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn external_program_without_output() {
+        let temp = test_dir();
+        let downloader = Downloader::new().with_external_downloader(Some(shell("true", &["${url}"])));
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+
+        let error = downloader.download("https://example.com/some_mod.zip", &temp.path().to_path_buf(),
+                                            PathBuf::from("some_mod.zip"), &opts, &None).await.unwrap_err();
+
+        assert!(error.to_string().contains("did not create"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn download_to_memory_fails_on_http_error() {
+        let server = TestServer::scripted(vec![not_found()]);
+        let url = server.url("/some_mod.zip");
+
+        let error = Downloader::new().download_to_memory(&url, &None).await.unwrap_err();
+
+        assert!(error.to_string().contains("404"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn post_form_fields() {
+        let server = TestServer::scripted(vec![ok(b"archive content")]);
+        let url = server.url("/download.php");
+        let dest = test_dir();
+        let fields = [("file_id", "1234"), ("confirm", "yes & sure")].into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always,
+                                    request: DownloadRequest::Post(PostBody::Form(fields)) };
+
+        let downloaded = Downloader::new()
+            .download(&url, &dest.path().to_path_buf(), PathBuf::from("some_mod.zip"), &opts, &None).await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/download.php"));
+        assert_eq!(request.header("content-type"), Some("application/x-www-form-urlencoded"));
+        assert_eq!(request.body, b"confirm=yes+%26+sure&file_id=1234");
+        assert_eq!(std::fs::read(&downloaded.path).unwrap(), b"archive content");
+    }
+
+    #[tokio::test]
+    async fn post_raw_body() {
+        let server = TestServer::scripted(vec![ok(b"archive content")]);
+        let url = server.url("/download.php");
+        let request = DownloadRequest::Post(PostBody::Raw(r#"{"file":"my_mod"}"#.to_string()));
+
+        let mut written: Vec<u8> = vec![];
+        Downloader::new().download_to_writer(&url, &request, &mut written, &None).await.unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/download.php"));
+        assert_eq!(request.body, br#"{"file":"my_mod"}"#);
+        assert_eq!(written, b"archive content");
+    }
+
+    #[tokio::test]
+    async fn throttled_download_stays_under_max_bytes_per_sec() {
+        let max_bytes_per_sec = 200_000;
+        let body: Vec<u8> = (0..150_000u32).map(|value| (value % 7) as u8).collect();
+        let server = TestServer::scripted(vec![ok(&body)]);
+        let url = server.url("/some_mod.zip");
+        let downloader = Downloader::new().with_max_bytes_per_sec(Some(max_bytes_per_sec));
+
+        let start = std::time::Instant::now();
+        let content = downloader.download_to_memory(&url, &None).await.unwrap();
+        let elapsed = start.elapsed();
+
+        assert_eq!(content, body);
+        let throughput = body.len() as f64 / elapsed.as_secs_f64();
+        assert!(throughput <= max_bytes_per_sec as f64, "{} bytes/s in {:?}", throughput, elapsed);
+    }
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn gzip_content_encoding_is_decoded() {
+        use sha2::{Digest, Sha256};
+
+        let body: Vec<u8> = (0..100_000u32).map(|value| (value % 17) as u8).collect();
+        let compressed = gzip(&body);
+        let server = TestServer::scripted(vec![response("200 OK", &[("Content-Encoding", "gzip")], &compressed)]);
+        let url = server.url("/some_mod.zip");
+        let dest = test_dir();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+
+        let downloaded = Downloader::new()
+            .download(&url, &dest.path().to_path_buf(), PathBuf::from("some_mod.zip"), &opts, &None).await
+            .unwrap();
+
+        // the stored size and checksum are the ones of the decoded content, not of what was received
+        assert_eq!(std::fs::read(&downloaded.path).unwrap(), body);
+        assert_eq!(downloaded.sha256, Some(format!("{:x}", Sha256::digest(&body))));
+    }
+
+    #[tokio::test]
+    async fn gzip_transfer_encoding_is_decoded() {
+        let body = b"some archive content".to_vec();
+        let compressed = gzip(&body);
+        let mut response = format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\nConnection: close\r\n\r\n{:x}\r\n",
+                                    compressed.len()).into_bytes();
+        response.extend(&compressed);
+        response.extend(b"\r\n0\r\n\r\n");
+        let server = TestServer::scripted(vec![response]);
+        let url = server.url("/some_mod.zip");
+
+        let content = Downloader::new().download_to_memory(&url, &None).await.unwrap();
+
+        assert_eq!(content, body);
+    }
+
+    #[test]
+    fn gzipped_archives_are_kept_as_they_are() {
+        use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING};
+        use super::{body_encoding, BodyEncoding};
+
+        let headers = HeaderMap::from_iter([(CONTENT_ENCODING, HeaderValue::from_static("gzip"))]);
+        assert_eq!(body_encoding(&headers, "https://example.com/my_mod.zip").unwrap(), BodyEncoding::Gzip);
+        assert_eq!(body_encoding(&headers, "https://example.com/my_mod.tar.gz").unwrap(), BodyEncoding::Identity);
+        assert_eq!(body_encoding(&headers, "https://example.com/my_mod.TGZ").unwrap(), BodyEncoding::Identity);
+        assert_eq!(body_encoding(&HeaderMap::new(), "https://example.com/my_mod.zip").unwrap(), BodyEncoding::Identity);
+        let headers = HeaderMap::from_iter([(CONTENT_ENCODING, HeaderValue::from_static("br"))]);
+        assert!(body_encoding(&headers, "https://example.com/my_mod.zip").is_err());
+    }
+
+    #[test]
+    fn content_range_values() {
+        assert_eq!(super::parse_content_range("bytes 100-199/1000"), Some((100, Some(1000))));
+        assert_eq!(super::parse_content_range("bytes 0-9/*"), Some((0, None)));
+        assert_eq!(super::parse_content_range("items 0-9/10"), None);
+    }
+
+    #[test]
+    fn retry_after_seconds_or_date() {
+        use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_400); // 2015-10-21 07:26:40 UTC
+        let header = |value: &'static str| HeaderMap::from_iter([(RETRY_AFTER, HeaderValue::from_static(value))]);
+
+        assert_eq!(super::retry_after(&header("120"), now), Some(Duration::from_secs(120)));
+        assert_eq!(super::retry_after(&header("Wed, 21 Oct 2015 07:28:00 GMT"), now), Some(Duration::from_secs(80)));
+        assert_eq!(super::retry_after(&header("Wed, 21 Oct 2015 07:00:00 GMT"), now), Some(Duration::ZERO));
+        assert_eq!(super::retry_after(&header("soon"), now), None);
+        assert_eq!(super::retry_after(&HeaderMap::new(), now), None);
+    }
+}
+
+#[cfg(test)]
+mod test_streaming_hash {
+    use std::path::PathBuf;
+
+    use sha2::{Digest, Sha256};
+
+    use crate::cache::file_sha256;
+    use crate::module::refresh::RefreshCondition;
+    use crate::test_utils::server::{ok, TestServer};
+    use crate::test_utils::test_dir;
+
+    use super::{DownloadOpts, DownloadRequest, Downloader};
+
+    #[tokio::test]
+    async fn download_computes_sha256_while_streaming() {
+        let body: Vec<u8> = (0..200_000u32).map(|value| (value % 251) as u8).collect();
+        let server = TestServer::scripted(vec![ok(&body)]);
+        let url = server.url("/some_mod.zip");
+        let dest = test_dir();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never, request: DownloadRequest::Get };
+
+        let downloaded = Downloader::new()
+            .download(&url, &dest.path().to_path_buf(), PathBuf::from("some_mod.zip"), &opts, &None).await
+            .unwrap();
+
+        assert_eq!(downloaded.path, dest.path().join("some_mod.zip"));
+        assert_eq!(std::fs::read(&downloaded.path).unwrap(), body);
+        assert_eq!(downloaded.sha256, Some(format!("{:x}", Sha256::digest(&body))));
+        // same value as hashing the file once it is written
+        assert_eq!(downloaded.sha256, Some(file_sha256(&downloaded.path).unwrap()));
+    }
 }
@@ -0,0 +1,137 @@
+
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+/// A program that can extract some archive format, with the `extractors` arguments it needs.
+struct KnownExtractor {
+    command: &'static str,
+    args: &'static [&'static str],
+}
+
+const SEVEN_ZIP_ARGS: &[&str] = &["x", "${input}", "-o${target}"];
+const UNRAR_ARGS: &[&str] = &["x", "${input}", "${target}"];
+
+/// Candidate programs for an archive extension, the preferred ones first.
+fn known_extractors(extension: &str) -> Vec<KnownExtractor> {
+    let seven_zip = ["7z", "7zz", "7za"].into_iter().map(|command| KnownExtractor { command, args: SEVEN_ZIP_ARGS });
+    match extension {
+        "rar" => ["unrar", "unrar-nonfree"].into_iter()
+            .map(|command| KnownExtractor { command, args: UNRAR_ARGS })
+            .chain(seven_zip)
+            .collect(),
+        "7z" | "bz2" | "xz" | "lzh" | "arj" | "cab" => seven_zip.collect(),
+        _ => vec![],
+    }
+}
+
+/// How to install a program for the extension on the current OS.
+fn install_hint(extension: &str) -> &'static str {
+    match (extension, std::env::consts::OS) {
+        ("rar", "windows") => "install 7-Zip (https://www.7-zip.org) or UnRAR (https://www.rarlab.com) and add it to the PATH",
+        ("rar", "macos") => "install unrar or 7-Zip (for example `brew install sevenzip`)",
+        ("rar", _) => "install unrar (for example `apt install unrar` or `dnf install unrar`) or 7-Zip",
+        (_, "windows") => "install 7-Zip (https://www.7-zip.org) and add it to the PATH",
+        (_, "macos") => "install 7-Zip (for example `brew install sevenzip`)",
+        (_, _) => "install 7-Zip (for example `apt install p7zip-full` or `dnf install p7zip p7zip-plugins`)",
+    }
+}
+
+/// What to tell when there is no `extractors` entry for `extension`: the config to add if a known program
+/// is in `path` (the value of the `PATH` environment variable), how to install one otherwise.
+pub fn missing_extractor_hint(extension: &str, path: Option<&OsStr>) -> String {
+    let extension = extension.to_lowercase();
+    let candidates = known_extractors(&extension);
+    let found = candidates.iter().find_map(|candidate| find_in_path(candidate.command, path).map(|found| (candidate, found)));
+    match (found, candidates.first()) {
+        (Some((extractor, location)), _) =>
+            format!("`{}` was found at {:?}, add this to the configuration file:\n{}",
+                    extractor.command, location, config_sample(&extension, extractor)),
+        (None, Some(extractor)) =>
+            format!("{}, then add this to the configuration file:\n{}", install_hint(&extension), config_sample(&extension, extractor)),
+        (None, None) =>
+            format!("configure a program that can extract it in the configuration file, for example:\n{}",
+                    config_sample(&extension, &KnownExtractor { command: "<program>", args: &["<args>", "${input}", "${target}"] })),
+    }
+}
+
+fn config_sample(extension: &str, extractor: &KnownExtractor) -> String {
+    let args = extractor.args.iter().map(|arg| format!("\"{}\"", arg)).collect::<Vec<_>>().join(", ");
+    format!("extractors:\n  {}:\n    command: {}\n    args: [ {} ]", extension, extractor.command, args)
+}
+
+/// Finds an executable named `program` in the directories of `path` (like `which`).
+pub fn find_in_path(program: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    let executable_names = if cfg!(windows) {
+        vec![format!("{}.exe", program), program.to_string()]
+    } else {
+        vec![program.to_string()]
+    };
+    std::env::split_paths(path?)
+        .flat_map(|dir| executable_names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::test_utils::test_dir;
+
+    use super::{find_in_path, missing_extractor_hint};
+
+    fn add_program(dir: &std::path::Path, name: &str) -> PathBuf {
+        let name = if cfg!(windows) { format!("{}.exe", name) } else { name.to_string() };
+        let program = dir.join(name);
+        std::fs::write(&program, "").unwrap();
+        crate::utils::permissions::set_executable(&program).unwrap();
+        program
+    }
+
+    #[test]
+    fn seven_zip_found_in_path() {
+        let empty = test_dir();
+        let bin = test_dir();
+        let program = add_program(bin.path(), "7z");
+        let path = std::env::join_paths([empty.path(), bin.path()]).unwrap();
+
+        assert_eq!(find_in_path("7z", Some(&path)), Some(program.clone()));
+        let hint = missing_extractor_hint("7z", Some(&path));
+
+        assert!(hint.contains(&format!("`7z` was found at {:?}", program)), "{}", hint);
+        assert!(hint.contains("extractors:\n  7z:\n    command: 7z\n    args: [ \"x\", \"${input}\", \"-o${target}\" ]"), "{}", hint);
+    }
+
+    #[test]
+    fn seven_zip_not_in_path() {
+        let empty = test_dir();
+        if cfg!(unix) {
+            // not executable
+            std::fs::write(empty.path().join("7z"), "").unwrap();
+        }
+        let path = std::env::join_paths([empty.path()]).unwrap();
+
+        let hint = missing_extractor_hint("7Z", Some(&path));
+
+        assert!(hint.starts_with("install 7-Zip"), "{}", hint);
+        assert!(hint.contains("command: 7z"), "{}", hint);
+        assert_eq!(missing_extractor_hint("7z", None), hint);
+    }
+
+    #[test]
+    fn unknown_extension_gets_a_sample() {
+        let hint = missing_extractor_hint("sit", None);
+
+        assert!(hint.contains("extractors:\n  sit:\n    command: <program>"), "{}", hint);
+    }
+}
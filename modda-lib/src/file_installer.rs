@@ -1,60 +1,243 @@
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use anyhow::{Result, bail};
-use globwalk::GlobWalkerBuilder;
 use itertools::Itertools;
+use log::{debug, info};
 use path_clean::PathClean;
 use serde::{Serialize, Deserialize};
 
-use crate::args::Install;
 use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::file_system::{FileSystem, RealFileSystem};
 use crate::global::Global;
-use crate::module::file_module_origin::FileModuleOrigin;
+use crate::jobs::Pool;
+use crate::lowercase::LwcString;
+use crate::module::file_mod::FileModule;
+use crate::module::file_module_origin::{DirCopy, FileModuleOrigin};
+use crate::run_warnings::{report_warning, WarningCollector};
+use crate::utils::pathext::is_hidden_under;
+
+/// Directory (in the game directory) of the stamps of `install --sync-files`.
+pub const SYNC_DIR: &str = "weidu_external/modda/sync";
 
 pub struct FileInstaller<'a> {
     global: &'a Global,
-    opts: &'a Install,
+    /// Where `local_files` is resolved.
+    manifest_root: &'a CanonPath,
     game_dir: &'a CanonPath,
+    config: &'a Config,
+    warnings: Option<&'a WarningCollector>,
+    sync_dir: Option<PathBuf>,
+    /// Workers for walking the globs of the origins; without a pool, they are walked one after the other.
+    glob_pool: Option<&'a Pool>,
+    fs: &'a dyn FileSystem,
 }
 
 impl <'a> FileInstaller<'a> {
-    pub fn new(global: &'a Global, opts: &'a Install, game_dir: &'a CanonPath) -> FileInstaller<'a> {
-        FileInstaller { global, opts, game_dir }
+    pub fn new(global: &'a Global, manifest_root: &'a CanonPath, game_dir: &'a CanonPath, config: &'a Config) -> FileInstaller<'a> {
+        FileInstaller { global, manifest_root, game_dir, config, warnings: None, sync_dir: None, glob_pool: None, fs: &RealFileSystem }
+    }
+
+    /// Filesystem where the files are read and copied (the real one by default).
+    pub fn with_file_system(self, fs: &'a dyn FileSystem) -> Self {
+        Self { fs, ..self }
+    }
+
+    /// Incremental sync of file modules: each one has a stamp in `dir`, written with the time its copy started.
+    /// When the stamp exists, only the source files modified after it are copied.
+    pub fn with_sync_dir(self, dir: &Path) -> Self {
+        Self { sync_dir: Some(dir.to_path_buf()), ..self }
+    }
+
+    /// The globs of the origins are walked by the workers of `pool`.
+    pub fn with_glob_pool(self, pool: &'a Pool) -> Self {
+        Self { glob_pool: Some(pool), ..self }
+    }
+
+    /// Warnings (overwritten files) go to `warnings` instead of only being logged.
+    pub fn with_warnings(self, warnings: &'a WarningCollector) -> Self {
+        Self { warnings: Some(warnings), ..self }
+    }
+
+    /// Copies the files of a file module to its destination (see `FileModule::destination`).
+    /// Nothing is copied if its `requires_file`/`forbids_file` conditions are not met; the reason is returned.
+    pub fn copy_file_module(&self, file_module: &FileModule) -> Result<Option<String>> {
+        if let Some(reason) = file_module.skip_reason(self.game_dir.path())? {
+            info!("file module {} skipped - {}", file_module.file_mod, reason);
+            return Ok(Some(reason));
+        }
+        let (name, origins) = (&file_module.file_mod, [&file_module.from]);
+        let destination = file_module.destination(self.game_dir)?;
+        let allow_overwrite = file_module.allow_overwrite(self.global);
+        let stamp = self.sync_dir.as_ref().map(|dir| dir.join(format!("{}.stamp", name)));
+        let sync_start = SystemTime::now();
+        match self.last_sync(stamp.as_deref())? {
+            None => self.copy_from_origins(name, &origins, &destination, allow_overwrite)?,
+            Some(since) => self.copy_changed_files(name, &origins, &destination, allow_overwrite, since)?,
+        }
+        if let Some(stamp) = &stamp {
+            self.write_sync_stamp(stamp, sync_start)?;
+        }
+        Ok(None)
     }
 
-    pub fn copy_from_origins(&self, origins: &[&FileModuleOrigin], target: &PathBuf, allow_overwrite: bool) -> Result<()> {
+    pub fn copy_from_origins(&self, module: &LwcString, origins: &[&FileModuleOrigin], target: &Path,
+                                allow_overwrite: bool) -> Result<()> {
+        let conflicts = self.find_conflicts(origins, target)?;
+        self.check_conflicts(module, &conflicts, allow_overwrite)?;
         let globs = self.get_file_globs(origins)?;
         self.copy_from_globs(&globs, target, allow_overwrite)
     }
 
+    fn check_conflicts(&self, module: &LwcString, conflicts: &[PathBuf], allow_overwrite: bool) -> Result<()> {
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        let list = conflicts.iter().map(|path| format!("{:?}", path)).join("\n  - ");
+        if allow_overwrite {
+            report_warning(self.warnings, Some(module), "copy",
+                            &format!("module {} will overwrite existing files:\n  - {}", module, list));
+            Ok(())
+        } else {
+            bail!("module {} would overwrite existing files (set `allow_overwrite: true` if this is expected):\n  - {}",
+                    module, list);
+        }
+    }
+
+    /// The time of the last sync, if there is a sync stamp.
+    fn last_sync(&self, stamp: Option<&Path>) -> Result<Option<SystemTime>> {
+        match stamp {
+            Some(stamp) if self.fs.exists(stamp) => match self.fs.metadata(stamp) {
+                Ok(metadata) => Ok(Some(metadata.modified)),
+                Err(error) => bail!("Could not read the last sync time from {:?}\n -> {:?}", stamp, error),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn write_sync_stamp(&self, stamp: &Path, sync_start: SystemTime) -> Result<()> {
+        if let Some(parent) = stamp.parent() {
+            self.ensure_path(parent)?;
+        }
+        if let Err(error) = self.fs.touch(stamp, sync_start) {
+            bail!("Could not write sync stamp {:?}\n -> {:?}", stamp, error);
+        }
+        Ok(())
+    }
+
+    /// Copies the files modified after `since` one by one; only those can conflict.
+    fn copy_changed_files(&self, module: &LwcString, origins: &[&FileModuleOrigin], target: &Path,
+                            allow_overwrite: bool, since: SystemTime) -> Result<()> {
+        let files = self.source_files(origins, target)?;
+        let total = files.len();
+        let mut changed = vec![];
+        for (source, destination) in files {
+            let modified = match self.fs.metadata(&source) {
+                Ok(metadata) => metadata.modified,
+                Err(error) => bail!("Could not read modification time of {:?}\n -> {:?}", source, error),
+            };
+            if modified > since {
+                changed.push((source, destination));
+            }
+        }
+        let conflicts = changed.iter()
+            .map(|(_, destination)| destination)
+            .filter(|destination| self.fs.exists(destination))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.check_conflicts(module, &conflicts, allow_overwrite)?;
+        for (source, destination) in &changed {
+            debug!("copying changed file {:?} to {:?}", source, destination);
+            if let Some(parent) = destination.parent() {
+                self.ensure_path(parent)?;
+            }
+            if let Err(error) = self.fs.copy(source, destination) {
+                bail!("Could not copy file {:?} to {:?}\n -> {:?}", source, destination, error);
+            }
+        }
+        info!("module {}: copied {} changed file(s), {} unchanged", module, changed.len(), total - changed.len());
+        Ok(())
+    }
+
+    /// Lists each file that `copy_from_origins` copies, with its destination
+    /// (directories are listed file by file).
+    fn source_files(&self, origins: &[&FileModuleOrigin], target: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let globs = self.get_file_globs(origins)?;
+        let mut result = vec![];
+        for copy_glob in &globs {
+            let items = match &copy_glob.glob {
+                None if self.fs.is_dir(&copy_glob.base) => {
+                    let dir_target = match copy_glob.dir_copy {
+                        DirCopy::Content => target.to_path_buf(),
+                        DirCopy::Directory => target.join(dir_name(&copy_glob.base)?),
+                    };
+                    vec![(copy_glob.base.clone(), dir_target)]
+                }
+                None => vec![(copy_glob.base.clone(), target.join(dir_name(&copy_glob.base)?))],
+                Some(glob) => self.glob_items(copy_glob, glob)?.into_iter()
+                    .map(|item| {
+                        let destination = target.join(dir_name(&item)?);
+                        Ok((item, destination))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            };
+            for (source, destination) in items {
+                if self.fs.is_dir(&source) {
+                    for file in self.list_dir_files(&source)? {
+                        result.push((source.join(&file), destination.join(file)));
+                    }
+                } else {
+                    result.push((source, destination));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Destinations that would be written more than once: files already present in `target`
+    /// or provided by several origins.
+    fn find_conflicts(&self, origins: &[&FileModuleOrigin], target: &Path) -> Result<Vec<PathBuf>> {
+        let destinations = self.expected_destinations(origins, target)?;
+        let existing = destinations.iter().filter(|path| self.fs.exists(path));
+        let duplicates = destinations.iter().duplicates();
+        Ok(existing.chain(duplicates).unique().cloned().collect())
+    }
+
     fn get_file_globs(&self, origins: &[&FileModuleOrigin]) -> Result<Vec<CopyGlob>> {
-        let results: Vec<_> = origins.iter().map(|origin| (self.get_origin_base(origin), origin.glob()))
+        let results: Vec<_> = origins.iter()
+                                    .map(|origin| (self.get_origin_base(origin), origin.glob(), self.dir_copy(origin), origin.is_literal()))
                                     .collect();
         let (success, errors): (Vec<_>, Vec<_>) = results.iter().partition(|entry| entry.0.is_ok());
         let result = if !errors.is_empty() {
             bail!("Could not assemble FileModuleOrigins\n  {}",
                     errors.iter().map(|it| it.0.as_ref().unwrap_err()).join("\n  "))
         } else {
-            success.iter().map(|(path_buf, glob)| {
+            success.iter().map(|(path_buf, glob, dir_copy, literal)| {
                 CopyGlob {
                     base: path_buf.as_ref().map(|it| it.clone()).unwrap(),
-                    glob: glob.map(|it| it.to_owned() )
+                    glob: glob.map(|it| it.to_owned() ),
+                    dir_copy: *dir_copy,
+                    literal: *literal,
                 }
             }).collect()
         };
         Ok(result)
     }
 
+    fn dir_copy(&self, origin: &FileModuleOrigin) -> DirCopy {
+        origin.explicit_dir_copy().or(self.global.copy_mode).unwrap_or_default()
+    }
+
     fn get_origin_base(&self, origin: &FileModuleOrigin) -> Result<PathBuf> {
         match origin {
-            FileModuleOrigin::Absolute { absolute, .. } => check_absolute(absolute),
+            FileModuleOrigin::Absolute { absolute, .. } => self.check_absolute(absolute),
             FileModuleOrigin::Local { local, .. } => self.get_local_base_path(local),
         }
     }
 
     fn get_local_base_path(&self, file_path: &String) -> Result<PathBuf, anyhow::Error> {
-        let manifest_path = self.opts.get_manifest_root(self.game_dir);
         let local_files = match &self.global.local_files {
             None => PathBuf::new(),
             Some(path) => PathBuf::from(path).clean(),
@@ -63,97 +246,209 @@ impl <'a> FileInstaller<'a> {
             bail!("Invalid local_files value");
         }
         let file_path = PathBuf::from(file_path).clean();
-        if file_path.is_absolute() || local_files.starts_with("..") {
+        if file_path.is_absolute() || file_path.starts_with("..") {
             bail!("Invalid local value");
         }
         let relative_path = local_files.join(file_path);
-        Ok(manifest_path.join(relative_path)?.to_path_buf())
+        Ok(self.manifest_root.join(relative_path)?.to_path_buf())
     }
 
-    fn copy_from_globs(&self, globs: &[CopyGlob], target: &PathBuf, allow_overwrite: bool) -> Result<()> {
+    fn copy_from_globs(&self, globs: &[CopyGlob], target: &Path, allow_overwrite: bool) -> Result<()> {
         // ensure the destination path exists
-        ensure_path(target)?;
+        self.ensure_path(target)?;
 
-        for glob in globs {
-            self.copy_from_glob(glob, target, allow_overwrite)?;
+        for (glob, items) in globs.iter().zip(self.walk_globs(globs)?) {
+            self.copy_from_glob(glob, items, target, allow_overwrite)?;
         }
         Ok(())
     }
 
-    fn copy_from_glob(&self, copy_glob: &CopyGlob, target: &PathBuf, allow_overwrite: bool) -> Result<()> {
-        match &copy_glob.glob {
+    /// The items matched by each glob (`None` for an origin without glob), walked by the glob pool if there is one.
+    fn walk_globs(&self, globs: &[CopyGlob]) -> Result<Vec<Option<Vec<PathBuf>>>> {
+        let walk = |copy_glob: &CopyGlob| match &copy_glob.glob {
+            None => Ok(None),
+            Some(glob) => self.glob_items(copy_glob, glob).map(Some),
+        };
+        let walked = match self.glob_pool {
+            None => globs.iter().map(walk).collect::<Vec<_>>(),
+            Some(pool) => pool.map(globs.iter().collect(), walk),
+        };
+        walked.into_iter().collect()
+    }
+
+    fn copy_from_glob(&self, copy_glob: &CopyGlob, items: Option<Vec<PathBuf>>, target: &Path, allow_overwrite: bool) -> Result<()> {
+        match items {
             None => {
-                if copy_glob.base.is_dir() {
-                    copy_single_dir(&copy_glob.base, target, allow_overwrite)
+                if self.fs.is_dir(&copy_glob.base) {
+                    match copy_glob.dir_copy {
+                        DirCopy::Content => self.copy_dir_content(&copy_glob.base, target, allow_overwrite),
+                        DirCopy::Directory => self.copy_whole_dir(&copy_glob.base, target, allow_overwrite),
+                    }
                 } else {
-                    copy_single_file(&copy_glob.base, target, allow_overwrite)
+                    self.copy_single_file(&copy_glob.base, target, allow_overwrite)
                 }
             },
-            Some(glob) =>  {
-                let glob_builder = GlobWalkerBuilder::from_patterns(&copy_glob.base, &vec![glob])
-                        .case_insensitive(true);
-                let glob = match glob_builder.build() {
-                    Err(error) => bail!("Could not evaluate pattern {:?}\n -> {:?}", glob, error),
-                    Ok(glob) => glob,
-                };
-                for item in glob.into_iter().filter_map(Result::ok) {
-                    let copy_options = fs_extra::dir::CopyOptions {
-                        overwrite: allow_overwrite,
-                        ..Default::default()
-                    };
-                    let _bytes = fs_extra::copy_items(&vec![&item.into_path()], target, &copy_options)?;
+            Some(items) =>  {
+                for item in items {
+                    if self.fs.is_dir(&item) {
+                        self.copy_whole_dir(&item, target, allow_overwrite)?;
+                    } else {
+                        self.copy_single_file(&item, target, allow_overwrite)?;
+                    }
                 }
                 Ok(())
             }
         }
     }
 
-}
+    fn glob_case_sensitive(&self) -> bool {
+        self.config.glob_case_sensitive.unwrap_or(false)
+    }
 
-fn copy_single_file(path: &PathBuf, target: &PathBuf, allow_overwrite: bool) -> Result<()> {
-    let copy_options = fs_extra::dir::CopyOptions {
-        overwrite: allow_overwrite,
-        copy_inside: true,
-        ..Default::default()
-    };
-    if let Err(error) = fs_extra::copy_items(&[path], target, &copy_options) {
-        bail!("Could not copy single file {:?} to {:?}\n  {}", path, target, error);
+    fn glob_items(&self, copy_glob: &CopyGlob, glob: &str) -> Result<Vec<PathBuf>> {
+        let base = &copy_glob.base;
+        if copy_glob.literal {
+            let item = base.join(glob);
+            if !self.fs.exists(&item) {
+                bail!("File {:?} not found in {:?} (`literal` glob)", glob, base);
+            }
+            return Ok(vec![item]);
+        }
+        let items = self.fs.glob(base, glob, self.glob_case_sensitive())?;
+        if self.config.glob_include_hidden.unwrap_or(true) {
+            Ok(items)
+        } else {
+            Ok(items.into_iter().filter(|item| !is_hidden_under(item, base)).collect())
+        }
     }
-    Ok(())
-}
 
-fn copy_single_dir(path: &PathBuf, target: &PathBuf, allow_overwrite: bool) -> Result<()> {
-    let copy_options = fs_extra::dir::CopyOptions {
-        overwrite: allow_overwrite,
-        copy_inside: true,
-        content_only: true,
-        ..Default::default()
-    };
-    if let Err(error) = fs_extra::dir::copy(path, target, &copy_options) {
-        bail!("Could not copy single dir {:?} to {:?}\n  {}", path, target, error);
-    }
-    Ok(())
-}
+    /// Lists the paths where the files from `origins` end up when they are copied
+    /// to `target` with `copy_from_origins`.
+    pub fn expected_destinations(&self, origins: &[&FileModuleOrigin], target: &Path) -> Result<Vec<PathBuf>> {
+        let globs = self.get_file_globs(origins)?;
+        let mut result = vec![];
+        for (copy_glob, items) in globs.iter().zip(self.walk_globs(&globs)?) {
+            match items {
+                None if self.fs.is_dir(&copy_glob.base) => {
+                    let dir_target = match copy_glob.dir_copy {
+                        DirCopy::Content => target.to_path_buf(),
+                        DirCopy::Directory => target.join(dir_name(&copy_glob.base)?),
+                    };
+                    for file in self.list_dir_files(&copy_glob.base)? {
+                        result.push(dir_target.join(file));
+                    }
+                }
+                None => result.push(target.join(dir_name(&copy_glob.base)?)),
+                Some(items) => {
+                    for item in items {
+                        result.push(target.join(dir_name(&item)?));
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// The files that `copy_from_origins` puts in `target`, relative to it, each listed once.
+    pub fn staged_files(&self, origins: &[&FileModuleOrigin], target: &Path) -> Result<Vec<PathBuf>> {
+        let destinations = self.expected_destinations(origins, target)?;
+        destinations.iter().unique()
+            .map(|destination| match destination.strip_prefix(target) {
+                Ok(relative) => Ok(relative.to_path_buf()),
+                Err(error) => bail!("File destination {:?} is not in {:?}\n -> {:?}", destination, target, error),
+            })
+            .collect()
+    }
+
+    /// Lists all files under `dir`, recursively, relative to `dir`.
+    fn list_dir_files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut result = vec![];
+        for entry in self.fs.read_dir(dir)? {
+            let name = PathBuf::from(dir_name(&entry)?);
+            if self.fs.is_dir(&entry) {
+                for file in self.list_dir_files(&entry)? {
+                    result.push(name.join(file));
+                }
+            } else {
+                result.push(name);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Copies the `path` file into `target`.
+    fn copy_single_file(&self, path: &Path, target: &Path, allow_overwrite: bool) -> Result<()> {
+        let destination = target.join(dir_name(path)?);
+        if let Err(error) = self.copy_file(path, &destination, allow_overwrite) {
+            bail!("Could not copy single file {:?} to {:?}\n  {}", path, target, error);
+        }
+        Ok(())
+    }
+
+    /// Copies what is _inside_ the `path` directory into `target`
+    /// (`path/a.txt` ends up as `target/a.txt`).
+    fn copy_dir_content(&self, path: &Path, target: &Path, allow_overwrite: bool) -> Result<()> {
+        if let Err(error) = self.copy_entries(path, target, allow_overwrite) {
+            bail!("Could not copy single dir {:?} to {:?}\n  {}", path, target, error);
+        }
+        Ok(())
+    }
+
+    fn copy_entries(&self, path: &Path, target: &Path, allow_overwrite: bool) -> Result<()> {
+        self.ensure_path(target)?;
+        for entry in self.fs.read_dir(path)? {
+            let destination = target.join(dir_name(&entry)?);
+            if self.fs.is_dir(&entry) {
+                self.copy_entries(&entry, &destination, allow_overwrite)?;
+            } else {
+                self.copy_file(&entry, &destination, allow_overwrite)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the `path` directory itself into `target`
+    /// (`path/a.txt` ends up as `target/<path dir name>/a.txt`).
+    fn copy_whole_dir(&self, path: &Path, target: &Path, allow_overwrite: bool) -> Result<()> {
+        let destination = target.join(dir_name(path)?);
+        self.copy_dir_content(path, &destination, allow_overwrite)
+    }
 
-fn ensure_path(target: &PathBuf)-> Result<()> {
-    if let Err(error) = std::fs::create_dir_all(target) {
-        bail!("ensure_dirs: error creating destination {:?}\n -> {:?}", target, error);
-    } else {
+    fn copy_file(&self, path: &Path, destination: &Path, allow_overwrite: bool) -> Result<()> {
+        if !allow_overwrite && self.fs.exists(destination) {
+            bail!("{:?} already exists", destination);
+        }
+        self.fs.copy(path, destination)?;
         Ok(())
     }
+
+    fn ensure_path(&self, target: &Path) -> Result<()> {
+        if let Err(error) = self.fs.create_dir_all(target) {
+            bail!("ensure_dirs: error creating destination {:?}\n -> {:?}", target, error);
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_absolute(&self, path: &str) -> Result<PathBuf> {
+        let path_buf = self.fs.canonicalize(Path::new(path))?;
+        if !path_buf.is_absolute() {
+            bail!("path {} is not absolute", path)
+        } else if !self.fs.exists(&path_buf) {
+            bail!("path {} doesn't exist", path)
+        } else if path_buf.parent().is_none() {
+            // arbitrarily disallow root as a base location
+            bail!("path {} is not allowed as 'absolute' origin base; use a subdirectory", path)
+        } else {
+            Ok(path_buf)
+        }
+    }
 }
 
-fn check_absolute(path: &str) -> Result<PathBuf> {
-    let path_buf = PathBuf::from(path).canonicalize()?;
-    if !path_buf.is_absolute() {
-        bail!("path {} is not absolute", path)
-    } else if !path_buf.exists() {
-        bail!("path {} doesn't exist", path)
-    } else if path_buf.parent().is_none() {
-        // arbitrarily disallow root as a base location
-        bail!("path {} is not allowed as 'absolute' origin base; use a subdirectory", path)
-    } else {
-        Ok(path_buf)
+fn dir_name(path: &Path) -> Result<&std::ffi::OsStr> {
+    match path.file_name() {
+        None => bail!("Could not determine file or directory name for {:?}", path),
+        Some(name) => Ok(name),
     }
 }
 
@@ -181,4 +476,412 @@ pub struct CopyOptions {
 struct CopyGlob {
     pub base: PathBuf,
     pub glob: Option<String>,
+    pub dir_copy: DirCopy,
+    /// `glob` is a path, not a pattern.
+    pub literal: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    use filetime::FileTime;
+    use itertools::Itertools;
+
+        use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::file_system::{FileSystem, MemoryFileSystem};
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::file_mod::FileModule;
+    use crate::module::file_module_origin::{DirCopy, FileModuleOrigin};
+    use crate::test_utils::test_dir;
+
+    use super::FileInstaller;
+
+    /// Everything a `FileInstaller` borrows, the game directory (also the manifest root) defaults to the project directory.
+    struct InstallerSetup {
+        global: Global,
+        game_dir: CanonPath,
+        config: Config,
+    }
+
+    impl Default for InstallerSetup {
+        fn default() -> Self {
+            Self::in_dir(Path::new(env!("CARGO_MANIFEST_DIR")))
+        }
+    }
+
+    impl InstallerSetup {
+        fn in_dir(game_dir: &Path) -> Self {
+            Self {
+                global: Global::default(),
+                game_dir: CanonPath::new(game_dir).unwrap(),
+                config: Config::default(),
+            }
+        }
+
+        fn installer(&self) -> FileInstaller<'_> {
+            FileInstaller::new(&self.global, &self.game_dir, &self.game_dir, &self.config)
+        }
+    }
+
+    /// Copies `from` to `override`.
+    fn file_module(from: FileModuleOrigin) -> FileModule {
+        FileModule { file_mod: lwc!("my_mod"), from, to: "override".to_string(), ..Default::default() }
+    }
+
+    fn fixture_dir() -> String {
+        format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test/file_installer/some_dir")
+    }
+
+    #[test]
+    fn copy_absolute_dir_content() {
+        let setup = InstallerSetup::default();
+        let installer = setup.installer();
+        let origin = FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: None, dir_copy: Some(DirCopy::Content), literal: None };
+        let temp_dir = test_dir();
+        let target = temp_dir.path().join("data");
+
+        installer.copy_from_origins(&lwc!("my_mod"), &[&origin], &target, false).unwrap();
+
+        assert!(target.join("a.txt").is_file());
+        assert!(target.join("sub").join("b.txt").is_file());
+        assert!(!target.join("some_dir").exists());
+    }
+
+    #[test]
+    fn copy_absolute_dir_itself() {
+        let setup = InstallerSetup::default();
+        let installer = setup.installer();
+        let origin = FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: None, dir_copy: Some(DirCopy::Directory), literal: None };
+        let temp_dir = test_dir();
+        let target = temp_dir.path().join("data");
+
+        installer.copy_from_origins(&lwc!("my_mod"), &[&origin], &target, false).unwrap();
+
+        assert!(target.join("some_dir").join("a.txt").is_file());
+        assert!(target.join("some_dir").join("sub").join("b.txt").is_file());
+        assert!(!target.join("a.txt").exists());
+    }
+
+    #[test]
+    fn copy_dir_with_manifest_copy_mode() {
+        let setup = InstallerSetup { global: Global { copy_mode: Some(DirCopy::Directory), ..Default::default() }, ..Default::default() };
+        let installer = setup.installer();
+        let inherited = FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: None, dir_copy: None, literal: None };
+        let overridden = FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: None, dir_copy: Some(DirCopy::Content), literal: None };
+        let temp_dir = test_dir();
+
+        installer.copy_from_origins(&lwc!("my_mod"), &[&inherited], &temp_dir.path().join("inherited"), false).unwrap();
+        installer.copy_from_origins(&lwc!("my_mod"), &[&overridden], &temp_dir.path().join("overridden"), false).unwrap();
+
+        assert!(temp_dir.path().join("inherited/some_dir/a.txt").is_file());
+        assert!(!temp_dir.path().join("inherited/a.txt").exists());
+        assert!(temp_dir.path().join("overridden/a.txt").is_file());
+    }
+
+    #[test]
+    fn copy_refuses_to_overwrite_existing_file() {
+        let setup = InstallerSetup::default();
+        let installer = setup.installer();
+        let origin = FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: None, dir_copy: None, literal: None };
+        let temp_dir = test_dir();
+        let target = temp_dir.path().join("data");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("a.txt"), "from another mod").unwrap();
+
+        let error = installer.copy_from_origins(&lwc!("my_mod"), &[&origin], &target, false).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("my_mod"));
+        assert!(message.contains("a.txt"));
+        assert!(!message.contains("b.txt"));
+        // nothing was copied
+        assert_eq!(std::fs::read_to_string(target.join("a.txt")).unwrap(), "from another mod");
+        assert!(!target.join("sub").exists());
+    }
+
+    #[test]
+    fn copy_overwrites_existing_file_when_allowed() {
+        let setup = InstallerSetup::default();
+        let installer = setup.installer();
+        let origin = FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: None, dir_copy: None, literal: None };
+        let temp_dir = test_dir();
+        let target = temp_dir.path().join("data");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("a.txt"), "from another mod").unwrap();
+
+        assert_eq!(
+            installer.find_conflicts(&[&origin], &target).unwrap(),
+            vec![target.join("a.txt")]
+        );
+        installer.copy_from_origins(&lwc!("my_mod"), &[&origin], &target, true).unwrap();
+
+        assert_ne!(std::fs::read_to_string(target.join("a.txt")).unwrap(), "from another mod");
+        assert!(target.join("sub").join("b.txt").is_file());
+    }
+
+    #[test]
+    fn incremental_copy_only_copies_changed_files() {
+        let temp_dir = test_dir();
+        let setup = InstallerSetup::in_dir(temp_dir.path());
+        let source = temp_dir.path().join("source");
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::fs::write(source.join("a.txt"), "a v1").unwrap();
+        std::fs::write(source.join("sub").join("b.txt"), "b v1").unwrap();
+        let an_hour_ago = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(3600));
+        filetime::set_file_mtime(source.join("a.txt"), an_hour_ago).unwrap();
+        filetime::set_file_mtime(source.join("sub").join("b.txt"), an_hour_ago).unwrap();
+        let target = temp_dir.path().join("data");
+        let stamps = temp_dir.path().join("stamps");
+        let module = synced_file_module("my_mod", &source);
+
+        // first sync: no stamp yet, everything is copied
+        let installer = setup.installer().with_sync_dir(&stamps);
+        installer.copy_file_module(&module).unwrap();
+        assert_eq!(std::fs::read_to_string(target.join("a.txt")).unwrap(), "a v1");
+        assert_eq!(std::fs::read_to_string(target.join("sub").join("b.txt")).unwrap(), "b v1");
+        assert!(stamps.join("my_mod.stamp").is_file());
+
+        // only b.txt changes; a.txt is modified in the target to check it's not copied again
+        std::fs::write(target.join("a.txt"), "edited in target").unwrap();
+        std::fs::write(source.join("sub").join("b.txt"), "b v2").unwrap();
+        let in_a_minute = FileTime::from_system_time(SystemTime::now() + Duration::from_secs(60));
+        filetime::set_file_mtime(source.join("sub").join("b.txt"), in_a_minute).unwrap();
+
+        installer.copy_file_module(&module).unwrap();
+        assert_eq!(std::fs::read_to_string(target.join("a.txt")).unwrap(), "edited in target");
+        assert_eq!(std::fs::read_to_string(target.join("sub").join("b.txt")).unwrap(), "b v2");
+    }
+
+    #[test]
+    fn sync_stamps_are_per_module() {
+        let temp_dir = test_dir();
+        let setup = InstallerSetup::in_dir(temp_dir.path());
+        let source = temp_dir.path().join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("old.txt"), "old").unwrap();
+        filetime::set_file_mtime(source.join("old.txt"),
+                                 FileTime::from_system_time(SystemTime::now() - Duration::from_secs(3600))).unwrap();
+        let installer = setup.installer().with_sync_dir(&temp_dir.path().join("stamps"));
+
+        installer.copy_file_module(&synced_file_module("first", &source)).unwrap();
+        // the stamp of `first` is newer than old.txt, but `second` was never copied
+        installer.copy_file_module(&FileModule { to: "other".to_string(), ..synced_file_module("second", &source) }).unwrap();
+
+        assert!(temp_dir.path().join("data").join("old.txt").is_file());
+        assert!(temp_dir.path().join("other").join("old.txt").is_file());
+    }
+
+    /// Copies the content of `source` to `data` in the game directory, overwriting the previous copy.
+    fn synced_file_module(name: &str, source: &Path) -> FileModule {
+        FileModule {
+            file_mod: lwc!(name),
+            to: "data".to_string(),
+            allow_overwrite: Some(true),
+            ..file_module(FileModuleOrigin::Absolute {
+                absolute: source.to_string_lossy().to_string(),
+                glob: None,
+                dir_copy: Some(DirCopy::Content),
+                literal: None,
+            })
+        }
+    }
+
+    #[test]
+    fn copy_file_module_to_relative_and_absolute_destinations() {
+        let temp_dir = test_dir();
+        std::fs::create_dir_all(temp_dir.path().join("game")).unwrap();
+        let setup = InstallerSetup::in_dir(&temp_dir.path().join("game"));
+        let installer = setup.installer();
+        let a_txt = || file_module(FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: Some("a.txt".to_string()), dir_copy: None, literal: None });
+
+        installer.copy_file_module(&a_txt()).unwrap();
+        let documentation = temp_dir.path().join("Documentation");
+        installer.copy_file_module(&FileModule { to: documentation.to_string_lossy().to_string(), ..a_txt() }).unwrap();
+
+        assert!(setup.game_dir.path().join("override").join("a.txt").is_file());
+        assert!(documentation.join("a.txt").is_file());
+    }
+
+    fn conditional_file_module(requires_file: Option<&str>, forbids_file: Option<&str>) -> FileModule {
+        FileModule {
+            requires_file: requires_file.map(str::to_string),
+            forbids_file: forbids_file.map(str::to_string),
+            ..file_module(FileModuleOrigin::Absolute { absolute: fixture_dir(), glob: Some("a.txt".to_string()), dir_copy: None, literal: None })
+        }
+    }
+
+    fn conditional_game_dir() -> (tempfile::TempDir, InstallerSetup) {
+        let temp_dir = test_dir();
+        std::fs::create_dir_all(temp_dir.path().join("lang/en_US")).unwrap();
+        std::fs::write(temp_dir.path().join("lang/en_US/DIALOG.TLK"), "").unwrap();
+        let setup = InstallerSetup::in_dir(temp_dir.path());
+        (temp_dir, setup)
+    }
+
+    #[test]
+    fn file_module_skipped_without_required_file() {
+        let (_temp_dir, setup) = conditional_game_dir();
+        let installer = setup.installer();
+
+        let missing = installer.copy_file_module(&conditional_file_module(Some("sod-dlc.zip"), None)).unwrap();
+        let forbidden = installer.copy_file_module(&conditional_file_module(None, Some("lang/*/dialog.tlk"))).unwrap();
+
+        assert_eq!(missing.as_deref(), Some("required file sod-dlc.zip is not in the game directory"));
+        assert_eq!(forbidden.as_deref(), Some("forbidden file lang/*/dialog.tlk is in the game directory"));
+
+        assert!(!setup.game_dir.path().join("override").exists());
+    }
+
+    #[test]
+    fn file_module_copied_with_required_file() {
+        let (_temp_dir, setup) = conditional_game_dir();
+        let installer = setup.installer();
+
+        let skipped = installer.copy_file_module(&conditional_file_module(Some("lang/*/dialog.tlk"), Some("sod-dlc.zip"))).unwrap();
+
+        assert_eq!(skipped, None);
+
+        assert!(setup.game_dir.path().join("override").join("a.txt").is_file());
+    }
+
+    fn memory_file_system() -> MemoryFileSystem {
+        MemoryFileSystem::new()
+            .with_file("/mods/items/sw1h01.itm", "new sword")
+            .with_file("/mods/items/shields/shld01.itm", "new shield")
+            .with_file("/mods/items/readme.txt", "")
+            .with_file("/game/override/sw1h01.itm", "original sword")
+    }
+
+    fn items_origin() -> FileModuleOrigin {
+        FileModuleOrigin::Absolute { absolute: "/mods/items".to_string(), glob: Some("*.ITM".to_string()), dir_copy: None, literal: None }
+    }
+
+    #[test]
+    fn memory_copy_refuses_to_overwrite() {
+        let fs = memory_file_system();
+        let setup = InstallerSetup::in_dir(Path::new("/game"));
+        let installer = setup.installer().with_file_system(&fs);
+        let target = PathBuf::from("/game/override");
+
+        let error = installer.copy_from_origins(&lwc!("my_items"), &[&items_origin()], &target, false).unwrap_err();
+
+        assert!(error.to_string().contains("sw1h01.itm"), "{}", error);
+        assert!(!error.to_string().contains("shld01.itm"), "{}", error);
+        assert_eq!(fs.content(&target.join("sw1h01.itm")).unwrap(), "original sword");
+        assert_eq!(fs.content(&target.join("shld01.itm")), None);
+    }
+
+    #[test]
+    fn memory_copy_overwrites_when_allowed() {
+        let fs = memory_file_system();
+        let setup = InstallerSetup::in_dir(Path::new("/game"));
+        let installer = setup.installer().with_file_system(&fs);
+        let target = PathBuf::from("/game/override");
+
+        installer.copy_from_origins(&lwc!("my_items"), &[&items_origin()], &target, true).unwrap();
+
+        assert_eq!(fs.content(&target.join("sw1h01.itm")).unwrap(), "new sword");
+        assert_eq!(fs.content(&target.join("shld01.itm")).unwrap(), "new shield");
+        assert_eq!(fs.content(&target.join("readme.txt")), None);
+    }
+
+    #[test]
+    fn memory_copy_stays_in_sandbox() {
+        let fs = memory_file_system();
+        let setup = InstallerSetup::in_dir(Path::new("/game"));
+        let installer = setup.installer().with_file_system(&fs);
+        let target = PathBuf::from("/game/override");
+
+        let outside_local = FileModuleOrigin::Local { local: "../mods/items".to_string(), glob: None, dir_copy: None, literal: None };
+        let error = installer.copy_from_origins(&lwc!("my_items"), &[&outside_local], &target, false).unwrap_err();
+        assert!(error.to_string().contains("Invalid local value"), "{}", error);
+
+        let root = FileModuleOrigin::Absolute { absolute: "/".to_string(), glob: None, dir_copy: None, literal: None };
+        let error = installer.copy_from_origins(&lwc!("my_items"), &[&root], &target, false).unwrap_err();
+        assert!(error.to_string().contains("use a subdirectory"), "{}", error);
+
+        let outside_game = FileModule { to: "../override".to_string(), ..file_module(items_origin()) };
+        assert!(installer.copy_file_module(&outside_game).is_err());
+        assert!(!fs.exists(Path::new("/override")));
+    }
+
+    #[test]
+    fn deserialize_dir_copy() {
+        let yaml = r#"
+        absolute: /location
+        dir_copy: directory
+        "#;
+        let origin: FileModuleOrigin = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            origin,
+            FileModuleOrigin::Absolute { absolute: "/location".to_string(), glob: None, dir_copy: Some(DirCopy::Directory), literal: None }
+        );
+        assert_eq!(origin.dir_copy(), DirCopy::Directory);
+    }
+
+    /// Names of the files and directories copied from `fixture` by a `glob` origin.
+    fn copy_glob(config: Config, fixture: &str, glob: &str) -> Vec<String> {
+        let setup = InstallerSetup { config, ..Default::default() };
+        let installer = setup.installer();
+        let origin = FileModuleOrigin::Absolute {
+            absolute: format!("{}/resources/test/{}", env!("CARGO_MANIFEST_DIR"), fixture),
+            glob: Some(glob.to_string()),
+            dir_copy: None,
+            literal: None,
+        };
+        let temp_dir = test_dir();
+        let target = temp_dir.path().join("data");
+
+        installer.copy_from_origins(&lwc!("my_mod"), &[&origin], &target, true).unwrap();
+
+        std::fs::read_dir(&target).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn copy_glob_with_config() {
+        let hidden = |config| copy_glob(config, "hidden_files", "**/*");
+        assert_eq!(hidden(Config::default()), vec![".gitkeep", ".hidden_dir", "inside.itm", "visible.itm"]);
+        assert_eq!(hidden(Config { glob_include_hidden: Some(false), ..Default::default() }), vec!["visible.itm"]);
+
+        let case = |config| copy_glob(config, "glob_case/files", "*.txt");
+        assert_eq!(case(Config::default()), vec!["Mixed.Txt", "UPPER.TXT", "lower.txt"]);
+        assert_eq!(case(Config { glob_case_sensitive: Some(true), ..Default::default() }), vec!["lower.txt"]);
+    }
+
+    fn copy_bracket_name(literal: Option<bool>) -> Vec<String> {
+        let temp_dir = test_dir();
+        let source = temp_dir.path().join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("sword[1].itm"), "literal").unwrap();
+        std::fs::write(source.join("sword1.itm"), "glob match").unwrap();
+        let setup = InstallerSetup::default();
+        let installer = setup.installer();
+        let origin = FileModuleOrigin::Absolute {
+            absolute: source.to_string_lossy().to_string(),
+            glob: Some("sword[1].itm".to_string()),
+            dir_copy: None,
+            literal,
+        };
+        let target = temp_dir.path().join("data");
+
+        installer.copy_from_origins(&lwc!("my_mod"), &[&origin], &target, false).unwrap();
+
+        std::fs::read_dir(&target).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn copy_literal_glob() {
+        // `[1]` is a character class for a glob
+        assert_eq!(copy_bracket_name(None), vec!["sword1.itm"]);
+        assert_eq!(copy_bracket_name(Some(true)), vec!["sword[1].itm"]);
+    }
 }
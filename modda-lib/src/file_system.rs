@@ -0,0 +1,230 @@
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Result};
+use filetime::FileTime;
+use globwalk::GlobWalkerBuilder;
+
+/// What `FileSystem::metadata` tells about a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileMetadata {
+    pub is_dir: bool,
+    pub modified: SystemTime,
+}
+
+/// The filesystem operations of `FileInstaller`, so that its copy logic can run on an in-memory tree in tests.
+pub trait FileSystem: Sync {
+    fn exists(&self, path: &Path) -> bool;
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+    /// Entries of the directory (full paths), in no particular order.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    /// Copies a file, `to` is replaced if it exists.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Writes an empty file with the given modification time.
+    fn touch(&self, path: &Path, modified: SystemTime) -> io::Result<()>;
+    /// Files and directories under `base` matched by `pattern` (gitignore-like: a pattern without `/` matches
+    /// the names at any depth).
+    fn glob(&self, base: &Path, pattern: &str, case_sensitive: bool) -> Result<Vec<PathBuf>>;
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.metadata(path).map(|metadata| metadata.is_dir).unwrap_or(false)
+    }
+}
+
+/// The actual filesystem.
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(FileMetadata { is_dir: metadata.is_dir(), modified: metadata.modified()? })
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::copy(from, to).map(|_| ())
+    }
+
+    fn touch(&self, path: &Path, modified: SystemTime) -> io::Result<()> {
+        std::fs::write(path, "")?;
+        filetime::set_file_mtime(path, FileTime::from_system_time(modified))
+    }
+
+    fn glob(&self, base: &Path, pattern: &str, case_sensitive: bool) -> Result<Vec<PathBuf>> {
+        let glob_builder = GlobWalkerBuilder::from_patterns(base, &[pattern])
+                .case_insensitive(!case_sensitive);
+        let walker = match glob_builder.build() {
+            Err(error) => bail!("Could not evaluate pattern {:?}\n -> {:?}", pattern, error),
+            Ok(walker) => walker,
+        };
+        Ok(walker.into_iter().filter_map(Result::ok).map(|item| item.into_path()).collect())
+    }
+}
+
+#[cfg(test)]
+pub use memory::MemoryFileSystem;
+
+#[cfg(test)]
+mod memory {
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    use anyhow::{bail, Result};
+    use globset::GlobBuilder;
+    use path_clean::PathClean;
+
+    use super::{FileMetadata, FileSystem};
+
+    #[derive(Debug, Clone)]
+    enum Entry {
+        Dir,
+        File { content: Vec<u8>, modified: SystemTime },
+    }
+
+    /// In-memory tree of absolute paths (the parents of an entry always exist).
+    #[derive(Debug, Default)]
+    pub struct MemoryFileSystem {
+        entries: Mutex<BTreeMap<PathBuf, Entry>>,
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, format!("{:?} not found", path))
+    }
+
+    impl MemoryFileSystem {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_file(self, path: &str, content: &str) -> Self {
+            self.insert(Path::new(path), Entry::File { content: content.as_bytes().to_vec(), modified: SystemTime::now() })
+                .unwrap();
+            self
+        }
+
+        /// Content of a file, `None` if there is no file at `path`.
+        pub fn content(&self, path: &Path) -> Option<String> {
+            match self.entries.lock().unwrap().get(&path.clean()) {
+                Some(Entry::File { content, .. }) => Some(String::from_utf8_lossy(content).to_string()),
+                _ => None,
+            }
+        }
+
+        fn insert(&self, path: &Path, entry: Entry) -> io::Result<()> {
+            let path = path.clean();
+            let mut entries = self.entries.lock().unwrap();
+            for ancestor in path.ancestors().skip(1) {
+                match entries.get(ancestor) {
+                    Some(Entry::File { .. }) => return Err(io::Error::other(format!("{:?} is a file", ancestor))),
+                    Some(Entry::Dir) => break,
+                    None => { entries.insert(ancestor.to_path_buf(), Entry::Dir); }
+                }
+            }
+            match (entries.get(&path), &entry) {
+                (Some(Entry::Dir), Entry::File { .. }) => Err(io::Error::other(format!("{:?} is a directory", path))),
+                (Some(Entry::File { .. }), Entry::Dir) => Err(io::Error::other(format!("{:?} is a file", path))),
+                _ => {
+                    entries.insert(path, entry);
+                    Ok(())
+                }
+            }
+        }
+
+        /// `path` and everything below it.
+        fn subtree(&self, path: &Path) -> Vec<(PathBuf, Entry)> {
+            self.entries.lock().unwrap().iter()
+                .filter(|(entry_path, _)| entry_path.starts_with(path))
+                .map(|(entry_path, entry)| (entry_path.clone(), entry.clone()))
+                .collect()
+        }
+    }
+
+    impl FileSystem for MemoryFileSystem {
+        fn exists(&self, path: &Path) -> bool {
+            self.entries.lock().unwrap().contains_key(&path.clean())
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+            match self.entries.lock().unwrap().get(&path.clean()) {
+                None => Err(not_found(path)),
+                Some(Entry::Dir) => Ok(FileMetadata { is_dir: true, modified: SystemTime::UNIX_EPOCH }),
+                Some(Entry::File { modified, .. }) => Ok(FileMetadata { is_dir: false, modified: *modified }),
+            }
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            let path = path.clean();
+            if !self.is_dir(&path) {
+                return Err(not_found(&path));
+            }
+            Ok(self.entries.lock().unwrap().keys()
+                .filter(|entry_path| entry_path.parent() == Some(path.as_path()))
+                .cloned()
+                .collect())
+        }
+
+        fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+            if self.exists(path) { Ok(path.clean()) } else { Err(not_found(path)) }
+        }
+
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            self.insert(path, Entry::Dir)
+        }
+
+        fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let content = match self.entries.lock().unwrap().get(&from.clean()) {
+                Some(Entry::File { content, .. }) => content.clone(),
+                _ => return Err(not_found(from)),
+            };
+            match to.parent() {
+                Some(parent) if self.is_dir(parent) => {}
+                _ => return Err(not_found(to)),
+            }
+            self.insert(to, Entry::File { content, modified: SystemTime::now() })
+        }
+
+        fn touch(&self, path: &Path, modified: SystemTime) -> io::Result<()> {
+            self.insert(path, Entry::File { content: vec![], modified })
+        }
+
+        fn glob(&self, base: &Path, pattern: &str, case_sensitive: bool) -> Result<Vec<PathBuf>> {
+            let pattern = pattern.trim_start_matches('/');
+            let matcher = match GlobBuilder::new(pattern).case_insensitive(!case_sensitive).literal_separator(true).build() {
+                Err(error) => bail!("Could not evaluate pattern {:?}\n -> {:?}", pattern, error),
+                Ok(glob) => glob.compile_matcher(),
+            };
+            let base = base.clean();
+            Ok(self.subtree(&base).into_iter()
+                .map(|(path, _)| path)
+                .filter(|path| match path.strip_prefix(&base) {
+                    Ok(relative) if relative.as_os_str().is_empty() => false,
+                    Ok(relative) if !pattern.contains('/') => relative.file_name().map(|name| matcher.is_match(name)).unwrap_or(false),
+                    Ok(relative) => matcher.is_match(relative),
+                    Err(_) => false,
+                })
+                .collect())
+        }
+    }
+}
@@ -0,0 +1,131 @@
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use log::{debug, info};
+
+use crate::chitin::has_chitin_key;
+
+/// Directories where the stores (Steam, GOG, Beamdog) install games by default, for the current OS.<br>
+/// Each of them is either a game directory or (usually) contains game directories.
+pub fn common_install_roots() -> Vec<PathBuf> {
+    let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    let mut roots = vec![];
+    if cfg!(target_os = "windows") {
+        let program_files = [
+            std::env::var_os("ProgramFiles(x86)").map(PathBuf::from),
+            std::env::var_os("ProgramFiles").map(PathBuf::from),
+        ];
+        for program_files in program_files.into_iter().flatten() {
+            roots.push(program_files.join("Steam").join("steamapps").join("common"));
+            roots.push(program_files.join("GOG Galaxy").join("Games"));
+            roots.push(program_files.join("BeamdogLibrary"));
+        }
+        roots.push(PathBuf::from(r"C:\GOG Games"));
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = &home {
+            roots.push(home.join("Library").join("Application Support").join("Steam").join("steamapps").join("common"));
+            roots.push(home.join("Documents").join("BeamdogLibrary"));
+            roots.push(home.join("GOG Games"));
+        }
+    } else if let Some(home) = &home {
+        roots.push(home.join(".steam").join("steam").join("steamapps").join("common"));
+        roots.push(home.join(".local").join("share").join("Steam").join("steamapps").join("common"));
+        roots.push(home.join("GOG Games"));
+    }
+    roots
+}
+
+/// Returns the directories containing a `chitin.key` file among the given roots and their direct children.<br>
+/// Roots that don't exist are ignored.
+pub fn find_game_dir_candidates(roots: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut candidates = vec![];
+    for root in roots {
+        if !root.is_dir() {
+            debug!("Game directory lookup - {:?} does not exist", root);
+            continue;
+        }
+        if has_chitin_key(root)? {
+            candidates.push(root.to_path_buf());
+        }
+        let entries = match std::fs::read_dir(root) {
+            Ok(entries) => entries,
+            Err(error) => bail!("Could not read directory {:?}\n -> {:?}", root, error),
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.is_dir() && has_chitin_key(&path)? {
+                candidates.push(path);
+            }
+        }
+    }
+    candidates.sort();
+    candidates.dedup();
+    Ok(candidates)
+}
+
+/// Used when the program is not run from a game directory: looks for installed games in
+/// the common locations and asks the user to choose one.<br>
+/// Never chooses by itself; in non-interactive mode, returns `None`.
+pub fn ask_game_dir(interactive: bool) -> Result<Option<PathBuf>> {
+    if !interactive {
+        return Ok(None);
+    }
+    let candidates = find_game_dir_candidates(&common_install_roots())?;
+    if candidates.is_empty() {
+        info!("No game directory found in the usual install locations");
+        return Ok(None);
+    }
+    let items = candidates.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>();
+    let selection = dialoguer::Select::new()
+        .with_prompt("No chitin.key in the current directory; use one of these game directories? (Esc to cancel)")
+        .items(&items)
+        .default(0)
+        .interact_opt()?;
+    Ok(selection.map(|index| candidates[index].clone()))
+}
+
+pub fn enter_game_dir(game_dir: &Path) -> Result<()> {
+    if let Err(error) = std::env::set_current_dir(game_dir) {
+        bail!("Could not enter game directory {:?}\n -> {:?}", game_dir, error);
+    }
+    info!("Entered game directory {:?}", game_dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::test_utils::test_dir;
+
+    use super::find_game_dir_candidates;
+
+    #[test]
+    fn find_candidates_in_fake_install_roots() {
+        let temp = test_dir();
+
+        let steam = temp.path().join("steam/steamapps/common");
+        let bgee = steam.join("Baldur's Gate Enhanced Edition");
+        let iwd = steam.join("Icewind Dale Enhanced Edition");
+        let other_game = steam.join("Some Other Game");
+        let gog_game = temp.path().join("GOG Games/Baldur's Gate 2");
+        for dir in [&bgee, &iwd, &other_game, &gog_game] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(bgee.join("chitin.key"), "").unwrap();
+        std::fs::write(iwd.join("CHITIN.KEY"), "").unwrap();
+        std::fs::write(other_game.join("data.pak"), "").unwrap();
+        std::fs::write(steam.join("chitin.key.bak"), "").unwrap();
+        std::fs::write(gog_game.join("chitin.key"), "").unwrap();
+
+        let roots = vec![
+            steam.clone(),
+            gog_game.clone(),
+            temp.path().join("GOG Games"),
+            temp.path().join("not_installed"),
+        ];
+        let candidates = find_game_dir_candidates(&roots).unwrap();
+
+        assert_eq!(candidates, vec![gog_game, bgee, iwd]);
+    }
+}
@@ -0,0 +1,121 @@
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::lowercase::LwcString;
+use crate::module::location::github::{GithubResolution, DEFAULT_MAX_RATE_LIMIT_WAIT};
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::manifest_diff::effective_location;
+use crate::module::module::Module;
+
+#[derive(Debug)]
+pub struct GithubModuleResolution {
+    pub module: LwcString,
+    /// `user/repository`
+    pub repository: String,
+    /// The error of the API call if the resolution failed, the other modules are still resolved.
+    pub resolution: Result<GithubResolution>,
+}
+
+/// What each github module of the manifest would download now (latest release, head of the branch...),
+/// in manifest order; only the github API is called, nothing is downloaded.<br>
+/// `manifest` is an assembled one (with the `github_base_url` of the config applied).
+/// `api_cache` is the github API cache (`GITHUB_API_CACHE_DIR` in the archive cache).
+pub async fn resolve_github_modules(manifest: &Manifest, config: &Config, api_cache: &Path) -> Vec<GithubModuleResolution> {
+    let max_rate_limit_wait = config.github_rate_limit_max_wait
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MAX_RATE_LIMIT_WAIT);
+    let mut resolutions = vec![];
+    for module in &manifest.modules {
+        let weidu_mod = match module {
+            Module::Mod { weidu_mod } => weidu_mod,
+            Module::Generated { .. } | Module::File { .. } => continue,
+        };
+        let github = match effective_location(manifest, &weidu_mod.name, &weidu_mod.location).map(|location| &location.source) {
+            Some(Source::Github(github)) => github,
+            _ => continue,
+        };
+        resolutions.push(GithubModuleResolution {
+            module: weidu_mod.name.clone(),
+            repository: format!("{}/{}", github.github_user, github.repository),
+            resolution: github.resolve(api_cache, max_rate_limit_wait).await,
+        });
+    }
+    resolutions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::module::location::github::GithubResolution;
+    use crate::module::manifest::Manifest;
+    use crate::test_utils::test_dir;
+
+    use super::resolve_github_modules;
+
+    const MANIFEST: &str = r#"
+version: "1"
+global:
+  lang_dir: en_US
+locations:
+  entries:
+    tagged:
+      github_user: someone
+      repository: tagged_mod
+      tag: v2.1
+modules:
+  - name: tagged
+    components: all
+  - name: local_mod
+    components: all
+    location:
+      local: mods/local_mod.zip
+  - name: released
+    components: all
+    location:
+      github_user: someone
+      repository: released_mod
+      release: v1
+      asset: released_mod-v1.zip
+  - name: pinned
+    components: all
+    location:
+      github_user: other
+      repository: pinned_mod
+      commit: 0a1b2c3d
+"#;
+
+    #[tokio::test]
+    async fn api_errors_are_kept_per_module() {
+        // nothing listens there anymore
+        let closed_port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let config = Config {
+            github_rate_limit_max_wait: Some(0),
+            ..Default::default()
+        };
+        let mut manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+        manifest.apply_github_base_url(&format!("http://{closed_port}"));
+        let api_cache = test_dir();
+
+        let resolutions = resolve_github_modules(&manifest, &config, api_cache.path()).await;
+
+        let modules = resolutions.iter().map(|resolution| (resolution.module.clone(), resolution.repository.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(modules, vec![
+            (lwc!("tagged"), "someone/tagged_mod"),
+            (lwc!("released"), "someone/released_mod"),
+            (lwc!("pinned"), "other/pinned_mod"),
+        ]);
+        assert_eq!(resolutions[0].resolution.as_ref().unwrap(), &GithubResolution::Tag { tag: "v2.1".to_string() });
+        let error = resolutions[1].resolution.as_ref().unwrap_err().to_string();
+        assert!(error.contains("Could not find release `v1` in github repository someone/released_mod"), "{}", error);
+        assert_eq!(resolutions[2].resolution.as_ref().unwrap(), &GithubResolution::Commit { commit: "0a1b2c3d".to_string() });
+    }
+}
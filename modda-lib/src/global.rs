@@ -1,14 +1,23 @@
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+use crate::archive_layout::Layout;
+use crate::module::file_module_origin::DirCopy;
+use crate::module::language::LanguageFallback;
+
 
 
 #[skip_serializing_none]
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
 pub struct Global {
     /// The "language code" configured in the game e.g. en_US, es_ES, fr_FR
-    #[serde(rename = "lang_dir")]
+    /// Empty when it was left out of the manifest (read with `lang_dir()`).
+    #[serde(rename = "lang_dir", default, skip_serializing_if = "String::is_empty")]
     pub game_language: String,
+    /// If `true`, `lang_dir` (and `lang_preferences`) can be left out, they are then read from (and guessed from)
+    /// the `lang_dir` of the game `weidu.conf`.
+    pub lang_from_weidu_conf: Option<bool>,
 
     /// List of language _names_ that should be selected if available, in decreasing order of priority
     /// items in the list are used as regexp (case insensitive by default)
@@ -18,8 +27,54 @@ pub struct Global {
     ///   syntax here https://docs.rs/regex/1.5.4/regex/#syntax
     ///   ex. `["#rx#^fran[cç]ais", french, english]`
     pub lang_preferences: Option<Vec<String>>,
-    pub local_patches: Option<String>,
+    /// What to do when a mod has none of the `lang_preferences` languages (default: `error`).
+    pub lang_fallback: Option<LanguageFallback>,
+    /// Directory (from manifest root) where `relative` patches are searched; can be a list
+    /// of directories, searched in order (the first one that has the patch wins).
+    pub local_patches: Option<PathList>,
     /// Path from manifest root (yml file location directory) where "local" mods can be found.
     pub local_mods: Option<String>,
+    /// If `true`, a `local` value that is just a mod name (and is not directly in `local_mods`)
+    /// is looked up in the whole `local_mods` tree (see `LocalModsIndex`).
+    pub local_mods_index: Option<bool>,
     pub local_files: Option<String>,
+    /// Layout used by all locations (in modules and in `locations`) that don't set their own `layout`.
+    pub default_layout: Option<Layout>,
+    /// If `true`, the existing mod directory (and tp2 files) in the game directory are removed
+    /// before the mod is fetched and extracted again (modules can override it with their own `clean_reinstall`).
+    pub clean_reinstall: Option<bool>,
+    /// Weidu version required by this manifest (same syntax as `weidu_version` in the config),
+    /// checked before the installation starts.
+    pub weidu_version: Option<String>,
+    /// Default `allow_overwrite` of the generated mods and file modules that don't set it.
+    pub allow_overwrite: Option<bool>,
+    /// Default `dir_copy` (`content` or `directory`) of the file origins that don't set it.
+    pub copy_mode: Option<DirCopy>,
+}
+
+impl Global {
+    /// The `lang_dir` of the manifest, or the one read from `weidu.conf` (see `complete_lang_from_weidu_conf`).
+    pub fn lang_dir(&self) -> Result<&str> {
+        if self.game_language.is_empty() {
+            bail!("Missing `lang_dir` in the manifest `global` section\n -> add it or set `lang_from_weidu_conf: true`");
+        }
+        Ok(&self.game_language)
+    }
+}
+
+/// A single path, or a list of paths.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum PathList {
+    Single(String),
+    List(Vec<String>),
+}
+
+impl PathList {
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            PathList::Single(path) => vec![path],
+            PathList::List(paths) => paths.iter().map(String::as_str).collect(),
+        }
+    }
 }
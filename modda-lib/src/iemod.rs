@@ -0,0 +1,145 @@
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::lowercase::{lwc, LwcString};
+
+/// Content of the `[Metadata]` section of the `.ini` file that Project Infinity
+/// puts in `.iemod` packages (usually `<mod>/<mod>.ini`).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IemodMetadata {
+    /// Name of the directory containing the `.ini` file, which is the mod (tp2) name.
+    pub mod_dir: Option<LwcString>,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    /// Other keys of the section, lowercased.
+    pub properties: HashMap<String, String>,
+}
+
+impl IemodMetadata {
+    pub fn describe(&self) -> String {
+        let mut result = self.name.clone().unwrap_or_else(|| "<unnamed>".to_string());
+        if let Some(author) = &self.author {
+            result.push_str(&format!(" by {}", author));
+        }
+        if let Some(description) = &self.description {
+            result.push_str(&format!(" - {}", description));
+        }
+        result
+    }
+
+    /// Description of the module when the manifest has none: the package description, or its name.
+    pub fn module_description(&self) -> Option<String> {
+        self.description.clone().or_else(|| self.name.clone())
+    }
+}
+
+/// Looks for an `.ini` file with a `[Metadata]` section at the top or one level down in the archive.
+/// Returns `None` if there is none.
+pub fn read_iemod_metadata(archive: &Path) -> Result<Option<IemodMetadata>> {
+    let file = match File::open(archive) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open archive {:?}\n -> {:?}", archive, error),
+    };
+    let mut zip_archive = match zip::ZipArchive::new(BufReader::new(file)) {
+        Ok(zip_archive) => zip_archive,
+        Err(error) => bail!("Could not open iemod archive at {:?}\n -> {:?}", archive, error),
+    };
+    for index in 0..zip_archive.len() {
+        let mut entry = zip_archive.by_index(index)?;
+        let path = match entry.enclosed_name() {
+            Some(path) => path.to_path_buf(),
+            None => continue,
+        };
+        let is_ini = path.extension().map(|ext| ext.eq_ignore_ascii_case("ini")).unwrap_or(false);
+        if !entry.is_file() || !is_ini || path.components().count() > 2 {
+            continue;
+        }
+        let mut buffer = vec![];
+        entry.read_to_end(&mut buffer)?;
+        let content = String::from_utf8_lossy(&buffer);
+        if let Some(mut metadata) = parse_metadata(&content) {
+            metadata.mod_dir = path.parent()
+                .and_then(|parent| parent.to_str())
+                .filter(|parent| !parent.is_empty())
+                .map(|parent| lwc!(parent));
+            return Ok(Some(metadata));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_metadata(content: &str) -> Option<IemodMetadata> {
+    let mut in_metadata = false;
+    let mut found = false;
+    let mut properties = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            in_metadata = line[1..line.len() - 1].trim().eq_ignore_ascii_case("metadata");
+            found |= in_metadata;
+            continue;
+        }
+        if in_metadata {
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                if !value.is_empty() {
+                    properties.insert(key.trim().to_lowercase(), value.to_string());
+                }
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+    Some(IemodMetadata {
+        mod_dir: None,
+        name: properties.remove("name"),
+        author: properties.remove("author"),
+        description: properties.remove("description"),
+        properties,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::lowercase::lwc;
+
+    use super::{read_iemod_metadata, IemodMetadata};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/iemod").join(name)
+    }
+
+    #[test]
+    fn read_metadata_from_iemod() {
+        let metadata = read_iemod_metadata(&fixture("my_mod.iemod")).unwrap();
+        assert_eq!(
+            metadata,
+            Some(IemodMetadata {
+                mod_dir: Some(lwc!("my_mod")),
+                name: Some("My Mod".to_string()),
+                author: Some("Someone".to_string()),
+                description: Some("Does things".to_string()),
+                properties: [
+                    ("readme".to_string(), "https://example.com/my_mod/readme.html".to_string()),
+                ].into_iter().collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn read_metadata_from_iemod_without_ini() {
+        assert_eq!(read_iemod_metadata(&fixture("no_metadata.iemod")).unwrap(), None);
+    }
+}
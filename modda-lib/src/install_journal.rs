@@ -0,0 +1,325 @@
+
+use std::fmt::{self, Display};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use itertools::Itertools;
+
+use crate::lowercase::LwcString;
+use crate::module::location::github::GithubDescriptor;
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::manifest_diff::effective_location;
+use crate::module::module::Module;
+
+/// Location of the journal, relative to the game directory.
+pub const JOURNAL_PATH: &str = "weidu_external/modda/install_journal.txt";
+
+const JOURNAL_HEADER: &str = "# modda install journal: <timestamp> <started|completed> <module index> <module name> <source>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalStatus {
+    Started,
+    Completed,
+}
+
+/// One line of the journal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// RFC 3339, local time.
+    pub timestamp: String,
+    pub status: JournalStatus,
+    /// Position of the module in install order, counting from one (like `install --from-index`).
+    pub index: usize,
+    pub name: LwcString,
+    /// Where the module came from (see `journal_source`).
+    pub source: String,
+}
+
+/// What `install` does with a module, according to the journal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalAction {
+    /// Normal installation.
+    Install,
+    /// The last run stopped during the installation of this module, it is installed again.
+    Resume,
+    /// Already completed in a previous run.
+    Skip,
+}
+
+/// Record of the modules installed in the game directory, written as they are installed (one line per step)
+/// so that a run that was interrupted can be resumed.<br>
+/// A module is identified by its index and its name, editing the manifest before the already installed modules
+/// makes them look not installed (the components already in `weidu.log` are still detected).
+#[derive(Debug)]
+pub struct InstallJournal {
+    path: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+impl InstallJournal {
+    /// Reads the journal of the game directory, an absent journal is empty.
+    pub fn open(game_dir: &Path) -> Result<Self> {
+        let path = game_dir.join(JOURNAL_PATH);
+        let entries = if path.exists() {
+            match std::fs::read_to_string(&path) {
+                Err(error) => bail!("Could not read install journal {:?}\n -> {:?}", path, error),
+                Ok(content) => parse_journal(&content, &path)?,
+            }
+        } else {
+            vec![]
+        };
+        Ok(InstallJournal { path, entries })
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Status of the last entry of the module, if any.
+    pub fn last_status(&self, index: usize, name: &LwcString) -> Option<JournalStatus> {
+        self.entries.iter().rev()
+            .find(|entry| entry.index == index && &entry.name == name)
+            .map(|entry| entry.status)
+    }
+
+    /// Whether the last entry of the module is `completed` with a timestamp after `since` (any time for `None`).
+    pub fn completed_since(&self, index: usize, name: &LwcString, since: Option<DateTime<Local>>) -> bool {
+        self.entries.iter().rev()
+            .find(|entry| entry.index == index && &entry.name == name)
+            .filter(|entry| entry.status == JournalStatus::Completed)
+            .map(|entry| match since {
+                None => true,
+                Some(since) => DateTime::parse_from_rfc3339(&entry.timestamp).map(|time| time > since).unwrap_or(false),
+            })
+            .unwrap_or(false)
+    }
+
+    /// Modules completed in a previous run are skipped unless `force` is set.
+    pub fn action(&self, index: usize, name: &LwcString, force: bool) -> JournalAction {
+        match self.last_status(index, name) {
+            Some(JournalStatus::Completed) if !force => JournalAction::Skip,
+            Some(JournalStatus::Started) => JournalAction::Resume,
+            _ => JournalAction::Install,
+        }
+    }
+
+    /// Appends a line to the journal file (written immediately).
+    pub fn record(&mut self, status: JournalStatus, index: usize, name: &LwcString, source: &str) -> Result<()> {
+        let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+        self.record_at(JournalEntry { timestamp, status, index, name: name.clone(), source: source.to_string() })
+    }
+
+    fn record_at(&mut self, entry: JournalEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if let Err(error) = std::fs::create_dir_all(parent) {
+                bail!("Could not create install journal directory {:?}\n -> {:?}", parent, error);
+            }
+        }
+        let new_file = !self.path.exists();
+        let mut file = match OpenOptions::new().create(true).append(true).open(&self.path) {
+            Err(error) => bail!("Could not open install journal {:?}\n -> {:?}", self.path, error),
+            Ok(file) => file,
+        };
+        let header = if new_file { format!("{}\n", JOURNAL_HEADER) } else { String::new() };
+        if let Err(error) = writeln!(file, "{}{}", header, entry).and_then(|_| file.sync_data()) {
+            bail!("Could not write install journal {:?}\n -> {:?}", self.path, error);
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Removes the entries of the modules from `index` (counting from one), for example after they were uninstalled.
+    pub fn forget_from(&mut self, index: usize) -> Result<()> {
+        self.entries.retain(|entry| entry.index < index);
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let content = std::iter::once(JOURNAL_HEADER.to_string())
+            .chain(self.entries.iter().map(|entry| entry.to_string()))
+            .join("\n");
+        if let Err(error) = std::fs::write(&self.path, content + "\n") {
+            bail!("Could not write install journal {:?}\n -> {:?}", self.path, error);
+        }
+        Ok(())
+    }
+}
+
+impl Display for JournalStatus {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            JournalStatus::Started => "started",
+            JournalStatus::Completed => "completed",
+        })
+    }
+}
+
+impl Display for JournalEntry {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} {} {} {} {}", self.timestamp, self.status, self.index, self.name, self.source)
+    }
+}
+
+fn parse_journal(content: &str, path: &Path) -> Result<Vec<JournalEntry>> {
+    let mut entries = vec![];
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_entry(line) {
+            None => bail!("Invalid line {} in install journal {:?}\n -> {}", line_number + 1, path, line),
+            Some(entry) => entries.push(entry),
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_entry(line: &str) -> Option<JournalEntry> {
+    let mut parts = line.splitn(5, ' ');
+    let timestamp = parts.next()?.to_string();
+    let status = match parts.next()? {
+        "started" => JournalStatus::Started,
+        "completed" => JournalStatus::Completed,
+        _ => return None,
+    };
+    let index = parts.next()?.parse().ok()?;
+    let name = LwcString::new(parts.next()?);
+    let source = parts.next().unwrap_or("").to_string();
+    Some(JournalEntry { timestamp, status, index, name, source })
+}
+
+/// Short description of where the module comes from, for the journal.
+pub fn journal_source(manifest: &Manifest, module: &Module) -> String {
+    let weidu_mod = match module {
+        Module::Generated { .. } | Module::File { .. } => return "files".to_string(),
+        Module::Mod { weidu_mod } => weidu_mod,
+    };
+    let location = match effective_location(manifest, &weidu_mod.name, &weidu_mod.location) {
+        None => return "game-dir".to_string(),
+        Some(location) => location,
+    };
+    match &location.source {
+        Source::Http(http) => http.http.clone(),
+        Source::Github(github) => match (github.archive_url(), &github.descriptor) {
+            (Some(url), _) => url,
+            (None, GithubDescriptor::Release { release, asset }) =>
+                format!("github:{}/{}/{}/{}", github.github_user, github.repository,
+                        release.as_deref().unwrap_or("latest"), asset),
+            (None, _) => format!("github:{}/{}", github.github_user, github.repository),
+        },
+        Source::Local { local } => format!("local:{}", local),
+        Source::Absolute { path } => format!("absolute:{}", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::{DateTime, Local};
+
+    use crate::lowercase::lwc;
+    use crate::test_utils::test_dir;
+
+    use super::{InstallJournal, JournalAction, JournalEntry, JournalStatus, JOURNAL_PATH};
+
+    fn entry(status: JournalStatus, index: usize, name: &str, source: &str) -> JournalEntry {
+        JournalEntry { timestamp: "2024-05-01T10:00:00+02:00".to_string(), status, index, name: lwc!(name), source: source.to_string() }
+    }
+
+    #[test]
+    fn skip_completed_modules() {
+        let game_dir = test_dir();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        journal.record(JournalStatus::Started, 1, &lwc!("dlcmerger"), "https://example.com/dlcmerger.zip").unwrap();
+        journal.record(JournalStatus::Completed, 1, &lwc!("dlcmerger"), "https://example.com/dlcmerger.zip").unwrap();
+
+        let journal = InstallJournal::open(game_dir.path()).unwrap();
+
+        assert_eq!(journal.action(1, &lwc!("dlcmerger"), false), JournalAction::Skip);
+        assert_eq!(journal.action(2, &lwc!("bg1npc"), false), JournalAction::Install);
+        // same position, other module: the manifest was changed
+        assert_eq!(journal.action(1, &lwc!("bg1npc"), false), JournalAction::Install);
+    }
+
+    #[test]
+    fn resume_after_interruption() {
+        let game_dir = test_dir();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        journal.record(JournalStatus::Started, 1, &lwc!("dlcmerger"), "files").unwrap();
+        journal.record(JournalStatus::Completed, 1, &lwc!("dlcmerger"), "files").unwrap();
+        journal.record(JournalStatus::Started, 2, &lwc!("bg1npc"), "local:bg1npc").unwrap();
+        drop(journal);
+
+        let journal = InstallJournal::open(game_dir.path()).unwrap();
+
+        assert_eq!(journal.action(1, &lwc!("dlcmerger"), false), JournalAction::Skip);
+        assert_eq!(journal.action(2, &lwc!("bg1npc"), false), JournalAction::Resume);
+        assert_eq!(journal.action(3, &lwc!("tweaks"), false), JournalAction::Install);
+    }
+
+    #[test]
+    fn force_reinstall() {
+        let game_dir = test_dir();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        journal.record(JournalStatus::Started, 1, &lwc!("dlcmerger"), "files").unwrap();
+        journal.record(JournalStatus::Completed, 1, &lwc!("dlcmerger"), "files").unwrap();
+        journal.record(JournalStatus::Started, 2, &lwc!("bg1npc"), "files").unwrap();
+
+        assert_eq!(journal.action(1, &lwc!("dlcmerger"), true), JournalAction::Install);
+        assert_eq!(journal.action(2, &lwc!("bg1npc"), true), JournalAction::Resume);
+    }
+
+    #[test]
+    fn completed_after_a_time() {
+        let game_dir = test_dir();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        journal.record_at(entry(JournalStatus::Completed, 1, "dlcmerger", "files")).unwrap();
+        journal.record_at(entry(JournalStatus::Started, 2, "bg1npc", "files")).unwrap();
+        let before = DateTime::parse_from_rfc3339("2024-05-01T09:59:59+02:00").unwrap().with_timezone(&Local);
+        let after = DateTime::parse_from_rfc3339("2024-05-01T08:00:01Z").unwrap().with_timezone(&Local);
+
+        assert!(journal.completed_since(1, &lwc!("dlcmerger"), None));
+        assert!(journal.completed_since(1, &lwc!("dlcmerger"), Some(before)));
+        assert!(!journal.completed_since(1, &lwc!("dlcmerger"), Some(after)));
+        assert!(!journal.completed_since(2, &lwc!("bg1npc"), None));
+        assert!(!journal.completed_since(3, &lwc!("tweaks"), None));
+    }
+
+    #[test]
+    fn journal_text_format() {
+        let game_dir = test_dir();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        journal.record_at(entry(JournalStatus::Started, 1, "dlcmerger", "github:Argent77/A7-DlcMerger/latest/dlc.zip")).unwrap();
+        journal.record_at(entry(JournalStatus::Completed, 1, "dlcmerger", "github:Argent77/A7-DlcMerger/latest/dlc.zip")).unwrap();
+        journal.record_at(entry(JournalStatus::Started, 2, "my_mod", "absolute:/home/me/my mods/my_mod.zip")).unwrap();
+
+        let content = std::fs::read_to_string(game_dir.path().join(JOURNAL_PATH)).unwrap();
+
+        assert_eq!(content, "\
+# modda install journal: <timestamp> <started|completed> <module index> <module name> <source>
+2024-05-01T10:00:00+02:00 started 1 dlcmerger github:Argent77/A7-DlcMerger/latest/dlc.zip
+2024-05-01T10:00:00+02:00 completed 1 dlcmerger github:Argent77/A7-DlcMerger/latest/dlc.zip
+2024-05-01T10:00:00+02:00 started 2 my_mod absolute:/home/me/my mods/my_mod.zip
+");
+        assert_eq!(InstallJournal::open(game_dir.path()).unwrap().entries(), journal.entries());
+    }
+
+    #[test]
+    fn forget_reset_modules() {
+        let game_dir = test_dir();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        for (index, name) in [(1, "aaa"), (2, "bbb"), (3, "ccc")] {
+            journal.record_at(entry(JournalStatus::Completed, index, name, "files")).unwrap();
+        }
+
+        journal.forget_from(2).unwrap();
+
+        let journal = InstallJournal::open(game_dir.path()).unwrap();
+        assert_eq!(journal.entries(), &[entry(JournalStatus::Completed, 1, "aaa", "files")]);
+        assert_eq!(journal.action(2, &lwc!("bbb"), false), JournalAction::Install);
+    }
+}
@@ -0,0 +1,143 @@
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+
+use crate::module::location::location::Location;
+use crate::module::manifest::Manifest;
+use crate::module::manifest_diff::{effective_location, keyed_modules, FieldChange};
+use crate::module::module::Module;
+
+/// The manifest as it was installed by `install --since-lock`: the locations of the modules are inlined
+/// (the `locations` section is not needed) so that a change in a location registry is seen too.
+pub fn lock_manifest(manifest: &Manifest) -> Manifest {
+    let modules = manifest.modules.iter().map(|module| match module {
+        Module::Mod { weidu_mod } => {
+            let location = effective_location(manifest, &weidu_mod.name, &weidu_mod.location)
+                .map(|concrete| Location::Concrete { concrete: concrete.clone() });
+            let mut weidu_mod = weidu_mod.clone();
+            weidu_mod.location = location;
+            Module::Mod { weidu_mod }
+        }
+        Module::Generated { .. } | Module::File { .. } => module.clone(),
+    }).collect();
+    Manifest {
+        locations: Default::default(),
+        modules,
+        remove_modules: vec![],
+        ..manifest.clone()
+    }
+}
+
+/// Reads a lock written by `write_lock`, `None` if there is no file at `path`.
+pub fn read_lock(path: &Path) -> Result<Option<Manifest>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    match path.to_str() {
+        None => bail!("Invalid lock file path {:?}", path),
+        Some(path) => Ok(Some(Manifest::read_path(path)?)),
+    }
+}
+
+pub fn write_lock(path: &Path, manifest: &Manifest) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    lock_manifest(manifest).write_path(path)
+}
+
+/// When the lock at `path` was last written, `None` if there is no lock.
+pub fn lock_written(path: &Path) -> Result<Option<DateTime<Local>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Err(error) => bail!("Could not read the modification time of lock {:?}\n -> {:?}", path, error),
+        Ok(time) => Ok(Some(DateTime::from(time))),
+    }
+}
+
+/// Positions (in `manifest.modules`) of the modules that are not in the lock, or whose kind, components,
+/// location or files changed since the lock was written; other changes (description, post_install...)
+/// don't need a new installation.<br>
+/// Without a lock, all the modules are selected.
+pub fn modules_changed_since(lock: Option<&Manifest>, manifest: &Manifest) -> HashSet<usize> {
+    let lock = match lock {
+        None => return (0..manifest.modules.len()).collect(),
+        Some(lock) => lock,
+    };
+    let diff = lock.diff(&lock_manifest(manifest));
+    let changed = diff.changed.iter()
+        .filter(|change| change.changes.iter().any(|field| !matches!(field, FieldChange::Other)))
+        .map(|change| &change.key);
+    let selected = diff.added.iter().chain(changed).collect::<HashSet<_>>();
+    keyed_modules(manifest).iter().enumerate()
+        .filter(|(_, (key, _))| selected.contains(key))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::test_utils::{manifest, test_dir};
+
+    use super::{lock_written, modules_changed_since, read_lock, write_lock};
+
+    const INSTALLED: &str = r#"
+locations:
+  entries:
+    bg1npc:
+      http: https://example.com/bg1npc-v1.zip
+modules:
+  - name: dlcmerger
+    components: [1]
+  - name: bg1npc
+    components: [0, 1]
+  - name: my_tweaks
+    components: [2]
+    description: first version
+"#;
+
+    #[test]
+    fn only_changed_modules_are_selected() {
+        let temp = test_dir();
+        let lock_path = temp.path().join("modda.lock.yml");
+        write_lock(&lock_path, &manifest(INSTALLED)).unwrap();
+        let updated = manifest(&INSTALLED
+            .replace("bg1npc-v1.zip", "bg1npc-v2.zip")
+            .replace("first version", "only the description changed")
+            .replace("  - name: dlcmerger\n", "  - name: iwdification\n    components: [10]\n  - name: dlcmerger\n"));
+
+        let lock = read_lock(&lock_path).unwrap();
+        let selected = modules_changed_since(lock.as_ref(), &updated);
+
+        // iwdification is new, the bg1npc archive changed in `locations`
+        assert_eq!(selected, HashSet::from([0, 2]));
+    }
+
+    #[test]
+    fn lock_is_updated() {
+        let temp = test_dir();
+        let lock_path = temp.path().join("modda.lock.yml");
+        let updated = manifest(&INSTALLED.replace("components: [0, 1]", "components: [0, 1, 3]"));
+
+        assert_eq!(read_lock(&lock_path).unwrap(), None);
+        assert_eq!(lock_written(&lock_path).unwrap(), None);
+        assert_eq!(modules_changed_since(None, &updated), HashSet::from([0, 1, 2]));
+        write_lock(&lock_path, &manifest(INSTALLED)).unwrap();
+        assert_eq!(modules_changed_since(read_lock(&lock_path).unwrap().as_ref(), &updated), HashSet::from([1]));
+
+        let written = lock_written(&lock_path).unwrap().unwrap();
+        write_lock(&lock_path, &updated).unwrap();
+        assert!(lock_written(&lock_path).unwrap().unwrap() >= written);
+
+        let lock = read_lock(&lock_path).unwrap().unwrap();
+        assert!(lock.locations.entries.is_empty());
+        assert!(modules_changed_since(Some(&lock), &updated).is_empty());
+    }
+}
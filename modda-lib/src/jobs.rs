@@ -0,0 +1,267 @@
+
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use futures_util::stream::{self, StreamExt};
+
+use crate::config::Config;
+
+/// Upper bounds for the worker pools, derived from the single `jobs` setting (`--jobs` or `jobs` in the config).
+///
+/// The archives are downloaded ahead of the installation, the entries of a zip archive are extracted and the globs
+/// of generated mods and file modules are walked by that many workers; the modules are still installed in order.
+/// `jobs: 1` means everything runs serially, one operation after the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jobs {
+    pub download: usize,
+    pub extract: usize,
+    pub glob: usize,
+}
+
+impl Jobs {
+    pub fn new(count: NonZeroUsize) -> Self {
+        let count = count.get();
+        Jobs { download: count, extract: count, glob: count }
+    }
+
+    pub fn serial() -> Self {
+        Jobs::new(NonZeroUsize::MIN)
+    }
+
+    /// Command-line value wins over the config value, falls back to the CPU count.
+    pub fn resolve(cli_jobs: Option<usize>, config: &Config) -> Result<Self> {
+        match cli_jobs.or(config.jobs) {
+            None => Ok(Jobs::new(default_jobs())),
+            Some(count) => match NonZeroUsize::new(count) {
+                None => bail!("Invalid jobs value {} - must be at least 1", count),
+                Some(count) => Ok(Jobs::new(count)),
+            }
+        }
+    }
+
+    pub fn is_serial(&self) -> bool {
+        self.download == 1 && self.extract == 1 && self.glob == 1
+    }
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Jobs::new(default_jobs())
+    }
+}
+
+fn default_jobs() -> NonZeroUsize {
+    std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+/// Runs the futures with at most `limit` of them in flight at once; results keep the input order.
+/// With `limit == 1` each future is only started after the previous one completed.
+pub async fn run_bounded<I, F, T>(limit: usize, tasks: I) -> Vec<T>
+        where I: IntoIterator<Item = F>, F: Future<Output = T> {
+    stream::iter(tasks).buffered(limit.max(1)).collect().await
+}
+
+/// Worker threads for blocking work (extraction, glob walking), at most `limit` tasks at once.<br>
+/// Also records the most tasks that ran at the same time.
+#[derive(Debug)]
+pub struct Pool {
+    limit: usize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl Pool {
+    pub fn new(limit: usize) -> Self {
+        Pool { limit: limit.max(1), in_flight: AtomicUsize::new(0), max_in_flight: AtomicUsize::new(0) }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Applies `task` to each item, at most `limit` at once; results keep the input order.<br>
+    /// With `limit == 1` (or a single item), the items are processed on the calling thread, one after the other.
+    pub fn map<T, R, F>(&self, items: Vec<T>, task: F) -> Vec<R>
+            where T: Send, R: Send, F: Fn(T) -> R + Sync {
+        let workers = self.limit.min(items.len());
+        if workers <= 1 {
+            return items.into_iter().map(|item| self.tracked(|| task(item))).collect();
+        }
+        let queue = Mutex::new(items.into_iter().enumerate());
+        let results = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let next = queue.lock().unwrap().next();
+                    match next {
+                        None => break,
+                        Some((index, item)) => {
+                            let result = self.tracked(|| task(item));
+                            results.lock().unwrap().push((index, result));
+                        }
+                    }
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// The most tasks that ran at the same time since the pool was created.
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    fn tracked<R>(&self, task: impl FnOnce() -> R) -> R {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        let result = task();
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use crate::archive_layout::Layout;
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::download::Downloader;
+    use crate::file_installer::FileInstaller;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::file_module_origin::FileModuleOrigin;
+    use crate::module::global_locations::GlobalLocations;
+    use crate::module::location::http::Http;
+    use crate::module::location::location::{ConcreteLocation, Location};
+    use crate::module::location::source::Source;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::obtain::get_module::ModuleDownload;
+    use crate::test_utils::server::{ok, TestServer};
+    use crate::test_utils::test_dir;
+
+    use super::{run_bounded, Jobs, Pool};
+
+    async fn max_in_flight(limit: usize) -> usize {
+        let current = AtomicUsize::new(0);
+        let max = AtomicUsize::new(0);
+        let tasks = (0..6).map(|_| async {
+            let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            current.fetch_sub(1, Ordering::SeqCst);
+        });
+        run_bounded(limit, tasks).await;
+        max.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn jobs_1_serializes() {
+        let jobs = Jobs::resolve(Some(1), &Config::default()).unwrap();
+        assert!(jobs.is_serial());
+        assert_eq!(max_in_flight(jobs.download).await, 1);
+    }
+
+    #[tokio::test]
+    async fn jobs_bounds_concurrency() {
+        assert_eq!(max_in_flight(3).await, 3);
+    }
+
+    #[tokio::test]
+    async fn run_bounded_keeps_order() {
+        let tasks = (0..5u64).map(|index| async move {
+            tokio::time::sleep(Duration::from_millis(10 * (5 - index))).await;
+            index
+        });
+        assert_eq!(run_bounded(4, tasks).await, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn pool_bounds_threads_and_keeps_order() {
+        for limit in [1, 3] {
+            let pool = Pool::new(limit);
+            let results = pool.map((0..6u64).collect(), |index| {
+                std::thread::sleep(Duration::from_millis(10 * (6 - index)));
+                index
+            });
+            assert_eq!(results, vec![0, 1, 2, 3, 4, 5]);
+            assert_eq!(pool.max_in_flight(), limit);
+        }
+    }
+
+    /// Downloads 3 archives, extracts one and copies its files with 3 globs, each in the pool sized by `jobs`;
+    /// returns the most operations of each kind that ran at the same time.
+    fn max_in_flight_per_pool(jobs: usize) -> (usize, usize, usize) {
+        let config = Config { jobs: Some(jobs), ..Default::default() };
+        let jobs = Jobs::resolve(None, &config).unwrap();
+        let (extract_pool, glob_pool) = (Pool::new(jobs.extract), Pool::new(jobs.glob));
+        let temp = test_dir();
+        let game_dir = CanonPath::new(temp.path()).unwrap();
+        let (global, global_locations, opts) = (Global::default(), GlobalLocations::default(), Install::default());
+
+        let server = TestServer::start_with_delay(Duration::from_millis(100), |_| ok(b"archive"));
+        let modules = (0..3).map(|index| WeiduMod {
+            name: lwc!(&format!("mod_{}", index)),
+            location: Some(Location::Concrete { concrete: ConcreteLocation {
+                source: Source::Http(Http::from(&server.url(&format!("/mod_{}.zip", index)))),
+                ..Default::default()
+            }}),
+            ..Default::default()
+        }).collect::<Vec<_>>();
+        let downloader = Downloader::new();
+        let cache = Cache::Path(temp.path().join("cache"));
+        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts, &downloader, &game_dir, &cache)
+            .with_extract_pool(&extract_pool);
+        module_download.prefetch(&modules.iter().collect::<Vec<_>>(), jobs.download);
+        assert_eq!(server.requests().len(), 3);
+
+        let archive = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/archives/my_mod.zip");
+        let location = ConcreteLocation {
+            layout: Some(Layout::with_tp2_default_and_strip(1)),
+            extract_location: Some(temp.path().join("extract").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        module_download.extractor.extract_files(&archive, &lwc!("my_mod"), &location).unwrap();
+
+        let installer = FileInstaller::new(&global, &game_dir, &game_dir, &config).with_glob_pool(&glob_pool);
+        let origins = ["*.tp2", "tra/*.tra", "*.txt"].map(|glob| FileModuleOrigin::Absolute {
+            absolute: game_dir.join_path("my_mod").to_string_lossy().to_string(),
+            glob: Some(glob.to_string()),
+            dir_copy: None,
+            literal: None,
+        });
+        installer.copy_from_origins(&lwc!("copy"), &origins.iter().collect::<Vec<_>>(), &temp.path().join("copy"), false).unwrap();
+        assert!(temp.path().join("copy").join("english.tra").is_file());
+
+        (server.max_in_flight(), extract_pool.max_in_flight(), glob_pool.max_in_flight())
+    }
+
+    #[test]
+    fn jobs_propagates_to_each_pool() {
+        assert_eq!(max_in_flight_per_pool(1), (1, 1, 1));
+
+        let (downloads, extractions, globs) = max_in_flight_per_pool(2);
+        assert_eq!(downloads, 2);
+        assert!((1..=2).contains(&extractions), "{} extractions at once", extractions);
+        assert!((1..=2).contains(&globs), "{} glob walks at once", globs);
+    }
+
+    #[test]
+    fn jobs_default_and_invalid() {
+        let jobs = Jobs::resolve(None, &Config::default()).unwrap();
+        assert!(jobs.download >= 1);
+        assert_eq!(jobs, Jobs::new(NonZeroUsize::new(jobs.download).unwrap()));
+        Jobs::resolve(Some(0), &Config::default()).unwrap_err();
+    }
+}
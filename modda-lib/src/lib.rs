@@ -1,16 +1,30 @@
 
 pub mod archive_layout;
 pub mod archive_extractor;
+pub mod archive_name_encoding;
 pub mod apply_patch;
 pub mod args;
+pub mod bandwidth;
 pub mod cache;
 pub mod canon_path;
+pub mod case_collisions;
 pub mod chitin;
+pub mod clean_reinstall;
 pub mod credentials;
 pub mod download;
+pub mod effective_config;
+pub mod extractor_hint;
 pub mod file_installer;
+pub mod file_system;
+pub mod game_dir;
+pub mod github_resolution;
 pub mod global;
+pub mod install_journal;
+pub mod install_lock;
+pub mod iemod;
+pub mod jobs;
 pub mod list_components;
+pub mod local_paths;
 pub mod log_parser;
 pub mod lowercase;
 pub mod module;
@@ -21,14 +35,25 @@ pub mod patch_source;
 pub mod post_install;
 pub mod process_weidu_mod;
 pub mod progname;
+pub mod prune_local;
+pub mod remote_zip;
+pub mod required_tools;
 pub mod run_result;
+pub mod run_warnings;
 pub mod run_weidu;
+pub mod split_archive;
+pub mod tar_symlinks;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod config;
 pub mod sub;
 pub mod timeline;
 pub mod tp2;
 pub mod tp2_template;
+pub mod tp2_components;
 pub mod unique_component;
 pub mod utils;
 pub mod weidu_conf;
+pub mod weidu_output;
+pub mod weidu_version;
 pub mod modda_context;
@@ -0,0 +1,167 @@
+
+use std::path::{Path, PathBuf};
+
+use crate::module::file_module_origin::FileModuleOrigin;
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::manifest_diff::effective_location;
+use crate::module::module::Module;
+use crate::prune_local::{check_relative, local_mod_path};
+
+/// The problems with the files the manifest takes from the computer: `local`/`path` module sources and
+/// `local`/`absolute` generated module origins that don't exist, or that are not allowed (a `local` value going
+/// outside of `local_mods`/`local_files`, the root directory as `absolute` origin).<br>
+/// Paths are resolved like the installation does, relative to `manifest_root`; remote sources are not checked.
+pub fn local_path_errors(manifest: &Manifest, manifest_root: &Path) -> Vec<String> {
+    let global = &manifest.global;
+    let mut errors = vec![];
+    let local_mods = match global.local_mods.as_ref().map(|dir| check_relative(dir, "local_mods")).transpose() {
+        Err(error) => { errors.push(error.to_string()); None }
+        Ok(dir) => Some(manifest_root.join(dir.unwrap_or_default())),
+    };
+    let local_files = match global.local_files.as_ref().map(|dir| check_relative(dir, "local_files")).transpose() {
+        Err(error) => { errors.push(error.to_string()); None }
+        Ok(dir) => Some(manifest_root.join(dir.unwrap_or_default())),
+    };
+    let mut mods_index = None;
+    for module in &manifest.modules {
+        match module {
+            Module::Mod { weidu_mod } => {
+                let location = effective_location(manifest, &weidu_mod.name, &weidu_mod.location);
+                let context = format!("module {}", weidu_mod.name);
+                match location.map(|location| &location.source) {
+                    Some(Source::Local { local }) => if let Some(local_mods) = &local_mods {
+                        let use_index = global.local_mods_index.unwrap_or(false);
+                        match local_mod_path(local_mods, local, use_index, &mut mods_index) {
+                            Err(error) => errors.push(format!("{}: {}", context, error)),
+                            Ok(path) => errors.extend(missing(&context, "local", local, path)),
+                        }
+                    }
+                    Some(Source::Absolute { path }) =>
+                        errors.extend(missing(&context, "path", path, Some(PathBuf::from(path)))),
+                    _ => {}
+                }
+            }
+            Module::Generated { gen } => {
+                for (index, origin) in gen.files.iter().enumerate() {
+                    let context = format!("module {}, files[{}]", gen.gen_mod, index);
+                    errors.extend(origin_errors(&context, origin, &local_files));
+                }
+            }
+            Module::File { file } => {
+                let context = format!("module {}, from", file.file_mod);
+                errors.extend(origin_errors(&context, &file.from, &local_files));
+            }
+        }
+    }
+    errors
+}
+
+fn origin_errors(context: &str, origin: &FileModuleOrigin, local_files: &Option<PathBuf>) -> Vec<String> {
+    let mut errors = vec![];
+    match origin {
+        FileModuleOrigin::Local { local, .. } => if let Some(local_files) = local_files {
+            match check_relative(local, "local") {
+                Err(error) => errors.push(format!("{}: {}", context, error)),
+                Ok(relative) => errors.extend(missing(context, "local", local, Some(local_files.join(relative)))),
+            }
+        }
+        FileModuleOrigin::Absolute { absolute, .. } => {
+            let path = Path::new(absolute);
+            if !path.is_absolute() {
+                errors.push(format!("{}: absolute {:?} is not an absolute path", context, absolute));
+            } else if path.parent().is_none() {
+                errors.push(format!("{}: absolute {:?} is not allowed as origin base, use a subdirectory", context, absolute));
+            } else {
+                errors.extend(missing(context, "absolute", absolute, Some(path.to_path_buf())));
+            }
+        }
+    }
+    errors
+}
+
+/// `path` is where `value` (of `property`) was resolved, `None` if it was not found at all.
+fn missing(context: &str, property: &str, value: &str, path: Option<PathBuf>) -> Option<String> {
+    match path {
+        Some(path) if path.exists() => None,
+        Some(path) => Some(format!("{}: {} {:?} not found ({:?})", context, property, value, path)),
+        None => Some(format!("{}: {} {:?} not found in the local mods index", context, property, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::module::manifest::Manifest;
+    use crate::test_utils::test_dir;
+
+    use super::local_path_errors;
+
+    const MANIFEST: &str = r#"
+version: "1"
+global:
+  lang_dir: en_US
+  local_mods: mods
+  local_files: files
+locations:
+  entries:
+    registered:
+      local: registered.zip
+modules:
+  - name: my_mod
+    components: all
+    location:
+      local: my_mod.zip
+  - name: registered
+    components: all
+  - name: from_github
+    components: all
+    location:
+      github_user: some_user
+      repository: some_repo
+      tag: v1
+  - gen_mod: my_files
+    component: { index: 0 }
+    files:
+      - local: my_files
+      - local: ../../outside
+"#;
+
+    #[test]
+    fn all_missing_local_paths_are_reported() {
+        let temp = test_dir();
+        std::fs::create_dir_all(temp.path().join("mods")).unwrap();
+        std::fs::write(temp.path().join("mods/my_mod.zip"), "").unwrap();
+        let manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+
+        let errors = local_path_errors(&manifest, temp.path());
+
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("module registered: local \"registered.zip\" not found"), "{}", errors[0]);
+        assert!(errors[1].starts_with("module my_files, files[0]: local \"my_files\" not found"), "{}", errors[1]);
+        assert!(errors[2].starts_with("module my_files, files[1]: Invalid local value"), "{}", errors[2]);
+    }
+
+    #[test]
+    fn missing_local_file_is_reported_by_the_validation() {
+        let temp = test_dir();
+        let manifest_path = temp.path().join("manifest.yml");
+        let manifest_root = CanonPath::new(temp.path()).unwrap();
+        std::fs::write(&manifest_path, MANIFEST.replace("      - local: ../../outside\n", "")).unwrap();
+        let manifest_path = manifest_path.to_str().unwrap();
+
+        // loading the manifest doesn't need the files
+        let manifest = Manifest::assemble_from_path(manifest_path, &manifest_root, &Config::default()).unwrap();
+        let error = manifest.validate_local_paths(temp.path()).unwrap_err().to_string();
+        assert!(error.contains("my_mod.zip"), "{}", error);
+        assert!(error.contains("registered.zip"), "{}", error);
+
+        for path in ["mods/my_mod.zip", "mods/registered.zip", "files/my_files/readme.txt"] {
+            std::fs::create_dir_all(temp.path().join(path).parent().unwrap()).unwrap();
+            std::fs::write(temp.path().join(path), "").unwrap();
+        }
+        manifest.validate_local_paths(temp.path()).unwrap();
+    }
+}
@@ -92,6 +92,8 @@ pub fn check_install_complete(module: &Module) -> Result<()> {
     match module {
         Module::Mod { weidu_mod } => check_install_weidu_mod(weidu_mod),
         Module::Generated { gen } => check_install_weidu_mod(&gen.as_weidu()),
+        // not a weidu mod, nothing in weidu.log
+        Module::File { .. } => Ok(()),
     }
 }
 
@@ -109,6 +111,7 @@ fn check_installed_components(module: &WeiduMod) -> Result<Vec<u32>> {
         Components::None => Ok(vec![]),
         Components::Ask => Ok(vec![]),
         Components::All => Ok(vec![]),
+        Components::File { file } => bail!("Components file {} for mod {} was not loaded", file, module.name),
         Components::List(components) => {
             let log_rows = match parse_weidu_log(Some(&module.name)) {
                 Ok(log_rows) => log_rows,
@@ -117,11 +120,11 @@ fn check_installed_components(module: &WeiduMod) -> Result<Vec<u32>> {
             let installed = log_rows.iter().map(|row| row.component_index).collect::<HashSet<_>>();
             info!("installed={:?}", installed);
 
-            let missing = components.iter().filter(|component|
-                !installed.contains(&component.index())
-            )
-            .map(|component| component.index())
-            .collect::<Vec<_>>();
+            // named components are only resolved (from the tp2) when running weidu, they are not checked
+            let missing = components.iter()
+                .filter_map(|component| component.index())
+                .filter(|index| !installed.contains(index))
+                .collect::<Vec<_>>();
             Ok(missing)
         }
     }
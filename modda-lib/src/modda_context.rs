@@ -10,7 +10,9 @@ use crate::args::Install;
 use crate::canon_path::CanonPath;
 use crate::file_installer::FileInstaller;
 use crate::config::Config;
+use crate::jobs::Jobs;
 use crate::obtain::get_module::ModuleDownload;
+use crate::run_warnings::WarningCollector;
 
 
 pub struct ModdaContext<'a> {
@@ -19,7 +21,9 @@ pub struct ModdaContext<'a> {
     pub opts: &'a Install,
     pub module_downloader: &'a ModuleDownload<'a>,
     pub file_installer: &'a FileInstaller<'a>,
-    pub log: RefCell<Option<BufWriter<File>>>
+    pub jobs: Jobs,
+    pub log: RefCell<Option<BufWriter<File>>>,
+    pub warnings: &'a WarningCollector,
 }
 
 impl <'a> ModdaContext<'a> {
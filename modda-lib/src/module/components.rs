@@ -1,10 +1,15 @@
 
 use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 use std::str::FromStr;
 use std::marker::PhantomData;
 
+use anyhow::bail;
 use serde::{Deserialize, Deserializer, Serialize};
-use serde::de::{self, Visitor, SeqAccess};
+use serde::de::{self, Visitor, SeqAccess, MapAccess};
+use serde::ser::SerializeMap;
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
@@ -13,6 +18,9 @@ pub enum Components {
     None,
     All,
     List(Vec<Component>),
+    /// The list of components is in a separate YAML (or JSON) file, path relative to the manifest.<br>
+    /// Replaced by the `List` read from the file when the manifest is assembled.
+    File { file: String },
 }
 
 impl Default for Components {
@@ -28,6 +36,26 @@ impl Components {
             _ => false,
         }
     }
+
+    /// If this is a `file` reference, reads the components list from the file
+    /// (`manifest_root` is the manifest location).
+    pub fn load_file(&self, manifest_root: &Path) -> anyhow::Result<Components> {
+        let file_name = match self {
+            Components::File { file } => file,
+            other => return Ok(other.clone()),
+        };
+        let path = manifest_root.join(file_name);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) => bail!("Could not open components file {:?}\n -> {:?}", path, error),
+        };
+        // JSON is valid YAML
+        let components: Vec<Component> = match serde_yaml::from_reader(BufReader::new(file)) {
+            Ok(components) => components,
+            Err(error) => bail!("Could not read components list from file {:?}\n -> {}", path, error),
+        };
+        Ok(Components::List(components))
+    }
 }
 
 impl Serialize for Components {
@@ -38,6 +66,11 @@ impl Serialize for Components {
             Components::None => serializer.serialize_str("none"),
             Components::All => serializer.serialize_str("all"),
             Components::List(list) => serializer.collect_seq(list.iter()),
+            Components::File { file } => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("file", file)?;
+                map.end()
+            }
         }
     }
 }
@@ -80,15 +113,24 @@ impl FromStr for Components {
 pub enum Component {
     Simple(u32),
     Full(FullComponent),
+    /// Component name, as displayed by weidu in the selected language.<br>
+    /// Resolved to an index by reading the mod tp2 (and tra files) at install time.
+    Named(String),
 }
 
 impl Component {
-    pub fn index(&self) -> u32 {
+    /// The component index, `None` for a named component that was not resolved.
+    pub fn index(&self) -> Option<u32> {
         match &self {
-            Component::Simple(index) => *index,
-            Component::Full(full_component) => full_component.index,
+            Component::Simple(index) => Some(*index),
+            Component::Full(full_component) => Some(full_component.index),
+            Component::Named(_) => None,
         }
     }
+
+    pub fn is_named(&self) -> bool {
+        matches!(self, Component::Named(_))
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
@@ -112,7 +154,7 @@ where
         type Value = Components;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("'ask', 'none' or list of components")
+            formatter.write_str("'ask', 'none', list of components or components file reference")
         }
 
         fn visit_str<E>(self, value: &str) -> Result<Components, E>
@@ -127,6 +169,16 @@ where
         where A: SeqAccess<'de>, {
             Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq))
         }
+
+        fn visit_map<A>(self, map: A) -> Result<Components, A::Error>
+        where A: MapAccess<'de>, {
+            #[derive(Deserialize)]
+            #[serde(deny_unknown_fields)]
+            struct ComponentsFile { file: String }
+
+            let ComponentsFile { file } = Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))?;
+            Ok(Components::File { file })
+        }
     }
 
     deserializer.deserialize_any(StringOrComponents(PhantomData))
@@ -138,7 +190,7 @@ mod test_deserialize {
     use crate::lowercase::lwc;
     use crate::module::weidu_mod::WeiduMod;
 
-    use super::{Component, Components};
+    use super::{Component, Components, FullComponent};
 
     #[test]
     fn deserialize_ask() {
@@ -209,4 +261,47 @@ mod test_deserialize {
             }
         );
     }
+
+    #[test]
+    fn deserialize_list_with_names() {
+        let yaml = r#"
+        name: mod_name
+        components:
+            - 1
+            - Faster travel
+            - index: 3
+              component_name: Better items
+        "#;
+
+        let module: WeiduMod = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            module.components,
+            Components::List(vec![
+                Component::Simple(1),
+                Component::Named("Faster travel".to_string()),
+                Component::Full(FullComponent { index: 3, component_name: "Better items".to_string() }),
+            ])
+        );
+    }
+
+    #[test]
+    fn deserialize_file() {
+        let yaml = r#"
+        name: mod_name
+        components:
+            file: some/components.yml
+        "#;
+
+        let module: WeiduMod = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            module,
+            WeiduMod {
+                name: lwc!("mod_name"),
+                components: Components::File { file: "some/components.yml".to_string() },
+                ..Default::default()
+            }
+        );
+        let serialized = serde_yaml::to_string(&module.components).unwrap();
+        assert_eq!(serialized.trim(), "file: some/components.yml");
+    }
 }
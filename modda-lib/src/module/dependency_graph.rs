@@ -0,0 +1,216 @@
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::lowercase::LwcString;
+
+use super::install_comment::InstallationHint;
+use super::manifest::Manifest;
+
+/// Why a module must be installed before another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    /// `requires` property of the second module.
+    Requires,
+    /// `installation.before`/`installation.after` hints.
+    Order,
+}
+
+/// `from` must be installed before `to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyEdge {
+    pub from: LwcString,
+    pub to: LwcString,
+    pub kind: DependencyKind,
+}
+
+/// Modules of a manifest (one node per name, in manifest order) and the install order constraints
+/// between them.<br>
+/// Relations with mods that are not in the manifest are ignored (they may already be installed).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyGraph {
+    pub nodes: Vec<LwcString>,
+    pub edges: Vec<DependencyEdge>,
+}
+
+impl DependencyGraph {
+    pub fn from_manifest(manifest: &Manifest) -> Self {
+        let nodes = manifest.modules.iter().map(|module| module.get_name().clone()).unique().collect::<Vec<_>>();
+        let mut edges = vec![];
+        let mut add = |from: &LwcString, to: &LwcString, kind: DependencyKind| {
+            let edge = DependencyEdge { from: from.clone(), to: to.clone(), kind };
+            if from != to && nodes.contains(from) && nodes.contains(to) && !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        };
+        for module in &manifest.modules {
+            let name = module.get_name();
+            for required in module.get_requires() {
+                add(required, name, DependencyKind::Requires);
+            }
+            if let Some(hints) = module.get_installation_hints() {
+                for after in hints.after.iter().flat_map(hint_names) {
+                    add(&after, name, DependencyKind::Order);
+                }
+                for before in hints.before.iter().flat_map(hint_names) {
+                    add(name, &before, DependencyKind::Order);
+                }
+            }
+        }
+        DependencyGraph { nodes, edges }
+    }
+
+    /// The modules that must be installed before `name`.
+    pub fn dependencies_of(&self, name: &LwcString) -> Vec<&LwcString> {
+        self.edges.iter().filter(|edge| &edge.to == name).map(|edge| &edge.from).unique().collect()
+    }
+
+    /// A cycle of modules (the first one is repeated at the end), if there is one.
+    pub fn find_cycle(&self) -> Option<Vec<LwcString>> {
+        let successors = self.successors();
+        // 0: not visited, 1: on the current path, 2: done
+        let mut state = vec![0u8; self.nodes.len()];
+        let mut path = vec![];
+        for start in 0..self.nodes.len() {
+            if let Some(cycle) = self.visit(start, &successors, &mut state, &mut path) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+
+    fn visit(&self, node: usize, successors: &[Vec<usize>], state: &mut [u8], path: &mut Vec<usize>) -> Option<Vec<LwcString>> {
+        match state[node] {
+            2 => return None,
+            1 => {
+                let start = path.iter().position(|index| *index == node).unwrap_or(0);
+                let cycle = path[start..].iter().chain(std::iter::once(&node));
+                return Some(cycle.map(|index| self.nodes[*index].clone()).collect());
+            }
+            _ => {}
+        }
+        state[node] = 1;
+        path.push(node);
+        for next in &successors[node] {
+            if let Some(cycle) = self.visit(*next, successors, state, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        state[node] = 2;
+        None
+    }
+
+    /// The module names in an order that satisfies all the constraints; when there is a choice,
+    /// the manifest order is kept.<br>
+    /// Fails with the modules involved if there is a cycle.
+    pub fn topological_order(&self) -> Result<Vec<LwcString>> {
+        let successors = self.successors();
+        let mut remaining_deps = vec![0usize; self.nodes.len()];
+        for next in successors.iter().flatten() {
+            remaining_deps[*next] += 1;
+        }
+        let mut ready = (0..self.nodes.len()).filter(|index| remaining_deps[*index] == 0).collect::<BTreeSet<_>>();
+        let mut result = vec![];
+        while let Some(node) = ready.pop_first() {
+            result.push(self.nodes[node].clone());
+            for next in &successors[node] {
+                remaining_deps[*next] -= 1;
+                if remaining_deps[*next] == 0 {
+                    ready.insert(*next);
+                }
+            }
+        }
+        if result.len() < self.nodes.len() {
+            let cycle = self.find_cycle().unwrap_or_default();
+            bail!("Dependency cycle between modules\n -> {}", cycle.iter().join(" -> "));
+        }
+        Ok(result)
+    }
+
+    /// Indexes (in `nodes`) of the modules that must be installed after each module.
+    fn successors(&self) -> Vec<Vec<usize>> {
+        let positions = self.nodes.iter().enumerate().map(|(index, name)| (name, index)).collect::<HashMap<_, _>>();
+        let mut successors = vec![vec![]; self.nodes.len()];
+        for edge in &self.edges {
+            let (from, to) = (positions[&edge.from], positions[&edge.to]);
+            if !successors[from].contains(&to) {
+                successors[from].push(to);
+            }
+        }
+        successors
+    }
+}
+
+fn hint_names(hint: &InstallationHint) -> Vec<LwcString> {
+    match hint {
+        InstallationHint::ModHint(name) => vec![LwcString::new(name)],
+        InstallationHint::ComponentHint { name, .. } => vec![LwcString::new(name)],
+        InstallationHint::List(hints) => hints.iter().flat_map(hint_names).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lowercase::lwc;
+    use crate::test_utils::manifest;
+
+    use super::{DependencyEdge, DependencyKind};
+
+    #[test]
+    fn topological_order_of_dag() {
+        let manifest = manifest(r#"
+modules:
+  - name: tweaks
+    components: [1]
+    requires: [bg1npc, ascension]
+  - name: bg1npc
+    components: [1]
+    installation:
+      after: { name: ascension, component: 0 }
+  - name: ascension
+    components: [1]
+  - name: not_related
+    components: [1]
+    requires: [not_in_manifest]
+  - gen_mod: my_tweaks
+    files:
+      - local: tweaks
+    requires: [ascension]
+"#);
+        let graph = manifest.dependency_graph();
+
+        assert_eq!(graph.edges.len(), 4);
+        assert!(graph.edges.contains(&DependencyEdge { from: lwc!("ascension"), to: lwc!("bg1npc"), kind: DependencyKind::Order }));
+        assert_eq!(graph.dependencies_of(&lwc!("tweaks")), vec![&lwc!("bg1npc"), &lwc!("ascension")]);
+        assert_eq!(graph.find_cycle(), None);
+        assert_eq!(graph.topological_order().unwrap(),
+                    vec![lwc!("ascension"), lwc!("bg1npc"), lwc!("tweaks"), lwc!("not_related"), lwc!("my_tweaks")]);
+    }
+
+    #[test]
+    fn cycle_is_reported_with_module_names() {
+        let manifest = manifest(r#"
+modules:
+  - name: first
+    components: [1]
+  - name: aaa
+    components: [1]
+    requires: [ccc]
+  - name: bbb
+    components: [1]
+    requires: [aaa]
+  - name: ccc
+    components: [1]
+    installation:
+      after: bbb
+"#);
+        let graph = manifest.dependency_graph();
+
+        assert_eq!(graph.find_cycle(), Some(vec![lwc!("aaa"), lwc!("bbb"), lwc!("ccc"), lwc!("aaa")]));
+        let error = graph.topological_order().unwrap_err().to_string();
+        assert!(error.contains("aaa -> bbb -> ccc -> aaa"), "{}", error);
+    }
+}
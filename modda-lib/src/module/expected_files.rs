@@ -0,0 +1,109 @@
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use globwalk::GlobWalkerBuilder;
+use itertools::Itertools;
+
+use crate::run_warnings::WarningCollector;
+
+use super::module::Module;
+
+/// Checks that each `expect` pattern of the module matches something in the game directory (once it's installed).<br>
+/// Fails unless the module has `ignore_warnings`, in which case they are added to `warnings`.
+pub fn check_expected_files(module: &Module, game_dir: &Path, warnings: &WarningCollector) -> Result<()> {
+    let missing = unmatched_patterns(game_dir, module.get_expect())?;
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let message = format!("expected files were not produced by the installation of {}\n  - {}",
+                            module.get_name(), missing.iter().join("\n  - "));
+    if module.ignores_warnings() {
+        warnings.warn(Some(module.get_name()), "expect", &message);
+        Ok(())
+    } else {
+        bail!("{}\n -> a component may have failed silently", message)
+    }
+}
+
+/// The patterns (relative to `game_dir`, case-insensitive) that don't match any file or directory.
+pub fn unmatched_patterns<'a>(game_dir: &Path, patterns: &'a [String]) -> Result<Vec<&'a String>> {
+    let mut unmatched = vec![];
+    for pattern in patterns {
+        let walker = match GlobWalkerBuilder::from_patterns(game_dir, &[pattern]).case_insensitive(true).build() {
+            Err(error) => bail!("Invalid glob pattern {}\n -> {:?}", pattern, error),
+            Ok(walker) => walker,
+        };
+        if walker.into_iter().filter_map(Result::ok).next().is_none() {
+            unmatched.push(pattern);
+        }
+    }
+    Ok(unmatched)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::lowercase::lwc;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::run_warnings::WarningCollector;
+    use crate::test_utils::test_dir;
+
+    use super::{check_expected_files, unmatched_patterns};
+
+    fn game_dir() -> tempfile::TempDir {
+        let temp = test_dir();
+        std::fs::create_dir_all(temp.path().join("override")).unwrap();
+        std::fs::write(temp.path().join("override").join("SW1H01.ITM"), "").unwrap();
+        std::fs::write(temp.path().join("override").join("ajantis.cre"), "").unwrap();
+        temp
+    }
+
+    fn module(expect: &[&str], ignore_warnings: bool) -> Module {
+        Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!("my_mod"),
+            expect: expect.iter().map(|pattern| pattern.to_string()).collect(),
+            ignore_warnings,
+            ..WeiduMod::default()
+        }}
+    }
+
+    #[test]
+    fn satisfied_expectations() {
+        let game_dir = game_dir();
+        let warnings = WarningCollector::new();
+        let module = module(&["override/sw1h01.itm", "override/*.cre", "override"], false);
+
+        check_expected_files(&module, game_dir.path(), &warnings).unwrap();
+
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn unsatisfied_expectations_fail() {
+        let game_dir = game_dir();
+        let warnings = WarningCollector::new();
+        let module = module(&["override/sw1h01.itm", "override/my_mod*.spl", "weidu_external/my_mod"], false);
+
+        let error = check_expected_files(&module, game_dir.path(), &warnings).unwrap_err().to_string();
+
+        assert!(error.contains("override/my_mod*.spl"), "{}", error);
+        assert!(error.contains("weidu_external/my_mod"), "{}", error);
+        assert!(!error.contains("sw1h01"), "{}", error);
+        assert_eq!(unmatched_patterns(game_dir.path(), module.get_expect()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn unsatisfied_expectations_with_ignore_warnings() {
+        let game_dir = game_dir();
+        let warnings = WarningCollector::new();
+        let module = module(&["override/my_mod*.spl"], true);
+
+        check_expected_files(&module, game_dir.path(), &warnings).unwrap();
+
+        let warnings = warnings.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("override/my_mod*.spl"), "{:?}", warnings);
+    }
+}
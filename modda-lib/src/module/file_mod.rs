@@ -1,22 +1,133 @@
 
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use path_clean::PathClean;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+use crate::canon_path::CanonPath;
+use crate::global::Global;
 use crate::lowercase::LwcString;
 use crate::post_install::PostInstall;
 
+use super::expected_files::unmatched_patterns;
 use super::file_module_origin::FileModuleOrigin;
 
 
 #[skip_serializing_none]
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
 pub struct FileModule {
     pub file_mod: LwcString,
     pub description: Option<String>,
     pub from: FileModuleOrigin,
-    /// Path from game directory (location of chitin.key)
+    /// Path from game directory (location of chitin.key), or an absolute path to put the files
+    /// outside of the game directory (a documentation folder for example).
     pub to: String,
     pub post_install: Option<PostInstall>,
-    #[serde(default)]
-    pub allow_overwrite: bool,
+    /// Defaults to the manifest `global.allow_overwrite`, false if it isn't set.
+    pub allow_overwrite: Option<bool>,
+    /// The files are only copied if this pattern (relative to the game directory, case-insensitive)
+    /// matches a file or directory.
+    pub requires_file: Option<String>,
+    /// The files are not copied if this pattern (relative to the game directory, case-insensitive)
+    /// matches a file or directory.
+    pub forbids_file: Option<String>,
+}
+
+impl FileModule {
+    pub fn allow_overwrite(&self, global: &Global) -> bool {
+        self.allow_overwrite.or(global.allow_overwrite).unwrap_or(false)
+    }
+
+    /// Why the files must not be copied (`requires_file` or `forbids_file` not satisfied), `None` if they can be.
+    pub fn skip_reason(&self, game_dir: &Path) -> Result<Option<String>> {
+        if let Some(pattern) = &self.requires_file {
+            if !unmatched_patterns(game_dir, std::slice::from_ref(pattern))?.is_empty() {
+                return Ok(Some(format!("required file {} is not in the game directory", pattern)));
+            }
+        }
+        if let Some(pattern) = &self.forbids_file {
+            if unmatched_patterns(game_dir, std::slice::from_ref(pattern))?.is_empty() {
+                return Ok(Some(format!("forbidden file {} is in the game directory", pattern)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Directory where the files are copied.<br>
+    /// A relative `to` must stay inside the game directory; an absolute one can't be a root directory.
+    pub fn destination(&self, game_dir: &CanonPath) -> Result<PathBuf> {
+        let to = PathBuf::from(&self.to).clean();
+        if to.is_absolute() {
+            if to.parent().is_none() {
+                bail!("Invalid destination {:?} for file module {}\n -> a root directory is not allowed, use a subdirectory",
+                        self.to, self.file_mod);
+            }
+            Ok(to)
+        } else if to.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+            bail!("Invalid destination {:?} for file module {}\n -> a relative destination must be inside the game directory \
+                    (use an absolute path for another location)", self.to, self.file_mod);
+        } else {
+            Ok(game_dir.path().join(to))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::canon_path::CanonPath;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::file_module_origin::FileModuleOrigin;
+
+    use super::FileModule;
+
+    fn file_module(to: &str) -> FileModule {
+        FileModule {
+            file_mod: lwc!("docs"),
+            description: None,
+            from: FileModuleOrigin::Local { local: "docs".to_string(), glob: None, dir_copy: None, literal: None },
+            to: to.to_string(),
+            post_install: None,
+            allow_overwrite: None,
+            requires_file: None,
+            forbids_file: None,
+        }
+    }
+
+    #[test]
+    fn allow_overwrite_from_manifest() {
+        let global = Global { allow_overwrite: Some(true), ..Default::default() };
+        let module = file_module("override");
+        assert!(module.allow_overwrite(&global));
+        assert!(!module.allow_overwrite(&Global::default()));
+
+        let module = FileModule { allow_overwrite: Some(false), ..file_module("override") };
+        assert!(!module.allow_overwrite(&global));
+        let module = FileModule { allow_overwrite: Some(true), ..file_module("override") };
+        assert!(module.allow_overwrite(&Global::default()));
+    }
+
+    #[test]
+    fn relative_destination() {
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        assert_eq!(file_module("override/").destination(&game_dir).unwrap(), game_dir.path().join("override"));
+        assert_eq!(file_module("./lang/fr_FR/../en_US").destination(&game_dir).unwrap(), game_dir.path().join("lang/en_US"));
+        assert!(file_module("../Documentation").destination(&game_dir).is_err());
+    }
+
+    #[test]
+    fn absolute_destination() {
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let outside = game_dir.path().parent().unwrap().join("Documentation");
+        let to = outside.to_string_lossy().to_string();
+        assert_eq!(file_module(&to).destination(&game_dir).unwrap(), outside);
+
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).ancestors().last().unwrap().to_path_buf();
+        let error = file_module(&root.to_string_lossy()).destination(&game_dir).unwrap_err();
+        assert!(error.to_string().contains("root directory"), "{}", error);
+    }
 }
@@ -11,14 +11,28 @@ pub enum FileModuleOrigin {
     Local {
         local: String,
         glob: Option<String>,
+        /// What is copied when `local` is a directory and there is no `glob`.
+        dir_copy: Option<DirCopy>,
+        /// `glob` is the exact (relative) path of a file or directory, `[`, `?` or `*` are not special.
+        literal: Option<bool>,
     },
     /// Any path on the computer.
     Absolute {
         absolute: String,
         glob: Option<String>,
+        /// What is copied when `absolute` is a directory and there is no `glob`.
+        dir_copy: Option<DirCopy>,
+        /// `glob` is the exact (relative) path of a file or directory, `[`, `?` or `*` are not special.
+        literal: Option<bool>,
     },
 }
 
+impl Default for FileModuleOrigin {
+    fn default() -> Self {
+        FileModuleOrigin::Local { local: String::new(), glob: None, dir_copy: None, literal: None }
+    }
+}
+
 impl FileModuleOrigin {
     pub fn glob(&self) -> Option<&str> {
         match self {
@@ -26,4 +40,35 @@ impl FileModuleOrigin {
             Self::Absolute { glob, .. } => glob.as_ref().map(|glob| glob.as_str()),
         }
     }
+
+    pub fn is_literal(&self) -> bool {
+        match self {
+            Self::Local { literal, .. } => literal.unwrap_or(false),
+            Self::Absolute { literal, .. } => literal.unwrap_or(false),
+        }
+    }
+
+    pub fn dir_copy(&self) -> DirCopy {
+        self.explicit_dir_copy().unwrap_or_default()
+    }
+
+    /// The `dir_copy` set on this origin, if any (the manifest `global.copy_mode` applies otherwise).
+    pub fn explicit_dir_copy(&self) -> Option<DirCopy> {
+        match self {
+            Self::Local { dir_copy, .. } => *dir_copy,
+            Self::Absolute { dir_copy, .. } => *dir_copy,
+        }
+    }
+}
+
+/// Decides how a directory origin (without glob) is copied into the target.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+pub enum DirCopy {
+    /// The content of the directory is copied into the target (`origin/a.txt` -> `target/a.txt`)
+    #[default]
+    #[serde(rename = "content")]
+    Content,
+    /// The directory itself is copied into the target (`origin/a.txt` -> `target/origin/a.txt`)
+    #[serde(rename = "directory")]
+    Directory,
 }
@@ -1,13 +1,19 @@
 
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+use crate::global::Global;
 use crate::module::components::{Component, Components};
 use crate::lowercase::LwcString;
 use crate::post_install::PostInstall;
 
 use super::disable_condition::DisableCondition;
 use super::file_module_origin::FileModuleOrigin;
+use super::module::Module;
 use super::weidu_mod::WeiduMod;
 
 /// Generates a skeleton weidu mod that just copies a bunch of files into `games/override`
@@ -24,13 +30,35 @@ pub struct GeneratedMod {
     pub component: GenModComponent,
     #[serde(default)]
     pub ignore_warnings: bool,
-    #[serde(default)]
-    pub allow_overwrite: bool,
+    /// Defaults to the manifest `global.allow_overwrite`, false if it isn't set.
+    pub allow_overwrite: Option<bool>,
     /// Condition that disables the mod installation (if absent, not disabled)
     pub disabled_if: Option<DisableCondition>,
+    /// Raw weidu actions put in an `ALWAYS` block (before the component).<br>
+    /// This is trusted input, inserted as-is in the generated tp2.
+    pub always: Option<String>,
+    /// Raw weidu actions put at the end of the component, after the files are copied
+    /// (for example `AT_EXIT ~...~`).<br>
+    /// This is trusted input, inserted as-is in the generated tp2.
+    pub at_exit: Option<String>,
+    /// Free-form tags/categories, for tooling (grouping, filtering).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Install priority, see `WeiduMod::order`.
+    pub order: Option<i32>,
+    /// Mods that must be installed before this one, see `WeiduMod::requires`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<LwcString>,
+    /// Files the installation must produce, see `WeiduMod::expect`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expect: Vec<String>,
 }
 
 impl GeneratedMod {
+    pub fn allow_overwrite(&self, global: &Global) -> bool {
+        self.allow_overwrite.or(global.allow_overwrite).unwrap_or(false)
+    }
+
     pub fn as_weidu(&self) -> WeiduMod {
         WeiduMod {
             name: self.gen_mod.clone(),
@@ -40,6 +68,9 @@ impl GeneratedMod {
             ]),
             ignore_warnings: self.ignore_warnings,
             post_install: self.post_install.clone(),
+            tags: self.tags.clone(),
+            order: self.order,
+            requires: self.requires.clone(),
             ..Default::default()
         }
     }
@@ -51,3 +82,89 @@ pub struct GenModComponent {
     pub index: u32,
     pub name: Option<String>,
 }
+
+/// The generated modules that have the same `gen_mod` name are components of the same weidu mod:
+/// fails if two of them have the same component index (weidu would uninstall the first one to install the second).<br>
+/// Returns warnings for the mods whose indexes have gaps.
+pub fn check_component_indexes(modules: &[Module]) -> Result<Vec<String>> {
+    let mut indexes: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+    for module in modules {
+        if let Module::Generated { gen } = module {
+            indexes.entry(gen.gen_mod.as_ref().as_str()).or_default().push(gen.component.index);
+        }
+    }
+    let mut errors = vec![];
+    let mut warnings = vec![];
+    for (name, indexes) in indexes {
+        let duplicates = indexes.iter().duplicates().sorted().collect::<Vec<_>>();
+        if !duplicates.is_empty() {
+            errors.push(format!("generated mod {}: component index {} used more than once", name, duplicates.iter().join(", ")));
+            continue;
+        }
+        let sorted = indexes.iter().sorted().collect::<Vec<_>>();
+        let gaps = sorted.windows(2).filter(|pair| *pair[1] != *pair[0] + 1).collect::<Vec<_>>();
+        if !gaps.is_empty() {
+            warnings.push(format!("generated mod {}: component indexes are not contiguous ({})",
+                                    name, sorted.iter().join(", ")));
+        }
+    }
+    if !errors.is_empty() {
+        bail!("Invalid generated mod components\n  {}", errors.join("\n  "));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::manifest;
+
+    use super::check_component_indexes;
+
+    #[test]
+    fn duplicate_component_index_fails() {
+        let manifest = manifest(r#"
+modules:
+  - gen_mod: tweaks
+    files: [{ local: items }]
+    component: { index: 0 }
+  - gen_mod: other
+    files: [{ local: other }]
+  - gen_mod: tweaks
+    files: [{ local: spells }]
+    component: { index: 0, name: Spells }
+"#);
+
+        let error = check_component_indexes(&manifest.modules).unwrap_err().to_string();
+
+        assert!(error.contains("generated mod tweaks: component index 0 used more than once"), "{}", error);
+        assert!(!error.contains("other"), "{}", error);
+        assert!(manifest.validate().is_err());
+    }
+
+    #[test]
+    fn non_contiguous_component_indexes_warn() {
+        let manifest = manifest(r#"
+modules:
+  - gen_mod: tweaks
+    files: [{ local: items }]
+    component: { index: 10 }
+  - gen_mod: tweaks
+    files: [{ local: spells }]
+    component: { index: 12 }
+  - gen_mod: tweaks
+    files: [{ local: creatures }]
+    component: { index: 11 }
+  - gen_mod: other
+    files: [{ local: other }]
+    component: { index: 0 }
+  - gen_mod: other
+    files: [{ local: more }]
+    component: { index: 2 }
+"#);
+
+        let warnings = check_component_indexes(&manifest.modules).unwrap();
+
+        assert_eq!(warnings, vec!["generated mod other: component indexes are not contiguous (0, 2)"]);
+        manifest.validate().unwrap();
+    }
+}
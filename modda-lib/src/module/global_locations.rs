@@ -4,6 +4,7 @@ use std::iter::FromIterator;
 use serde::{Deserialize, Serialize};
 
 use crate::lowercase::LwcString;
+use crate::utils::sorted_map::serialize_sorted;
 
 use super::location::location::ConcreteLocation;
 
@@ -28,14 +29,16 @@ use super::location::location::ConcreteLocation;
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
 pub struct GlobalLocations {
     #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub external: Vec<LocationRegistry>,
     #[serde(default)]
+    #[serde(serialize_with = "serialize_sorted")]
     pub entries: HashMap<LwcString, ConcreteLocation>,
 }
 
 impl GlobalLocations {
     pub fn is_empty(&self) -> bool {
-        self.entries.is_empty()
+        self.entries.is_empty() && self.external.is_empty()
     }
 
     pub fn find(&self, name: &LwcString) -> Option<&ConcreteLocation> {
@@ -1,6 +1,8 @@
 
 use anyhow::bail;
 use anyhow::Result;
+use log::info;
+use serde::{Deserialize, Serialize};
 
 use crate::lowercase::LwcString;
 use crate::modda_context::WeiduContext;
@@ -13,6 +15,19 @@ pub struct LanguageOption {
     pub name: String,
 }
 
+/// Language used for a mod that has none of the preferred languages (`lang_fallback` in the manifest `global`).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageFallback {
+    /// The installation stops.
+    #[default]
+    Error,
+    /// The language named `english` (or `american english`...) if the mod has one, otherwise the installation stops.
+    English,
+    /// The first language of the mod (usually the original one).
+    FirstAvailable,
+}
+
 #[derive(Clone, Debug)]
 pub enum LanguageSelection {
     Selected(u32),
@@ -73,3 +88,77 @@ pub fn select_language_pref(tp2:&str, mod_name: &LwcString, lang_preferences: &O
         }
     }
 }
+
+/// Picks the language of a mod that has none of the preferred languages, according to `fallback`.
+pub fn fallback_language(available: &[LanguageOption], fallback: LanguageFallback, mod_name: &LwcString,
+                            lang_preferences: &Option<Vec<String>>) -> Result<u32> {
+    let found = match fallback {
+        LanguageFallback::Error => None,
+        LanguageFallback::English => available.iter()
+            .find(|lang| lang.name.trim().eq_ignore_ascii_case("english"))
+            .or_else(|| available.iter().find(|lang| lang.name.to_lowercase().contains("english"))),
+        LanguageFallback::FirstAvailable => available.first(),
+    };
+    match found {
+        None => bail!(
+            r#"No matching language found for module {} with language preferences {:?} (fallback: {:?})
+        Available choices are {:?}
+        "#,
+            mod_name, lang_preferences, fallback, available),
+        Some(LanguageOption { index, name }) => {
+            info!("no preferred language for mod {}, using {:?} fallback: {} ({})", mod_name, fallback, name, index);
+            Ok(*index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lowercase::lwc;
+
+    use super::{fallback_language, LanguageFallback, LanguageOption};
+
+    fn available(names: &[&str]) -> Vec<LanguageOption> {
+        names.iter().enumerate()
+            .map(|(index, name)| LanguageOption { index: index as u32, name: name.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn fallback_error() {
+        let preferences = Some(vec!["french".to_string()]);
+
+        let error = fallback_language(&available(&["English", "Deutsch"]), LanguageFallback::Error, &lwc!("my_mod"), &preferences)
+            .unwrap_err().to_string();
+
+        assert!(error.contains("No matching language found for module my_mod"), "{}", error);
+        assert!(error.contains("Deutsch"), "{}", error);
+    }
+
+    #[test]
+    fn fallback_english() {
+        let mod_name = lwc!("my_mod");
+
+        let english = fallback_language(&available(&["Deutsch", "American English", "English"]),
+                                        LanguageFallback::English, &mod_name, &None);
+        let american = fallback_language(&available(&["Deutsch", "American English"]),
+                                        LanguageFallback::English, &mod_name, &None);
+        let missing = fallback_language(&available(&["Deutsch", "Polski"]),
+                                        LanguageFallback::English, &mod_name, &None);
+
+        assert_eq!(english.unwrap(), 2);
+        assert_eq!(american.unwrap(), 1);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn fallback_first_available() {
+        let mod_name = lwc!("my_mod");
+
+        let first = fallback_language(&available(&["Polski", "English"]), LanguageFallback::FirstAvailable, &mod_name, &None);
+        let none = fallback_language(&[], LanguageFallback::FirstAvailable, &mod_name, &None);
+
+        assert_eq!(first.unwrap(), 0);
+        assert!(none.is_err());
+    }
+}
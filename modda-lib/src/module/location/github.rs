@@ -1,18 +1,22 @@
 
 use std::error::Error;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Result, bail, anyhow};
 use lazy_static::lazy_static;
-use log::info;
-use reqwest::header::{AUTHORIZATION, HeaderMap, ACCEPT, HeaderValue, HeaderName, USER_AGENT};
+use log::{debug, info, warn};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::header::{AUTHORIZATION, ETAG, HeaderMap, ACCEPT, HeaderValue, HeaderName, IF_NONE_MATCH, RETRY_AFTER, USER_AGENT};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
 use crate::credentials::{Credentials, GithubCredentials};
-use crate::download::{Downloader, DownloadOpts};
+use crate::download::{Downloader, DownloadOpts, DownloadRequest, DownloadedFile};
 use crate::module::refresh::RefreshCondition;
 use crate::progname::PROGNAME;
+use crate::split_archive::volume_name;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
 pub struct Github {
@@ -25,12 +29,67 @@ pub struct Github {
     pub no_cache: bool,
     /// None -> no auth needed, Some -> method + credentials for example `PAT <token_name>`
     pub auth: Option<String>,
+    /// Format of the source archive for `tag`, `branch` and `commit` (`zip` if absent).
+    /// Ignored for `release` which downloads an uploaded asset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_archive: Option<SourceArchive>,
+    /// URL of a GitHub Enterprise server (`https://github.mycorp.com`), github.com if absent.<br>
+    /// The API is expected at `<base_url>/api/v3`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Number of volumes of a split archive uploaded as release assets (`asset` ends with `.001`):
+    /// the other volumes (`.002`, `.003`...) are downloaded next to the first one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<u32>,
+}
+
+/// Format of the archives github generates from the repository content.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceArchive {
+    #[default]
+    Zip,
+    Tar,
+}
+
+impl SourceArchive {
+    /// Extension of the saved file, must be something the extractor recognizes.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SourceArchive::Zip => "zip",
+            SourceArchive::Tar => "tar.gz",
+        }
+    }
+
+    /// Both formats have all the content inside a single `<repository>-<ref>` top directory.
+    pub fn strip_leading(&self) -> usize {
+        match self {
+            SourceArchive::Zip | SourceArchive::Tar => 1,
+        }
+    }
+
+    fn codeload_path(&self) -> &'static str {
+        match self {
+            SourceArchive::Zip => "zip",
+            SourceArchive::Tar => "tar.gz",
+        }
+    }
+
+    fn api_path(&self) -> &'static str {
+        match self {
+            SourceArchive::Zip => "zipball",
+            SourceArchive::Tar => "tarball",
+        }
+    }
 }
 
 impl Github {
-    pub async fn get_github(&self, downloader: &Downloader, dest: &PathBuf, save_name: PathBuf) -> Result<PathBuf> {
-        let url = self.descriptor.get_url(&self.github_user, &self.repository, &self.auth).await?;
-        let opts = &DownloadOpts { no_cache: self.no_cache, refresh: self.refresh() };
+    /// `api_cache` is the directory where github API responses are kept (along with their ETag).<br>
+    /// `max_rate_limit_wait` is how long an API call can wait when github says it is rate-limited.
+    pub async fn get_github(&self, downloader: &Downloader, dest: &PathBuf, save_name: PathBuf,
+                            api_cache: &Path, max_rate_limit_wait: Duration) -> Result<DownloadedFile> {
+        let url = self.get_url(api_cache, max_rate_limit_wait).await?;
+        let opts = &DownloadOpts { no_cache: self.no_cache, refresh: self.refresh(), request: DownloadRequest::Get };
         let mut headers = HeaderMap::new();
         headers.insert(ACCEPT, self.descriptor.get_media_type());
         headers.insert(&*GITHUB_API_VERSION_NAME, (*GITHUB_API_VERSION_VALUE).clone());
@@ -41,6 +100,122 @@ impl Github {
         downloader.download(&url, dest, save_name, opts, &Some(headers)).await
     }
 
+    /// The assets after the first one when `volumes` is set: the location of each volume and the name it is saved
+    /// with (the asset name, like the first volume).
+    pub fn other_volumes(&self) -> Result<Vec<(Github, PathBuf)>> {
+        let count = match self.volumes {
+            None => return Ok(vec![]),
+            Some(count) => count,
+        };
+        let (release, stem) = match &self.descriptor {
+            GithubDescriptor::Release { release, asset } => match asset.strip_suffix(".001") {
+                Some(stem) => (release, stem),
+                None => bail!("Location {}/{} has `volumes` but {} is not the first volume of a split archive\n -> the asset name must end with `.001`",
+                                self.github_user, self.repository, asset),
+            },
+            _ => bail!("Location {}/{} has `volumes` but is not a `release`\n -> only release assets can be split archives",
+                        self.github_user, self.repository),
+        };
+        Ok((2..=count).map(|number| {
+            let asset = volume_name(stem, number);
+            let descriptor = GithubDescriptor::Release { release: release.clone(), asset: asset.clone() };
+            (Github { descriptor, volumes: None, ..self.clone() }, PathBuf::from(asset))
+        }).collect())
+    }
+
+    /// URL of the archive to download; for `release`, the asset is looked up with the github API.
+    pub async fn get_url(&self, api_cache: &Path, max_rate_limit_wait: Duration) -> Result<String> {
+        let (user, repository) = (&self.github_user, &self.repository);
+        match &self.descriptor {
+            GithubDescriptor::Release { release, asset } =>
+                Ok(self.release_asset(release, asset, api_cache, max_rate_limit_wait).await?.1.url),
+            _ => self.archive_url().ok_or(anyhow!("No archive URL for github repository {user}/{repository}")),
+        }
+    }
+
+    /// What would be downloaded now, without downloading anything: the github API is called to find
+    /// the release and asset of `release` and the head commit of `branch`.
+    pub async fn resolve(&self, api_cache: &Path, max_rate_limit_wait: Duration) -> Result<GithubResolution> {
+        match &self.descriptor {
+            GithubDescriptor::Release { release, asset } => {
+                let (release_info, asset) = self.release_asset(release, asset, api_cache, max_rate_limit_wait).await?;
+                Ok(GithubResolution::Release { tag: release_info.tag_name, asset: asset.name, url: asset.url })
+            }
+            GithubDescriptor::Branch(GitBranch { branch, .. }) => {
+                let commit = self.api_client(api_cache, max_rate_limit_wait)?
+                    .get_branch_commit(&self.github_user, &self.repository, branch).await?;
+                Ok(GithubResolution::Branch { branch: branch.to_owned(), commit })
+            }
+            GithubDescriptor::Tag { tag } => Ok(GithubResolution::Tag { tag: tag.to_owned() }),
+            GithubDescriptor::Commit { commit } => Ok(GithubResolution::Commit { commit: commit.to_owned() }),
+        }
+    }
+
+    async fn release_asset(&self, release: &Option<String>, asset: &str, api_cache: &Path,
+                            max_rate_limit_wait: Duration) -> Result<(ReleaseInfo, Asset)> {
+        let (user, repository) = (&self.github_user, &self.repository);
+        let release = match &release {
+            None => String::from("latest"),
+            Some(release) => release.to_owned(),
+        };
+        // First search the release by tag-name
+        let release_info = match self.api_client(api_cache, max_rate_limit_wait)?
+                                        .get_release_info(user, repository, &release).await {
+            Ok(value) => value,
+            Err(error) => bail!("Could not find release `{release}` in github repository {user}/{repository}\n{error}")
+        };
+
+        // Search a match in the listed assets
+        let lookup = asset.replace("{{release}}", &release);
+        match release_info.assets.iter().find(|asset| asset.name == lookup).cloned() {
+            None => bail!("No asset named {asset} found for release {release} in github repository {user}/{repository}"),
+            Some(asset) => Ok((release_info, asset)),
+        }
+    }
+
+    fn api_client(&self, api_cache: &Path, max_rate_limit_wait: Duration) -> Result<GithubClient> {
+        Ok(GithubClient::new(&self.auth)?.with_base(&api_base(self.base_url.as_deref()))
+            .with_api_cache(api_cache)
+            .with_max_rate_limit_wait(max_rate_limit_wait))
+    }
+
+    pub fn source_archive(&self) -> SourceArchive {
+        self.source_archive.unwrap_or_default()
+    }
+
+    /// URL of the source archive for `tag`, `branch` and `commit`; `None` for `release`
+    /// (the asset URL is only known after a call to the github API).
+    pub fn archive_url(&self) -> Option<String> {
+        use GithubDescriptor::*;
+        match &self.descriptor {
+            Release { .. } => None,
+            Tag { tag } => Some(source_archive_url(&self.github_user, &self.repository, &self.auth, self.base_url.as_deref(),
+                                                    self.source_archive(), "refs/tags/", tag)),
+            Branch(GitBranch { branch, .. }) => Some(source_archive_url(&self.github_user, &self.repository, &self.auth,
+                                                    self.base_url.as_deref(), self.source_archive(), "refs/heads/", branch)),
+            Commit { commit } => Some(source_archive_url(&self.github_user, &self.repository, &self.auth, self.base_url.as_deref(),
+                                                    self.source_archive(), "", commit)),
+        }
+    }
+
+    /// Host of the GitHub Enterprise server, `None` for github.com.
+    pub fn enterprise_host(&self) -> Result<Option<String>> {
+        let base_url = match &self.base_url {
+            None => return Ok(None),
+            Some(base_url) => base_url,
+        };
+        match url::Url::parse(base_url) {
+            Err(error) => bail!("Couldn't parse github base_url {}\n -> {:?}", base_url, error),
+            Ok(url) => match url.host_str() {
+                None => bail!("Invalid github base_url {}", base_url),
+                Some(host) => Ok(Some(match url.port() {
+                    None => host.to_string(),
+                    Some(port) => format!("{}_{}", host, port),
+                })),
+            },
+        }
+    }
+
     pub fn refresh(&self) -> RefreshCondition {
         match &self.descriptor {
             GithubDescriptor::Branch(GitBranch { refresh, .. }) => refresh.clone(),
@@ -90,6 +265,28 @@ pub enum GithubDescriptor {
     Tag { tag: String },
 }
 
+/// The concrete reference a github descriptor resolves to (see `Github::resolve`).
+#[derive(Debug, PartialEq, Clone)]
+pub enum GithubResolution {
+    /// `tag` is the tag of the release (`latest` is resolved), `url` the API URL of the asset
+    Release { tag: String, asset: String, url: String },
+    /// `commit` is the head of the branch at the time of the resolution
+    Branch { branch: String, commit: String },
+    Tag { tag: String },
+    Commit { commit: String },
+}
+
+impl fmt::Display for GithubResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GithubResolution::Release { tag, asset, .. } => write!(f, "release {tag}, asset {asset}"),
+            GithubResolution::Branch { branch, commit } => write!(f, "branch {branch} at commit {commit}"),
+            GithubResolution::Tag { tag } => write!(f, "tag {tag}"),
+            GithubResolution::Commit { commit } => write!(f, "commit {commit}"),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct GitBranch {
     pub branch: String,
@@ -112,42 +309,40 @@ impl GithubDescriptor {
             _ => (*GITHUB_CUSTOM_MEDIA_TYPE).clone()
         }
     }
+}
 
-    pub async fn get_url(&self, user: &str, repository: &str, auth: &Option<String>,) -> Result<String> {
-        use GithubDescriptor::*;
-
-        match self {
-            Release { release, asset } => {
-                let release = match &release {
-                    None => String::from("latest"),
-                    Some(release) => release.to_owned(),
-                };
-                // First search the release by tag-name
-                let release_info = match GithubClient::new(auth)?.get_release_info(user, repository, &release).await {
-                    Ok(value) => value,
-                    Err(error) => bail!("Could not find release `{release}` in github repository {user}/{repository}\n{error}")
-                };
-
-                // Search a match in the listed assets
-                let lookup = asset.replace("{{release}}", &release);
-                release_info.assets.iter()
-                    .find(|asset| asset.name == lookup)
-                    .map(|asset| asset.url.to_owned())
-                    .ok_or(anyhow!("No asset named {asset} found for release {release} in github repository {user}/{repository}"))
-            }
-            Tag { tag } =>
-                Ok(format!("https://api.github.com/repos/{user}/{repository}/zipball/{tag}")),
-            Branch(GitBranch { branch, refresh: _}) =>
-                Ok(format!("https://api.github.com/repos/{user}/{repository}/zipball/{branch}")),
-            Commit { commit } =>
-                Ok(format!("https://api.github.com/repos/{user}/{repository}/zipball/{commit}")),
-        }
+/// Public repositories are downloaded directly from `codeload.github.com` (from the `archive` page of the
+/// repository for GitHub Enterprise).<br>
+/// With `auth`, the API endpoint is used instead: it accepts the token and redirects
+/// to codeload with a temporary access.
+fn source_archive_url(user: &str, repository: &str, auth: &Option<String>, base_url: Option<&str>,
+                        source_archive: SourceArchive, ref_prefix: &str, git_ref: &str) -> String {
+    match (auth, base_url) {
+        (None, None) => format!("https://codeload.github.com/{user}/{repository}/{archive}/{ref_prefix}{git_ref}",
+                        archive = source_archive.codeload_path()),
+        (None, Some(base_url)) => format!("{base}/{user}/{repository}/archive/{ref_prefix}{git_ref}.{extension}",
+                        base = base_url.trim_end_matches('/'), extension = source_archive.extension()),
+        (Some(_), _) => format!("{api}/repos/{user}/{repository}/{archive}/{git_ref}",
+                        api = api_base(base_url), archive = source_archive.api_path()),
     }
+}
 
+/// `https://api.github.com`, or the API of a GitHub Enterprise server.
+fn api_base(base_url: Option<&str>) -> String {
+    match base_url {
+        None => GITHUB_API_BASE.to_string(),
+        Some(base_url) => format!("{}/api/v3", base_url.trim_end_matches('/')),
+    }
 }
 
 pub struct GithubClient {
     client: reqwest::Client,
+    base: String,
+    /// If set, API responses are cached there and revalidated with their ETag
+    /// (`304 Not Modified` responses don't count against the rate limit).
+    api_cache: Option<PathBuf>,
+    /// Total time the client can spend waiting for a rate limit to end before giving up.
+    max_rate_limit_wait: Duration,
 }
 
 impl GithubClient {
@@ -160,19 +355,38 @@ impl GithubClient {
         } else {
             client_builder
         };
-        Ok(Self { client: client_builder.build()? })
+        Ok(Self {
+            client: client_builder.build()?,
+            base: GITHUB_API_BASE.to_string(),
+            api_cache: None,
+            max_rate_limit_wait: DEFAULT_MAX_RATE_LIMIT_WAIT,
+        })
+    }
+
+    pub fn with_api_cache(self, api_cache: &Path) -> Self {
+        Self { api_cache: Some(api_cache.to_path_buf()), ..self }
     }
 
-    fn base() -> String { "https://api.github.com".to_string() }
+    pub fn with_max_rate_limit_wait(self, max_rate_limit_wait: Duration) -> Self {
+        Self { max_rate_limit_wait, ..self }
+    }
 
-    async fn get_release_info(&self, user: &str, repository: &str, tag: &str) -> Result<ReleaseInfo> {
+    /// Base URL of the API, `https://api.github.com` by default.
+    pub fn with_base(self, base: &str) -> Self {
+        Self { base: base.to_string(), ..self }
+    }
 
-        let url = format!("{base}/repos/{user}/{repository}/releases/tags/{tag}", base = GithubClient::base());
-        let request = self.client.get(&url)
-            .header(USER_AGENT, PROGNAME);
+    async fn get_release_info(&self, user: &str, repository: &str, tag: &str) -> Result<ReleaseInfo> {
 
-        let response = request.send().await?;
-        info!("{:?}", response);
+        let url = format!("{base}/repos/{user}/{repository}/releases/tags/{tag}", base = self.base);
+        let cached = self.read_cached_response(&url);
+        let response = self.send_with_rate_limit(&url, cached.as_ref()).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                debug!("github API response for {url} was not modified, using cached value");
+                return parse_release_info(&cached.body);
+            }
+        }
         if !response.status().is_success() {
             match response.status() {
                 StatusCode::NOT_FOUND => bail!("Release with tag {tag} was not found in {user}/{repository}"),
@@ -180,20 +394,154 @@ impl GithubClient {
                             code = response.status().as_str())
             }
         }
-        let result = match response.json::<ReleaseInfo>().await {
-            Ok(result) => result,
+        let etag = response.headers().get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+        let body = match response.text().await {
+            Ok(body) => body,
             Err(error) => {
                 if error.is_connect() { bail!("connection error for get release endpoint\n{}", error) }
                 if let Some(status) = error.status() {
                     bail!("get release endpoint returned HTTP error {}: {:?}\n{}", status.as_u16(), status.canonical_reason(), error)
                 }
-                if error.is_decode() { bail!("get release endpoint returned incorrect data\n{}\ncaused by\n{:?}", error, error.source()) }
-
                 bail!("get release endpoint error\n{}", error)
             }
         };
+        let result = parse_release_info(&body)?;
+        if let Some(etag) = etag {
+            self.write_cached_response(&url, &CachedResponse { etag, body });
+        }
         Ok(result)
     }
+
+    /// The commit at the head of `branch`.
+    async fn get_branch_commit(&self, user: &str, repository: &str, branch: &str) -> Result<String> {
+        let url = format!("{base}/repos/{user}/{repository}/commits/{branch}", base = self.base);
+        let response = self.send_with_rate_limit(&url, None).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => bail!("Branch {branch} was not found in {user}/{repository}"),
+            status if !status.is_success() =>
+                bail!("Couldn't find the head of branch {branch} in {user}/{repository} - HTTP error was {code}",
+                        code = status.as_str()),
+            _ => {}
+        }
+        let body = response.text().await?;
+        match serde_json::from_str::<CommitInfo>(&body) {
+            Ok(commit_info) => Ok(commit_info.sha),
+            Err(error) => bail!("get commit endpoint returned incorrect data\n{}", error),
+        }
+    }
+
+    /// Sends a GET request to the API, waiting and trying again when github answers that
+    /// the rate limit is exceeded (`403` or `429` with `Retry-After` or `x-ratelimit-*` headers),
+    /// as long as the total wait stays under `max_rate_limit_wait`.
+    async fn send_with_rate_limit(&self, url: &str, cached: Option<&CachedResponse>) -> Result<reqwest::Response> {
+        let mut waited = Duration::ZERO;
+        loop {
+            let mut request = self.client.get(url)
+                .header(USER_AGENT, PROGNAME);
+            if let Some(CachedResponse { etag, .. }) = cached {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            let response = request.send().await?;
+            info!("{:?}", response);
+
+            let wait = match rate_limit_wait(response.status(), response.headers(), SystemTime::now()) {
+                None => return Ok(response),
+                Some(wait) => wait,
+            };
+            if waited + wait > self.max_rate_limit_wait {
+                let retry_at = chrono::Local::now() + chrono::Duration::from_std(wait).unwrap_or(chrono::Duration::zero());
+                bail!("Github API rate limit exceeded for {url} (HTTP {code})\n -> retry after {retry_at} (in {seconds}s), \
+                        or raise `github_rate_limit_max_wait` in the config (currently {max}s)",
+                        code = response.status().as_str(), retry_at = retry_at.format("%H:%M:%S"),
+                        seconds = wait.as_secs(), max = self.max_rate_limit_wait.as_secs());
+            }
+            warn!("Github API rate limit exceeded, waiting {}s before trying again", wait.as_secs());
+            tokio::time::sleep(wait).await;
+            // never loop without waiting at all
+            waited += wait.max(Duration::from_secs(1));
+        }
+    }
+
+    fn cached_response_path(&self, url: &str) -> Option<PathBuf> {
+        self.api_cache.as_ref()
+            .map(|api_cache| api_cache.join(format!("{}.json", utf8_percent_encode(url, NON_ALPHANUMERIC))))
+    }
+
+    /// Cache problems are never fatal, at worst the request is made without `If-None-Match`.
+    fn read_cached_response(&self, url: &str) -> Option<CachedResponse> {
+        let path = self.cached_response_path(url)?;
+        let content = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(cached) => Some(cached),
+            Err(error) => {
+                warn!("Ignoring invalid github API cache entry {:?}\n  {}", path, error);
+                None
+            }
+        }
+    }
+
+    fn write_cached_response(&self, url: &str, cached: &CachedResponse) {
+        let path = match self.cached_response_path(url) {
+            None => return,
+            Some(path) => path,
+        };
+        if let Err(error) = write_cache_entry(&path, cached) {
+            warn!("Could not write github API cache entry {:?}\n  {}", path, error);
+        }
+    }
+}
+
+const GITHUB_API_BASE: &str = "https://api.github.com";
+
+/// Used when `github_rate_limit_max_wait` is not set in the config.
+pub const DEFAULT_MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// How long to wait before trying again if the response says that a rate limit was hit, `None` otherwise.<br>
+/// Secondary (abuse) limits come with `Retry-After`, the primary limit with `x-ratelimit-remaining: 0`
+/// and `x-ratelimit-reset` (epoch seconds).
+fn rate_limit_wait(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| headers.get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    if let Some(seconds) = header(RETRY_AFTER.as_str()) {
+        return Some(Duration::from_secs(seconds));
+    }
+    match (header("x-ratelimit-remaining"), header("x-ratelimit-reset")) {
+        (Some(0), Some(reset)) => {
+            let now = now.duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0);
+            Some(Duration::from_secs(reset.saturating_sub(now)))
+        }
+        // github asks to wait at least one minute when it doesn't tell how long
+        _ if status == StatusCode::TOO_MANY_REQUESTS => Some(Duration::from_secs(60)),
+        // a 403 without rate limit information is a real "forbidden"
+        _ => None,
+    }
+}
+
+fn write_cache_entry(path: &Path, cached: &CachedResponse) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(cached)?)?;
+    Ok(())
+}
+
+fn parse_release_info(body: &str) -> Result<ReleaseInfo> {
+    match serde_json::from_str::<ReleaseInfo>(body) {
+        Ok(result) => Ok(result),
+        Err(error) => bail!("get release endpoint returned incorrect data\n{}\ncaused by\n{:?}", error, error.source()),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+struct CachedResponse {
+    etag: String,
+    body: String,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
@@ -209,6 +557,11 @@ struct ReleaseInfo {
     pub name: String,
     pub assets: Vec<Asset>,
 }
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+struct CommitInfo {
+    pub sha: String,
+}
+
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 struct Asset {
     pub url: String,
@@ -220,3 +573,323 @@ struct Asset {
     pub size: usize,
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use reqwest::header::HeaderMap;
+    use reqwest::StatusCode;
+
+    use crate::test_utils::server::{not_found, response, TestServer};
+    use crate::test_utils::test_dir;
+
+    use super::{api_base, rate_limit_wait, GitBranch, Github, GithubClient, GithubDescriptor, GithubResolution, SourceArchive,
+                DEFAULT_MAX_RATE_LIMIT_WAIT};
+
+    const ETAG_VALUE: &str = r#""0123456789abcdef""#;
+    const RELEASE_BODY: &str = r#"{
+        "url": "https://api.github.com/repos/user/repo/releases/1",
+        "html_url": "https://github.com/user/repo/releases/tag/v1",
+        "assets_url": "https://api.github.com/repos/user/repo/releases/1/assets",
+        "tarball_url": "https://api.github.com/repos/user/repo/tarball/v1",
+        "zipball_url": "https://api.github.com/repos/user/repo/zipball/v1",
+        "id": 1,
+        "tag_name": "v1",
+        "body": "",
+        "name": "v1",
+        "assets": [{
+            "url": "https://api.github.com/repos/user/repo/releases/assets/2",
+            "browser_download_url": "https://github.com/user/repo/releases/download/v1/mod.zip",
+            "id": 2,
+            "name": "mod.zip",
+            "label": null,
+            "content_type": "application/zip",
+            "size": 1234
+        }]
+    }"#;
+
+    /// Minimal github API stand-in: answers `304` when the request has the expected `If-None-Match`,
+    /// the release JSON (with an ETag) otherwise.
+    /// Returns the server and the list of statuses that were served.
+    fn start_mock_api() -> (TestServer, Arc<Mutex<Vec<u16>>>) {
+        let served = Arc::new(Mutex::new(vec![]));
+        let served_by_server = served.clone();
+        let server = TestServer::start(move |request| {
+            let not_modified = request.header("if-none-match") == Some(ETAG_VALUE);
+            served_by_server.lock().unwrap().push(if not_modified { 304 } else { 200 });
+            if not_modified {
+                response("304 Not Modified", &[("ETag", ETAG_VALUE)], b"")
+            } else {
+                response("200 OK", &[("ETag", ETAG_VALUE), ("Content-Type", "application/json")], RELEASE_BODY.as_bytes())
+            }
+        });
+        (server, served)
+    }
+
+    #[tokio::test]
+    async fn release_info_is_cached_with_etag() {
+        let (server, served) = start_mock_api();
+        let base = server.address();
+        let api_cache = test_dir();
+        let client = GithubClient::new(&None).unwrap().with_api_cache(api_cache.path()).with_base(base);
+
+        let release_info = client.get_release_info("user", "repo", "v1").await.unwrap();
+
+        assert_eq!(release_info.tag_name, "v1");
+        assert_eq!(*served.lock().unwrap(), vec![200]);
+        let cached = client.read_cached_response(&format!("{base}/repos/user/repo/releases/tags/v1")).unwrap();
+        assert_eq!(cached.etag, ETAG_VALUE);
+    }
+
+    #[tokio::test]
+    async fn not_modified_reuses_cached_release_info() {
+        let (server, served) = start_mock_api();
+        let base = server.address();
+        let api_cache = test_dir();
+        let client = GithubClient::new(&None).unwrap().with_api_cache(api_cache.path()).with_base(base);
+
+        let first = client.get_release_info("user", "repo", "v1").await.unwrap();
+        let second = client.get_release_info("user", "repo", "v1").await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(second.assets[0].name, "mod.zip");
+        assert_eq!(*served.lock().unwrap(), vec![200, 304]);
+    }
+
+    #[tokio::test]
+    async fn no_api_cache_never_revalidates() {
+        let (server, served) = start_mock_api();
+        let base = server.address();
+        let client = GithubClient::new(&None).unwrap().with_base(base);
+
+        client.get_release_info("user", "repo", "v1").await.unwrap();
+        client.get_release_info("user", "repo", "v1").await.unwrap();
+
+        assert_eq!(*served.lock().unwrap(), vec![200, 200]);
+    }
+
+    fn release_response() -> Vec<u8> {
+        response("200 OK", &[("Content-Type", "application/json")], RELEASE_BODY.as_bytes())
+    }
+
+    const COMMIT_SHA: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+
+    fn commit_response() -> Vec<u8> {
+        let body = format!(r#"{{"sha": "{COMMIT_SHA}", "commit": {{"message": "Fix the tp2"}}}}"#);
+        response("200 OK", &[("Content-Type", "application/json")], body.as_bytes())
+    }
+
+    async fn resolve_with(base: &str, descriptor: GithubDescriptor) -> anyhow::Result<GithubResolution> {
+        let api_cache = test_dir();
+        let github = Github {
+            github_user: "user".to_string(),
+            repository: "repo".to_string(),
+            descriptor,
+            base_url: Some(base.to_string()),
+            ..Default::default()
+        };
+        github.resolve(api_cache.path(), Duration::from_secs(5)).await
+    }
+
+    #[tokio::test]
+    async fn resolve_each_descriptor_kind() {
+        let server = TestServer::scripted(vec![release_response(), commit_response()]);
+        let base = server.address();
+
+        let release = resolve_with(base, GithubDescriptor::Release { release: None, asset: "mod.zip".to_string() }).await;
+        let branch = resolve_with(base, GithubDescriptor::Branch(GitBranch {
+            branch: "main".to_string(),
+            refresh: Default::default(),
+        })).await;
+        let tag = resolve_with(base, GithubDescriptor::Tag { tag: "v2".to_string() }).await;
+        let commit = resolve_with(base, GithubDescriptor::Commit { commit: "abc123".to_string() }).await;
+
+        assert_eq!(release.unwrap(), GithubResolution::Release {
+            tag: "v1".to_string(),
+            asset: "mod.zip".to_string(),
+            url: "https://api.github.com/repos/user/repo/releases/assets/2".to_string(),
+        });
+        assert_eq!(branch.unwrap(), GithubResolution::Branch { branch: "main".to_string(), commit: COMMIT_SHA.to_string() });
+        assert_eq!(tag.unwrap(), GithubResolution::Tag { tag: "v2".to_string() });
+        assert_eq!(commit.unwrap(), GithubResolution::Commit { commit: "abc123".to_string() });
+        // tags and commits don't need the API
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn resolve_errors() {
+        let server = TestServer::scripted(vec![not_found(), release_response()]);
+        let base = server.address();
+
+        let branch = resolve_with(base, GithubDescriptor::Branch(GitBranch {
+            branch: "dev".to_string(),
+            refresh: Default::default(),
+        })).await;
+        let release = resolve_with(base, GithubDescriptor::Release {
+            release: Some("v1".to_string()),
+            asset: "other.zip".to_string(),
+        }).await;
+
+        let error = branch.unwrap_err().to_string();
+        assert!(error.contains("Branch dev was not found in user/repo"), "{}", error);
+        let error = release.unwrap_err().to_string();
+        assert!(error.contains("No asset named other.zip found for release v1"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn abuse_limit_waits_for_retry_after() {
+        let abuse = response("429 Too Many Requests", &[("Retry-After", "1")], b"");
+        let server = TestServer::scripted(vec![abuse, release_response()]);
+        let base = server.address();
+        let client = GithubClient::new(&None).unwrap().with_base(base)
+            .with_max_rate_limit_wait(Duration::from_secs(5));
+
+        let start = Instant::now();
+        let release_info = client.get_release_info("user", "repo", "v1").await.unwrap();
+
+        assert_eq!(release_info.tag_name, "v1");
+        assert_eq!(server.requests().len(), 2);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_longer_than_max_wait_fails() {
+        let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 3600;
+        let reset = reset.to_string();
+        let rate_limited = response("403 Forbidden", &[("x-ratelimit-limit", "60"), ("x-ratelimit-remaining", "0"),
+                                                       ("x-ratelimit-reset", &reset)], b"");
+        let server = TestServer::scripted(vec![rate_limited]);
+        let base = server.address();
+        let client = GithubClient::new(&None).unwrap().with_base(base)
+            .with_max_rate_limit_wait(Duration::from_secs(5));
+
+        let error = client.get_release_info("user", "repo", "v1").await.unwrap_err();
+
+        let message = format!("{:?}", error);
+        assert!(message.contains("rate limit exceeded"), "{}", message);
+        assert!(message.contains("retry after"), "{}", message);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn rate_limit_wait_from_headers() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        let headers = |values: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in values {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        let primary = headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1030")]);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &primary, now), Some(Duration::from_secs(30)));
+        assert_eq!(rate_limit_wait(StatusCode::OK, &primary, now), None);
+        let secondary = headers(&[("retry-after", "12")]);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &secondary, now), Some(Duration::from_secs(12)));
+        assert_eq!(rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now), Some(Duration::from_secs(60)));
+        // not rate-limited, really forbidden
+        let remaining = headers(&[("x-ratelimit-remaining", "10"), ("x-ratelimit-reset", "1030")]);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &remaining, now), None);
+    }
+
+    fn url_for(descriptor: &GithubDescriptor, auth: &Option<String>, source_archive: SourceArchive) -> String {
+        enterprise_url_for(descriptor, auth, None, source_archive)
+    }
+
+    fn enterprise_url_for(descriptor: &GithubDescriptor, auth: &Option<String>, base_url: Option<&str>,
+                            source_archive: SourceArchive) -> String {
+        let api_cache = test_dir();
+        let github = Github {
+            github_user: "user".to_string(),
+            repository: "repo".to_string(),
+            descriptor: descriptor.clone(),
+            auth: auth.clone(),
+            source_archive: Some(source_archive),
+            base_url: base_url.map(|base_url| base_url.to_string()),
+            ..Default::default()
+        };
+        tokio::runtime::Runtime::new().unwrap()
+            .block_on(github.get_url(api_cache.path(), DEFAULT_MAX_RATE_LIMIT_WAIT))
+            .unwrap()
+    }
+
+    #[test]
+    fn source_archive_urls() {
+        let tag = GithubDescriptor::Tag { tag: "v1.2".to_string() };
+        let branch = GithubDescriptor::Branch(GitBranch { branch: "main".to_string(), refresh: Default::default() });
+        let commit = GithubDescriptor::Commit { commit: "0123abc".to_string() };
+
+        assert_eq!(url_for(&tag, &None, SourceArchive::Zip), "https://codeload.github.com/user/repo/zip/refs/tags/v1.2");
+        assert_eq!(url_for(&tag, &None, SourceArchive::Tar), "https://codeload.github.com/user/repo/tar.gz/refs/tags/v1.2");
+        assert_eq!(url_for(&branch, &None, SourceArchive::Zip), "https://codeload.github.com/user/repo/zip/refs/heads/main");
+        assert_eq!(url_for(&branch, &None, SourceArchive::Tar), "https://codeload.github.com/user/repo/tar.gz/refs/heads/main");
+        assert_eq!(url_for(&commit, &None, SourceArchive::Zip), "https://codeload.github.com/user/repo/zip/0123abc");
+        assert_eq!(url_for(&commit, &None, SourceArchive::Tar), "https://codeload.github.com/user/repo/tar.gz/0123abc");
+    }
+
+    #[test]
+    fn source_archive_urls_with_auth() {
+        let tag = GithubDescriptor::Tag { tag: "v1.2".to_string() };
+        let auth = Some("PAT my_token".to_string());
+
+        assert_eq!(url_for(&tag, &auth, SourceArchive::Zip), "https://api.github.com/repos/user/repo/zipball/v1.2");
+        assert_eq!(url_for(&tag, &auth, SourceArchive::Tar), "https://api.github.com/repos/user/repo/tarball/v1.2");
+    }
+
+    #[test]
+    fn enterprise_source_archive_urls() {
+        let tag = GithubDescriptor::Tag { tag: "v1.2".to_string() };
+        let commit = GithubDescriptor::Commit { commit: "0123abc".to_string() };
+        let base_url = Some("https://github.mycorp.com/");
+
+        assert_eq!(enterprise_url_for(&tag, &None, base_url, SourceArchive::Zip),
+                    "https://github.mycorp.com/user/repo/archive/refs/tags/v1.2.zip");
+        assert_eq!(enterprise_url_for(&commit, &None, base_url, SourceArchive::Tar),
+                    "https://github.mycorp.com/user/repo/archive/0123abc.tar.gz");
+        assert_eq!(enterprise_url_for(&tag, &Some("PAT my_token".to_string()), base_url, SourceArchive::Zip),
+                    "https://github.mycorp.com/api/v3/repos/user/repo/zipball/v1.2");
+    }
+
+    #[test]
+    fn enterprise_api_base() {
+        assert_eq!(api_base(None), "https://api.github.com");
+        assert_eq!(api_base(Some("https://github.mycorp.com")), "https://github.mycorp.com/api/v3");
+    }
+
+    #[test]
+    fn deserialize_source_archive() {
+        let yaml = r#"
+        github_user: user
+        repository: repo
+        tag: v1
+        source_archive: tar
+        "#;
+        let github: super::Github = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(github.source_archive(), SourceArchive::Tar);
+        assert_eq!(github.descriptor, GithubDescriptor::Tag { tag: "v1".to_string() });
+    }
+
+    #[test]
+    fn other_volumes_of_split_release() {
+        let release = |asset: &str| Github {
+            github_user: "user".to_string(),
+            repository: "repo".to_string(),
+            descriptor: GithubDescriptor::Release { release: None, asset: asset.to_string() },
+            volumes: Some(3),
+            ..Default::default()
+        };
+
+        let volumes = release("my_mod.7z.001").other_volumes().unwrap();
+
+        let volumes = volumes.iter().map(|(volume, name)| (&volume.descriptor, name.to_str().unwrap())).collect::<Vec<_>>();
+        assert_eq!(volumes, vec![
+            (&GithubDescriptor::Release { release: None, asset: "my_mod.7z.002".to_string() }, "my_mod.7z.002"),
+            (&GithubDescriptor::Release { release: None, asset: "my_mod.7z.003".to_string() }, "my_mod.7z.003"),
+        ]);
+        assert!(release("my_mod.7z").other_volumes().is_err());
+        let tag = Github { descriptor: GithubDescriptor::Tag { tag: "v1".to_string() }, ..release("my_mod.7z.001") };
+        assert!(tag.other_volumes().is_err());
+    }
+}
@@ -1,11 +1,16 @@
 
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use log::debug;
 use serde::{Deserialize, Serialize};
 
-use crate::download::{Downloader, DownloadOpts};
+use crate::download::{Downloader, DownloadOpts, DownloadRequest, DownloadedFile, PostBody, ProxySetting};
 use crate::module::refresh::RefreshCondition;
+use crate::remote_zip::{list_remote_zip, RemoteZip};
+use crate::split_archive::volume_name;
 
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
@@ -17,13 +22,327 @@ pub struct Http {
     #[serde(default)]
     #[serde(with = "crate::module::refresh::RefreshConditionAsString")]
     pub refresh: RefreshCondition,
+    /// Defaults to `post` when there is a `form` or a `body`, `get` otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<HttpMethod>,
+    /// Fields sent url-encoded in a POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub form: Option<BTreeMap<String, String>>,
+    /// Sent as-is in a POST.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Page that gives the download token (for gateways that want a CSRF/download token):
+    /// it is fetched first, and `${token}` in `http`, `form` values and `body` is replaced with the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_url: Option<String>,
+    /// Regex that finds the token in the `token_url` page: its first group if it has one, else the whole match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_regex: Option<String>,
+    /// Proxy for this source only (instead of the `proxy` config value).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Connects directly to this source, even with a `proxy` in the config or in the environment.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_proxy: bool,
+    /// Number of volumes of a split archive (`http` ends with `.001`): the other volumes (`.002`, `.003`...)
+    /// are downloaded next to the first one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<u32>,
+}
+
+/// Placeholder replaced with the token obtained from `token_url`.
+const TOKEN_PLACEHOLDER: &str = "${token}";
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpMethod {
+    Get,
+    Post,
 }
 
 impl Http {
     pub fn from(http: &str) -> Self { Self { http: http.to_owned(), ..Self::default() } }
 
-    pub async fn download(&self, downloader: &Downloader, dest: &PathBuf, save_name: PathBuf) -> Result<PathBuf> {
-        let opts = &DownloadOpts { no_cache: self.no_cache, refresh: self.refresh.clone() };
-        downloader.download(&self.http, dest, save_name, opts, &None).await
+    pub async fn download(&self, downloader: &Downloader, dest: &PathBuf, save_name: PathBuf) -> Result<DownloadedFile> {
+        let source_downloader = self.source_downloader(downloader)?;
+        let downloader = source_downloader.as_ref().unwrap_or(downloader);
+        // no need for a token when the cached archive is reused
+        let http = if self.refresh == RefreshCondition::Never && dest.join(&save_name).exists() {
+            Cow::Borrowed(self)
+        } else {
+            self.with_token(downloader).await?
+        };
+        let opts = &DownloadOpts { no_cache: http.no_cache, refresh: http.refresh.clone(), request: http.request()? };
+        downloader.download(&http.http, dest, save_name, opts, &None).await
+    }
+
+    /// The volumes after the first one when `volumes` is set: the location of each volume and the name it is saved
+    /// with, derived from the URL and the name of the first volume (`save_name`).
+    pub fn other_volumes(&self, save_name: &Path) -> Result<Vec<(Http, PathBuf)>> {
+        let count = match self.volumes {
+            None => return Ok(vec![]),
+            Some(count) => count,
+        };
+        let name_stem = save_name.to_str().and_then(|name| name.strip_suffix(".001"));
+        let (url_stem, name_stem) = match (self.http.strip_suffix(".001"), name_stem) {
+            (Some(url_stem), Some(name_stem)) => (url_stem, name_stem),
+            _ => bail!("Location {} has `volumes` but is not the first volume of a split archive\n -> its URL and name must end with `.001`",
+                        self.http),
+        };
+        Ok((2..=count).map(|number| (
+            Http { http: volume_name(url_stem, number), volumes: None, ..self.clone() },
+            PathBuf::from(volume_name(name_stem, number)),
+        )).collect())
+    }
+
+    /// With a `token_url`, fetches the token and returns the location with the placeholders replaced.
+    pub async fn with_token(&self, downloader: &Downloader) -> Result<Cow<'_, Http>> {
+        let (token_url, token_regex) = match (&self.token_url, &self.token_regex) {
+            (None, None) => return Ok(Cow::Borrowed(self)),
+            (Some(token_url), Some(token_regex)) => (token_url, token_regex),
+            _ => bail!("Location {} must have both `token_url` and `token_regex`, or none of them", self.http),
+        };
+        let regex = match regex::Regex::new(token_regex) {
+            Err(error) => bail!("Invalid `token_regex` {} for location {}\n -> {:?}", token_regex, self.http, error),
+            Ok(regex) => regex,
+        };
+        let page = match downloader.download_to_memory(token_url, &None).await {
+            Err(error) => bail!("Could not fetch the download token page {} for location {}\n -> {:?}", token_url, self.http, error),
+            Ok(page) => String::from_utf8_lossy(&page).to_string(),
+        };
+        let token = match regex.captures(&page).and_then(|found| found.get(1).or_else(|| found.get(0))) {
+            None => bail!("Download token for location {} not found in {}\n -> `token_regex` {} doesn't match the page",
+                            self.http, token_url, token_regex),
+            Some(token) => token.as_str(),
+        };
+        debug!("download token for {} obtained from {}", self.http, token_url);
+        Ok(Cow::Owned(self.replace_token(token)?))
+    }
+
+    fn replace_token(&self, token: &str) -> Result<Http> {
+        let uses_token = self.http.contains(TOKEN_PLACEHOLDER)
+            || self.form.iter().flat_map(|form| form.values()).any(|value| value.contains(TOKEN_PLACEHOLDER))
+            || self.body.iter().any(|body| body.contains(TOKEN_PLACEHOLDER));
+        if !uses_token {
+            bail!("Location {} has a `token_url` but doesn't use {}\n -> put it in the URL, a `form` value or the `body`",
+                    self.http, TOKEN_PLACEHOLDER);
+        }
+        Ok(Http {
+            http: self.http.replace(TOKEN_PLACEHOLDER, token),
+            form: self.form.as_ref().map(|form| form.iter()
+                .map(|(name, value)| (name.clone(), value.replace(TOKEN_PLACEHOLDER, token)))
+                .collect()),
+            body: self.body.as_ref().map(|body| body.replace(TOKEN_PLACEHOLDER, token)),
+            token_url: None,
+            token_regex: None,
+            ..self.clone()
+        })
+    }
+
+    /// Reads the content of a zip archive without downloading it (only its central directory,
+    /// if the server supports ranges).
+    pub async fn list_remote_zip(&self, downloader: &Downloader) -> Result<RemoteZip> {
+        if self.request()? != DownloadRequest::Get {
+            bail!("Location {} is obtained with a POST request\n -> it can't be read partially", self.http);
+        }
+        let source_downloader = self.source_downloader(downloader)?;
+        let downloader = source_downloader.as_ref().unwrap_or(downloader);
+        let http = self.with_token(downloader).await?;
+        list_remote_zip(downloader, &http.http, &None).await
+    }
+
+    /// The proxy setting of this source, `None` when it uses the one of the downloader.
+    pub fn proxy_override(&self) -> Result<Option<ProxySetting>> {
+        match (&self.proxy, self.no_proxy) {
+            (Some(_), true) => bail!("Location {} has both `proxy` and `no_proxy: true`\n -> only one of them can be set", self.http),
+            (Some(proxy), false) => Ok(Some(ProxySetting::Url(proxy.clone()))),
+            (None, true) => Ok(Some(ProxySetting::Direct)),
+            (None, false) => Ok(None),
+        }
+    }
+
+    /// How this source is reached when `global` is the proxy setting of the config.
+    pub fn effective_proxy(&self, global: &ProxySetting) -> Result<ProxySetting> {
+        Ok(self.proxy_override()?.unwrap_or_else(|| global.clone()))
+    }
+
+    /// A downloader with the proxy of this source when it overrides the one of `downloader`.
+    pub fn source_downloader(&self, downloader: &Downloader) -> Result<Option<Downloader>> {
+        Ok(self.proxy_override()?.map(|proxy| downloader.for_proxy(proxy)))
+    }
+
+    pub fn request(&self) -> Result<DownloadRequest> {
+        let body = match (&self.form, &self.body) {
+            (Some(_), Some(_)) => bail!("Location {} has both a `form` and a `body`\n -> only one of them can be sent", self.http),
+            (Some(form), None) => Some(PostBody::Form(form.clone())),
+            (None, Some(body)) => Some(PostBody::Raw(body.clone())),
+            (None, None) => None,
+        };
+        match (self.method, body) {
+            (None | Some(HttpMethod::Get), None) => Ok(DownloadRequest::Get),
+            (None | Some(HttpMethod::Post), Some(body)) => Ok(DownloadRequest::Post(body)),
+            (Some(HttpMethod::Post), None) => Ok(DownloadRequest::Post(PostBody::Form(BTreeMap::new()))),
+            (Some(HttpMethod::Get), Some(_)) =>
+                bail!("Location {} has a `form` or a `body` with `method: get`\n -> use `method: post` to send them", self.http),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use crate::download::{DownloadRequest, Downloader, PostBody, ProxySetting};
+    use crate::test_utils::server::TestServer;
+    use crate::test_utils::test_dir;
+
+    use super::{Http, HttpMethod};
+
+    fn token_location(address: &str, token_regex: &str) -> Http {
+        Http {
+            token_url: Some(format!("{}/landing", address)),
+            token_regex: Some(token_regex.to_string()),
+            rename: Some("my_mod.zip".to_string()),
+            ..Http::from(&format!("{}/download?file=my_mod&token=${{token}}", address))
+        }
+    }
+
+    #[tokio::test]
+    async fn download_with_token() {
+        let server = TestServer::pages(vec![
+            ("/landing", b"<form><input type=\"hidden\" name=\"csrf\" value=\"a1b2c3\"></form>"),
+            ("/download?file=my_mod&token=a1b2c3", b"archive content"),
+        ]);
+        let http = token_location(server.address(), r#"name="csrf" value="(\w+)""#);
+        let dest = test_dir();
+
+        let downloaded = http.download(&Downloader::new(), &dest.path().to_path_buf(), PathBuf::from("my_mod.zip")).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(downloaded.path).unwrap(), "archive content");
+        assert_eq!(server.paths(), vec!["/landing".to_string(), "/download?file=my_mod&token=a1b2c3".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn token_not_found() {
+        let server = TestServer::pages(vec![("/landing", b"<p>Too many downloads, try later</p>")]);
+        let http = token_location(server.address(), r#"name="csrf" value="(\w+)""#);
+        let dest = test_dir();
+
+        let error = http.download(&Downloader::new(), &dest.path().to_path_buf(), PathBuf::from("my_mod.zip")).await
+            .unwrap_err().to_string();
+
+        assert!(error.contains("Download token for location"), "{}", error);
+        assert!(error.contains("/landing"), "{}", error);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn effective_proxy_per_source() {
+        let global = ProxySetting::Url("http://corporate.example:3128".to_string());
+        let yaml = r#"
+            - http: https://example.com/my_mod.zip
+            - http: http://mirror.internal/my_mod.zip
+              no_proxy: true
+            - http: http://other.example/my_mod.zip
+              proxy: http://mirror-proxy.example:8080
+            - http: http://other.example/my_mod.zip
+              proxy: http://mirror-proxy.example:8080
+              no_proxy: true
+        "#;
+        let sources: Vec<Http> = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(sources[0].effective_proxy(&global).unwrap(), global);
+        assert_eq!(sources[1].effective_proxy(&global).unwrap(), ProxySetting::Direct);
+        assert_eq!(sources[1].effective_proxy(&ProxySetting::System).unwrap(), ProxySetting::Direct);
+        assert_eq!(sources[2].effective_proxy(&global).unwrap(), ProxySetting::Url("http://mirror-proxy.example:8080".to_string()));
+        assert!(sources[3].effective_proxy(&global).is_err());
+        assert_eq!(sources[0].effective_proxy(&ProxySetting::System).unwrap(), ProxySetting::System);
+    }
+
+    #[tokio::test]
+    async fn download_through_source_proxy() {
+        // a proxy receives the absolute URL in the request line
+        let global_proxy = TestServer::pages(vec![("http://mods.invalid/my_mod.zip", b"through the global proxy")]);
+        let source_proxy = TestServer::pages(vec![("http://mods.invalid/my_mod.zip", b"through the source proxy")]);
+        let mirror = TestServer::pages(vec![("/my_mod.zip", b"direct")]);
+        let downloader = Downloader::new().with_proxy(ProxySetting::Url(global_proxy.address().to_string()));
+        let dest = test_dir();
+        let download = |http: Http, name: &'static str| {
+            let dest = dest.path().to_path_buf();
+            let downloader = &downloader;
+            async move {
+                let downloaded = http.download(downloader, &dest, PathBuf::from(name)).await.unwrap();
+                std::fs::read_to_string(downloaded.path).unwrap()
+            }
+        };
+
+        let global = download(Http::from("http://mods.invalid/my_mod.zip"), "global.zip").await;
+        let source = download(Http { proxy: Some(source_proxy.address().to_string()), ..Http::from("http://mods.invalid/my_mod.zip") }, "source.zip").await;
+        let direct = download(Http { no_proxy: true, ..Http::from(&mirror.url("/my_mod.zip")) }, "direct.zip").await;
+
+        assert_eq!(global, "through the global proxy");
+        assert_eq!(source, "through the source proxy");
+        assert_eq!(direct, "direct");
+        assert_eq!(global_proxy.requests().len(), 1);
+        assert_eq!(source_proxy.requests().len(), 1);
+        assert_eq!(mirror.paths(), vec!["/my_mod.zip".to_string()]);
+    }
+
+    #[test]
+    fn token_replaced_in_form() {
+        let http = Http {
+            form: Some(BTreeMap::from([("csrf".to_string(), "${token}".to_string()), ("id".to_string(), "12".to_string())])),
+            token_url: Some("https://portal.example/file/12".to_string()),
+            token_regex: Some("csrf=(\\w+)".to_string()),
+            ..Http::from("https://portal.example/download.php")
+        };
+
+        let replaced = http.replace_token("xyz").unwrap();
+
+        let form = BTreeMap::from([("csrf".to_string(), "xyz".to_string()), ("id".to_string(), "12".to_string())]);
+        assert_eq!(replaced.request().unwrap(), DownloadRequest::Post(PostBody::Form(form)));
+        assert_eq!(replaced.token_url, None);
+        assert!(Http { form: None, ..http }.replace_token("xyz").is_err());
+    }
+
+    #[test]
+    fn read_post_location() {
+        let yaml = r#"
+            http: https://portal.example/download.php
+            rename: my_mod.zip
+            form:
+              file_id: "1234"
+              confirm: "yes"
+        "#;
+        let http: Http = serde_yaml::from_str(yaml).unwrap();
+
+        let form = BTreeMap::from([("file_id".to_string(), "1234".to_string()), ("confirm".to_string(), "yes".to_string())]);
+        assert_eq!(http.request().unwrap(), DownloadRequest::Post(PostBody::Form(form)));
+    }
+
+    #[test]
+    fn get_is_the_default() {
+        assert_eq!(Http::from("https://example.com/my_mod.zip").request().unwrap(), DownloadRequest::Get);
+        let http = Http { method: Some(HttpMethod::Post), body: Some("id=1".to_string()), ..Http::from("https://example.com/get") };
+        assert_eq!(http.request().unwrap(), DownloadRequest::Post(PostBody::Raw("id=1".to_string())));
+        let http = Http { method: Some(HttpMethod::Get), body: Some("id=1".to_string()), ..Http::from("https://example.com/get") };
+        assert!(http.request().is_err());
+    }
+
+    #[test]
+    fn other_volumes_of_split_archive() {
+        let http = Http { volumes: Some(3), rename: Some("my_mod.7z.001".to_string()), ..Http::from("https://example.com/files/MyMod.7z.001") };
+
+        let volumes = http.other_volumes(&PathBuf::from("my_mod.7z.001")).unwrap();
+
+        let volumes = volumes.iter().map(|(volume, name)| (volume.http.as_str(), name.to_str().unwrap())).collect::<Vec<_>>();
+        assert_eq!(volumes, vec![
+            ("https://example.com/files/MyMod.7z.002", "my_mod.7z.002"),
+            ("https://example.com/files/MyMod.7z.003", "my_mod.7z.003"),
+        ]);
+        assert_eq!(Http::from("https://example.com/files/MyMod.7z.001").other_volumes(&PathBuf::from("MyMod.7z.001")).unwrap(), vec![]);
+        let not_split = Http { volumes: Some(3), ..Http::from("https://example.com/files/MyMod.7z") };
+        assert!(not_split.other_volumes(&PathBuf::from("MyMod.7z")).is_err());
     }
 }
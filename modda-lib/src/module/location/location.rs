@@ -1,4 +1,5 @@
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::marker::PhantomData;
 use std::str::FromStr;
@@ -11,8 +12,12 @@ use void::Void;
 
 use crate::lowercase::{LwcString, lwc};
 use crate::module::pre_copy_command::PrecopyCommand;
+use crate::archive_name_encoding::ArchiveNameEncoding;
+use crate::case_collisions::CaseCollisions;
+use crate::tar_symlinks::TarSymlinks;
 use crate::{archive_layout::Layout, patch_source::PatchDesc};
 
+use super::normalize::NormalizeSpec;
 use super::replace::ReplaceSpec;
 use super::source::Source;
 
@@ -55,15 +60,62 @@ pub struct ConcreteLocation {
     #[serde(flatten)]
     pub source: Source,
     /// Specifies which files from the archive will be copied to the game directory.
-    /// Read as a Unix shell style glob pattern (https://docs.rs/glob/0.3.0/glob/struct.Pattern.html)
-    #[serde(default)]
-    pub layout: Layout,
+    /// Read as a Unix shell style glob pattern (https://docs.rs/glob/0.3.0/glob/struct.Pattern.html)<br>
+    /// If absent, the manifest `default_layout` is used, or the default layout if there is none.
+    pub layout: Option<Layout>,
     pub patch: Option<PatchDesc>,
     #[serde(default)]
     pub patches: Vec<PatchDesc>,
     /// regex-based search and replace, runs after patch.
     pub replace: Option<Vec<ReplaceSpec>>,
     pub precopy: Option<PrecopyCommand>,
+    /// Overrides the `extract_location` from the config for this module only
+    /// (same `~` and environment variable expansion).
+    pub extract_location: Option<String>,
+    /// Overrides the `normalize_permissions` config value for this module.
+    pub normalize_permissions: Option<bool>,
+    /// Password of a password-protected zip archive.<br>
+    /// Environment variables are expanded (for example `${MY_MOD_PASSWORD}`), so that the actual password
+    /// doesn't need to be in the manifest.
+    pub password: Option<String>,
+    /// Overrides the `archive_name_encoding` config value for this module.
+    pub archive_name_encoding: Option<ArchiveNameEncoding>,
+    /// Overrides the `case_collisions` config value for this module.
+    pub case_collisions: Option<CaseCollisions>,
+    /// Overrides the `tar_symlinks` config value for this module.
+    pub tar_symlinks: Option<TarSymlinks>,
+    /// Line endings and executable bits fixed once the files are in the game directory.
+    pub normalize: Option<NormalizeSpec>,
+    /// Files of the archive moved to another place in the mod directory during extraction:
+    /// maps a glob (relative to the extracted archive content) to a destination relative to the mod directory
+    /// (the file name is kept if it ends with `/`).<br>
+    /// Not named `rename` because http locations already have a `rename` property.
+    pub relocate: Option<BTreeMap<String, String>>,
+    /// Merges the extracted files, one by one, into a mod directory that already exists (an add-on archive
+    /// extracted as the same module as its base mod).<br>
+    /// Without it, the directories are moved as a whole and the extraction stops at the first file that exists.
+    pub merge: Option<MergeExisting>,
+    /// How many files of the location are downloaded at once (the `volumes` of a split http archive or github release);
+    /// one after the other by default.
+    pub download_concurrency: Option<usize>,
+}
+
+/// What a `merge` extraction does with the files that already exist in the game directory.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeExisting {
+    /// The files of the archive replace the existing ones
+    Overwrite,
+    /// The existing files are kept, only the new files of the archive are added
+    KeepExisting,
+    /// Nothing is moved if a file of the archive already exists
+    Fail,
+}
+
+impl ConcreteLocation {
+    pub fn layout(&self) -> Layout {
+        self.layout.clone().unwrap_or_default()
+    }
 }
 
 pub fn location_deser<'de, D>(deserializer: D) -> Result<Location, D::Error>
@@ -245,6 +297,8 @@ mod test_deserialize {
                         check: CheckReplace::BoolValue(false),
                         max_depth: None,
                         regex: false,
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -282,6 +336,8 @@ mod test_deserialize {
                         max_depth: None,
                         regex: false,
                         check: CheckReplace::BoolValue(false),
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -319,6 +375,8 @@ mod test_deserialize {
                         max_depth: None,
                         regex: false,
                         check: CheckReplace::BoolValue(true),
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -356,6 +414,8 @@ mod test_deserialize {
                         max_depth: None,
                         regex: false,
                         check: CheckReplace::Exact(NonZeroU32::new(123).unwrap()),
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -423,6 +483,8 @@ mod test_deserialize {
                         max_depth: None,
                         regex: false,
                         check: CheckReplace::MoreThan(NonZeroU32::new(123).unwrap()),
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -460,6 +522,8 @@ mod test_deserialize {
                         max_depth: None,
                         regex: false,
                         check: CheckReplace::MoreThan(NonZeroU32::new(123).unwrap()),
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -497,6 +561,8 @@ mod test_deserialize {
                         max_depth: None,
                         regex: false,
                         check: CheckReplace::BoolValue(true),
+                        only_if_contains: None,
+                        skip_if_contains: None,
                     }
                 ]),
                 ..Default::default()
@@ -1,6 +1,7 @@
 pub mod github;
 pub mod http;
 pub mod location;
+pub mod normalize;
 pub mod replace;
 pub mod source;
 pub mod strict_replace;
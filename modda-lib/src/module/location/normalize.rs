@@ -0,0 +1,279 @@
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use globwalk::GlobWalkerBuilder;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::permissions::set_executable;
+
+/// Fixes for mods authored on another system, applied to the mod files once they are in the game
+/// directory (before `patch` and `replace`).
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
+pub struct NormalizeSpec {
+    /// Globs of text files (_inside the mod root directory_, like `replace`) whose CRLF line endings are converted to LF.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub line_endings: Vec<String>,
+    /// Globs of text files whose UTF-8 byte order mark is removed (WeiDU doesn't expect one, in `.tra` files for example).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip_bom: Vec<String>,
+    /// Globs of files (scripts, helper programs) that are made executable; nothing is done on Windows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub executable: Vec<String>,
+}
+
+impl NormalizeSpec {
+    pub fn exec(&self, root: &Path) -> Result<()> {
+        let to_lf = matching_files(root, &self.line_endings)?.into_iter().collect::<BTreeSet<_>>();
+        let strip_bom = matching_files(root, &self.strip_bom)?.into_iter().collect::<BTreeSet<_>>();
+        let (mut converted, mut stripped) = (0, 0);
+        for file in to_lf.union(&strip_bom) {
+            let changes = normalize_file(file, strip_bom.contains(file), to_lf.contains(file))?;
+            converted += usize::from(changes.crlf_converted);
+            stripped += usize::from(changes.bom_removed);
+        }
+        let executables = matching_files(root, &self.executable)?;
+        for file in &executables {
+            set_executable(file)?;
+        }
+        info!("normalized {:?}: {} file(s) converted to LF line endings, {} BOM(s) removed, {} file(s) made executable",
+                root, converted, stripped, executables.len());
+        Ok(())
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+fn matching_files(root: &Path, globs: &[String]) -> Result<Vec<std::path::PathBuf>> {
+    if globs.is_empty() {
+        return Ok(vec![]);
+    }
+    let walker = match GlobWalkerBuilder::from_patterns(root, globs).case_insensitive(true).file_type(globwalk::FileType::FILE).build() {
+        Ok(walker) => walker,
+        Err(error) => bail!("Invalid normalize patterns {:?}\n -> {:?}", globs, error),
+    };
+    Ok(walker.filter_map(Result::ok).map(|entry| entry.into_path()).collect())
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct TextChanges {
+    bom_removed: bool,
+    crlf_converted: bool,
+}
+
+/// Rewrites the file (through a temporary file next to it, so that large files are not read in memory);
+/// it is only replaced if something changed.
+fn normalize_file(file: &Path, strip_bom: bool, to_lf: bool) -> Result<TextChanges> {
+    let input = match File::open(file) {
+        Ok(input) => input,
+        Err(error) => bail!("Could not read {:?}\n -> {:?}", file, error),
+    };
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let mut output = match tempfile::NamedTempFile::new_in(dir) {
+        Ok(output) => output,
+        Err(error) => bail!("Could not create a temporary file in {:?}\n -> {:?}", dir, error),
+    };
+    let changes = match normalize_stream(input, output.as_file_mut(), strip_bom, to_lf) {
+        Ok(changes) => changes,
+        Err(error) => bail!("Could not normalize {:?}\n -> {:?}", file, error),
+    };
+    if changes == TextChanges::default() {
+        return Ok(changes);
+    }
+    debug!("normalizing {:?} ({:?})", file, changes);
+    let permissions = std::fs::metadata(file)?.permissions();
+    std::fs::set_permissions(output.path(), permissions)?;
+    if let Err(error) = output.persist(file) {
+        bail!("Could not write {:?}\n -> {:?}", file, error);
+    }
+    Ok(changes)
+}
+
+/// Copies `input` to `output` without the leading UTF-8 BOM (with `strip_bom`) and with CRLF line endings
+/// converted to LF (with `to_lf`), one buffer at a time.
+fn normalize_stream<R: Read, W: Write>(mut input: R, output: W, strip_bom: bool, to_lf: bool) -> io::Result<TextChanges> {
+    let mut writer = BufWriter::new(output);
+    let mut start = vec![];
+    let mut bom_removed = false;
+    if strip_bom {
+        input.by_ref().take(UTF8_BOM.len() as u64).read_to_end(&mut start)?;
+        if start == UTF8_BOM {
+            bom_removed = true;
+            start.clear();
+        }
+    }
+    let mut line_endings = LineEndings { to_lf, pending_cr: false, converted: false, buffer: vec![] };
+    line_endings.write(&start, &mut writer)?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => line_endings.write(&buffer[..read], &mut writer)?,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    line_endings.finish(&mut writer)?;
+    writer.flush()?;
+    Ok(TextChanges { bom_removed, crlf_converted: line_endings.converted })
+}
+
+/// CRLF to LF conversion of a stream given in chunks.
+struct LineEndings {
+    to_lf: bool,
+    /// The previous chunk ended with a CR, it is only written once the next byte is known.
+    pending_cr: bool,
+    converted: bool,
+    buffer: Vec<u8>,
+}
+
+impl LineEndings {
+    fn write<W: Write>(&mut self, chunk: &[u8], writer: &mut W) -> io::Result<()> {
+        if !self.to_lf {
+            return writer.write_all(chunk);
+        }
+        self.buffer.clear();
+        for &byte in chunk {
+            if self.pending_cr {
+                self.pending_cr = false;
+                if byte == b'\n' {
+                    self.converted = true;
+                } else {
+                    self.buffer.push(b'\r');
+                }
+            }
+            if byte == b'\r' {
+                self.pending_cr = true;
+            } else {
+                self.buffer.push(byte);
+            }
+        }
+        writer.write_all(&self.buffer)
+    }
+
+    fn finish<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.pending_cr {
+            self.pending_cr = false;
+            writer.write_all(b"\r")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    use crate::test_utils::test_dir;
+
+    use super::{normalize_stream, NormalizeSpec, TextChanges};
+
+    fn mod_dir() -> tempfile::TempDir {
+        let temp = test_dir();
+        std::fs::create_dir_all(temp.path().join("tra")).unwrap();
+        std::fs::create_dir_all(temp.path().join("tools")).unwrap();
+        std::fs::write(temp.path().join("my_mod.tp2"), "BACKUP ~backup~\r\nAUTHOR ~me~\r\n").unwrap();
+        std::fs::write(temp.path().join("tra").join("english.tra"), "@1 = ~Hello\r\nthere~\r\n").unwrap();
+        std::fs::write(temp.path().join("readme.txt"), "windows\r\n").unwrap();
+        std::fs::write(temp.path().join("tools").join("build.sh"), "#!/bin/sh\r\n").unwrap();
+        temp
+    }
+
+    #[test]
+    fn convert_crlf_to_lf() {
+        let temp = mod_dir();
+        let spec = NormalizeSpec { line_endings: vec!["*.tp2".to_string(), "*.TRA".to_string()], ..Default::default() };
+
+        spec.exec(temp.path()).unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(temp.path().join(path)).unwrap();
+        assert_eq!(read("my_mod.tp2"), "BACKUP ~backup~\nAUTHOR ~me~\n");
+        assert_eq!(read("tra/english.tra"), "@1 = ~Hello\nthere~\n");
+        // not matched
+        assert_eq!(read("readme.txt"), "windows\r\n");
+        assert_eq!(read("tools/build.sh"), "#!/bin/sh\r\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn set_exec_bit() {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = mod_dir();
+        let script = temp.path().join("tools").join("build.sh");
+        std::fs::set_permissions(&script, Permissions::from_mode(0o644)).unwrap();
+        std::fs::set_permissions(temp.path().join("readme.txt"), Permissions::from_mode(0o644)).unwrap();
+        let spec = NormalizeSpec { executable: vec!["tools/*.sh".to_string()], ..Default::default() };
+
+        spec.exec(temp.path()).unwrap();
+
+        let mode = |path: PathBuf| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(script), 0o755);
+        assert_eq!(mode(temp.path().join("readme.txt")), 0o644);
+    }
+
+    /// A copy of the `resources/test/normalize` fixture (UTF-8 BOMs and CRLF line endings).
+    fn fixture_dir() -> tempfile::TempDir {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let temp = test_dir();
+        let options = fs_extra::dir::CopyOptions { content_only: true, ..Default::default() };
+        fs_extra::dir::copy(project.join("resources/test/normalize"), temp.path(), &options).unwrap();
+        temp
+    }
+
+    #[test]
+    fn strip_bom_and_convert_line_endings() {
+        let temp = fixture_dir();
+        let spec = NormalizeSpec {
+            strip_bom: vec!["*.tra".to_string()],
+            line_endings: vec!["tra/french.tra".to_string()],
+            ..Default::default()
+        };
+
+        spec.exec(temp.path()).unwrap();
+
+        let read = |path: &str| std::fs::read(temp.path().join(path)).unwrap();
+        assert_eq!(read("tra/french.tra"), "@1 = ~Bonjour\nlà~\n@2 = ~Au revoir~\n".as_bytes());
+        assert_eq!(read("tra/english.tra"), b"@1 = ~Hello~\n@2 = ~Goodbye~\n");
+        // not matched
+        assert_eq!(read("readme.txt"), b"\xEF\xBB\xBFnot matched\r\n");
+    }
+
+    /// Reads one byte at a time, like a stream whose buffers end anywhere.
+    struct ByteByByte<'a>(&'a [u8]);
+
+    impl Read for ByteByByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) if !buf.is_empty() => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn normalize_across_buffers() {
+        let normalize = |input: &[u8], strip_bom: bool, to_lf: bool| {
+            let mut output = vec![];
+            let changes = normalize_stream(ByteByByte(input), &mut output, strip_bom, to_lf).unwrap();
+            (String::from_utf8(output).unwrap(), changes)
+        };
+
+        assert_eq!(normalize(b"\xEF\xBB\xBFa\r\nb\r\r\nc\rd\r", true, true),
+                    ("a\nb\r\nc\rd\r".to_string(), TextChanges { bom_removed: true, crlf_converted: true }));
+        assert_eq!(normalize(b"\xEF\xBB\xBFa\r\n", false, false),
+                    ("\u{FEFF}a\r\n".to_string(), TextChanges::default()));
+        assert_eq!(normalize(b"ab", true, true), ("ab".to_string(), TextChanges::default()));
+        assert_eq!(normalize(b"", true, true), (String::new(), TextChanges::default()));
+    }
+}
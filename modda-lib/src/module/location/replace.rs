@@ -37,6 +37,12 @@ pub struct ReplaceSpec {
     /// - if set to `>XXX` (for example `>123`) check there were more than XXX replacements done.
     #[serde(default)]
     pub check: CheckReplace,
+    /// If set, a file is only processed if its content contains this pattern.<br>
+    /// Like `replace`, it is a regular expression if `regex` is true, a plain string otherwise.
+    pub only_if_contains: Option<String>,
+    /// If set, a file is skipped if its content contains this pattern (for example because it was already patched).<br>
+    /// Like `replace`, it is a regular expression if `regex` is true, a plain string otherwise.
+    pub skip_if_contains: Option<String>,
 }
 
 impl ReplaceSpec {
@@ -83,20 +89,32 @@ impl ReplaceSpec {
         Ok(())
     }
 
-    pub fn exec(&self, root: &PathBuf, get_options: &GetOptions) -> Result<()> {
-        info!("ReplaceSpec.exec on {:?} - {} => {}", &self.file_globs, &self.replace, &self.with);
-        let walker = self.find_matching_files(root)?;
+    fn build_regex(&self, value: &str) -> Result<Regex> {
         let pattern = if self.regex {
-            Cow::Borrowed(&self.replace)
+            Cow::Borrowed(value)
         } else {
-            Cow::Owned(regex::escape(&self.replace))
+            Cow::Owned(regex::escape(value))
         };
         debug!("actual regex is {:?}", pattern);
-        let regex = match Regex::new(&pattern) {
-            Err(err) => bail!("Incorrect regex {} - {}", &self.replace, err),
-            Ok(regex) => regex,
-        };
-        let result = self.exec_with_walker(&regex, walker, get_options);
+        match Regex::new(&pattern) {
+            Err(err) => bail!("Incorrect regex {} - {}", value, err),
+            Ok(regex) => Ok(regex),
+        }
+    }
+
+    fn build_guards(&self) -> Result<ReplaceGuards> {
+        Ok(ReplaceGuards {
+            only_if_contains: self.only_if_contains.as_deref().map(|value| self.build_regex(value)).transpose()?,
+            skip_if_contains: self.skip_if_contains.as_deref().map(|value| self.build_regex(value)).transpose()?,
+        })
+    }
+
+    pub fn exec(&self, root: &PathBuf, get_options: &GetOptions) -> Result<()> {
+        info!("ReplaceSpec.exec on {:?} - {} => {}", &self.file_globs, &self.replace, &self.with);
+        let walker = self.find_matching_files(root)?;
+        let regex = self.build_regex(&self.replace)?;
+        let guards = self.build_guards()?;
+        let result = self.exec_with_walker(&regex, &guards, walker, get_options);
         match result {
             Err(ref err) => error!("ReplaceSpec.exec failure - {}", err),
             Ok(_) => info!("ReplaceSpec.exec success"),
@@ -104,13 +122,18 @@ impl ReplaceSpec {
         result
     }
 
-    fn exec_with_walker(&self, regex: &Regex, walker: GlobWalker, get_options: &GetOptions) -> Result<()> {
+    fn exec_with_walker(&self, regex: &Regex, guards: &ReplaceGuards, walker: GlobWalker, get_options: &GetOptions) -> Result<()> {
         let mut replace_count = 0;
         for dir_entry in walker.into_iter().filter_map(Result::ok) {
             if dir_entry.file_type().is_file() {
                 let file_path = dir_entry.into_path();
                 debug!("process matching file {:?}", file_path);
-                let ReplaceResult { count, replaced } = self.apply_replace(&file_path, &regex)?;
+                let content = self.read_content(&file_path)?;
+                if !guards.accept(&content) {
+                    info!("ReplaceSpec.exec - skip file {:?} (only_if_contains/skip_if_contains)", file_path);
+                    continue;
+                }
+                let ReplaceResult { count, replaced } = self.apply_replace_content(regex, &content)?;
                 replace_count += count;
                 self.swap_file_content(&file_path, &replaced)?;
             } else {
@@ -146,7 +169,7 @@ impl ReplaceSpec {
         }
     }
 
-    fn apply_replace(&self, file_path: &PathBuf, regex: &Regex) -> Result<ReplaceResult> {
+    fn read_content(&self, file_path: &PathBuf) -> Result<String> {
         let mut file = match File::open(file_path) {
             Err(err) => bail!("apply_replace - fail to open old file {:?} - {}", file_path, err),
             Ok(file) => file,
@@ -156,11 +179,10 @@ impl ReplaceSpec {
             bail!("apply_replace: could not read content of file {file_path:?}\n  {err}")
         }
 
-        let content = match String::from_utf8(buf) {
+        match String::from_utf8(buf) {
             Err(err)  => bail!("apply_replace: content of {file_path:?} does not appear to be UTF8\n  {err}"),
-            Ok(what) => what,
-        };
-        self.apply_replace_content(regex, &content)
+            Ok(what) => Ok(what),
+        }
     }
 
     fn apply_replace_content(&self, regex: &Regex, content: &str) -> Result<ReplaceResult> {
@@ -170,6 +192,19 @@ impl ReplaceSpec {
     }
 }
 
+struct ReplaceGuards {
+    only_if_contains: Option<Regex>,
+    skip_if_contains: Option<Regex>,
+}
+
+impl ReplaceGuards {
+    fn accept(&self, content: &str) -> bool {
+        let only_if = self.only_if_contains.as_ref().map(|regex| regex.is_match(content)).unwrap_or(true);
+        let skip_if = self.skip_if_contains.as_ref().map(|regex| regex.is_match(content)).unwrap_or(false);
+        only_if && !skip_if
+    }
+}
+
 pub struct ReplaceResult {
     pub count: u32,
     pub replaced: String,
@@ -221,6 +256,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: true,
             check: CheckReplace::BoolValue(false),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap();
@@ -256,6 +293,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: true,
             check: CheckReplace::BoolValue(false),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap();
@@ -291,6 +330,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::BoolValue(false),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap();
@@ -329,6 +370,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::BoolValue(true),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap();
@@ -363,6 +406,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::BoolValue(true),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap_err();
@@ -391,6 +436,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::Exact(NonZeroU32::new(2u32).unwrap()),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap();
@@ -425,6 +472,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::Exact(NonZeroU32::new(3u32).unwrap()),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap_err();
@@ -453,6 +502,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::Exact(NonZeroU32::new(1u32).unwrap()),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap_err();
@@ -481,6 +532,8 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::MoreThan(NonZeroU32::new(2u32).unwrap()),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap();
@@ -515,8 +568,76 @@ mod replace_tests {
             max_depth: Some(1),
             regex: false,
             check: CheckReplace::MoreThan(NonZeroU32::new(3u32).unwrap()),
+            only_if_contains: None,
+            skip_if_contains: None,
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap_err();
     }
+
+    #[test]
+    fn replace_when_only_if_contains_matches() {
+        let _ = env_logger::builder().is_test(true).filter_level(log::LevelFilter::Debug).try_init();
+
+        let marker = "only_if_contains_matches";
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let test_dir = project.join("target").join("replace_guard").join(marker);
+        std::fs::create_dir_all(&test_dir).unwrap();
+
+        let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/replace/input.txt");
+        let matching_file_path = test_dir.join(format!("{marker}.txt"));
+        std::fs::copy(origin, &matching_file_path).unwrap();
+        let other_file_path = test_dir.join(format!("{marker}_other.txt"));
+        std::fs::write(&other_file_path, "aaaa version 2").unwrap();
+
+        let replace_spec = ReplaceSpec {
+            file_globs: vec![format!("{marker}*.txt")],
+            replace: "aa".to_string(),
+            with: "[11]".to_string(),
+            max_depth: Some(1),
+            regex: false,
+            check: CheckReplace::Exact(NonZeroU32::new(2u32).unwrap()),
+            only_if_contains: Some("(abc)".to_string()),
+            skip_if_contains: None,
+        };
+        let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
+        replace_spec.exec(&test_dir, &get_options).unwrap();
+
+        assert_eq!(read_all(&matching_file_path).unwrap().join("\n"), "[11][11]BBcc\n(abc)def");
+        assert_eq!(read_all(&other_file_path).unwrap().join("\n"), "aaaa version 2");
+    }
+
+    #[test]
+    fn replace_skipped_when_already_patched() {
+        let _ = env_logger::builder().is_test(true).filter_level(log::LevelFilter::Debug).try_init();
+
+        let marker = "skip_if_contains";
+        let file_name = format!("{marker}.txt");
+
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let test_dir = project.join("target").join("replace_guard").join(marker);
+        std::fs::create_dir_all(&test_dir).unwrap();
+
+        let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/replace/input.txt");
+        let target_file_path = test_dir.join(&file_name);
+        std::fs::copy(origin, &target_file_path).unwrap();
+
+        let replace_spec = ReplaceSpec {
+            file_globs: vec![file_name],
+            replace: "(a+)BB".to_string(),
+            with: "${1}BBB".to_string(),
+            max_depth: Some(1),
+            regex: true,
+            check: CheckReplace::BoolValue(false),
+            only_if_contains: None,
+            skip_if_contains: Some("a+BBB".to_string()),
+        };
+        let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
+        replace_spec.exec(&test_dir, &get_options).unwrap();
+        // second run must not patch again
+        replace_spec.exec(&test_dir, &get_options).unwrap();
+
+        let result = read_all(&target_file_path).unwrap().join("\n");
+        assert_eq!(result, "aaaaBBBcc\n(abc)def");
+    }
 }
@@ -1,6 +1,6 @@
 
 
-use std::{path::PathBuf, borrow::Cow};
+use std::{path::{Component, Path, PathBuf}, borrow::Cow};
 
 use anyhow::{bail, Result};
 
@@ -54,8 +54,11 @@ impl Source {
                 Ok(PathBuf::from("http").join(&*host))
             }
             Absolute { .. } | Local { .. } => Ok(PathBuf::new()),
-            Github(super::github::Github { github_user, repository, .. }) =>
-                Ok(PathBuf::from("github").join(github_user).join(repository)),
+            // the host of a GitHub Enterprise server keeps its repositories apart from the github.com ones
+            Github(github) => match github.enterprise_host()? {
+                None => Ok(PathBuf::from("github").join(&github.github_user).join(&github.repository)),
+                Some(host) => Ok(PathBuf::from("github").join(host).join(&github.github_user).join(&github.repository)),
+            },
         }
     }
 
@@ -64,7 +67,7 @@ impl Source {
         match self {
             Http(super::http::Http { ref http, ref rename,.. }) => {
                 match rename {
-                    Some(rename) => Ok(PathBuf::from(rename)),
+                    Some(rename) => check_rename(rename),
                     None => {
                         let url = match url::Url::parse(http) {
                             Err(error) => bail!("Couldn't parse url {}\n -> {:?}", http, error),
@@ -83,29 +86,61 @@ impl Source {
                 }
             }
             Absolute { .. } | Local { .. } => Ok(PathBuf::new()),
-            Github(self::Github { descriptor, .. }) => match descriptor {
-                GithubDescriptor::Release { asset , ..} =>
-                                                    Ok(PathBuf::from(asset.to_owned())),
-                GithubDescriptor::Commit { commit } =>
-                                                    Ok(PathBuf::from(format!("{}-{}.zip", module_name, commit))),
-                GithubDescriptor::Branch(GitBranch { branch, .. }) =>
-                                                    Ok(PathBuf::from(format!("{}-{}.zip", module_name, branch))),
-                GithubDescriptor::Tag { tag } => Ok(PathBuf::from(format!("{}-{}.zip", module_name, tag))),
+            Github(github) => {
+                let ext = github.source_archive().extension();
+                match &github.descriptor {
+                    GithubDescriptor::Release { asset , ..} =>
+                                                        Ok(PathBuf::from(asset.to_owned())),
+                    GithubDescriptor::Commit { commit } =>
+                                                        Ok(PathBuf::from(format!("{}-{}.{}", module_name, commit, ext))),
+                    GithubDescriptor::Branch(GitBranch { branch, .. }) =>
+                                                        Ok(PathBuf::from(format!("{}-{}.{}", module_name, branch, ext))),
+                    GithubDescriptor::Tag { tag } => Ok(PathBuf::from(format!("{}-{}.{}", module_name, tag, ext))),
+                }
             }
         }
     }
 
+    /// The other volumes of a split archive (`volumes` of an http or github release location)
+    /// and the names they are saved with, `save_name` being the name of the first volume.
+    pub fn other_volumes(&self, save_name: &Path) -> Result<Vec<(Source, PathBuf)>> {
+        match self {
+            Source::Http(http) => Ok(http.other_volumes(save_name)?.into_iter()
+                .map(|(volume, name)| (Source::Http(volume), name)).collect()),
+            Source::Github(github) => Ok(github.other_volumes()?.into_iter()
+                .map(|(volume, name)| (Source::Github(volume), name)).collect()),
+            Source::Absolute { .. } | Source::Local { .. } => Ok(vec![]),
+        }
+    }
+
     pub fn default_strip_leading(&self) -> usize {
         use GithubDescriptor::*;
         match self {
-            Source::Github(Github { descriptor: Commit{..}, .. })
-            | Source::Github(Github { descriptor: Tag{..}, .. })
-            | Source::Github(Github { descriptor: Branch{..}, .. }) => 1,
+            Source::Github(github @ Github { descriptor: Commit{..}, .. })
+            | Source::Github(github @ Github { descriptor: Tag{..}, .. })
+            | Source::Github(github @ Github { descriptor: Branch{..}, .. }) => github.source_archive().strip_leading(),
             _ => 0,
         }
     }
 }
 
+/// `rename` can contain sub-directories but must stay inside the cache sub-directory
+/// (relative, no `..`).
+fn check_rename(rename: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(rename);
+    if path.file_name().is_none() {
+        bail!("Invalid 'rename' value {} - must end with a file name", rename);
+    }
+    for component in path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) =>
+                bail!("Invalid 'rename' value {} - must be a relative path without '..'", rename),
+        }
+    }
+    Ok(path)
+}
+
 #[cfg(test)]
 impl Source {
     pub fn http_source() -> Source {
@@ -137,3 +172,68 @@ impl Source {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::lowercase::lwc;
+    use crate::module::location::github::{Github, GithubDescriptor, SourceArchive};
+    use crate::module::location::http::Http;
+
+    use super::Source;
+
+    #[test]
+    fn save_name_with_subdir_rename() {
+        let source = Source::Http(Http {
+            http: "https://example.com/download?id=123".to_string(),
+            rename: Some("my_mods/v2/my_mod.zip".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            source.save_name(&lwc!("my_mod")).unwrap(),
+            PathBuf::from("my_mods/v2/my_mod.zip")
+        );
+    }
+
+    #[test]
+    fn save_name_rejects_traversal_rename() {
+        let source = Source::Http(Http {
+            http: "https://example.com/download?id=123".to_string(),
+            rename: Some("my_mods/../../my_mod.zip".to_string()),
+            ..Default::default()
+        });
+        source.save_name(&lwc!("my_mod")).unwrap_err();
+    }
+
+    #[test]
+    fn save_name_rejects_absolute_rename() {
+        let source = Source::Http(Http {
+            http: "https://example.com/download?id=123".to_string(),
+            rename: Some("/tmp/my_mod.zip".to_string()),
+            ..Default::default()
+        });
+        source.save_name(&lwc!("my_mod")).unwrap_err();
+    }
+
+    fn github_tag(source_archive: Option<SourceArchive>) -> Source {
+        Source::Github(Github {
+            github_user: "user".to_string(),
+            repository: "repo".to_string(),
+            descriptor: GithubDescriptor::Tag { tag: "v1".to_string() },
+            source_archive,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn save_name_and_strip_for_github_source_archive() {
+        let zip = github_tag(None);
+        assert_eq!(zip.save_name(&lwc!("my_mod")).unwrap(), PathBuf::from("my_mod-v1.zip"));
+        assert_eq!(zip.default_strip_leading(), 1);
+
+        let tar = github_tag(Some(SourceArchive::Tar));
+        assert_eq!(tar.save_name(&lwc!("my_mod")).unwrap(), PathBuf::from("my_mod-v1.tar.gz"));
+        assert_eq!(tar.default_strip_leading(), 1);
+    }
+}
@@ -1,12 +1,14 @@
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::collections::hash_map::Entry;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, Seek, SeekFrom, BufWriter, Write};
 use std::path::{PathBuf, Path};
 
+use globwalk::GlobWalkerBuilder;
+use itertools::Itertools;
 use lazy_static::lazy_static;
-use log::{info, debug};
+use log::{info, debug, warn};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
@@ -14,16 +16,39 @@ use anyhow::{bail, Result, Ok, anyhow};
 use serde_yaml::Deserializer;
 
 use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::download::{DownloadOpts, DownloadRequest, Downloader};
 use crate::global::Global;
+use crate::local_paths::local_path_errors;
 use crate::lowercase::LwcString;
+use crate::module::gen_mod::{check_component_indexes, GeneratedMod};
 use crate::module::module::Module;
+use crate::module::weidu_mod::WeiduMod;
+use crate::module::refresh::RefreshCondition;
 use crate::utils::pathext;
 
+use super::dependency_graph::DependencyGraph;
 use super::global_locations::{GlobalLocations, LocationRegistry};
-use super::location::location::ConcreteLocation;
+use super::location::location::{ConcreteLocation, Location};
+use super::location::source::Source;
 use super::manifest_conditions::ManifestConditions;
+use super::manifest_diff::{diff_manifests, ManifestDiff};
+use super::manifest_merge::{merge_manifests, ModuleMergeMode};
+use super::manifest_normalize::normalize_manifest;
+use super::module_listing::{list_modules, ModuleFilter, ModuleListing};
 
 
+/// How manifest parse errors are reported (`manifest_errors` config value).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestErrors {
+    /// Only the first error is reported.
+    #[default]
+    FailFast,
+    /// The top-level properties and each module are read separately, all their errors are reported.
+    Aggregate,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct VersionDetect {
     pub version: String,
@@ -47,11 +72,15 @@ pub struct Manifest {
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub modules: Vec<Module>,
+    /// Only used in an overlay manifest (see `merge`): names of the base modules to drop.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remove_modules: Vec<LwcString>,
 }
 
 impl Manifest {
-    pub fn assemble_from_path(path: &str, manifest_root: &CanonPath) -> Result<Self> {
-        let mut base = Self::read_path(path)?;
+    pub fn assemble_from_path(path: &str, manifest_root: &CanonPath, config: &Config) -> Result<Self> {
+        let mut base = Self::read_path_with(path, config.manifest_errors.unwrap_or_default())?;
         if !base.locations.external.is_empty() {
             let init: HashMap<LwcString, ConcreteLocation> = HashMap::new();
             let locations = base.locations.external.iter()
@@ -68,9 +97,211 @@ impl Manifest {
                 })?;
             base.merge_location_registry(locations);
         }
+        if let Some(base_url) = &config.github_base_url {
+            base.apply_github_base_url(base_url);
+        }
+        base.load_component_files(manifest_root.path())?;
+        base.sort_by_order();
+        base.apply_default_layout();
+        base.validate()?;
         Ok(base)
     }
 
+    /// Blocking version of `assemble_from_url_async`, with its own tokio runtime.<br>
+    /// Must not be called from async code (nested runtimes panic), use `assemble_from_url_async` there.
+    #[tokio::main]
+    pub async fn assemble_from_url(url: &str, downloader: &Downloader, cache_dir: &Path,
+                                    manifest_root: &CanonPath, config: &Config) -> Result<(Self, PathBuf)> {
+        Self::assemble_from_url_async(url, downloader, cache_dir, manifest_root, config).await
+    }
+
+    /// Downloads the manifest at `url` (http or https) into `cache_dir`, then assembles it like
+    /// a local file; paths relative to the manifest are resolved against `manifest_root`.<br>
+    /// Returns the manifest and the path of the downloaded copy.
+    pub async fn assemble_from_url_async(url: &str, downloader: &Downloader, cache_dir: &Path,
+                                            manifest_root: &CanonPath, config: &Config) -> Result<(Self, PathBuf)> {
+        let local_copy = fetch_manifest(url, downloader, cache_dir).await?;
+        let local_path = match local_copy.to_str() {
+            Some(path) => path,
+            None => bail!("Invalid path for downloaded manifest {:?}", local_copy),
+        };
+        let manifest = Self::assemble_from_path(local_path, manifest_root, config)?;
+        Ok((manifest, local_copy))
+    }
+
+    /// Replaces the `components: { file: ... }` references with the component lists read from
+    /// those files (relative to `manifest_root`).
+    pub fn load_component_files(&mut self, manifest_root: &Path) -> Result<()> {
+        for module in &mut self.modules {
+            if let Module::Mod { weidu_mod } = module {
+                weidu_mod.components = match weidu_mod.components.load_file(manifest_root) {
+                    Err(error) => bail!("Could not load components for module {}\n  {}", weidu_mod.name, error),
+                    std::result::Result::Ok(components) => components,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the modules added, removed or changed in `other` (the new version of this manifest).
+    pub fn diff(&self, other: &Manifest) -> ManifestDiff {
+        diff_manifests(self, other)
+    }
+
+    /// Name, kind, source type and number of components of the modules kept by `filter`
+    /// (what an installation would go through).
+    pub fn list_modules(&self, filter: &ModuleFilter) -> ModuleListing {
+        list_modules(self, filter)
+    }
+
+    /// Combines a base manifest with an overlay (for example personal tweaks of a shared modlist).
+    /// - base modules named in the overlay `remove_modules` are dropped
+    /// - overlay modules replace the base modules with the same name (paired by rank when a name
+    ///   appears several times), according to `mode`
+    /// - the other overlay modules are added after the base modules, in the overlay order
+    /// - overlay `locations` and `manifest_conditions` win over the base ones with the same key;
+    ///   `version` and `global` are the base ones
+    pub fn merge(base: &Manifest, overlay: &Manifest, mode: ModuleMergeMode) -> Result<Manifest> {
+        merge_manifests(base, overlay, mode)
+    }
+
+    /// Rewrites the module list in canonical form, for example after composing includes and overlays:
+    /// - modules are sorted in install order (see `sort_by_order`, which keeps the manifest order for ties)
+    /// - exact duplicates of a module are removed
+    /// - modules with the same name that can't be both right (weidu mod obtained from different locations,
+    ///   generated mods with different content, weidu and generated mod) are reported as errors,
+    ///   in which case the manifest is left unchanged
+    pub fn normalize(&mut self) -> Result<()> {
+        normalize_manifest(self)
+    }
+
+    /// The install order constraints between the modules (`requires` properties and `installation`
+    /// `before`/`after` hints), with cycle detection and a topological order.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::from_manifest(self)
+    }
+
+    /// Groups the modules by tag (modules without tags are not listed, modules with several tags
+    /// appear in each group), keeping the manifest order inside each group.
+    pub fn modules_by_tag(&self) -> BTreeMap<&str, Vec<&Module>> {
+        let mut result: BTreeMap<&str, Vec<&Module>> = BTreeMap::new();
+        for module in &self.modules {
+            for tag in module.get_tags().iter().unique() {
+                result.entry(tag.as_str()).or_default().push(module);
+            }
+        }
+        result
+    }
+
+    /// Manifest positions of the modules, in install order: by increasing `order`, with
+    /// ties (and modules without `order`) in manifest order.
+    pub fn install_order(&self) -> Vec<usize> {
+        (0..self.modules.len())
+            .sorted_by_key(|index| self.modules[*index].get_order())
+            .collect()
+    }
+
+    /// Reorders the modules in install order (see `install_order`).
+    pub fn sort_by_order(&mut self) {
+        // sort_by_key is stable, which keeps the manifest order for ties
+        self.modules.sort_by_key(|module| module.get_order());
+    }
+
+    /// Sets the global `default_layout` (if any) on all locations that don't have their own `layout`.
+    pub fn apply_default_layout(&mut self) {
+        let default_layout = match &self.global.default_layout {
+            None => return,
+            Some(default_layout) => default_layout,
+        };
+        let module_locations = self.modules.iter_mut().filter_map(|module| match module {
+            Module::Mod { weidu_mod } => match &mut weidu_mod.location {
+                Some(Location::Concrete { concrete }) => Some(concrete),
+                _ => None,
+            },
+            Module::Generated { .. } | Module::File { .. } => None,
+        });
+        for location in module_locations.chain(self.locations.entries.values_mut()) {
+            if location.layout.is_none() {
+                location.layout = Some(default_layout.clone());
+            }
+        }
+    }
+
+    /// Sets `base_url` (the `github_base_url` config value) on all github locations that don't have their own.<br>
+    /// Done when the manifest is assembled.
+    pub fn apply_github_base_url(&mut self, base_url: &str) {
+        let module_locations = self.modules.iter_mut().filter_map(|module| match module {
+            Module::Mod { weidu_mod } => match &mut weidu_mod.location {
+                Some(Location::Concrete { concrete }) => Some(concrete),
+                _ => None,
+            },
+            Module::Generated { .. } | Module::File { .. } => None,
+        });
+        for location in module_locations.chain(self.locations.entries.values_mut()) {
+            if let Source::Github(github) = &mut location.source {
+                if github.base_url.is_none() {
+                    github.base_url = Some(base_url.to_string());
+                }
+            }
+        }
+    }
+
+    /// Checks the manifest content that can be checked without any file access.<br>
+    /// For now, compiles all glob patterns (`layout`, `replace.file_globs`, generated mod `files.glob`, file module `from.glob`)
+    /// and reports all invalid ones at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+        for module in &self.modules {
+            match module {
+                Module::Mod { weidu_mod } => {
+                    if let Some(Location::Concrete { concrete }) = &weidu_mod.location {
+                        let context = format!("module {}", weidu_mod.name);
+                        errors.extend(location_glob_errors(&context, &weidu_mod.name, concrete));
+                    }
+                }
+                Module::Generated { gen } => {
+                    for (index, origin) in gen.files.iter().enumerate() {
+                        if let Some(glob) = origin.glob().filter(|_| !origin.is_literal()) {
+                            if let Some(error) = glob_error(glob) {
+                                errors.push(format!("module {}, files[{}].glob: {}", gen.gen_mod, index, error));
+                            }
+                        }
+                    }
+                }
+                Module::File { file } => {
+                    if let Some(glob) = file.from.glob().filter(|_| !file.from.is_literal()) {
+                        if let Some(error) = glob_error(glob) {
+                            errors.push(format!("module {}, from.glob: {}", file.file_mod, error));
+                        }
+                    }
+                }
+            }
+        }
+        let entries = self.locations.entries.iter().sorted_by(|left, right| left.0.as_ref().cmp(right.0.as_ref()));
+        for (name, location) in entries {
+            errors.extend(location_glob_errors(&format!("location {}", name), name, location));
+        }
+        if !errors.is_empty() {
+            bail!("Invalid patterns in manifest\n  {}", errors.join("\n  "))
+        }
+        for warning in check_component_indexes(&self.modules)? {
+            warn!("{}", warning);
+        }
+        Ok(())
+    }
+
+    /// Checks that the files of the `local`/`path` sources and of the `local`/`absolute` generated module origins
+    /// exist (relative to `manifest_root`) and reports all the missing ones at once.<br>
+    /// Not done when the manifest is assembled (commands that only read it work without the files), `install` calls it
+    /// before anything is installed.
+    pub fn validate_local_paths(&self, manifest_root: &Path) -> Result<()> {
+        let errors = local_path_errors(self, manifest_root);
+        if !errors.is_empty() {
+            bail!("Missing or invalid local paths in manifest\n  {}", errors.join("\n  "))
+        }
+        Ok(())
+    }
+
     pub fn read_path_convert_comments(path: &Path) -> Result<Self> {
         let file = std::fs::File::open(path)
                 .map_err(|error| anyhow!("Could not open manifest file {:?} - {:?}", path, error))?;
@@ -78,12 +309,20 @@ impl Manifest {
     }
 
     pub fn read_path(path: &str) -> Result<Self> {
+        Self::read_path_with(path, ManifestErrors::FailFast)
+    }
+
+    pub fn read_path_with(path: &str, errors: ManifestErrors) -> Result<Self> {
         let file = std::fs::File::open(path)
                 .map_err(|error| anyhow!("Could not open manifest file {} - {:?}", path, error))?;
-        Self::read_file(file, false)
+        Self::read_file_with(file, false, errors)
+    }
+
+    pub fn read_file(file: File, convert_comments: bool) -> Result<Self> {
+        Self::read_file_with(file, convert_comments, ManifestErrors::FailFast)
     }
 
-    pub fn read_file(mut file: File, convert_comments: bool) -> Result<Self> {
+    pub fn read_file_with(mut file: File, convert_comments: bool, errors: ManifestErrors) -> Result<Self> {
         {
             let reader = BufReader::new(&file);
             let version: VersionDetect = serde_yaml::from_reader(reader)?;
@@ -101,11 +340,23 @@ impl Manifest {
             std::io::read_to_string(reader)?
         };
         debug!("reformatted content:\n{}", content);
-        let deserializer = Deserializer::from_str(&content);
+        Self::parse(&content, errors)
+    }
+
+    fn parse(content: &str, errors: ManifestErrors) -> Result<Self> {
+        let deserializer = Deserializer::from_str(content);
         let result: Result<Manifest, _> = serde_path_to_error::deserialize(deserializer);
-        let manifest: Manifest = result
-                    .map_err(|error| anyhow!("Failed to parse manifest\n -> {}\npath:{}", error, error.path()))?;
-        Ok(manifest)
+        match (result, errors) {
+            (std::result::Result::Ok(manifest), _) => Ok(manifest),
+            (Err(error), ManifestErrors::FailFast) => bail!("Failed to parse manifest\n -> {}\npath:{}", error, error.path()),
+            (Err(error), ManifestErrors::Aggregate) => {
+                let all_errors = collect_parse_errors(content);
+                if all_errors.is_empty() {
+                    bail!("Failed to parse manifest\n -> {}\npath:{}", error, error.path())
+                }
+                bail!("Failed to parse manifest ({} errors)\n -> {}", all_errors.len(), all_errors.join("\n -> "))
+            }
+        }
     }
 
     pub fn merge_location_registry(&mut self, locations: HashMap<LwcString, ConcreteLocation>) {
@@ -118,6 +369,20 @@ impl Manifest {
         }
     }
 
+    /// Serializes the manifest as YAML: properties in declaration order, unset ones omitted
+    /// and map keys (`locations` entries, `manifest_conditions`) sorted, so that the output only depends on the content.
+    pub fn to_yaml(&self) -> Result<String> {
+        match serde_yaml::to_string(self) {
+            Err(error) => bail!("Could not serialize manifest\n -> {:?}", error),
+            std::result::Result::Ok(content) => Ok(content),
+        }
+    }
+
+    /// Writes the manifest to `path` (see `to_yaml`), reading it back gives the same manifest.
+    pub fn write_path(&self, path: &Path) -> Result<()> {
+        self.write(path, false)
+    }
+
     pub fn write(&self, path: &Path, reformat_comments: bool) -> Result<()> {
         let temp_path = pathext::append_extension("new", path);
         let dest = match OpenOptions::new().create(true).truncate(true).write(true).open(&temp_path) {
@@ -125,13 +390,13 @@ impl Manifest {
             std::io::Result::Ok(file) => file,
         };
         let mut buf_writer = BufWriter::new(&dest);
+        let content = self.to_yaml()?;
         if reformat_comments {
-            let input = serde_yaml::to_string(self)?;
-            let reformatted = reformat_to_yaml_comments(&input)?;
-            write!(buf_writer, "{}", reformatted)?;
+            write!(buf_writer, "{}", reformat_to_yaml_comments(&content)?)?;
         } else {
-            serde_yaml::to_writer(buf_writer, self)?;
+            write!(buf_writer, "{}", content)?;
         }
+        buf_writer.flush()?;
 
         // rename temp file to output file
         if let Err(error) = std::fs::rename(&temp_path, path) {
@@ -181,6 +446,104 @@ fn try_replace_component_comment<'a>(re: &Regex, haystack: &'a str) -> Result<St
     Ok(new)
 }
 
+fn location_glob_errors(context: &str, module_name: &LwcString, location: &ConcreteLocation) -> Vec<String> {
+    let mut errors = vec![];
+    let layout = location.layout();
+    let layout_patterns = match layout.literal {
+        Some(true) => vec![],
+        _ => layout.to_glob(module_name, &location.source).patterns,
+    };
+    for pattern in layout_patterns {
+        if let Some(error) = glob_error(&pattern) {
+            errors.push(format!("{}, layout: {}", context, error));
+        }
+    }
+    for (index, replace) in location.replace.iter().flatten().enumerate() {
+        for pattern in &replace.file_globs {
+            if let Some(error) = glob_error(pattern) {
+                errors.push(format!("{}, replace[{}].file_globs: {}", context, index, error));
+            }
+        }
+    }
+    errors
+}
+
+/// Compiles the pattern the same way it is used at install time, without walking any directory.
+fn glob_error(pattern: &str) -> Option<String> {
+    match GlobWalkerBuilder::from_patterns(".", &[pattern]).case_insensitive(true).build() {
+        std::result::Result::Ok(_) => None,
+        Err(error) => Some(format!("invalid pattern {:?} ({})", pattern, error)),
+    }
+}
+
+/// The manifest is always downloaded again, the local copy is kept (in a `<host>` sub-directory)
+/// so that `--record` and error messages can refer to it.
+async fn fetch_manifest(url: &str, downloader: &Downloader, cache_dir: &Path) -> Result<PathBuf> {
+    let parsed = match url::Url::parse(url) {
+        std::result::Result::Ok(parsed) => parsed,
+        Err(error) => bail!("Invalid manifest url {}\n -> {:?}", url, error),
+    };
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        bail!("Unsupported manifest url {} (only http and https are supported)", url);
+    }
+    let host = match parsed.host_str() {
+        Some(host) => host.to_string(),
+        None => bail!("Invalid manifest url {} (no host)", url),
+    };
+    let file_name = parsed.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| name.ends_with(".yml") || name.ends_with(".yaml"))
+        .unwrap_or("manifest.yml");
+    let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always, request: DownloadRequest::Get };
+    let downloaded = match downloader.download(url, &cache_dir.join(host), PathBuf::from(file_name), &opts, &None).await {
+        std::result::Result::Ok(downloaded) => downloaded,
+        Err(error) => bail!("Could not download manifest from {}\n  {}", url, error),
+    };
+    info!("manifest downloaded from {} to {:?}", url, downloaded.path);
+    Ok(downloaded.path)
+}
+
+/// Reads the manifest without its modules, then each module on its own, and returns all the errors found.
+fn collect_parse_errors(content: &str) -> Vec<String> {
+    let mut value: serde_yaml::Value = match serde_yaml::from_str(content) {
+        Err(error) => return vec![format!("{}", error)],
+        std::result::Result::Ok(value) => value,
+    };
+    let modules = match value.as_mapping_mut().and_then(|mapping| mapping.remove("modules")) {
+        Some(serde_yaml::Value::Sequence(modules)) => modules,
+        Some(_) => return vec!["`modules` must be a list".to_string()],
+        None => vec![],
+    };
+    let mut errors = vec![];
+    let result: Result<Manifest, _> = serde_path_to_error::deserialize(value);
+    if let Err(error) = result {
+        errors.push(format!("{}\n    path:{}", error, error.path()));
+    }
+    for (index, module) in modules.into_iter().enumerate() {
+        let name = ["name", "gen_mod"].iter()
+            .find_map(|key| module.get(key).and_then(|name| name.as_str()))
+            .unwrap_or("?")
+            .to_string();
+        if let Some(error) = module_parse_error(module) {
+            errors.push(format!("module #{} ({}): {}", index + 1, name, error));
+        }
+    }
+    errors
+}
+
+/// Reads the module with the type it looks like, so that the error has the path inside the module.
+fn module_parse_error(module: serde_yaml::Value) -> Option<String> {
+    fn error_of<T: serde::de::DeserializeOwned>(module: serde_yaml::Value) -> Option<String> {
+        let result: Result<T, _> = serde_path_to_error::deserialize(module);
+        result.err().map(|error| format!("{}\n    path:{}", error, error.path()))
+    }
+    match (module.get("name"), module.get("gen_mod")) {
+        (Some(_), None) => error_of::<WeiduMod>(module),
+        (None, Some(_)) => error_of::<GeneratedMod>(module),
+        _ => error_of::<Module>(module),
+    }
+}
+
 fn read_external_registry(registry: &LocationRegistry, manifest_root: &CanonPath) -> Result<HashMap<LwcString, ConcreteLocation>> {
     let path = match registry {
         LocationRegistry::Absolute { path } => PathBuf::from(path),
@@ -251,10 +614,14 @@ mod test_deserialize {
     use std::io::BufReader;
     use std::path::PathBuf;
 
+    use crate::archive_layout::Layout;
     use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::download::{Downloader, DownloadedFile};
     use crate::module::components::{Component, Components, FullComponent};
     use crate::lowercase::lwc;
     use crate::module::disable_condition::DisableCondition;
+    use crate::module::file_mod::FileModule;
     use crate::module::file_module_origin::FileModuleOrigin;
     use crate::module::gen_mod::{GeneratedMod, GenModComponent};
     use crate::module::global_locations::{GlobalLocations, LocationRegistry};
@@ -266,8 +633,9 @@ mod test_deserialize {
     use crate::module::module::Module;
     use crate::module::weidu_mod::WeiduMod;
     use crate::post_install::PostInstall;
+    use crate::test_utils::test_dir;
 
-    use super::Manifest;
+    use super::{Manifest, ManifestErrors};
 
     #[test]
     fn check_read_manifest() {
@@ -279,14 +647,23 @@ mod test_deserialize {
                 version : "1".to_string(),
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
+                    lang_from_weidu_conf: None,
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     local_patches: None,
                     local_mods: None,
+                    local_mods_index: None,
                     local_files: None,
+                    default_layout: None,
+                    clean_reinstall: None,
+                    weidu_version: None,
+                    allow_overwrite: None,
+                    copy_mode: None,
                 },
                 locations : GlobalLocations::default(),
                 manifest_conditions: ManifestConditions::default(),
                 modules : vec![],
+                remove_modules: vec![],
             }
         )
     }
@@ -301,10 +678,18 @@ mod test_deserialize {
                 version : "1".to_string(),
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
+                    lang_from_weidu_conf: None,
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
+                    local_mods_index: None,
                     local_files: None,
+                    default_layout: None,
+                    clean_reinstall: None,
+                    weidu_version: None,
+                    allow_overwrite: None,
+                    copy_mode: None,
                 },
                 locations : GlobalLocations::default(),
                 manifest_conditions: ManifestConditions::default(),
@@ -342,31 +727,44 @@ mod test_deserialize {
                         gen:  GeneratedMod {
                             gen_mod: lwc!("ccc"),
                             files: vec![
-                                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
+                                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None, dir_copy: None, literal: None },
                             ],
                             description: None,
                             component: GenModComponent { index: 0, name: None },
                             post_install: Some(PostInstall::WaitSeconds { wait_seconds:10 }),
                             ignore_warnings: true,
-                            allow_overwrite: true,
+                            allow_overwrite: Some(true),
                             disabled_if: None,
+                            always: None,
+                            at_exit: None,
+                            tags: vec![],
+                            order: None,
+                            requires: vec![],
+                            expect: vec![],
                         },
                     },
                     Module::Generated {
                         gen:  GeneratedMod {
                             gen_mod: lwc!("ddd"),
                             files: vec![
-                                FileModuleOrigin::Local { local: "my_other_subdir".to_string(), glob: Some("*.itm".to_string()) },
+                                FileModuleOrigin::Local { local: "my_other_subdir".to_string(), glob: Some("*.itm".to_string()), dir_copy: None, literal: None },
                             ],
                             description: None,
                             post_install: None,
                             component: GenModComponent { index: 10, name: Some("Do whatever".to_string()) },
                             ignore_warnings: true,
-                            allow_overwrite: true,
+                            allow_overwrite: Some(true),
                             disabled_if: None,
+                            always: None,
+                            at_exit: None,
+                            tags: vec![],
+                            order: None,
+                            requires: vec![],
+                            expect: vec![],
                         },
                     },
                 ],
+                remove_modules: vec![],
             }
         )
     }
@@ -378,10 +776,18 @@ mod test_deserialize {
             version : "1".to_string(),
             global : super::Global {
                 game_language: "fr_FR".to_string(),
+                lang_from_weidu_conf: None,
                 lang_preferences: Some(vec!["french".to_string()]),
+                lang_fallback: None,
                 local_patches: None,
                 local_mods: Some("mods".to_string()),
+                local_mods_index: None,
                 local_files: None,
+                default_layout: None,
+                clean_reinstall: None,
+                weidu_version: None,
+                allow_overwrite: None,
+                copy_mode: None,
             },
             locations : GlobalLocations::default(),
             manifest_conditions: ManifestConditions::default(),
@@ -404,31 +810,44 @@ mod test_deserialize {
                     gen:  GeneratedMod {
                         gen_mod: lwc!("ccc"),
                         files: vec![
-                            FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
+                            FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None, dir_copy: None, literal: None },
                         ],
                         description: None,
                         post_install: None,
                         component: GenModComponent { index: 0, name: None },
                         ignore_warnings: false,
-                        allow_overwrite: false,
+                        allow_overwrite: None,
                         disabled_if: None,
+                        always: None,
+                        at_exit: None,
+                        tags: vec![],
+                        order: None,
+                        requires: vec![],
+                        expect: vec![],
                     },
                 },
                 Module::Generated {
                     gen:  GeneratedMod {
                         gen_mod: lwc!("ddd"),
                         files: vec![
-                            FileModuleOrigin::Local { local: "my_other_subdir".to_string(), glob: Some("*.itm".to_string()) },
+                            FileModuleOrigin::Local { local: "my_other_subdir".to_string(), glob: Some("*.itm".to_string()), dir_copy: None, literal: None },
                         ],
                         description: None,
                         post_install: None,
                         component: GenModComponent { index: 10, name: Some("Do whatever".to_string()) },
                         ignore_warnings: true,
-                        allow_overwrite: true,
+                        allow_overwrite: Some(true),
                         disabled_if: None,
+                        always: None,
+                        at_exit: None,
+                        tags: vec![],
+                        order: None,
+                        requires: vec![],
+                        expect: vec![],
                     },
                 },
             ],
+            remove_modules: vec![],
         };
 
         let serialized = serde_yaml::to_string(&manifest).unwrap();
@@ -450,10 +869,18 @@ mod test_deserialize {
                 version : "1".to_string(),
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
+                    lang_from_weidu_conf: None,
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
+                    local_mods_index: None,
                     local_files: None,
+                    default_layout: None,
+                    clean_reinstall: None,
+                    weidu_version: None,
+                    allow_overwrite: None,
+                    copy_mode: None,
                 },
                 locations : GlobalLocations::from([
                     (lwc!("aaa"), ConcreteLocation { source: Source::Http(Http::from("http://example.com/my-mod")), ..Default::default() }),
@@ -466,6 +893,7 @@ mod test_deserialize {
                 ]).with_external(LocationRegistry::Absolute { path: "/directory/locations.yml".to_owned() }),
                 manifest_conditions: ManifestConditions::default(),
                 modules : vec![],
+                remove_modules: vec![],
             }
         )
     }
@@ -480,14 +908,23 @@ mod test_deserialize {
                 version : "1".to_string(),
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
+                    lang_from_weidu_conf: None,
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
+                    local_mods_index: None,
                     local_files: None,
+                    default_layout: None,
+                    clean_reinstall: None,
+                    weidu_version: None,
+                    allow_overwrite: None,
+                    copy_mode: None,
                 },
                 locations : GlobalLocations::from([]),
                 manifest_conditions: ManifestConditions::default(),
                 modules : vec![],
+                remove_modules: vec![],
             }
         )
     }
@@ -497,17 +934,25 @@ mod test_deserialize {
         use crate::module::location::github::Github;
         let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
         let manifest_path = format!("{}/{}", manifest_root, "manifest_with_real_ext_locations.yml");
-        let manifest = Manifest::assemble_from_path(&manifest_path, &&CanonPath::new(&manifest_root).unwrap()).unwrap();
+        let manifest = Manifest::assemble_from_path(&manifest_path, &&CanonPath::new(&manifest_root).unwrap(), &Config::default()).unwrap();
         assert_eq!(
             manifest,
             super::Manifest {
                 version : "1".to_string(),
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
+                    lang_from_weidu_conf: None,
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
+                    local_mods_index: None,
                     local_files: None,
+                    default_layout: None,
+                    clean_reinstall: None,
+                    weidu_version: None,
+                    allow_overwrite: None,
+                    copy_mode: None,
                 },
                 locations : GlobalLocations::from([
                     (lwc!("aaa"), ConcreteLocation { source: Source::Http(Http::from("http://example.com/my-mod")), ..Default::default() }),
@@ -520,10 +965,25 @@ mod test_deserialize {
                 ]).with_external(LocationRegistry::Local { local: "registries/external-locations.yml".to_owned() }),
                 manifest_conditions: ManifestConditions::default(),
                 modules : vec![],
+                remove_modules: vec![],
             }
         )
     }
 
+    #[test]
+    fn assemble_manifest_applies_github_base_url() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_real_ext_locations.yml");
+        let config = Config { github_base_url: Some("https://github.mycorp.com".to_string()), ..Default::default() };
+
+        let manifest = Manifest::assemble_from_path(&manifest_path, &CanonPath::new(&manifest_root).unwrap(), &config).unwrap();
+
+        match &manifest.locations.find(&lwc!("bbb")).unwrap().source {
+            Source::Github(github) => assert_eq!(github.base_url.as_deref(), Some("https://github.mycorp.com")),
+            other => panic!("unexpected source {:?}", other),
+        }
+    }
+
     #[test]
     fn read_manifest_convert_comments() {
         let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
@@ -536,6 +996,7 @@ mod test_deserialize {
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     ..Default::default()
                 },
                 modules : vec![
@@ -561,6 +1022,7 @@ mod test_deserialize {
                 ],
                 locations: GlobalLocations::default(),
                 manifest_conditions: ManifestConditions::default(),
+                remove_modules: vec![],
             }
         );
     }
@@ -577,6 +1039,7 @@ mod test_deserialize {
             global : super::Global {
                 game_language: "fr_FR".to_string(),
                 lang_preferences: Some(vec!["french".to_string()]),
+                lang_fallback: None,
                 ..Default::default()
             },
             modules : vec![
@@ -602,6 +1065,7 @@ mod test_deserialize {
             ],
             locations: GlobalLocations::default(),
             manifest_conditions: ManifestConditions::default(),
+            remove_modules: vec![],
         };
         manifest.write(&output_path, true).unwrap();
 
@@ -614,6 +1078,34 @@ mod test_deserialize {
         assert!(content.contains("- 2 # comment as field 789"));
     }
 
+    #[test]
+    fn read_manifest_with_file_module() {
+        let manifest = crate::test_utils::manifest(r#"
+modules:
+  - file_mod: my_docs
+    description: readmes
+    from:
+      local: docs
+      glob: "*.txt"
+    to: Documentation
+    forbids_file: sod-dlc.zip
+"#);
+        let file = FileModule {
+            file_mod: lwc!("my_docs"),
+            description: Some("readmes".to_string()),
+            from: FileModuleOrigin::Local { local: "docs".to_string(), glob: Some("*.txt".to_string()), dir_copy: None, literal: None },
+            to: "Documentation".to_string(),
+            post_install: None,
+            allow_overwrite: None,
+            requires_file: None,
+            forbids_file: Some("sod-dlc.zip".to_string()),
+        };
+        assert_eq!(manifest.modules, vec![Module::File { file }]);
+
+        let mixed = serde_yaml::from_str::<Module>("name: a\nfile_mod: b\nto: c\nfrom:\n  local: d\n").unwrap_err();
+        assert!(mixed.to_string().contains("only one of 'name', 'gen_mod' or 'file_mod'"), "{}", mixed);
+    }
+
     #[test]
     fn read_manifest_with_global_condition() {
         let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
@@ -626,6 +1118,7 @@ mod test_deserialize {
                 global : super::Global {
                     game_language: "fr_FR".to_string(),
                     lang_preferences: Some(vec!["french".to_string()]),
+                    lang_fallback: None,
                     ..Default::default()
                 },
                 locations: GlobalLocations::default(),
@@ -647,7 +1140,297 @@ mod test_deserialize {
                     }),
                 ])),
                 modules: vec! [],
+                remove_modules: vec![],
             }
         );
     }
+
+    #[test]
+    fn validate_reports_all_invalid_globs() {
+        let manifest_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test/manifest_with_invalid_globs.yml");
+        let manifest = Manifest::read_path(&manifest_path).unwrap();
+
+        let message = manifest.validate().unwrap_err().to_string();
+
+        assert!(message.contains("module aaa, replace[0].file_globs: invalid pattern \"**/[abc.tp2\""));
+        assert!(!message.contains("*.tra"));
+        assert!(message.contains("module ccc, files[1].glob: invalid pattern \"{*.itm,*.spl\""));
+        assert!(!message.contains("files[0]"));
+        assert!(message.contains("location bbb, layout: invalid pattern \"bbb_{extra\""));
+    }
+
+    #[test]
+    fn validate_accepts_valid_globs() {
+        let manifest_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test/manifest_with_modules.yml");
+        let manifest = Manifest::read_path(&manifest_path).unwrap();
+        manifest.validate().unwrap();
+    }
+
+    #[test]
+    fn assemble_manifest_applies_default_layout() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_default_layout.yml");
+        let manifest = Manifest::assemble_from_path(&manifest_path, &CanonPath::new(manifest_root).unwrap(), &Config::default()).unwrap();
+
+        let default_layout = Layout::with_tp2_default_and_strip(1);
+        let module_layout = |index: usize| match &manifest.modules[index] {
+            Module::Mod { weidu_mod: WeiduMod { location: Some(Location::Concrete { concrete }), .. } } =>
+                concrete.layout.clone(),
+            other => panic!("unexpected module {:?}", other),
+        };
+        assert_eq!(module_layout(0), Some(default_layout.clone()));
+        assert_eq!(module_layout(1), Some(Layout::multi_dir(vec!["bbb".to_string(), "bbb_extra".to_string()])));
+        assert_eq!(manifest.locations.find(&lwc!("ccc")).unwrap().layout, Some(default_layout));
+    }
+
+    #[test]
+    fn assemble_manifest_loads_components_files() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_components_file.yml");
+        let manifest = Manifest::assemble_from_path(&manifest_path, &CanonPath::new(manifest_root).unwrap(), &Config::default()).unwrap();
+
+        let components = manifest.modules.iter().map(|module| module.get_components()).collect::<Vec<_>>();
+        assert_eq!(
+            components,
+            vec![
+                Components::List(vec![Component::Simple(1)]),
+                Components::List(vec![
+                    Component::Simple(1),
+                    Component::Full(FullComponent { index: 3, component_name: "Some component".to_string() }),
+                ]),
+                Components::List(vec![
+                    Component::Simple(2),
+                    Component::Full(FullComponent { index: 4, component_name: "Other component".to_string() }),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_manifest_missing_components_file() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_missing_components_file.yml");
+        let error = Manifest::assemble_from_path(&manifest_path, &CanonPath::new(manifest_root).unwrap(), &Config::default()).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("module aaa"), "{}", message);
+        assert!(message.contains("components/does_not_exist.yml"), "{}", message);
+    }
+
+    /// Downloader mock that "downloads" a test resource into the destination directory.
+    fn mock_downloader(expected_url: &'static str, resource: &'static str) -> Downloader {
+        let mut downloader = Downloader::faux();
+        faux::when!(downloader.download(expected_url, _, _, _, _)).then(move |(_, dest, save_name, _, _)| {
+            let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test").join(resource);
+            std::fs::create_dir_all(&dest).unwrap();
+            std::fs::copy(source, dest.join(&save_name)).unwrap();
+            Ok(DownloadedFile { path: dest.join(save_name), sha256: None })
+        });
+        downloader
+    }
+
+    #[test]
+    fn assemble_manifest_from_url() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let cache = test_dir();
+        let manifest_root = CanonPath::new(project.join("resources/test")).unwrap();
+        let url = "https://example.com/team/install.yml";
+        let downloader = mock_downloader(url, "manifest_with_real_ext_locations.yml");
+
+        let (manifest, local_copy) = Manifest::assemble_from_url(url, &downloader, cache.path(), &manifest_root, &Config::default()).unwrap();
+
+        assert_eq!(local_copy, cache.path().join("example.com").join("install.yml"));
+        assert_eq!(manifest.version, "1");
+        assert_eq!(manifest.global.local_mods, Some("mods".to_string()));
+        // the external registry is found relative to the given manifest root, not to the download location
+        assert!(manifest.locations.find(&lwc!("bbb")).is_some());
+    }
+
+    #[test]
+    fn assemble_manifest_from_url_validates() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let cache = test_dir();
+        let manifest_root = CanonPath::new(project.join("resources/test")).unwrap();
+        let url = "http://example.com/manifest?version=2";
+        let downloader = mock_downloader(url, "manifest_with_invalid_globs.yml");
+
+        Manifest::assemble_from_url(url, &downloader, cache.path(), &manifest_root, &Config::default()).unwrap_err();
+        assert!(cache.path().join("example.com").join("manifest.yml").exists());
+    }
+
+    #[test]
+    fn assemble_manifest_from_unsupported_url() {
+        let manifest_root = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let downloader = Downloader::faux();
+
+        Manifest::assemble_from_url("ftp://example.com/install.yml", &downloader, manifest_root.path(), &manifest_root, &Config::default())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn assemble_manifest_sorts_by_order() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_order.yml");
+
+        let unsorted = Manifest::read_path(&manifest_path).unwrap();
+        assert_eq!(unsorted.install_order(), vec![2, 4, 0, 3, 1]);
+
+        let manifest = Manifest::assemble_from_path(&manifest_path, &CanonPath::new(manifest_root).unwrap(), &Config::default()).unwrap();
+        let names = manifest.modules.iter().map(|module| module.get_name().as_ref().to_owned()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["ccc", "eee", "aaa", "ddd", "bbb"]);
+        assert_eq!(manifest.install_order(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn module_tags_round_trip() {
+        let yaml = r#"
+        version: "1"
+        global:
+          lang_dir: "fr_FR"
+        modules:
+          - name: aaa
+            components: [1]
+            tags: [ui, fixes]
+          - gen_mod: bbb
+            files:
+              - local: my_subdir
+            tags: [ui]
+          - name: ccc
+            components: [1]
+        "#;
+        let manifest: Manifest = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(manifest.modules[0].get_tags(), &["ui".to_string(), "fixes".to_string()]);
+        assert_eq!(manifest.modules[1].get_tags(), &["ui".to_string()]);
+        assert!(manifest.modules[2].get_tags().is_empty());
+
+        let serialized = serde_yaml::to_string(&manifest).unwrap();
+        assert_eq!(serialized.matches("tags:").count(), 2);
+        let read_back: Manifest = serde_yaml::from_str(&serialized).unwrap();
+        assert_eq!(read_back, manifest);
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let temp = test_dir();
+        for fixture in ["manifest.yml", "manifest_with_locations.yml", "manifest_with_global_conditions.yml",
+                        "manifest_with_mixed_modules.yml", "manifest_with_default_layout.yml", "manifest_with_order.yml"] {
+            let manifest = Manifest::read_path(project.join("resources/test").join(fixture).to_str().unwrap()).unwrap();
+            let output = temp.path().join(fixture);
+
+            manifest.write_path(&output).unwrap();
+            let read_back = Manifest::read_path(output.to_str().unwrap()).unwrap();
+
+            assert_eq!(read_back, manifest, "{}", fixture);
+            assert_eq!(read_back.to_yaml().unwrap(), std::fs::read_to_string(&output).unwrap(), "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn map_keys_are_written_sorted() {
+        let yaml = r#"
+        version: "1"
+        global:
+          lang_dir: "fr_FR"
+        locations:
+          entries:
+            zzz: { local: zzz.zip }
+            mmm: { local: mmm.zip }
+            aaa: { local: aaa.zip }
+            ccc: { local: ccc.zip }
+        manifest_conditions:
+          no_ui: { because: not wanted }
+          has_ee: { env_is_set: EE_GAME }
+        "#;
+        // each HashMap has its own random iteration order
+        let texts = (0..5).map(|_| serde_yaml::from_str::<Manifest>(yaml).unwrap().to_yaml().unwrap()).collect::<Vec<_>>();
+
+        assert!(texts.iter().all(|text| text == &texts[0]));
+        let positions = ["aaa:", "ccc:", "mmm:", "zzz:", "has_ee:", "no_ui:"].map(|key| texts[0].find(key).unwrap());
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{}", texts[0]);
+        assert!(!texts[0].contains("external"), "{}", texts[0]);
+    }
+
+    #[test]
+    fn group_modules_by_tag() {
+        let weidu = |name: &str, tags: &[&str]| Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!(name),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        }};
+        let manifest = Manifest {
+            version: "1".to_string(),
+            global: super::Global::default(),
+            locations: GlobalLocations::default(),
+            manifest_conditions: ManifestConditions::default(),
+            modules: vec![
+                weidu("aaa", &["ui", "fixes"]),
+                weidu("bbb", &[]),
+                weidu("ccc", &["fixes", "fixes"]),
+            ],
+            remove_modules: vec![],
+        };
+
+        let groups = manifest.modules_by_tag();
+
+        let names = |tag: &str| groups[tag].iter().map(|module| module.get_name().to_string()).collect::<Vec<_>>();
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec![&"fixes", &"ui"]);
+        assert_eq!(names("fixes"), vec!["aaa", "ccc"]);
+        assert_eq!(names("ui"), vec!["aaa"]);
+    }
+
+    const TWO_MODULE_ERRORS: &str = r#"
+version: "1"
+global:
+  lang_dir: fr_FR
+modules:
+  - name: aaa
+    components: [1]
+    language: not_a_number
+  - name: bbb
+    components: [1]
+  - gen_mod: ccc
+    files: 12
+"#;
+
+    #[test]
+    fn fail_fast_reports_first_error() {
+        let error = Manifest::parse(TWO_MODULE_ERRORS, ManifestErrors::FailFast).unwrap_err().to_string();
+
+        assert!(error.contains("path:modules[0]"), "{}", error);
+        assert!(error.contains("not_a_number"), "{}", error);
+        assert!(!error.contains("expected a sequence"), "{}", error);
+    }
+
+    #[test]
+    fn aggregate_reports_all_module_errors() {
+        let error = Manifest::parse(TWO_MODULE_ERRORS, ManifestErrors::Aggregate).unwrap_err().to_string();
+
+        assert!(error.contains("(2 errors)"), "{}", error);
+        assert!(error.contains("module #1 (aaa)"), "{}", error);
+        assert!(error.contains("path:language"), "{}", error);
+        assert!(error.contains("module #3 (ccc)"), "{}", error);
+        assert!(error.contains("path:files"), "{}", error);
+        assert!(!error.contains("(bbb)"), "{}", error);
+    }
+
+    #[test]
+    fn aggregate_reports_top_level_errors() {
+        let content = TWO_MODULE_ERRORS.replace("lang_dir: fr_FR", "lang_dir: [fr_FR]");
+        let error = Manifest::parse(&content, ManifestErrors::Aggregate).unwrap_err().to_string();
+
+        assert!(error.contains("(3 errors)"), "{}", error);
+        assert!(error.contains("path:global.lang_dir"), "{}", error);
+    }
+
+    #[test]
+    fn missing_lang_dir_is_an_error_when_used() {
+        let content = TWO_MODULE_ERRORS.replace("  lang_dir: fr_FR\n", "  lang_from_weidu_conf: true\n")
+            .replace("not_a_number", "1").replace("files: 12", "files: []");
+        let manifest = Manifest::parse(&content, ManifestErrors::FailFast).unwrap();
+
+        let error = manifest.global.lang_dir().unwrap_err().to_string();
+        assert!(error.contains("Missing `lang_dir`"), "{}", error);
+        assert!(!manifest.to_yaml().unwrap().contains("lang_dir"));
+    }
 }
@@ -2,11 +2,13 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use crate::utils::sorted_map::serialize_sorted;
+
 use super::disable_condition::DisableCondition;
 
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
-pub struct ManifestConditions (HashMap<String, DisableCondition>);
+pub struct ManifestConditions (#[serde(serialize_with = "serialize_sorted")] HashMap<String, DisableCondition>);
 
 impl ManifestConditions {
     pub fn new(conditions: HashMap<String, DisableCondition>) -> Self { Self(conditions) }
@@ -18,4 +20,9 @@ impl ManifestConditions {
     pub fn get(&self, key: &str) -> Option<&DisableCondition>{
         self.0.get(key)
     }
+
+    /// All conditions of both, the ones of `overlay` win when the same key is in both.
+    pub fn merge(&self, overlay: &ManifestConditions) -> ManifestConditions {
+        Self(self.0.iter().chain(&overlay.0).map(|(key, value)| (key.clone(), value.clone())).collect())
+    }
 }
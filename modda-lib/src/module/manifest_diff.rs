@@ -0,0 +1,369 @@
+
+use std::collections::HashMap;
+
+use crate::lowercase::LwcString;
+
+use super::components::Components;
+use super::file_module_origin::FileModuleOrigin;
+use super::gen_mod::GeneratedMod;
+use super::location::location::{ConcreteLocation, Location};
+use super::location::source::Source;
+use super::manifest::Manifest;
+use super::module::Module;
+
+/// Identifies a module across two manifests.<br>
+/// The same mod can appear several times (fragments), so fragments are paired by their rank among
+/// the modules with the same name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleKey {
+    pub name: LwcString,
+    /// 0 for the first module with this name, 1 for the second...
+    pub occurrence: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleKind {
+    Mod,
+    Generated,
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldChange {
+    /// A weidu mod became a generated mod or the reverse; nothing else is compared.
+    Kind { old: ModuleKind, new: ModuleKind },
+    Components {
+        old: Components,
+        new: Components,
+        /// Component indexes only present in the new list (when both are lists).
+        added: Vec<u32>,
+        /// Component indexes only present in the old list (when both are lists).
+        removed: Vec<u32>,
+    },
+    /// Where the mod is obtained from (http, github...); `None` when there is no location.
+    Source { old: Option<Box<Source>>, new: Option<Box<Source>> },
+    /// Other location properties (layout, patches, replace...), when the source didn't change.
+    Location { old: Option<Box<ConcreteLocation>>, new: Option<Box<ConcreteLocation>> },
+    /// `files` of a generated mod.
+    Files { old: Vec<FileModuleOrigin>, new: Vec<FileModuleOrigin> },
+    /// Any other property (description, post_install, disabled_if...).
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleChange {
+    pub key: ModuleKey,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Result of `Manifest::diff`; entries are in the order of the manifests.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ManifestDiff {
+    /// Modules that are only in the new manifest.
+    pub added: Vec<ModuleKey>,
+    /// Modules that are only in the old manifest.
+    pub removed: Vec<ModuleKey>,
+    pub changed: Vec<ModuleChange>,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub fn diff_manifests(old: &Manifest, new: &Manifest) -> ManifestDiff {
+    let old_modules = keyed_modules(old);
+    let new_modules = keyed_modules(new);
+    let new_index = new_modules.iter().map(|(key, module)| (key, *module)).collect::<HashMap<_, _>>();
+    let old_index = old_modules.iter().map(|(key, module)| (key, *module)).collect::<HashMap<_, _>>();
+
+    let mut result = ManifestDiff::default();
+    for (key, old_module) in &old_modules {
+        match new_index.get(key) {
+            None => result.removed.push(key.clone()),
+            Some(new_module) => {
+                let changes = module_changes(old, old_module, new, new_module);
+                if !changes.is_empty() {
+                    result.changed.push(ModuleChange { key: key.clone(), changes });
+                }
+            }
+        }
+    }
+    result.added = new_modules.iter()
+        .filter(|(key, _)| !old_index.contains_key(key))
+        .map(|(key, _)| key.clone())
+        .collect();
+    result
+}
+
+pub(crate) fn keyed_modules(manifest: &Manifest) -> Vec<(ModuleKey, &Module)> {
+    let mut counts: HashMap<&LwcString, usize> = HashMap::new();
+    manifest.modules.iter().map(|module| {
+        let count = counts.entry(module.get_name()).or_default();
+        let key = ModuleKey { name: module.get_name().clone(), occurrence: *count };
+        *count += 1;
+        (key, module)
+    }).collect()
+}
+
+fn module_changes(old_manifest: &Manifest, old: &Module, new_manifest: &Manifest, new: &Module) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    match (old, new) {
+        (Module::Mod { weidu_mod: old_mod }, Module::Mod { weidu_mod: new_mod }) => {
+            if let Some(change) = components_change(&old_mod.components, &new_mod.components) {
+                changes.push(change);
+            }
+            let old_location = effective_location(old_manifest, &old_mod.name, &old_mod.location);
+            let new_location = effective_location(new_manifest, &new_mod.name, &new_mod.location);
+            let old_source = old_location.map(|location| &location.source);
+            let new_source = new_location.map(|location| &location.source);
+            if old_source != new_source {
+                changes.push(FieldChange::Source { old: old_source.cloned().map(Box::new), new: new_source.cloned().map(Box::new) });
+            } else if old_location != new_location {
+                changes.push(FieldChange::Location { old: old_location.cloned().map(Box::new), new: new_location.cloned().map(Box::new) });
+            }
+            let mut old_rest = old_mod.clone();
+            let mut new_rest = new_mod.clone();
+            old_rest.components = Components::None;
+            new_rest.components = Components::None;
+            old_rest.location = None;
+            new_rest.location = None;
+            if old_rest != new_rest {
+                changes.push(FieldChange::Other);
+            }
+        }
+        (Module::Generated { gen: old_gen }, Module::Generated { gen: new_gen }) => {
+            if old_gen.files != new_gen.files {
+                changes.push(FieldChange::Files { old: old_gen.files.clone(), new: new_gen.files.clone() });
+            }
+            let old_rest = GeneratedMod { files: vec![], ..old_gen.clone() };
+            let new_rest = GeneratedMod { files: vec![], ..new_gen.clone() };
+            if old_rest != new_rest {
+                changes.push(FieldChange::Other);
+            }
+        }
+        (Module::File { file: old_file }, Module::File { file: new_file }) => {
+            if old_file != new_file {
+                changes.push(FieldChange::Other);
+            }
+        }
+        (old, new) => changes.push(FieldChange::Kind { old: module_kind(old), new: module_kind(new) }),
+    }
+    changes
+}
+
+pub(crate) fn module_kind(module: &Module) -> ModuleKind {
+    match module {
+        Module::Mod { .. } => ModuleKind::Mod,
+        Module::Generated { .. } => ModuleKind::Generated,
+        Module::File { .. } => ModuleKind::File,
+    }
+}
+
+/// The location that would be used to obtain the mod (module location, or from the manifest `locations`).
+pub(crate) fn effective_location<'a>(manifest: &'a Manifest, name: &LwcString, location: &'a Option<Location>) -> Option<&'a ConcreteLocation> {
+    match location {
+        None => manifest.locations.find(name),
+        Some(Location::Ref { r#ref }) => manifest.locations.find(r#ref),
+        Some(Location::Concrete { concrete }) => Some(concrete),
+    }
+}
+
+fn components_change(old: &Components, new: &Components) -> Option<FieldChange> {
+    if old == new {
+        return None;
+    }
+    let (added, removed) = match (old, new) {
+        (Components::List(old_list), Components::List(new_list)) => {
+            // `None` if there are named components; then the lists are only compared as a whole
+            let old_indexes = old_list.iter().map(|component| component.index()).collect::<Option<Vec<_>>>();
+            let new_indexes = new_list.iter().map(|component| component.index()).collect::<Option<Vec<_>>>();
+            let (old_indexes, new_indexes) = match (old_indexes, new_indexes) {
+                (Some(old_indexes), Some(new_indexes)) => (old_indexes, new_indexes),
+                _ => return Some(FieldChange::Components { old: old.clone(), new: new.clone(), added: vec![], removed: vec![] }),
+            };
+            if old_indexes == new_indexes {
+                // only component names (which are comments) changed
+                return None;
+            }
+            (
+                new_indexes.iter().filter(|index| !old_indexes.contains(index)).cloned().collect(),
+                old_indexes.iter().filter(|index| !new_indexes.contains(index)).cloned().collect(),
+            )
+        }
+        _ => (vec![], vec![]),
+    };
+    Some(FieldChange::Components { old: old.clone(), new: new.clone(), added, removed })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components};
+    use crate::test_utils::manifest;
+
+    use super::{FieldChange, ModuleChange, ModuleKey, ModuleKind};
+
+    fn key(name: &str, occurrence: usize) -> ModuleKey {
+        ModuleKey { name: lwc!(name), occurrence }
+    }
+
+    #[test]
+    fn diff_identical_manifests() {
+        let old = manifest(r#"
+modules:
+  - name: aaa
+    components: [1, 2]
+  - gen_mod: bbb
+    files:
+      - local: my_subdir
+"#);
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn diff_added_and_removed() {
+        let old = manifest(r#"
+modules:
+  - name: aaa
+    components: [1]
+  - name: bbb
+    components: [1]
+  - name: aaa
+    components: [2]
+"#);
+        let new = manifest(r#"
+modules:
+  - name: aaa
+    components: [1]
+  - gen_mod: ccc
+    files:
+      - local: my_subdir
+"#);
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.added, vec![key("ccc", 0)]);
+        assert_eq!(diff.removed, vec![key("bbb", 0), key("aaa", 1)]);
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn diff_changed_components_and_source() {
+        let old = manifest(r#"
+locations:
+  entries:
+    ccc:
+      http: http://example.com/ccc-v1.zip
+modules:
+  - name: aaa
+    components: [1, 2, 3]
+    location:
+      http: http://example.com/aaa.zip
+  - name: bbb
+    components: ask
+    location:
+      http: http://example.com/bbb.zip
+  - name: ccc
+    components: [1]
+  - name: ddd
+    components:
+      - index: 1
+        component_name: old name
+"#);
+        let new = manifest(r#"
+locations:
+  entries:
+    ccc:
+      http: http://example.com/ccc-v2.zip
+modules:
+  - name: aaa
+    components: [1, 3, 4]
+    location:
+      http: http://example.com/aaa.zip
+      layout:
+        layout_type: multi_dir
+        dirs: [aaa]
+  - name: bbb
+    components: all
+    location:
+      github_user: someone
+      repository: bbb
+      tag: v1
+    description: moved to github
+  - name: ccc
+    components: [1]
+  - name: ddd
+    components:
+      - index: 1
+        component_name: new name
+"#);
+        let diff = old.diff(&new);
+
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 3);
+
+        let ModuleChange { key: aaa_key, changes: aaa_changes } = &diff.changed[0];
+        assert_eq!(aaa_key, &key("aaa", 0));
+        assert_eq!(aaa_changes.len(), 2);
+        assert_eq!(aaa_changes[0], FieldChange::Components {
+            old: Components::List(vec![Component::Simple(1), Component::Simple(2), Component::Simple(3)]),
+            new: Components::List(vec![Component::Simple(1), Component::Simple(3), Component::Simple(4)]),
+            added: vec![4],
+            removed: vec![2],
+        });
+        assert!(matches!(aaa_changes[1], FieldChange::Location { .. }));
+
+        let ModuleChange { key: bbb_key, changes: bbb_changes } = &diff.changed[1];
+        assert_eq!(bbb_key, &key("bbb", 0));
+        assert_eq!(bbb_changes[0], FieldChange::Components {
+            old: Components::Ask, new: Components::All, added: vec![], removed: vec![],
+        });
+        assert!(matches!(bbb_changes[1], FieldChange::Source { .. }));
+        assert_eq!(bbb_changes[2], FieldChange::Other);
+
+        // location from the manifest `locations`
+        let ModuleChange { key: ccc_key, changes: ccc_changes } = &diff.changed[2];
+        assert_eq!(ccc_key, &key("ccc", 0));
+        match &ccc_changes[..] {
+            [FieldChange::Source { old: Some(old), new: Some(new) }] => assert_ne!(old, new),
+            other => panic!("unexpected changes {:?}", other),
+        }
+    }
+
+    #[test]
+    fn diff_changed_generated_mod_and_kind() {
+        let old = manifest(r#"
+modules:
+  - gen_mod: aaa
+    files:
+      - local: my_subdir
+  - gen_mod: bbb
+    files:
+      - local: my_subdir
+"#);
+        let new = manifest(r#"
+modules:
+  - gen_mod: aaa
+    files:
+      - local: my_subdir
+        glob: "*.itm"
+    ignore_warnings: true
+  - name: bbb
+    components: [0]
+"#);
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.changed.len(), 2);
+        assert!(matches!(diff.changed[0].changes[0], FieldChange::Files { .. }));
+        assert_eq!(diff.changed[0].changes[1], FieldChange::Other);
+        assert_eq!(
+            diff.changed[1],
+            ModuleChange {
+                key: key("bbb", 0),
+                changes: vec![FieldChange::Kind { old: ModuleKind::Generated, new: ModuleKind::Mod }],
+            }
+        );
+    }
+}
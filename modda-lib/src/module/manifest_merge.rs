@@ -0,0 +1,183 @@
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use serde_yaml::Value;
+
+use super::manifest::Manifest;
+use super::manifest_diff::{keyed_modules, ModuleKey};
+use super::module::Module;
+
+/// How a module of the overlay is combined with the base module it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModuleMergeMode {
+    /// The overlay module is used as it is.
+    #[default]
+    Replace,
+    /// The properties set in the overlay module replace the same properties of the base module,
+    /// the other properties of the base module are kept.
+    MergeFields,
+}
+
+/// Combines a base manifest and an overlay (see `Manifest::merge`).
+pub fn merge_manifests(base: &Manifest, overlay: &Manifest, mode: ModuleMergeMode) -> Result<Manifest> {
+    let overlay_modules: HashMap<ModuleKey, (usize, &Module)> = keyed_modules(overlay).into_iter()
+        .enumerate()
+        .map(|(index, (key, module))| (key, (index, module)))
+        .collect();
+    let mut used = vec![false; overlay.modules.len()];
+    let mut modules = vec![];
+    for (key, module) in keyed_modules(base) {
+        if overlay.remove_modules.contains(&key.name) {
+            continue;
+        }
+        match overlay_modules.get(&key) {
+            None => modules.push(module.clone()),
+            Some((index, replacement)) => {
+                used[*index] = true;
+                modules.push(merge_module(module, replacement, mode)?);
+            }
+        }
+    }
+    // overlay modules that matched nothing are added at the end, in the overlay order
+    modules.extend(overlay.modules.iter().zip(used).filter(|(_, used)| !used).map(|(module, _)| module.clone()));
+
+    let mut locations = base.locations.clone();
+    locations.external.extend(overlay.locations.external.iter().cloned());
+    locations.entries.extend(overlay.locations.entries.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+    Ok(Manifest {
+        version: base.version.clone(),
+        global: base.global.clone(),
+        locations,
+        manifest_conditions: base.manifest_conditions.merge(&overlay.manifest_conditions),
+        modules,
+        remove_modules: vec![],
+    })
+}
+
+fn merge_module(base: &Module, overlay: &Module, mode: ModuleMergeMode) -> Result<Module> {
+    let same_kind = matches!((base, overlay), (Module::Mod { .. }, Module::Mod { .. })
+                                            | (Module::Generated { .. }, Module::Generated { .. })
+                                            | (Module::File { .. }, Module::File { .. }));
+    if mode == ModuleMergeMode::Replace || !same_kind {
+        return Ok(overlay.clone());
+    }
+    let (mut merged, overlay_value) = match (serde_yaml::to_value(base), serde_yaml::to_value(overlay)) {
+        (Ok(Value::Mapping(base)), Ok(Value::Mapping(overlay))) => (base, overlay),
+        _ => bail!("Could not merge module {}\n -> modules must be serializable as maps", base.get_name()),
+    };
+    for (key, value) in overlay_value {
+        merged.insert(key, value);
+    }
+    match serde_yaml::from_value(Value::Mapping(merged)) {
+        Ok(module) => Ok(module),
+        Err(error) => bail!("Could not merge module {}\n -> {:?}", base.get_name(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components};
+    use crate::module::location::location::Location;
+    use crate::module::manifest::Manifest;
+    use crate::test_utils::manifest;
+    use crate::module::module::Module;
+
+    use super::ModuleMergeMode;
+
+    const BASE: &str = r#"
+modules:
+  - name: aaa
+    components: [1, 2]
+    location:
+      http: https://example.com/aaa.zip
+    ignore_warnings: true
+  - name: bbb
+    components: [1]
+  - name: ccc
+    components: [3]
+"#;
+
+    fn names(manifest: &Manifest) -> Vec<String> {
+        manifest.modules.iter().map(|module| module.get_name().to_string()).collect()
+    }
+
+    fn weidu_mod(module: &Module) -> &crate::module::weidu_mod::WeiduMod {
+        match module {
+            Module::Mod { weidu_mod } => weidu_mod,
+            Module::Generated { .. } | Module::File { .. } => panic!("not a weidu mod"),
+        }
+    }
+
+    #[test]
+    fn replace_matching_module() {
+        let overlay = manifest(r#"
+modules:
+  - name: AAA
+    components: [5]
+"#);
+        let merged = Manifest::merge(&manifest(BASE), &overlay, ModuleMergeMode::Replace).unwrap();
+
+        assert_eq!(names(&merged), vec!["aaa", "bbb", "ccc"]);
+        let aaa = weidu_mod(&merged.modules[0]);
+        assert_eq!(aaa.components, Components::List(vec![Component::Simple(5)]));
+        assert_eq!(aaa.location, None);
+        assert!(!aaa.ignore_warnings);
+    }
+
+    #[test]
+    fn field_merge_matching_module() {
+        let overlay = manifest(r#"
+modules:
+  - name: aaa
+    components: [5]
+    language: 2
+"#);
+        let merged = Manifest::merge(&manifest(BASE), &overlay, ModuleMergeMode::MergeFields).unwrap();
+
+        let aaa = weidu_mod(&merged.modules[0]);
+        assert_eq!(aaa.components, Components::List(vec![Component::Simple(5)]));
+        assert_eq!(aaa.language, Some(2));
+        assert!(matches!(aaa.location, Some(Location::Concrete { .. })));
+        assert!(aaa.ignore_warnings);
+    }
+
+    #[test]
+    fn append_new_modules_in_overlay_order() {
+        let overlay = manifest(r#"
+modules:
+  - name: zzz
+    components: [1]
+  - name: bbb
+    components: [2]
+  - gen_mod: my_tweaks
+    files:
+      - local: tweaks
+  - name: bbb
+    components: [3]
+"#);
+        let merged = Manifest::merge(&manifest(BASE), &overlay, ModuleMergeMode::Replace).unwrap();
+
+        // the second `bbb` of the overlay has no second `bbb` in the base to replace
+        assert_eq!(names(&merged), vec!["aaa", "bbb", "ccc", "zzz", "my_tweaks", "bbb"]);
+        assert_eq!(weidu_mod(&merged.modules[1]).components, Components::List(vec![Component::Simple(2)]));
+        assert_eq!(weidu_mod(&merged.modules[5]).components, Components::List(vec![Component::Simple(3)]));
+    }
+
+    #[test]
+    fn remove_base_modules() {
+        let overlay = manifest(r#"
+remove_modules: [bbb, not_in_base]
+modules:
+  - name: ccc
+    components: [4]
+"#);
+        let merged = Manifest::merge(&manifest(BASE), &overlay, ModuleMergeMode::Replace).unwrap();
+
+        assert_eq!(names(&merged), vec!["aaa", "ccc"]);
+        assert!(merged.remove_modules.is_empty());
+        assert_eq!(merged.modules[1].get_name(), &lwc!("ccc"));
+    }
+}
@@ -0,0 +1,159 @@
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::lowercase::LwcString;
+
+use super::manifest::Manifest;
+use super::module::Module;
+
+/// Puts the modules in canonical form (see `Manifest::normalize`).
+pub fn normalize_manifest(manifest: &mut Manifest) -> Result<()> {
+    let conflicts = find_conflicts(&manifest.modules);
+    if !conflicts.is_empty() {
+        bail!("Conflicting modules in manifest\n  {}", conflicts.join("\n  "));
+    }
+    manifest.sort_by_order();
+    let mut kept: Vec<Module> = vec![];
+    for module in std::mem::take(&mut manifest.modules) {
+        if !kept.contains(&module) {
+            kept.push(module);
+        }
+    }
+    manifest.modules = kept;
+    Ok(())
+}
+
+/// Modules with the same name that can't both be right.<br>
+/// The same weidu mod can appear several times (to install some components later), but it must
+/// always be obtained from the same place.
+fn find_conflicts(modules: &[Module]) -> Vec<String> {
+    let mut conflicts = vec![];
+    let mut first_by_name: HashMap<&LwcString, (usize, &Module)> = HashMap::new();
+    let mut location_by_name = HashMap::new();
+    for (index, module) in modules.iter().enumerate() {
+        let name = module.get_name();
+        let (first_index, first) = *first_by_name.entry(name).or_insert((index, module));
+        if first_index == index || first == module {
+            if let Module::Mod { weidu_mod } = module {
+                if let Some(location) = &weidu_mod.location {
+                    location_by_name.entry(name).or_insert((index, location));
+                }
+            }
+            continue;
+        }
+        match (first, module) {
+            (Module::Mod { .. }, Module::Mod { weidu_mod }) => {
+                if let Some(location) = &weidu_mod.location {
+                    match location_by_name.get(name) {
+                        None => { location_by_name.insert(name, (index, location)); }
+                        Some((other_index, other)) if *other != location =>
+                            conflicts.push(format!("module {} (positions {} and {}): different locations",
+                                                    name, other_index + 1, index + 1)),
+                        Some(_) => {}
+                    }
+                }
+            }
+            (Module::Generated { .. }, Module::Generated { .. }) =>
+                conflicts.push(format!("module {} (positions {} and {}): generated mods with the same name and different content",
+                                        name, first_index + 1, index + 1)),
+            (Module::File { .. }, Module::File { .. }) =>
+                conflicts.push(format!("module {} (positions {} and {}): file modules with the same name and different content",
+                                        name, first_index + 1, index + 1)),
+            (Module::File { .. }, _) | (_, Module::File { .. }) =>
+                conflicts.push(format!("module {} (positions {} and {}): a file module and another module have the same name",
+                                        name, first_index + 1, index + 1)),
+            _ => conflicts.push(format!("module {} (positions {} and {}): a weidu mod and a generated mod have the same name",
+                                        name, first_index + 1, index + 1)),
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components};
+    use crate::module::manifest::Manifest;
+    use crate::test_utils::manifest;
+
+    fn names(manifest: &Manifest) -> Vec<String> {
+        manifest.modules.iter().map(|module| module.get_name().to_string()).collect()
+    }
+
+    #[test]
+    fn remove_identical_modules() {
+        let mut manifest = manifest(r#"
+modules:
+  - name: aaa
+    components: [1]
+    location:
+      http: https://example.com/aaa.zip
+  - name: zzz
+    components: [1]
+    order: 10
+  - name: bbb
+    components: [2]
+  - name: aaa
+    components: [1]
+    location:
+      http: https://example.com/aaa.zip
+  - name: aaa
+    components: [3]
+  - gen_mod: my_tweaks
+    files:
+      - local: tweaks
+  - gen_mod: my_tweaks
+    files:
+      - local: tweaks
+"#);
+        manifest.normalize().unwrap();
+
+        assert_eq!(names(&manifest), vec!["aaa", "bbb", "aaa", "my_tweaks", "zzz"]);
+        // a second fragment of the same mod is kept
+        assert_eq!(manifest.modules[2].get_name(), &lwc!("aaa"));
+        assert_eq!(manifest.modules[2].get_components(), Components::List(vec![Component::Simple(3)]));
+
+        // already canonical
+        let normalized = manifest.clone();
+        manifest.normalize().unwrap();
+        assert_eq!(manifest, normalized);
+    }
+
+    #[test]
+    fn conflicting_modules() {
+        let original = manifest(r#"
+modules:
+  - name: aaa
+    components: [1]
+    location:
+      http: https://example.com/aaa.zip
+  - name: aaa
+    components: [2]
+  - name: aaa
+    components: [3]
+    location:
+      http: https://example.com/aaa-v2.zip
+  - gen_mod: bbb
+    files:
+      - local: tweaks
+  - name: bbb
+    components: [1]
+  - gen_mod: ccc
+    files:
+      - local: tweaks
+  - gen_mod: ccc
+    files:
+      - local: other_tweaks
+"#);
+        let mut manifest = original.clone();
+
+        let message = manifest.normalize().unwrap_err().to_string();
+
+        assert!(message.contains("module aaa (positions 1 and 3): different locations"), "{}", message);
+        assert!(message.contains("module bbb (positions 4 and 5): a weidu mod and a generated mod"), "{}", message);
+        assert!(message.contains("module ccc (positions 6 and 7): generated mods"), "{}", message);
+        assert_eq!(manifest, original);
+    }
+}
@@ -1,6 +1,8 @@
 
 pub mod components;
+pub mod dependency_graph;
 pub mod disable_condition;
+pub mod expected_files;
 pub mod file_mod;
 pub mod file_module_origin;
 pub mod gen_mod;
@@ -10,8 +12,12 @@ pub mod language;
 pub mod location;
 pub mod manifest;
 pub mod manifest_conditions;
+pub mod manifest_diff;
+pub mod manifest_merge;
+pub mod manifest_normalize;
 pub mod module;
 pub mod module_conf;
+pub mod module_listing;
 pub mod pre_copy_command;
 pub mod refresh;
 pub mod weidu_mod;
@@ -25,8 +31,10 @@ mod test_deserialize {
     use crate::module::components::{Components, Component};
     use crate::module::disable_condition::DisableCondition;
     use crate::module::file_mod::FileModule;
-    use crate::module::file_module_origin::FileModuleOrigin;
+    use crate::module::file_module_origin::{DirCopy, FileModuleOrigin};
     use crate::module::gen_mod::{GeneratedMod, GenModComponent};
+    use crate::module::manifest::Manifest;
+    use crate::module::module::Module;
     use crate::module::location::github::{Github, GithubDescriptor};
     use crate::module::location::http::Http;
     use crate::module::location::location::{ConcreteLocation, Location};
@@ -69,7 +77,7 @@ mod test_deserialize {
                         },
                         ..Default::default()
                     }),
-                    layout: Layout::single_dir(3),
+                    layout: Some(Layout::single_dir(3)),
                     ..ConcreteLocation::default()
                 }
             }),
@@ -111,7 +119,7 @@ mod test_deserialize {
                             },
                             ..Default::default()
                         }),
-                        layout: Layout::multi_dir(vec!["a".to_string(),"b".to_string()]),
+                        layout: Some(Layout::multi_dir(vec!["a".to_string(),"b".to_string()])),
                         ..ConcreteLocation::default()
                     }}),
                 ..WeiduMod::default()
@@ -220,12 +228,13 @@ mod test_deserialize {
                             rename: None,
                             ..Default::default()
                         }),
-                        layout: Layout::default(),
+                        layout: None,
                         patch: Some(PatchDesc {
                             patch_source: PatchSource::Http {
                                 http: "https://patch.location".to_owned(),
                             },
                             encoding: PatchEncoding::UTF8,
+                            fuzz: 0,
                         }),
                         ..ConcreteLocation::default()
                     }
@@ -259,12 +268,13 @@ mod test_deserialize {
                             rename: None,
                             ..Default::default()
                         }),
-                        layout: Layout::default(),
+                        layout: None,
                         patch: Some(PatchDesc {
                             patch_source: PatchSource::Relative {
                                 relative: "patches/my_patch.diff".to_owned(),
                             },
                             encoding: PatchEncoding::UTF8,
+                            fuzz: 0,
                         }),
                         ..ConcreteLocation::default()
                     }
@@ -299,19 +309,21 @@ mod test_deserialize {
                             rename: None,
                             ..Default::default()
                         }),
-                        layout: Layout::default(),
+                        layout: None,
                         patches: vec![
                             PatchDesc {
                                 patch_source: PatchSource::Relative {
                                     relative: "patches/my_patch1.diff".to_owned(),
                                 },
                                 encoding: PatchEncoding::UTF8,
+                                fuzz: 0,
                             },
                             PatchDesc {
                                 patch_source: PatchSource::Relative {
                                     relative: "patches/my_patch2.diff".to_owned(),
                                 },
                                 encoding: PatchEncoding::UTF8,
+                                fuzz: 0,
                             },
                         ],
                         ..ConcreteLocation::default()
@@ -339,12 +351,13 @@ mod test_deserialize {
                             rename: None,
                             ..Default::default()
                         }),
-                        layout: Layout::default(),
+                        layout: None,
                         patch: Some(PatchDesc {
                             patch_source: PatchSource::Inline {
                                 inline: expected_content.to_owned(),
                             },
                             encoding: PatchEncoding::UTF8,
+                            fuzz: 0,
                         }),
                         ..ConcreteLocation::default()
                     }
@@ -441,11 +454,13 @@ mod test_deserialize {
     fn serialize_filemodule() {
         let module = FileModule {
             file_mod: lwc!("DlcMerger"),
-            from: FileModuleOrigin::Local { local: "dir/file.bcs".to_string(), glob: None },
+            from: FileModuleOrigin::Local { local: "dir/file.bcs".to_string(), glob: None, dir_copy: None, literal: None },
             to: "override/".to_string(),
             description: None,
             post_install: None,
-            allow_overwrite: false,
+            allow_overwrite: None,
+            requires_file: None,
+            forbids_file: None,
         };
         println!("{}", serde_yaml::to_string(&module).unwrap());
     }
@@ -464,14 +479,42 @@ mod test_deserialize {
             FileModule {
                 file_mod: lwc!("configure_whatever"),
                 description: None,
-                from: FileModuleOrigin::Local { local: "path/file.idk".to_string(), glob: None },
+                from: FileModuleOrigin::Local { local: "path/file.idk".to_string(), glob: None, dir_copy: None, literal: None },
                 to: "override/".to_string(),
                 post_install: None,
-                allow_overwrite: false,
+                allow_overwrite: None,
+                requires_file: None,
+                forbids_file: None,
             }
         );
     }
 
+    #[test]
+    fn gen_mod_inherits_manifest_allow_overwrite() {
+        let yaml = r#"
+        version: "1"
+        global:
+            lang_dir: fr_FR
+            allow_overwrite: true
+            copy_mode: directory
+        modules:
+            - gen_mod: inherits
+              files:
+                - local: some_dir
+            - gen_mod: overrides
+              files:
+                - local: some_dir
+              allow_overwrite: false
+        "#;
+        let manifest: Manifest = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(manifest.global.copy_mode, Some(DirCopy::Directory));
+        let gen_mods = manifest.modules.iter().map(|module| match module {
+            Module::Generated { gen } => gen.allow_overwrite(&manifest.global),
+            Module::Mod { .. } | Module::File { .. } => panic!("not a generated mod"),
+        }).collect::<Vec<_>>();
+        assert_eq!(gen_mods, vec![true, false]);
+    }
+
     #[test]
     fn deserialize_gen_mod() {
         let yaml = r#"
@@ -495,14 +538,20 @@ mod test_deserialize {
                 description: Some("some description".to_string()),
                 post_install: Some(PostInstall::Interrupt),
                 files: vec![
-                    FileModuleOrigin::Local { local: "some_dir".to_string(), glob: None },
-                    FileModuleOrigin::Local { local: "other_dir".to_string(), glob: Some("*.itm".to_string()) },
-                    FileModuleOrigin::Absolute { absolute: "/location".to_string(), glob: None },
+                    FileModuleOrigin::Local { local: "some_dir".to_string(), glob: None, dir_copy: None, literal: None },
+                    FileModuleOrigin::Local { local: "other_dir".to_string(), glob: Some("*.itm".to_string()), dir_copy: None, literal: None },
+                    FileModuleOrigin::Absolute { absolute: "/location".to_string(), glob: None, dir_copy: None, literal: None },
                 ],
                 component: GenModComponent { index: 0, name: None },
-                allow_overwrite: true,
+                allow_overwrite: Some(true),
                 ignore_warnings: true,
                 disabled_if: None,
+                always: None,
+                at_exit: None,
+                tags: vec![],
+                order: None,
+                requires: vec![],
+                expect: vec![],
             }
         );
     }
@@ -11,7 +11,9 @@ use crate::lowercase::LwcString;
 use crate::post_install::{PostInstallExec, PostInstallOutcome};
 
 use super::disable_condition::{DisableCheck, DisableOutCome};
+use super::file_mod::FileModule;
 use super::gen_mod::GeneratedMod;
+use super::install_comment::InstallationComments;
 use super::manifest_conditions::ManifestConditions;
 use super::weidu_mod::WeiduMod;
 
@@ -19,7 +21,9 @@ use super::weidu_mod::WeiduMod;
 #[derive(Debug, PartialEq, Clone)]
 pub enum Module {
     Mod { weidu_mod: WeiduMod },
-    Generated { gen: GeneratedMod }
+    Generated { gen: GeneratedMod },
+    /// Files copied as they are (no weidu mod is generated or installed).
+    File { file: FileModule },
 }
 
 impl Module {
@@ -27,6 +31,7 @@ impl Module {
         match self {
             Module::Mod { weidu_mod } => &weidu_mod.name,
             Module::Generated { gen } => &gen.gen_mod,
+            Module::File { file } => &file.file_mod,
         }
     }
 
@@ -34,6 +39,58 @@ impl Module {
         match self {
             Module::Mod { weidu_mod } => &weidu_mod.description,
             Module::Generated { gen } => &gen.description,
+            Module::File { file } => &file.description,
+        }
+    }
+
+    pub fn get_tags(&self) -> &[String] {
+        match self {
+            Module::Mod { weidu_mod } => &weidu_mod.tags,
+            Module::Generated { gen } => &gen.tags,
+            Module::File { .. } => &[],
+        }
+    }
+
+    /// Install priority (`order` property, `0` when absent).
+    pub fn get_order(&self) -> i32 {
+        match self {
+            Module::Mod { weidu_mod } => weidu_mod.order,
+            Module::Generated { gen } => gen.order,
+            Module::File { .. } => None,
+        }.unwrap_or(0)
+    }
+
+    /// Mods that must be installed before this one (`requires` property).
+    pub fn get_requires(&self) -> &[LwcString] {
+        match self {
+            Module::Mod { weidu_mod } => &weidu_mod.requires,
+            Module::Generated { gen } => &gen.requires,
+            Module::File { .. } => &[],
+        }
+    }
+
+    /// Files the installation must produce (`expect` property).
+    pub fn get_expect(&self) -> &[String] {
+        match self {
+            Module::Mod { weidu_mod } => &weidu_mod.expect,
+            Module::Generated { gen } => &gen.expect,
+            Module::File { .. } => &[],
+        }
+    }
+
+    pub fn ignores_warnings(&self) -> bool {
+        match self {
+            Module::Mod { weidu_mod } => weidu_mod.ignore_warnings,
+            Module::Generated { gen } => gen.ignore_warnings,
+            Module::File { .. } => false,
+        }
+    }
+
+    pub fn get_installation_hints(&self) -> Option<&InstallationComments> {
+        match self {
+            Module::Mod { weidu_mod } => weidu_mod.installation.as_ref(),
+            Module::Generated { .. } => None,
+            Module::File { .. } => None,
         }
     }
 
@@ -41,6 +98,7 @@ impl Module {
         match self {
             Module::Mod { weidu_mod } => weidu_mod.components.clone(),
             Module::Generated { gen } => Components::List(vec![Component::Simple(gen.component.index)]),
+            Module::File { .. } => Components::None,
         }
     }
 
@@ -55,6 +113,7 @@ impl Module {
         match self {
             Module::Mod { weidu_mod } => weidu_mod.post_install.exec(mod_name),
             Module::Generated { gen } => gen.post_install.exec(mod_name),
+            Module::File { file } => file.post_install.exec(mod_name),
         }
     }
 
@@ -62,6 +121,7 @@ impl Module {
         match self {
             Module::Mod { weidu_mod } => weidu_mod.disabled_if.check(manifest_root, manifest_conditions),
             Module::Generated { gen } => gen.disabled_if.check(manifest_root, manifest_conditions),
+            Module::File { .. } => Ok(DisableOutCome::No(None)),
         }
     }
 }
@@ -74,20 +134,26 @@ impl <'de> Deserialize<'de> for Module {
             Value::Mapping(ref mapping) => {
                 let has_name = mapping.get(Value::String("name".to_string())).is_some();
                 let has_gen_mod = mapping.get(Value::String("gen_mod".to_string())).is_some();
-                match (has_name, has_gen_mod) {
-                    (false, false) =>
-                        Err(serde::de::Error::custom("'modules' item doesn't have a 'name' or 'gen_mod' field")),
-                    (true, false) => {
+                let has_file_mod = mapping.get(Value::String("file_mod".to_string())).is_some();
+                match (has_name, has_gen_mod, has_file_mod) {
+                    (false, false, false) =>
+                        Err(serde::de::Error::custom("'modules' item doesn't have a 'name', 'gen_mod' or 'file_mod' field")),
+                    (true, false, false) => {
                         WeiduMod::deserialize(helper.into_deserializer())
                             .map(|weidu_mod| Module::Mod { weidu_mod })
                             .map_err(serde::de::Error::custom)
                     }
-                    (false, true) => {
+                    (false, true, false) => {
                         GeneratedMod::deserialize(helper.into_deserializer())
                             .map(|gen| Module::Generated { gen })
                             .map_err(serde::de::Error::custom)
                     }
-                    _ => Err(serde::de::Error::custom("'modules' item must have only one of 'name' or 'gen_mod'")),
+                    (false, false, true) => {
+                        FileModule::deserialize(helper.into_deserializer())
+                            .map(|file| Module::File { file })
+                            .map_err(serde::de::Error::custom)
+                    }
+                    _ => Err(serde::de::Error::custom("'modules' item must have only one of 'name', 'gen_mod' or 'file_mod'")),
                 }
             }
             _ => Err(serde::de::Error::custom("'modules' item is not a map"))
@@ -102,6 +168,7 @@ impl Serialize for Module {
         match self {
             Module::Mod { weidu_mod } => weidu_mod.serialize(serializer),
             Module::Generated { gen } => gen.serialize(serializer),
+            Module::File { file } => file.serialize(serializer),
         }
     }
 }
@@ -0,0 +1,228 @@
+
+use std::fmt::{self, Display};
+
+use crate::lowercase::LwcString;
+
+use super::components::Components;
+use super::location::source::Source;
+use super::manifest::Manifest;
+use super::manifest_diff::{effective_location, module_kind, ModuleKind};
+use super::module::Module;
+
+/// Selects the modules of a listing by name; empty lists don't filter anything.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModuleFilter {
+    /// If not empty, only the modules with these names are kept.
+    pub only: Vec<LwcString>,
+    /// Modules with these names are removed.
+    pub skip: Vec<LwcString>,
+}
+
+impl ModuleFilter {
+    pub fn accepts(&self, name: &LwcString) -> bool {
+        (self.only.is_empty() || self.only.contains(name)) && !self.skip.contains(name)
+    }
+}
+
+/// Where a listed module comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Http,
+    Github,
+    Local,
+    Absolute,
+    /// No location: the mod must already be in the game directory.
+    GameDir,
+    /// The `location` reference is not in the manifest `locations`.
+    MissingRef,
+    /// Generated mods are built from their `files`.
+    Files,
+}
+
+/// Number of components a listed module installs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentCount {
+    /// Chosen interactively during the installation.
+    Ask,
+    All,
+    Listed(usize),
+    /// In a components file that was not read (the manifest was not assembled).
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedModule {
+    /// Position in the manifest module list, counting from one (like `install --from-index`).
+    pub index: usize,
+    pub name: LwcString,
+    pub kind: ModuleKind,
+    pub source: SourceKind,
+    pub components: ComponentCount,
+}
+
+/// Result of `Manifest::list_modules`, in installation order; displayed as a table.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModuleListing {
+    pub modules: Vec<ListedModule>,
+}
+
+pub fn list_modules(manifest: &Manifest, filter: &ModuleFilter) -> ModuleListing {
+    let modules = manifest.modules.iter().enumerate()
+        .filter(|(_, module)| filter.accepts(module.get_name()))
+        .map(|(index, module)| ListedModule {
+            index: index + 1,
+            name: module.get_name().clone(),
+            kind: module_kind(module),
+            source: source_kind(manifest, module),
+            components: component_count(&module.get_components()),
+        })
+        .collect();
+    ModuleListing { modules }
+}
+
+fn source_kind(manifest: &Manifest, module: &Module) -> SourceKind {
+    let weidu_mod = match module {
+        Module::Generated { .. } | Module::File { .. } => return SourceKind::Files,
+        Module::Mod { weidu_mod } => weidu_mod,
+    };
+    match (effective_location(manifest, &weidu_mod.name, &weidu_mod.location), &weidu_mod.location) {
+        (None, None) => SourceKind::GameDir,
+        (None, Some(_)) => SourceKind::MissingRef,
+        (Some(location), _) => match location.source {
+            Source::Http(_) => SourceKind::Http,
+            Source::Github(_) => SourceKind::Github,
+            Source::Local { .. } => SourceKind::Local,
+            Source::Absolute { .. } => SourceKind::Absolute,
+        },
+    }
+}
+
+fn component_count(components: &Components) -> ComponentCount {
+    match components {
+        Components::Ask => ComponentCount::Ask,
+        Components::All => ComponentCount::All,
+        Components::None => ComponentCount::Listed(0),
+        Components::List(list) => ComponentCount::Listed(list.len()),
+        Components::File { .. } => ComponentCount::File,
+    }
+}
+
+impl Display for SourceKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            SourceKind::Http => "http",
+            SourceKind::Github => "github",
+            SourceKind::Local => "local",
+            SourceKind::Absolute => "absolute",
+            SourceKind::GameDir => "game dir",
+            SourceKind::MissingRef => "missing ref",
+            SourceKind::Files => "files",
+        })
+    }
+}
+
+impl Display for ComponentCount {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComponentCount::Ask => formatter.write_str("ask"),
+            ComponentCount::All => formatter.write_str("all"),
+            ComponentCount::Listed(count) => write!(formatter, "{}", count),
+            ComponentCount::File => formatter.write_str("file"),
+        }
+    }
+}
+
+fn kind_name(kind: ModuleKind) -> &'static str {
+    match kind {
+        ModuleKind::Mod => "weidu",
+        ModuleKind::Generated => "generated",
+        ModuleKind::File => "file",
+    }
+}
+
+impl Display for ModuleListing {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let header = ["#", "name", "kind", "source", "components"].map(String::from);
+        let rows = self.modules.iter().map(|module| [
+            module.index.to_string(),
+            module.name.to_string(),
+            kind_name(module.kind).to_string(),
+            module.source.to_string(),
+            module.components.to_string(),
+        ]).collect::<Vec<_>>();
+        let mut widths = header.clone().map(|cell| cell.len());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&header).chain(&rows) {
+            let line = row.iter().zip(widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(formatter, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::lowercase::lwc;
+    use crate::module::manifest::Manifest;
+    use crate::module::manifest_diff::ModuleKind;
+
+    use super::{ComponentCount, ListedModule, ModuleFilter, SourceKind};
+
+    fn manifest() -> Manifest {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/manifest_with_mixed_modules.yml");
+        Manifest::read_path(path.to_str().unwrap()).unwrap()
+    }
+
+    fn listed(index: usize, name: &str, kind: ModuleKind, source: SourceKind, components: ComponentCount) -> ListedModule {
+        ListedModule { index, name: lwc!(name), kind, source, components }
+    }
+
+    #[test]
+    fn list_mixed_modules() {
+        let listing = manifest().list_modules(&ModuleFilter::default());
+
+        assert_eq!(listing.modules, vec![
+            listed(1, "dlcmerger", ModuleKind::Mod, SourceKind::Github, ComponentCount::Listed(1)),
+            listed(2, "bg1npc", ModuleKind::Mod, SourceKind::Http, ComponentCount::Listed(3)),
+            listed(3, "my_tweaks", ModuleKind::Generated, SourceKind::Files, ComponentCount::Listed(1)),
+            listed(4, "local_mod", ModuleKind::Mod, SourceKind::Local, ComponentCount::All),
+            listed(5, "in_place", ModuleKind::Mod, SourceKind::GameDir, ComponentCount::Ask),
+            listed(6, "broken_ref", ModuleKind::Mod, SourceKind::MissingRef, ComponentCount::Listed(0)),
+            listed(7, "bg1npc", ModuleKind::Mod, SourceKind::Http, ComponentCount::Listed(1)),
+        ]);
+    }
+
+    #[test]
+    fn list_filtered_modules() {
+        let filter = ModuleFilter { only: vec![lwc!("bg1npc"), lwc!("my_tweaks")], skip: vec![lwc!("my_tweaks")] };
+
+        let listing = manifest().list_modules(&filter);
+
+        let names = listing.modules.iter().map(|module| (module.index, module.name.as_ref().as_str())).collect::<Vec<_>>();
+        assert_eq!(names, vec![(2, "bg1npc"), (7, "bg1npc")]);
+    }
+
+    #[test]
+    fn listing_as_table() {
+        let filter = ModuleFilter { skip: vec![lwc!("broken_ref"), lwc!("in_place"), lwc!("local_mod")], ..Default::default() };
+
+        let table = manifest().list_modules(&filter).to_string();
+
+        assert_eq!(table, "\
+#  name       kind       source  components
+1  dlcmerger  weidu      github  1
+2  bg1npc     weidu      http    3
+3  my_tweaks  generated  files   1
+7  bg1npc     weidu      http    1
+");
+    }
+}
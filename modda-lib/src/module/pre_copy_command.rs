@@ -1,11 +1,104 @@
 
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 #[skip_serializing_none]
 pub struct PrecopyCommand {
-    pub command: String,
+    /// Command used on all OSes, or only on the OSes without a variant in `os`.
+    pub command: Option<String>,
     pub args: Option<Vec<String>>,
     pub subdir: Option<String>,
+    /// OS-specific variants, by OS name as in rust `target_os` (`linux`, `windows`, `macos`...).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub os: BTreeMap<String, OsPrecopyCommand>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[skip_serializing_none]
+pub struct OsPrecopyCommand {
+    pub command: String,
+    pub args: Option<Vec<String>>,
+}
+
+impl PrecopyCommand {
+    /// Command and args to run on the current OS.
+    pub fn for_current_os(&self) -> Result<(&str, &[String])> {
+        self.for_os(std::env::consts::OS)
+    }
+
+    /// Command and args to run on `os`: the variant for this OS if there is one,
+    /// else the cross-platform `command`.
+    pub fn for_os(&self, os: &str) -> Result<(&str, &[String])> {
+        if let Some(variant) = self.os.get(os) {
+            return Ok((&variant.command, variant.args.as_deref().unwrap_or_default()));
+        }
+        match &self.command {
+            Some(command) => Ok((command, self.args.as_deref().unwrap_or_default())),
+            None => bail!("No precopy command for the current OS ({})\n -> there are variants for {} only, \
+                            add one for {} or a `command` for all OSes",
+                            os, self.os.keys().join(", "), os),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrecopyCommand;
+
+    const PER_OS: &str = r#"
+        os:
+          linux:
+            command: ./fix.sh
+            args: [--all]
+          windows:
+            command: fix.bat
+        subdir: tools
+    "#;
+
+    #[test]
+    fn select_os_variant() {
+        let precopy: PrecopyCommand = serde_yaml::from_str(PER_OS).unwrap();
+
+        assert_eq!(precopy.for_os("linux").unwrap(), ("./fix.sh", &["--all".to_string()][..]));
+        assert_eq!(precopy.for_os("windows").unwrap(), ("fix.bat", &[][..]));
+        let error = precopy.for_os("macos").unwrap_err().to_string();
+        assert!(error.contains("macos") && error.contains("linux, windows"), "{}", error);
+    }
+
+    #[test]
+    fn cross_platform_command_is_the_fallback() {
+        let yaml = r#"
+            command: python3
+            args: [fix.py]
+            os:
+              windows:
+                command: py
+                args: [fix.py]
+        "#;
+        let precopy: PrecopyCommand = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(precopy.for_os("windows").unwrap().0, "py");
+        assert_eq!(precopy.for_os("linux").unwrap(), ("python3", &["fix.py".to_string()][..]));
+        assert_eq!(precopy.for_os("macos").unwrap().0, "python3");
+    }
+
+    #[test]
+    fn single_command_on_current_os() {
+        let precopy: PrecopyCommand = serde_yaml::from_str("command: unzip").unwrap();
+        assert_eq!(precopy.for_current_os().unwrap(), ("unzip", &[][..]));
+
+        let precopy: PrecopyCommand = serde_yaml::from_str(PER_OS).unwrap();
+        if cfg!(target_os = "linux") {
+            assert_eq!(precopy.for_current_os().unwrap().0, "./fix.sh");
+        } else if cfg!(target_os = "windows") {
+            assert_eq!(precopy.for_current_os().unwrap().0, "fix.bat");
+        } else {
+            assert!(precopy.for_current_os().is_err());
+        }
+    }
 }
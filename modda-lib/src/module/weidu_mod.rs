@@ -51,6 +51,10 @@ pub struct WeiduMod {
     /// In that case, it checks a `<mod_name.tp2>`,`setup-mod_name>.tp2` in the game dir and in
     /// `<nod_name>` sub-directory. If it is not found, the installation aborts.
     pub location: Option<Location>,
+    /// If `true`, removes the mod directory (and tp2 files) left in the game directory by a previous
+    /// installation, then fetches and extracts the mod again.<br>
+    /// Overrides the global `clean_reinstall`. Only applies to modules that have a location.
+    pub clean_reinstall: Option<bool>,
     /// Decides what will be done after the mod installation (in case of success).
     /// - `interrupt` will stop the installation and exist the program
     /// - `wait_seconds: xxx will wait xxx second before continuing to the next mod
@@ -64,6 +68,21 @@ pub struct WeiduMod {
     pub installation: Option<InstallationComments>,
     /// Condition that disables the mod installation (if absent, not disabled)
     pub disabled_if: Option<DisableCondition>,
+    /// Free-form tags/categories, for tooling (grouping, filtering).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Install priority: modules are installed by increasing `order` (absent means `0`),
+    /// modules with the same value keep their manifest order.<br>
+    /// This doesn't make weidu more lenient: the resulting sequence must still be a valid install order.
+    pub order: Option<i32>,
+    /// Mods that must be installed before this one (see `Manifest::dependency_graph`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<LwcString>,
+    /// Glob patterns (relative to the game directory, case-insensitive) of files the installation must produce,
+    /// for example `override/my_item.itm`.<br>
+    /// The installation fails if one of them matches nothing, with `ignore_warnings` it's only a warning.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expect: Vec<String>,
 }
 
 fn is_false(value: &bool) -> bool { !value }
@@ -1,19 +1,27 @@
 
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use anyhow::{bail, Result};
-use chrono::Local;
-use log::info;
+use chrono::{DateTime, Local};
+use itertools::Itertools;
+use log::{debug, info, warn};
+use tokio::runtime::RuntimeFlavor;
 use path_clean::PathClean;
 
 use crate::apply_patch::patch_module;
-use crate::archive_extractor::Extractor;
+use crate::archive_extractor::{ExtractionStats, Extractor, StreamFormat};
 use crate::args::Install;
-use crate::cache::Cache;
+use crate::cache::{Cache, CacheValidation};
 use crate::canon_path::CanonPath;
-use crate::download::Downloader;
+use crate::download::{Downloader, DownloadedFile};
 use crate::global::Global;
+use crate::jobs::{run_bounded, Pool};
 use crate::module::global_locations::GlobalLocations;
+use crate::module::location::github::DEFAULT_MAX_RATE_LIMIT_WAIT;
+use crate::module::location::http::Http;
 use crate::module::location::location::{ConcreteLocation, Location};
 use crate::module::location::replace::ReplaceSpec;
 use crate::module::location::source::Source;
@@ -21,8 +29,11 @@ use crate::timeline::SetupTimeline;
 use crate::lowercase::LwcString;
 use crate::module::weidu_mod::WeiduMod;
 use crate::config::Config;
+use crate::run_warnings::WarningCollector;
 
 use super::get_options::GetOptions;
+use super::local_mods_index::LocalModsIndex;
+use super::stream_pipe::pipe;
 
 pub struct ModuleDownload<'a> {
     pub global: &'a Global,
@@ -32,6 +43,17 @@ pub struct ModuleDownload<'a> {
     pub extractor: Extractor<'a>,
     pub cache: &'a Cache,
     pub game_dir: &'a CanonPath,
+    pub github_rate_limit_max_wait: Duration,
+    /// Removes downloaded archives from the cache once their content is in the game directory.
+    pub delete_archive_after_extract: bool,
+    /// What is checked before reusing an archive found in the cache.
+    pub cache_validation: CacheValidation,
+    /// Hash-prefix directory levels of the archives in the cache (`cache_shard_depth`).
+    pub cache_shard_depth: u8,
+    /// Http zip and tar.gz archives are extracted while they are downloaded, without a copy in the cache.
+    pub stream_extract: bool,
+    /// Built the first time a mod name has to be looked up (`global.local_mods_index`).
+    local_mods_index: OnceLock<LocalModsIndex>,
 }
 
 impl <'a> ModuleDownload<'a> {
@@ -47,17 +69,66 @@ impl <'a> ModuleDownload<'a> {
             extractor: Extractor::new(game_dir, config),
             cache,
             game_dir,
+            github_rate_limit_max_wait: config.github_rate_limit_max_wait
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_MAX_RATE_LIMIT_WAIT),
+            delete_archive_after_extract: config.delete_archive_after_extract.unwrap_or(false),
+            cache_validation: config.cache_validation.unwrap_or_default(),
+            cache_shard_depth: config.cache_shard_depth.unwrap_or(0),
+            stream_extract: config.stream_extract.unwrap_or(false),
+            local_mods_index: OnceLock::new(),
         }
     }
 
-    // at some point, I'd like to have a pool of downloads with installations done
-    // concurrently as soon as modules are there
+    pub fn with_warnings(self, warnings: &'a WarningCollector) -> Self {
+        Self { extractor: self.extractor.with_warnings(warnings), ..self }
+    }
+
+    /// Zip archives are extracted by the workers of `pool` (see `Extractor::with_pool`).
+    pub fn with_extract_pool(self, pool: &'a Pool) -> Self {
+        Self { extractor: self.extractor.with_pool(pool), ..self }
+    }
+
+    /// Blocking version of `get_module_async`, with its own tokio runtime.<br>
+    /// Must not be called from async code (nested runtimes panic), use `get_module_async` there.
     #[tokio::main]
     pub async fn get_module(&self, module: &WeiduMod, get_options: &GetOptions) -> Result<SetupTimeline> {
+        self.get_module_async(module, get_options).await
+    }
+
+    // at some point, I'd like to have a pool of downloads with installations done
+    // concurrently as soon as modules are there
+    /// Fetches the module and puts it in the game directory, in the caller's tokio runtime.
+    pub async fn get_module_async(&self, module: &WeiduMod, get_options: &GetOptions) -> Result<SetupTimeline> {
         let concrete_location = self.get_module_location(module)?;
         self.get_mod_from_concrete_location(concrete_location, &module.name, get_options).await
     }
 
+    /// Downloads the archives of `modules` to the cache ahead of their installation, at most `concurrency`
+    /// at once; `get_module` then finds them there.<br>
+    /// Only http and github locations are downloaded (not the streamed ones), an archive used by several modules
+    /// is downloaded once. Failures are only logged, the installation of the module retrieves the archive again
+    /// and reports the error.
+    #[tokio::main]
+    pub async fn prefetch(&'a self, modules: &[&'a WeiduMod], concurrency: usize) {
+        let locations = modules.iter()
+            .filter_map(|module| self.get_module_location(module).ok().map(|location| (&module.name, location)))
+            .filter(|(_, location)| matches!(location.source, Source::Http(_) | Source::Github(_)))
+            .filter(|(name, location)| self.stream_source(location, name).is_none())
+            .unique_by(|(name, location)| self.archive_path(location, name).ok())
+            .collect_vec();
+        if locations.is_empty() {
+            return;
+        }
+        info!("downloading {} archives, {} at once", locations.len(), concurrency);
+        let downloads = locations.iter().map(|(name, location)| self.retrieve_location(location, name));
+        for ((name, _), result) in locations.iter().zip(run_bounded(concurrency, downloads).await) {
+            if let Err(error) = result {
+                warn!("Could not download the archive of {} ahead of its installation\n  {:?}", name, error);
+            }
+        }
+    }
+
     pub fn get_module_location(&'a self, module: &'a WeiduMod) -> Result<&'a ConcreteLocation> {
         match &module.location {
             None => match self.global_locations.find(&module.name) {
@@ -79,20 +150,27 @@ impl <'a> ModuleDownload<'a> {
     /// 2. Extract the "archive" if needed (zip, rar, tge,...) -> the mod content is in a temporary location
     /// 3. run `precopy` command if any -> the mod content is modified in-place (temp location)
     /// 4. move content (whole or part, according to `layout`) to the game directory -> the mod content is in the game directory
+    ///    then `normalize` line endings and executable bits if asked
     /// 5. apply `patch` in-place (on mod data in game directory)
     /// 5. apply `replace` in-place (on mod data in game directory)
     async fn get_mod_from_concrete_location(&self, location: &ConcreteLocation,
                                             mod_name: &LwcString, get_options: &GetOptions) -> Result<SetupTimeline> {
         let start = Local::now();
-        let archive = match self.retrieve_location(&location, &mod_name).await {
-            Ok(archive) => archive,
-            Err(error) => bail!("retrieve archive failed for module {}\n-> {:?}", mod_name, error),
+        let (extraction, downloaded) = match self.stream_source(location, mod_name) {
+            Some((http, format)) => {
+                let extraction = self.stream_extract(http, format, location, mod_name)?;
+                (extraction, Some(Local::now()))
+            }
+            None => self.retrieve_and_extract(location, mod_name).await?,
         };
-        let downloaded = Some(Local::now());
+        info!("extracted {} files ({} bytes) for {} in {:?} ({:?})",
+                extraction.entries, extraction.bytes, mod_name, extraction.duration, extraction.method);
 
         let dest = std::env::current_dir()?;
         let dest = CanonPath::new(dest)?;
-        self.extractor.extract_files(&archive, &mod_name , location)?;
+        if let Some(normalize) = &location.normalize {
+            normalize.exec(&dest.join_path(mod_name.as_ref()))?;
+        }
         let copied = Some(Local::now());
 
         // modifications : patch then patches (in order) the replace
@@ -112,22 +190,146 @@ impl <'a> ModuleDownload<'a> {
         replace_module(&dest, &mod_name , &location.replace, get_options)?;
         let replaced = Some(Local::now());
 
-        Ok(SetupTimeline { start, downloaded, copied, patched, replaced, configured: None })
+        Ok(SetupTimeline { start, downloaded, copied, patched, replaced, configured: None, extraction: Some(extraction) })
     }
 
+    /// Obtains the archive (in the cache or in its original location) and extracts it; returns when the
+    /// archive was available.
+    async fn retrieve_and_extract(&self, location: &ConcreteLocation,
+                                    mod_name: &LwcString) -> Result<(ExtractionStats, Option<DateTime<Local>>)> {
+        let archive = match self.retrieve_location(location, mod_name).await {
+            Ok(archive) => archive,
+            Err(error) => bail!("retrieve archive failed for module {}\n-> {:?}", mod_name, error),
+        };
+        let downloaded = Some(Local::now());
+        match self.cache.uncompressed_size(&archive) {
+            Ok(Some(size)) => info!("archive {:?} for {} is {} bytes once extracted", archive, mod_name, size),
+            Ok(None) => {}
+            Err(error) => warn!("Could not compute the uncompressed size of {:?}\n  {}", archive, error),
+        }
+        let extraction = self.extractor.extract_files(&archive, mod_name, location)?;
+        // only after a successful extraction, a failed one leaves the archive for investigation
+        if self.delete_archive_after_extract {
+            match self.cache.remove_archive(&archive) {
+                Ok(true) => info!("archive {:?} removed from the cache", archive),
+                Ok(false) => {}
+                Err(error) => warn!("{}", error),
+            }
+        }
+        Ok((extraction, downloaded))
+    }
+
+    /// With `stream_extract`, the http location and archive format if the download can go directly to the extraction:
+    /// zip or tar.gz archive, not already in the cache, without `password`, and a multi-threaded runtime
+    /// (the extraction blocks while the download runs).
+    fn stream_source<'l>(&self, location: &'l ConcreteLocation, mod_name: &LwcString) -> Option<(&'l Http, StreamFormat)> {
+        if !self.stream_extract {
+            return None;
+        }
+        let http = match &location.source {
+            Source::Http(http) => http,
+            _ => return None,
+        };
+        let cached = self.cache.path_for(&location.source, mod_name, self.cache_shard_depth).ok()?;
+        let format = StreamFormat::from_name(&cached);
+        let multi_thread = tokio::runtime::Handle::try_current()
+            .map(|runtime| runtime.runtime_flavor() == RuntimeFlavor::MultiThread)
+            .unwrap_or(false);
+        if format.is_none() || cached.exists() || location.password.is_some() || !multi_thread {
+            debug!("archive of {} not streamed (format {:?}, cached {}, password {}, multi-thread runtime {})",
+                    mod_name, format, cached.exists(), location.password.is_some(), multi_thread);
+            return None;
+        }
+        Some((http, format?))
+    }
+
+    /// Downloads the archive straight into the extraction, nothing is written to the cache.
+    fn stream_extract(&self, http: &Http, format: StreamFormat, location: &ConcreteLocation,
+                        mod_name: &LwcString) -> Result<ExtractionStats> {
+        info!("streaming {} to the extraction of {} (not cached)", http.http, mod_name);
+        let runtime = tokio::runtime::Handle::current();
+        tokio::task::block_in_place(|| {
+            let source_downloader = http.source_downloader(self.downloader)?;
+            let downloader = source_downloader.as_ref().unwrap_or(self.downloader);
+            let http = runtime.block_on(http.with_token(downloader))?;
+            let request = http.request()?;
+            std::thread::scope(|scope| {
+                let (mut writer, mut reader) = pipe();
+                let (http, request) = (&http, &request);
+                scope.spawn(move || {
+                    if let Err(error) = runtime.block_on(downloader.download_to_writer(&http.http, request, &mut writer, &None)) {
+                        writer.fail(format!("{:?}", error));
+                    }
+                });
+                let result = self.extractor.extract_stream(&mut reader, format, mod_name, location);
+                // stops the download if the extraction ended first
+                drop(reader);
+                result
+            })
+        })
+    }
+
+    /// Where the mod "archive" is: `path`/`local` locations are used where they are (never copied
+    /// to the cache), the other ones are downloaded to the cache (or reused from it).
     pub async fn retrieve_location(&self, loc: &ConcreteLocation, mod_name: &LwcString) -> Result<PathBuf> {
-        let dest = self.cache.join(loc.source.save_subdir()?);
+        match &loc.source {
+            Source::Absolute { path } => return Ok(PathBuf::from(path)),
+            Source::Local { local } => return self.get_local_mod_path(local),
+            Source::Http(_) | Source::Github(_) => {}
+        }
+        let dest = self.cache.archive_dir(&loc.source, mod_name, self.cache_shard_depth)?;
         let save_name = loc.source.save_name(mod_name)?;
+        let mut downloaded = self.download_source(&loc.source, &dest, save_name.clone()).await?;
+        if downloaded.sha256.is_none() {
+            if let Some(reason) = self.cache.check_cached_archive(&downloaded.path, self.cache_validation) {
+                warn!("cached archive {:?} for {} can't be reused ({}), downloading it again", downloaded.path, mod_name, reason);
+                self.cache.remove_archive(&downloaded.path)?;
+                downloaded = self.download_source(&loc.source, &dest, save_name.clone()).await?;
+            }
+        }
+        self.record_download(&downloaded, mod_name);
+        let volumes = loc.source.other_volumes(&save_name)?;
+        let concurrency = loc.download_concurrency.unwrap_or(1);
+        let downloaded_volumes = download_volumes(&volumes, concurrency,
+            |volume, name| self.download_source(volume, &dest, name)).await?;
+        for volume in &downloaded_volumes {
+            self.record_download(volume, mod_name);
+        }
+        Ok(downloaded.path)
+    }
+
+    fn record_download(&self, downloaded: &DownloadedFile, mod_name: &LwcString) {
+        if let Some(sha256) = &downloaded.sha256 {
+            info!("downloaded {:?} for {} (sha256 {})", downloaded.path, mod_name, sha256);
+            // the metadata is only used to check the archive when it is reused
+            if let Err(error) = self.cache.record_download(&downloaded.path, sha256) {
+                warn!("Could not record the download of {:?}\n  {}", downloaded.path, error);
+            }
+        }
+    }
+
+    /// Where the mod "archive" is read from, without retrieving it: the `path`/`local` location,
+    /// or the file in the cache for the other ones.
+    pub fn archive_path(&self, loc: &ConcreteLocation, mod_name: &LwcString) -> Result<PathBuf> {
         match &loc.source {
-            Source::Http(http) => http.download(self.downloader, &dest, save_name).await,
-            Source::Github(github) => github.get_github(&self.downloader, &dest, save_name).await,
             Source::Absolute { path } => Ok(PathBuf::from(path)),
             Source::Local { local } => self.get_local_mod_path(local),
+            Source::Http(_) | Source::Github(_) => self.cache.path_for(&loc.source, mod_name, self.cache_shard_depth),
+        }
+    }
+
+    async fn download_source(&self, source: &Source, dest: &PathBuf, save_name: PathBuf) -> Result<DownloadedFile> {
+        match source {
+            Source::Http(http) => http.download(self.downloader, dest, save_name).await,
+            Source::Github(github) => github.get_github(&self.downloader, dest, save_name,
+                                                                &self.cache.join(GITHUB_API_CACHE_DIR),
+                                                                self.github_rate_limit_max_wait).await,
+            Source::Absolute { .. } | Source::Local { .. } => bail!("Nothing to download for {:?}", source),
         }
     }
 
     fn get_local_mod_path(&self, local_mod_name: &String) -> Result<PathBuf, anyhow::Error> {
-        let manifest_path = self.opts.get_manifest_root(self.game_dir);
+        let manifest_path = self.opts.get_manifest_root(self.game_dir)?;
         let local_mods = match &self.global.local_mods {
             None => PathBuf::new(),
             Some(path) => PathBuf::from(path).clean(),
@@ -139,10 +341,46 @@ impl <'a> ModuleDownload<'a> {
         if mod_name.is_absolute() || local_mods.starts_with("..") {
             bail!("Invalid local value");
         }
-        Ok(manifest_path.join(local_mods)?.join(mod_name)?.to_path_buf())
+        let local_mods = manifest_path.join(local_mods)?;
+        let direct_path = local_mods.join(&mod_name)?.to_path_buf();
+        let is_name_only = mod_name.components().count() == 1;
+        if direct_path.exists() || !is_name_only || !self.global.local_mods_index.unwrap_or(false) {
+            return Ok(direct_path);
+        }
+        let index = match self.local_mods_index.get() {
+            Some(index) => index,
+            None => {
+                let index = LocalModsIndex::build(local_mods.path())?;
+                self.local_mods_index.get_or_init(|| index)
+            }
+        };
+        match index.find(&LwcString::new(local_mod_name))? {
+            Some(path) => Ok(path.to_owned()),
+            None => bail!("Local mod {} was not found in {:?} (nor in its sub-directories)", local_mod_name, local_mods),
+        }
     }
 }
 
+/// Subdirectory of the archive cache where github API responses are kept.
+pub const GITHUB_API_CACHE_DIR: &str = "github-api";
+
+/// Downloads the other volumes of a split archive with `download`, at most `concurrency` at once.
+async fn download_volumes<'v, F, Fut>(volumes: &'v [(Source, PathBuf)], concurrency: usize,
+                                        download: F) -> Result<Vec<DownloadedFile>>
+        where F: Fn(&'v Source, PathBuf) -> Fut, Fut: Future<Output = Result<DownloadedFile>> {
+    if !volumes.is_empty() {
+        debug!("downloading {} more volumes, {} at once", volumes.len(), concurrency);
+    }
+    let downloads = volumes.iter().map(|(volume, name)| download(volume, name.clone()));
+    let mut downloaded = vec![];
+    for ((_, name), result) in volumes.iter().zip(run_bounded(concurrency, downloads).await) {
+        match result {
+            Err(error) => bail!("Could not download volume {:?} of split archive\n -> {:?}", name, error),
+            Ok(file) => downloaded.push(file),
+        }
+    }
+    Ok(downloaded)
+}
 
 fn replace_module(game_dir: &CanonPath, module_name: &LwcString, replace: &Option<Vec<ReplaceSpec>>, get_options: &GetOptions) -> Result<()> {
     if let Some(specs) = replace {
@@ -157,26 +395,75 @@ fn replace_module(game_dir: &CanonPath, module_name: &LwcString, replace: &Optio
 #[cfg(test)]
 mod test_retrieve_location {
 
-    use std::collections::HashMap;
     use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::test_utils::test_dir;
+
+    use sha2::{Digest, Sha256};
 
     use crate::global::Global;
-    use crate::download::Downloader;
+    use crate::download::{Downloader, DownloadedFile};
     use crate::args::Install;
     use crate::lowercase::lwc;
     use crate::module::global_locations::GlobalLocations;
+    use crate::module::location::github::{Github, GithubDescriptor};
     use crate::module::location::http::Http;
     use crate::module::location::location::{ConcreteLocation, Location};
     use crate::module::location::source::Source;
     use crate::module::weidu_mod::WeiduMod;
     use crate:: config::Config;
     use crate::canon_path::CanonPath;
-    use crate::cache::Cache;
+    use crate::cache::{Cache, CacheValidation};
     use crate::obtain::get_module::ModuleDownload;
+    use crate::obtain::get_options::{GetOptions, StrictReplaceAction};
+    use crate::archive_extractor::ExtractionMethod;
+    use crate::archive_layout::Layout;
+    use crate::test_utils::server::{ok, TestServer};
 
     use anyhow::bail;
     use faux::when;
 
+    /// Everything a `ModuleDownload` borrows, besides the downloader.
+    struct DownloadSetup {
+        config: Config,
+        global: Global,
+        global_locations: GlobalLocations,
+        opts: Install,
+        game_dir: CanonPath,
+        cache: Cache,
+    }
+
+    impl Default for DownloadSetup {
+        fn default() -> Self {
+            Self {
+                config: Config::default(),
+                global: Global::default(),
+                global_locations: GlobalLocations::default(),
+                opts: Install::default(),
+                game_dir: CanonPath::new("some_dir").unwrap(),
+                cache: Cache::Path(PathBuf::from("/cache_path")),
+            }
+        }
+    }
+
+    impl DownloadSetup {
+        /// The game directory is `dir`, the cache is in `dir/cache`.
+        fn in_dir(dir: &std::path::Path) -> Self {
+            Self {
+                game_dir: CanonPath::new(dir).unwrap(),
+                cache: Cache::Path(dir.join("cache")),
+                ..Self::default()
+            }
+        }
+
+        fn module_download<'a>(&'a self, downloader: &'a Downloader) -> ModuleDownload<'a> {
+            ModuleDownload::new(&self.config, &self.global, &self.global_locations, &self.opts,
+                                downloader, &self.game_dir, &self.cache)
+        }
+    }
+
     /**
      * Check http location.
      * Should be <cache_path>/http/<host_name>/<file_name>
@@ -194,36 +481,32 @@ mod test_retrieve_location {
             location: Some(Location::Concrete { concrete: location.clone() }),
             ..WeiduMod::default()
         };
-        let global = Global::default();
-        let global_locations = GlobalLocations::default();
-        let opts = Install::default();
-        let config = Config {
-            archive_cache: Some("/cache_path".to_string()),
-            extract_location: Some("/tmp".to_string()),
-            weidu_path: None,
-            ignore_current_dir_weidu: None,
-            extractors: HashMap::new(),
-            code_editor: None,
+        let setup = DownloadSetup {
+            config: Config {
+                archive_cache: Some("/cache_path".to_string()),
+                extract_location: Some("/tmp".to_string()),
+                ..Config::default()
+            },
+            ..DownloadSetup::default()
         };
 
         let expected_dest = PathBuf::from("/cache_path/http/example.com");
 
-        let game_dir = CanonPath::new("some_dir").unwrap();
-        let cache = Cache::Path(PathBuf::from("/cache_path"));
-
         let mut downloader = Downloader::faux();
         when!(
             downloader.download(_, {expected_dest}, _, _, _)
-        ).then(|(_, _, _, _, _)| Ok(PathBuf::from("/cache_path/http/example.com/some_mod.zip")));
+        ).then(|(_, _, _, _, _)| Ok(DownloadedFile {
+            path: PathBuf::from("/cache_path/http/example.com/some_mod.zip"),
+            sha256: Some("0123abcd".to_string()),
+        }));
         when!(
-            downloader.download_partial(_, _, _, _)
-        ).then(|(_, _, _, _)| bail!("Should not be called"));
+            downloader.download_partial(_, _, _, _, _)
+        ).then(|(_, _, _, _, _)| bail!("Should not be called"));
         when!(
             downloader.rename_partial(_, _)
         ).then(|(_, _)| bail!("Should not be called"));
 
-        let module_download: ModuleDownload<'_> = ModuleDownload::new(&config, &global, &global_locations, &opts,
-                                                                            &downloader, &game_dir, &cache);
+        let module_download = setup.module_download(&downloader);
 
         let result = module_download.retrieve_location(&location, &module.name);
         assert_eq!(
@@ -246,24 +529,21 @@ mod test_retrieve_location {
             location: Some(Location::Concrete { concrete: location.clone() }),
             ..WeiduMod::default()
         };
-        let global = Global {
-            local_mods: Some("my_mods".to_string()),
-            ..Default::default()
-        };
-        let global_locations = GlobalLocations::default();
-        let opts = Install {
-            manifest_path: "/home/me/my_install.yaml".to_string(),
-            ..Install::default()
+        let setup = DownloadSetup {
+            global: Global {
+                local_mods: Some("my_mods".to_string()),
+                ..Default::default()
+            },
+            opts: Install {
+                manifest_path: "/home/me/my_install.yaml".to_string(),
+                ..Install::default()
+            },
+            ..DownloadSetup::default()
         };
-        let config = Config::default();
-
-        let game_dir = CanonPath::new("some_dir").unwrap();
-        let cache = Cache::Path(PathBuf::from("/cache_path"));
 
         let downloader = Downloader::faux();
 
-        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts,
-                                                                            &downloader, &game_dir, &cache);
+        let module_download = setup.module_download(&downloader);
 
         let result = module_download.retrieve_location(&location, &module.name,);
         assert_eq!(
@@ -286,24 +566,21 @@ mod test_retrieve_location {
             location: Some(Location::Concrete { concrete: location.clone() }),
             ..WeiduMod::default()
         };
-        let global = Global {
-            local_mods: Some("my_mods".to_string()),
-            ..Default::default()
-        };
-        let global_locations = GlobalLocations::default();
-        let opts = Install {
-            manifest_path: "/home/me/my_install.yaml".to_string(),
-            ..Install::default()
+        let setup = DownloadSetup {
+            global: Global {
+                local_mods: Some("my_mods".to_string()),
+                ..Default::default()
+            },
+            opts: Install {
+                manifest_path: "/home/me/my_install.yaml".to_string(),
+                ..Install::default()
+            },
+            ..DownloadSetup::default()
         };
-        let config = Config::default();
-
-        let game_dir = CanonPath::new("some_dir").unwrap();
-        let cache = Cache::Path(PathBuf::from("/cache_path"));
 
         let downloader = Downloader::faux();
 
-        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts,
-                                                                            &downloader, &game_dir, &cache);
+        let module_download = setup.module_download(&downloader);
 
         let result = module_download.retrieve_location(&location, &module.name);
         assert_eq!(
@@ -319,7 +596,6 @@ mod test_retrieve_location {
             location: Some(Location::Ref { r#ref: lwc!("my_key") }),
             ..WeiduMod::default()
         };
-        let global = Global::default();
         let my_location = ConcreteLocation {
             source: Source::Http(Http {
                 http: "http://example.com/some_mod.zip".to_string(),
@@ -337,24 +613,19 @@ mod test_retrieve_location {
         let global_locations = GlobalLocations::default()
                 .put(&lwc!("my_key"), my_location.clone())
                 .put(&lwc!("my_mod"), other_location.clone());
-        let opts = Install::default();
-        let config = Config {
-            archive_cache: Some("/cache_path".to_string()),
-            extract_location: Some("/tmp".to_string()),
-            weidu_path: None,
-            ignore_current_dir_weidu: None,
-            extractors: HashMap::new(),
-            code_editor: None,
+        let setup = DownloadSetup {
+            global_locations,
+            config: Config {
+                archive_cache: Some("/cache_path".to_string()),
+                extract_location: Some("/tmp".to_string()),
+                ..Config::default()
+            },
+            ..DownloadSetup::default()
         };
 
-
-        let game_dir = CanonPath::new("some_dir").unwrap();
-        let cache = Cache::Path(PathBuf::from("/cache_path"));
-
         let downloader = Downloader::faux();
 
-        let module_download: ModuleDownload<'_> = ModuleDownload::new(&config, &global, &global_locations, &opts,
-                                                                            &downloader, &game_dir, &cache);
+        let module_download = setup.module_download(&downloader);
 
         let result = module_download.get_module_location(&module);
         assert_eq!(
@@ -369,7 +640,6 @@ mod test_retrieve_location {
             name: lwc!("my_mod"),
             ..WeiduMod::default()
         };
-        let global = Global::default();
         let my_location = ConcreteLocation {
             source: Source::Http(Http {
                 http: "http://example.com/some_mod.zip".to_string(),
@@ -387,24 +657,19 @@ mod test_retrieve_location {
         let global_locations = GlobalLocations::default()
                 .put(&lwc!("my_key"), my_location.clone())
                 .put(&lwc!("my_mod"), other_location.clone());
-        let opts = Install::default();
-        let config = Config {
-            archive_cache: Some("/cache_path".to_string()),
-            extract_location: Some("/tmp".to_string()),
-            weidu_path: None,
-            ignore_current_dir_weidu: None,
-            extractors: HashMap::new(),
-            code_editor: None,
+        let setup = DownloadSetup {
+            global_locations,
+            config: Config {
+                archive_cache: Some("/cache_path".to_string()),
+                extract_location: Some("/tmp".to_string()),
+                ..Config::default()
+            },
+            ..DownloadSetup::default()
         };
 
-
-        let game_dir = CanonPath::new("some_dir").unwrap();
-        let cache = Cache::Path(PathBuf::from("/cache_path"));
-
         let downloader = Downloader::faux();
 
-        let module_download: ModuleDownload<'_> = ModuleDownload::new(&config, &global, &global_locations, &opts,
-                                                                            &downloader, &game_dir, &cache);
+        let module_download = setup.module_download(&downloader);
 
         let result = module_download.get_module_location(&module);
         assert_eq!(
@@ -412,4 +677,394 @@ mod test_retrieve_location {
             &other_location
         )
     }
+
+    async fn retrieve_and_extract_local(local: &str) -> (super::SetupTimeline, tempfile::TempDir) {
+        extract_without_download(Source::Local { local: local.to_string() }, Config::default()).await
+    }
+
+    /// Installs a `local`/`path` mod, which must be read where it is: nothing is written to the cache.
+    async fn extract_without_download(source: Source, config: Config) -> (super::SetupTimeline, tempfile::TempDir) {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let temp = test_dir();
+        let game_dir = CanonPath::new(temp.path().join("game")).unwrap();
+        std::fs::create_dir_all(game_dir.path()).unwrap();
+
+        let location = ConcreteLocation {
+            source,
+            layout: Some(Layout::with_tp2_default_and_strip(1)),
+            extract_location: Some(temp.path().join("extract").to_string_lossy().to_string()),
+            ..ConcreteLocation::default()
+        };
+        let setup = DownloadSetup {
+            config,
+            global: Global {
+                local_mods: Some("local_mods".to_string()),
+                ..Default::default()
+            },
+            opts: Install {
+                manifest_path: project.join("resources/test/manifest.yml").to_string_lossy().to_string(),
+                ..Install::default()
+            },
+            game_dir: game_dir.clone(),
+            ..DownloadSetup::in_dir(temp.path())
+        };
+        let cache = &setup.cache;
+        std::fs::create_dir_all(cache.join("")).unwrap();
+        // no expectation set: any download call panics
+        let downloader = Downloader::faux();
+        let module_download = setup.module_download(&downloader);
+        let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
+
+        let module = WeiduMod {
+            name: lwc!("my_mod"),
+            location: Some(Location::Concrete { concrete: location }),
+            ..WeiduMod::default()
+        };
+
+        // awaited in the test runtime, `get_module` would panic here
+        let timeline = module_download.get_module_async(&module, &get_options).await.unwrap();
+        assert!(game_dir.join_path("my_mod/my_mod.tp2").is_file());
+        assert!(game_dir.join_path("my_mod/tra/english.tra").is_file());
+        // not selected by the layout
+        assert!(!game_dir.join_path("readme.txt").exists());
+        assert!(!game_dir.join_path("my_mod-1.0").exists());
+        assert_eq!(std::fs::read_dir(cache.join("")).unwrap().count(), 0);
+        (timeline, temp)
+    }
+
+    fn retrieve_local_with_index(manifest_dir: &std::path::Path, local: &str) -> anyhow::Result<PathBuf> {
+        let location = ConcreteLocation {
+            source: Source::Local { local: local.to_string() },
+            ..ConcreteLocation::default()
+        };
+        let setup = DownloadSetup {
+            global: Global {
+                local_mods: Some("local_mods".to_string()),
+                local_mods_index: Some(true),
+                ..Default::default()
+            },
+            opts: Install {
+                manifest_path: manifest_dir.join("manifest.yml").to_string_lossy().to_string(),
+                ..Install::default()
+            },
+            ..DownloadSetup::default()
+        };
+        let downloader = Downloader::faux();
+        let module_download = setup.module_download(&downloader);
+        tokio::runtime::Runtime::new().unwrap().block_on(module_download.retrieve_location(&location, &lwc!("my_mod")))
+    }
+
+    #[test]
+    fn local_mod_found_by_name_in_index() {
+        let temp = test_dir();
+        let archive = temp.path().join("local_mods").join("npcs").join("some_npc.zip");
+        std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        std::fs::write(&archive, "").unwrap();
+
+        assert_eq!(retrieve_local_with_index(temp.path(), "Some_NPC").unwrap(), archive);
+        // a full path is used as it is
+        assert_eq!(retrieve_local_with_index(temp.path(), "npcs/other.zip").unwrap(),
+                    temp.path().join("local_mods").join("npcs").join("other.zip"));
+        assert!(retrieve_local_with_index(temp.path(), "unknown").unwrap_err().to_string().contains("not found"));
+    }
+
+    #[test]
+    fn local_mod_name_in_index_is_ambiguous() {
+        // `local_mods/my_mod.zip` and `local_mods/unpacked/my_mod-1.0/my_mod/my_mod.tp2`
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let error = retrieve_local_with_index(&project.join("resources/test"), "my_mod").unwrap_err();
+        assert!(error.to_string().contains("ambiguous"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn local_archive_is_extracted() {
+        let (timeline, _temp) = retrieve_and_extract_local("my_mod.zip").await;
+        assert_eq!(timeline.extraction.unwrap().method, ExtractionMethod::Zip);
+    }
+
+    #[tokio::test]
+    async fn absolute_archive_is_read_in_place() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let archive = project.join("resources/test/local_mods/my_mod.zip");
+        let source = Source::Absolute { path: archive.to_string_lossy().to_string() };
+        let config = Config { delete_archive_after_extract: Some(true), ..Config::default() };
+
+        let (timeline, _temp) = extract_without_download(source, config).await;
+
+        assert_eq!(timeline.extraction.unwrap().method, ExtractionMethod::Zip);
+        assert!(archive.is_file());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_module_async_in_existing_runtime() {
+        let (timeline, _temp) = retrieve_and_extract_local("my_mod.zip").await;
+        assert!(timeline.downloaded.is_some());
+        assert!(timeline.replaced.is_some());
+    }
+
+    /// Extracts an archive "downloaded" (actually copied) to the cache, with `delete_archive_after_extract`.
+    async fn download_and_extract(archive_content: &[u8]) -> (anyhow::Result<super::SetupTimeline>, PathBuf, tempfile::TempDir) {
+        let temp = test_dir();
+        let setup = DownloadSetup {
+            config: Config { delete_archive_after_extract: Some(true), ..Config::default() },
+            game_dir: CanonPath::new(temp.path().join("game")).unwrap(),
+            ..DownloadSetup::in_dir(temp.path())
+        };
+        std::fs::create_dir_all(setup.game_dir.path()).unwrap();
+        let archive = setup.cache.join("http").join("example.com").join("my_mod.zip");
+        std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        std::fs::write(&archive, archive_content).unwrap();
+
+        let location = ConcreteLocation {
+            source: Source::Http(Http::from("https://example.com/my_mod.zip")),
+            layout: Some(Layout::with_tp2_default_and_strip(1)),
+            extract_location: Some(temp.path().join("extract").to_string_lossy().to_string()),
+            ..ConcreteLocation::default()
+        };
+        let mut downloader = Downloader::faux();
+        let downloaded = archive.clone();
+        when!(downloader.download(_, _, _, _, _)).then(move |_| Ok(DownloadedFile {
+            path: downloaded.clone(),
+            sha256: None,
+        }));
+        let module_download = setup.module_download(&downloader);
+        let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
+        let module = WeiduMod {
+            name: lwc!("my_mod"),
+            location: Some(Location::Concrete { concrete: location }),
+            ..WeiduMod::default()
+        };
+
+        let result = module_download.get_module_async(&module, &get_options).await;
+        (result, archive, temp)
+    }
+
+    #[tokio::test]
+    async fn archive_deleted_after_extraction() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let content = std::fs::read(project.join("resources/test/local_mods/my_mod.zip")).unwrap();
+
+        let (result, archive, temp) = download_and_extract(&content).await;
+
+        result.unwrap();
+        assert!(temp.path().join("game/my_mod/my_mod.tp2").is_file());
+        assert!(!archive.exists());
+    }
+
+    #[tokio::test]
+    async fn archive_kept_when_extraction_fails() {
+        let (result, archive, _temp) = download_and_extract(b"not a zip").await;
+
+        assert!(result.is_err());
+        assert!(archive.exists());
+    }
+
+    /// Retrieves an archive already in the cache (with `content`) whose download recorded the
+    /// checksum of `downloaded_content`; the mock download writes `downloaded_content`.
+    async fn retrieve_cached(content: &'static [u8], downloaded_content: &'static [u8],
+                                validation: CacheValidation) -> (PathBuf, usize, tempfile::TempDir) {
+        let temp = test_dir();
+        let setup = DownloadSetup {
+            config: Config { cache_validation: Some(validation), ..Config::default() },
+            ..DownloadSetup::in_dir(temp.path())
+        };
+        let archive = setup.cache.join("http").join("example.com").join("my_mod.zip");
+        std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        std::fs::write(&archive, downloaded_content).unwrap();
+        setup.cache.record_download(&archive, &format!("{:x}", Sha256::digest(downloaded_content))).unwrap();
+        std::fs::write(&archive, content).unwrap();
+
+        let location = ConcreteLocation {
+            source: Source::Http(Http::from("https://example.com/my_mod.zip")),
+            ..ConcreteLocation::default()
+        };
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut downloader = Downloader::faux();
+        let (downloaded, download_calls) = (archive.clone(), calls.clone());
+        when!(downloader.download(_, _, _, _, _)).then(move |_| {
+            // like the real downloader: reuses the file if it is there
+            download_calls.fetch_add(1, Ordering::SeqCst);
+            if downloaded.exists() {
+                return Ok(DownloadedFile { path: downloaded.clone(), sha256: None });
+            }
+            std::fs::write(&downloaded, downloaded_content).unwrap();
+            Ok(DownloadedFile { path: downloaded.clone(), sha256: Some(format!("{:x}", Sha256::digest(downloaded_content))) })
+        });
+        let module_download = setup.module_download(&downloader);
+
+        let result = module_download.retrieve_location(&location, &lwc!("my_mod")).await.unwrap();
+
+        assert_eq!(result, archive);
+        (archive, calls.load(Ordering::SeqCst), temp)
+    }
+
+    #[tokio::test]
+    async fn cached_archive_with_bad_checksum_is_downloaded_again() {
+        let (archive, calls, _temp) = retrieve_cached(b"corrupted", b"the content", CacheValidation::Checksum).await;
+
+        assert_eq!(calls, 2);
+        assert_eq!(std::fs::read(&archive).unwrap(), b"the content");
+    }
+
+    #[tokio::test]
+    async fn cached_archive_with_good_checksum_is_reused() {
+        let (archive, calls, _temp) = retrieve_cached(b"the content", b"the content", CacheValidation::Checksum).await;
+
+        assert_eq!(calls, 1);
+        assert_eq!(std::fs::read(&archive).unwrap(), b"the content");
+    }
+
+    #[tokio::test]
+    async fn cached_archive_checked_by_size_or_name() {
+        // same size, different content: only the checksum sees it
+        let (_, calls, _temp) = retrieve_cached(b"THE CONTENT", b"the content", CacheValidation::Size).await;
+        assert_eq!(calls, 1);
+        let (_, calls, _temp) = retrieve_cached(b"truncated", b"the content", CacheValidation::Size).await;
+        assert_eq!(calls, 2);
+        let (_, calls, _temp) = retrieve_cached(b"truncated", b"the content", CacheValidation::Name).await;
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn local_directory_is_copied() {
+        let (timeline, _temp) = retrieve_and_extract_local("unpacked").await;
+        assert_eq!(timeline.extraction.unwrap().method, ExtractionMethod::DirectoryCopy);
+    }
+
+    fn split_archive_location(volumes: u32) -> ConcreteLocation {
+        ConcreteLocation {
+            source: Source::Http(Http { volumes: Some(volumes), ..Http::from("http://example.com/my_mod.7z.001") }),
+            ..ConcreteLocation::default()
+        }
+    }
+
+    /// Faux downloader that records the url and the file name of each download.
+    fn recording_downloader() -> (Downloader, Arc<Mutex<Vec<(String, PathBuf)>>>) {
+        let requested = Arc::new(Mutex::new(vec![]));
+        let requested_copy = requested.clone();
+        let mut downloader = Downloader::faux();
+        when!(downloader.download(_, _, _, _, _)).then(move |(url, dest, name, _, _)| {
+            requested_copy.lock().unwrap().push((url.to_string(), name.clone()));
+            Ok(DownloadedFile { path: dest.join(name), sha256: Some("0123abcd".to_string()) })
+        });
+        (downloader, requested)
+    }
+
+    #[tokio::test]
+    async fn split_archive_volumes_are_downloaded() {
+        let location = split_archive_location(3);
+        let setup = DownloadSetup::default();
+        let (downloader, requested) = recording_downloader();
+        let module_download = setup.module_download(&downloader);
+
+        let result = module_download.retrieve_location(&location, &lwc!("my_mod")).await.unwrap();
+
+        assert_eq!(result, PathBuf::from("/cache_path/http/example.com/my_mod.7z.001"));
+        assert_eq!(*requested.lock().unwrap(), vec![
+            ("http://example.com/my_mod.7z.001".to_string(), PathBuf::from("my_mod.7z.001")),
+            ("http://example.com/my_mod.7z.002".to_string(), PathBuf::from("my_mod.7z.002")),
+            ("http://example.com/my_mod.7z.003".to_string(), PathBuf::from("my_mod.7z.003")),
+        ]);
+    }
+
+    #[test]
+    fn prefetch_downloads_each_archive_once() {
+        let module = |name: &str, source: Source| WeiduMod {
+            name: lwc!(name),
+            location: Some(Location::Concrete { concrete: ConcreteLocation { source, ..ConcreteLocation::default() } }),
+            ..WeiduMod::default()
+        };
+        let modules = vec![
+            module("my_mod", Source::Http(Http::from("http://example.com/my_mod.zip"))),
+            module("my_mod", Source::Http(Http::from("http://example.com/my_mod.zip"))),
+            module("other_mod", Source::Http(Http::from("http://example.com/other_mod.zip"))),
+            module("local_mod", Source::Local { local: "local_mod.zip".to_string() }),
+        ];
+        let setup = DownloadSetup::default();
+        let (downloader, requested) = recording_downloader();
+        let module_download = setup.module_download(&downloader);
+
+        module_download.prefetch(&modules.iter().collect::<Vec<_>>(), 2);
+
+        assert_eq!(*requested.lock().unwrap(), vec![
+            ("http://example.com/my_mod.zip".to_string(), PathBuf::from("my_mod.zip")),
+            ("http://example.com/other_mod.zip".to_string(), PathBuf::from("other_mod.zip")),
+        ]);
+    }
+
+    /// Most volume downloads in flight at once when the split archive is retrieved from a server
+    /// that takes a while to answer each request.
+    async fn max_volumes_in_flight(volumes: u32, download_concurrency: Option<usize>) -> usize {
+        let server = TestServer::start_with_delay(Duration::from_millis(100), |_| ok(b"volume"));
+        let location = ConcreteLocation {
+            source: Source::Http(Http { volumes: Some(volumes), ..Http::from(&server.url("/my_mod.7z.001")) }),
+            download_concurrency,
+            ..ConcreteLocation::default()
+        };
+        let temp = test_dir();
+        let setup = DownloadSetup::in_dir(temp.path());
+        let downloader = Downloader::new();
+        let module_download = setup.module_download(&downloader);
+
+        let archive = module_download.retrieve_location(&location, &lwc!("my_mod")).await.unwrap();
+
+        assert_eq!(archive.file_name().unwrap(), "my_mod.7z.001");
+        assert_eq!(server.requests().len(), volumes as usize);
+        for number in 2..=volumes {
+            assert_eq!(std::fs::read(archive.with_file_name(format!("my_mod.7z.{:03}", number))).unwrap(), b"volume");
+        }
+        server.max_in_flight()
+    }
+
+    #[tokio::test]
+    async fn volume_downloads_are_capped() {
+        assert_eq!(max_volumes_in_flight(6, None).await, 1);
+        assert_eq!(max_volumes_in_flight(6, Some(2)).await, 2);
+        assert_eq!(max_volumes_in_flight(3, Some(8)).await, 2);
+    }
+
+    #[tokio::test]
+    async fn github_release_volumes_are_downloaded() {
+        let asset = |number: u32| format!(r#"{{
+            "url": "https://api.github.com/repos/user/repo/releases/assets/{number}",
+            "browser_download_url": "https://github.com/user/repo/releases/download/v1/my_mod.7z.00{number}",
+            "id": {number}, "name": "my_mod.7z.00{number}", "label": null,
+            "content_type": "application/octet-stream", "size": 1234
+        }}"#);
+        let release = format!(r#"{{
+            "url": "https://api.github.com/repos/user/repo/releases/1",
+            "html_url": "https://github.com/user/repo/releases/tag/v1",
+            "assets_url": "https://api.github.com/repos/user/repo/releases/1/assets",
+            "tarball_url": "https://api.github.com/repos/user/repo/tarball/v1",
+            "zipball_url": "https://api.github.com/repos/user/repo/zipball/v1",
+            "id": 1, "tag_name": "v1", "body": "", "name": "v1",
+            "assets": [{}, {}, {}]
+        }}"#, asset(1), asset(2), asset(3));
+        let api = TestServer::start(move |_| ok(release.as_bytes()));
+        let location = ConcreteLocation {
+            source: Source::Github(Github {
+                github_user: "user".to_string(),
+                repository: "repo".to_string(),
+                descriptor: GithubDescriptor::Release { release: Some("v1".to_string()), asset: "my_mod.7z.001".to_string() },
+                base_url: Some(api.address().to_string()),
+                volumes: Some(3),
+                ..Github::default()
+            }),
+            download_concurrency: Some(2),
+            ..ConcreteLocation::default()
+        };
+        let temp = test_dir();
+        let setup = DownloadSetup::in_dir(temp.path());
+        let (downloader, requested) = recording_downloader();
+        let module_download = setup.module_download(&downloader);
+
+        let result = module_download.retrieve_location(&location, &lwc!("my_mod")).await.unwrap();
+
+        assert_eq!(result.file_name().unwrap(), "my_mod.7z.001");
+        assert_eq!(*requested.lock().unwrap(), vec![
+            ("https://api.github.com/repos/user/repo/releases/assets/1".to_string(), PathBuf::from("my_mod.7z.001")),
+            ("https://api.github.com/repos/user/repo/releases/assets/2".to_string(), PathBuf::from("my_mod.7z.002")),
+            ("https://api.github.com/repos/user/repo/releases/assets/3".to_string(), PathBuf::from("my_mod.7z.003")),
+        ]);
+    }
 }
@@ -0,0 +1,141 @@
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::debug;
+
+use crate::lowercase::LwcString;
+
+const ARCHIVE_EXTENSIONS: [&str; 7] = [".zip", ".iemod", ".tgz", ".tar.gz", ".7z", ".rar", ".gz"];
+
+/// Mod names found under `local_mods` (recursively) with their location, used to resolve
+/// `local: <mod name>` when the mod is not directly in `local_mods`.
+/// - an archive is found by its name without extension (`cat/my_mod.zip` -> `my_mod`)
+/// - an unpacked mod by its tp2: `cat/my_mod/my_mod.tp2` and `cat/setup-my_mod.tp2` both give `my_mod` -> `cat`
+///   (the directory that contains the mod directory, as expected by the default layout)
+#[derive(Debug, Default, PartialEq)]
+pub struct LocalModsIndex {
+    entries: HashMap<LwcString, Vec<PathBuf>>,
+}
+
+impl LocalModsIndex {
+    pub fn build(local_mods: &Path) -> Result<Self> {
+        let mut index = LocalModsIndex::default();
+        index.scan(local_mods)?;
+        debug!("local mods index for {:?}: {:?}", local_mods, index.entries);
+        Ok(index)
+    }
+
+    /// The location of the mod, `None` if it is unknown; fails if the name was found at several locations.
+    pub fn find(&self, mod_name: &LwcString) -> Result<Option<&PathBuf>> {
+        match self.entries.get(mod_name).map(|paths| paths.as_slice()) {
+            None | Some([]) => Ok(None),
+            Some([path]) => Ok(Some(path)),
+            Some(paths) => bail!("Local mod {} is ambiguous, it was found at several places in `local_mods`:\n  - {}\n \
+                                    -> use the path (relative to `local_mods`) instead of the name",
+                                    mod_name, paths.iter().map(|path| format!("{:?}", path)).join("\n  - ")),
+        }
+    }
+
+    fn scan(&mut self, dir: &Path) -> Result<()> {
+        let entries = match std::fs::read_dir(dir) {
+            Err(error) => bail!("Could not read local mods directory {:?}\n -> {:?}", dir, error),
+            Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()).sorted().collect::<Vec<_>>(),
+        };
+        for path in entries {
+            let file_name = match path.file_name() {
+                None => continue,
+                Some(name) => name.to_string_lossy().to_lowercase(),
+            };
+            if path.is_dir() {
+                // a mod directory (with its tp2 inside): the mod is what contains it, no need to look deeper
+                if has_tp2(&path, &file_name) {
+                    self.add(&file_name, dir);
+                } else {
+                    self.scan(&path)?;
+                }
+            } else if let Some(stem) = file_name.strip_suffix(".tp2") {
+                self.add(stem.strip_prefix("setup-").unwrap_or(stem), dir);
+            } else if let Some(stem) = ARCHIVE_EXTENSIONS.iter().find_map(|ext| file_name.strip_suffix(ext)) {
+                self.add(stem, &path);
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, name: &str, path: &Path) {
+        let paths = self.entries.entry(LwcString::new(name)).or_default();
+        if !paths.iter().any(|known| known == path) {
+            paths.push(path.to_path_buf());
+        }
+    }
+}
+
+/// Whether `dir` contains `<name>.tp2` or `setup-<name>.tp2` (case-insensitive, `name` is lowercase).
+fn has_tp2(dir: &Path, name: &str) -> bool {
+    let tp2_names = [format!("{}.tp2", name), format!("setup-{}.tp2", name)];
+    match std::fs::read_dir(dir) {
+        Err(_) => false,
+        Ok(entries) => entries.filter_map(Result::ok)
+            .any(|entry| tp2_names.contains(&entry.file_name().to_string_lossy().to_lowercase()) && entry.path().is_file()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::lowercase::lwc;
+    use crate::test_utils::test_dir;
+
+    use super::LocalModsIndex;
+
+    fn local_mods_tree() -> tempfile::TempDir {
+        let temp = test_dir();
+        let create = |path: &str| {
+            let path = temp.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        };
+        create("npcs/Ajantis/ajantis/ajantis.tp2");
+        create("npcs/Ajantis/ajantis/tra/english.tra");
+        create("tweaks/setup-cdtweaks.tp2");
+        create("tweaks/cdtweaks/readme.txt");
+        create("items/deep/down/item_pack.zip");
+        create("items/readme.txt");
+        temp
+    }
+
+    #[test]
+    fn index_nested_mods() {
+        let temp = local_mods_tree();
+        let root = temp.path();
+
+        let index = LocalModsIndex::build(root).unwrap();
+
+        assert_eq!(index.find(&lwc!("ajantis")).unwrap(), Some(&root.join("npcs/Ajantis")));
+        assert_eq!(index.find(&lwc!("CDTWEAKS")).unwrap(), Some(&root.join("tweaks")));
+        assert_eq!(index.find(&lwc!("item_pack")).unwrap(), Some(&root.join("items/deep/down/item_pack.zip")));
+        assert_eq!(index.find(&lwc!("readme")).unwrap(), None);
+        // inside a mod directory
+        assert_eq!(index.find(&lwc!("english")).unwrap(), None);
+    }
+
+    #[test]
+    fn ambiguous_mod_name() {
+        let temp = local_mods_tree();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("old")).unwrap();
+        std::fs::write(root.join("old").join("Ajantis.zip"), "").unwrap();
+
+        let index = LocalModsIndex::build(root).unwrap();
+
+        let message = index.find(&lwc!("ajantis")).unwrap_err().to_string();
+        assert!(message.contains("ambiguous"), "{}", message);
+        assert!(message.contains(&format!("{:?}", root.join("npcs/Ajantis"))), "{}", message);
+        assert!(message.contains(&format!("{:?}", root.join("old/Ajantis.zip"))), "{}", message);
+        // the other mods are still found
+        assert!(index.find(&lwc!("cdtweaks")).unwrap().is_some());
+    }
+}
@@ -1,3 +1,5 @@
 
 pub mod get_module;
 pub mod get_options;
+pub mod local_mods_index;
+pub mod stream_pipe;
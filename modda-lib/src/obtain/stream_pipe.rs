@@ -0,0 +1,102 @@
+
+use std::cmp::min;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// Chunks buffered between the download and the extraction (the download waits when they are all in use).
+const PIPE_CHUNKS: usize = 64;
+
+/// Writing side of `pipe`, given to the download.
+pub struct PipeWriter {
+    sender: SyncSender<io::Result<Vec<u8>>>,
+}
+
+/// Reading side of `pipe`, given to the extraction; the stream ends when the writer is dropped.
+pub struct PipeReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+/// Connects a writer and a reader that are used in different threads (the content is not stored).
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, receiver) = sync_channel(PIPE_CHUNKS);
+    (PipeWriter { sender }, PipeReader { receiver, chunk: vec![], position: 0 })
+}
+
+impl PipeWriter {
+    /// Makes the reader fail with `message` instead of seeing a normal end of stream
+    /// (so that a failed download is not read as a complete archive).
+    pub fn fail(self, message: String) {
+        // if the reader is gone, nobody cares
+        let _ = self.sender.send(Err(io::Error::other(message)));
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.sender.send(Ok(buf.to_vec())) {
+            Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "the reader stopped reading")),
+            Ok(()) => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.chunk.len() {
+            match self.receiver.recv() {
+                // writer dropped: end of stream
+                Err(_) => return Ok(0),
+                Ok(Err(error)) => return Err(error),
+                Ok(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+            }
+        }
+        let count = min(buf.len(), self.chunk.len() - self.position);
+        buf[..count].copy_from_slice(&self.chunk[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::pipe;
+
+    #[test]
+    fn content_goes_through_the_pipe() {
+        let (mut writer, mut reader) = pipe();
+        let content = (0..100_000u32).map(|value| value as u8).collect::<Vec<_>>();
+        let expected = content.clone();
+
+        let thread = std::thread::spawn(move || {
+            for chunk in content.chunks(1000) {
+                writer.write_all(chunk).unwrap();
+            }
+        });
+        let mut received = vec![];
+        reader.read_to_end(&mut received).unwrap();
+        thread.join().unwrap();
+
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn failed_writer_fails_the_reader() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"partial").unwrap();
+        writer.fail("connection reset".to_string());
+
+        let error = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert!(error.to_string().contains("connection reset"), "{}", error);
+    }
+}
@@ -11,6 +11,11 @@ pub struct PatchDesc {
     pub patch_source: PatchSource,
     #[serde(default)]
     pub encoding: PatchEncoding,
+    /// Like the `--fuzz` of GNU patch: up to this number of context lines at the start and at the end
+    /// of a hunk may differ from the file, and the hunk is searched around the line given by the patch.<br>
+    /// Default is 0, the hunks must apply exactly where the patch says.
+    #[serde(default)]
+    pub fuzz: u8,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
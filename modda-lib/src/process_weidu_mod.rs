@@ -10,16 +10,18 @@ use anyhow::Result;
 use anyhow::anyhow;
 use anyhow::bail;
 use chrono::Local;
-use log::info;
+use log::{debug, info};
 
 use crate::module::manifest::Manifest;
 use crate::obtain::get_options::GetOptions;
 use crate::timeline::InstallTimeline;
 use crate::timeline::SetupTimeline;
+use crate::module::file_mod::FileModule;
 use crate::module::gen_mod::GeneratedMod;
 use crate::module::module_conf::ModuleContent;
 use crate::module::weidu_mod::WeiduMod;
 use crate::run_weidu::format_install_result;
+use crate::clean_reinstall::clean_module_files;
 use crate::tp2::find_tp2;
 use crate::tp2_template::create_tp2;
 use crate::run_weidu::run_weidu_install;
@@ -36,7 +38,10 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
     let mod_count = manifest.modules.len();
     let ModdaContext { current_dir: current, opts, module_downloader, ..} = modda_context;
 
-    let mut install_timeline = InstallTimeline::new(weidu_mod.name.clone(), Local::now());
+    let mut install_timeline = InstallTimeline::new(weidu_mod.name.clone(), Local::now())
+        .with_description(weidu_mod.description.clone());
+
+    clean_before_reinstall(weidu_mod, modda_context, manifest)?;
 
     let tp2 = match find_tp2(current, &weidu_mod.name) {
         Ok(tp2) => tp2,
@@ -101,6 +106,8 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
         Some(3) => {
             let mut finished = false;
             let (message, color) = if opts.no_stop_on_warn || weidu_mod.ignore_warnings {
+                modda_context.warnings.warn(Some(&weidu_mod.name), "install",
+                                                "weidu finished with warnings (status=3), ignored as requested");
                 ignore_warnings(weidu_mod, real_index, mod_count)
             } else {
                 finished = true;
@@ -147,11 +154,17 @@ pub fn process_generated_mod(gen_mod: &GeneratedMod, modda_context: &ModdaContex
         if let Err(err) = std::fs::create_dir(&data_dir) {
             bail!("Could not create data directory {:?} for generated mod '{}'\n  {}", data_dir, gen_mod.gen_mod, err);
         }
-        if let Err(err) = file_installer.copy_from_origins(&gen_mod.files.iter().collect::<Vec<_>>(),
-                                                                        &data_dir.path().to_path_buf(), gen_mod.allow_overwrite) {
+        let origins = gen_mod.files.iter().collect::<Vec<_>>();
+        // the tp2 copies exactly the files that are staged in `data`
+        let staged_files = match file_installer.staged_files(&origins, data_dir.path()) {
+            Err(err) => bail!("Could not list the files of generated mod {}\n  {}", gen_mod.gen_mod, err),
+            Ok(files) => files,
+        };
+        if let Err(err) = file_installer.copy_from_origins(&gen_mod.gen_mod, &origins,
+                                                                        data_dir.path(), gen_mod.allow_overwrite(&manifest.global)) {
             bail!("Could not copy files to target for generated mod {}\n  {}", gen_mod.gen_mod, err);
         }
-        if let Err(err) = create_tp2(gen_mod, &mod_dir) {
+        if let Err(err) = create_tp2(gen_mod, &staged_files, &mod_dir) {
             bail!("Could not generate tp2 file for {}\n  {}", gen_mod.gen_mod, err);
         }
     } else {
@@ -161,6 +174,44 @@ pub fn process_generated_mod(gen_mod: &GeneratedMod, modda_context: &ModdaContex
     process_weidu_mod(&weidu_mod, modda_context, manifest, real_index)
 }
 
+/// Copies the files of a file module; no weidu mod is involved.
+pub fn process_file_module(file_module: &FileModule, modda_context: &ModdaContext) -> Result<ProcessResult> {
+    let ModdaContext { opts, file_installer, ..} = modda_context;
+    if opts.dry_run {
+        info!("dry run: files of module {} not copied", file_module.file_mod);
+        return Ok(ProcessResult {
+            stop: false,
+            timeline: InstallTimeline::skipped(file_module.file_mod.clone(), "dry run"),
+        });
+    }
+    let mut timeline = InstallTimeline::new(file_module.file_mod.clone(), Local::now())
+        .with_description(file_module.description.clone());
+    match file_installer.copy_file_module(file_module) {
+        Err(err) => bail!("Could not copy files for file module {}\n  {}", file_module.file_mod, err),
+        Ok(Some(reason)) => timeline.skipped = Some(reason),
+        Ok(None) => timeline.copied = Some(Local::now()),
+    }
+    Ok(ProcessResult { stop: false, timeline })
+}
+
+/// Applies `clean_reinstall` (module value, or global value if the module doesn't set it).
+/// Without a location, the module couldn't be fetched again, so it's only an error when the
+/// module itself asks for it.
+fn clean_before_reinstall(weidu_mod: &WeiduMod, modda_context: &ModdaContext, manifest: &Manifest) -> Result<()> {
+    let module_value = weidu_mod.clean_reinstall;
+    if !module_value.or(manifest.global.clean_reinstall).unwrap_or(false) {
+        return Ok(());
+    }
+    if modda_context.module_downloader.get_module_location(weidu_mod).is_err() {
+        if module_value == Some(true) {
+            bail!("Module {} has `clean_reinstall` but no location to fetch it again", weidu_mod.name);
+        }
+        debug!("no location for module {}, clean_reinstall ignored", weidu_mod.name);
+        return Ok(());
+    }
+    clean_module_files(modda_context.current_dir, &weidu_mod.name)?;
+    Ok(())
+}
 
 fn ignore_warnings(module: &WeiduMod, index: usize, total: usize) -> (String, Color) {
     let message = format!("module {modname} (index={idx}/{total}) finished with warning (status=3), ignoring as requested",
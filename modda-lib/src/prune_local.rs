@@ -0,0 +1,250 @@
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::info;
+use path_clean::PathClean;
+
+use crate::lowercase::LwcString;
+use crate::module::file_module_origin::FileModuleOrigin;
+use crate::module::location::location::{ConcreteLocation, Location};
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::obtain::local_mods_index::LocalModsIndex;
+
+/// Lists the files and directories of `local_mods` and `local_files` (relative to `manifest_root`) that are not
+/// used by the manifest, and deletes them unless `dry_run` is set.<br>
+/// This is conservative:
+/// - a directory that contains a used path is kept, only its unused entries are listed
+/// - all `Local` sources of the manifest `locations` count, even if no module uses them
+/// - the `local_patches` directories count as used
+/// - nothing is done for `local_mods`/`local_files` when they are not set (that would be the manifest directory)
+/// - it fails when they are the manifest directory itself (`.`)
+pub fn prune_local_mods(manifest: &Manifest, manifest_root: &Path, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let global = &manifest.global;
+    let local_mods = global.local_mods.as_ref().map(|dir| check_prune_root(dir, "local_mods")).transpose()?;
+    let local_files = global.local_files.as_ref().map(|dir| check_prune_root(dir, "local_files")).transpose()?;
+    let local_mods = local_mods.map(|dir| manifest_root.join(dir));
+    let local_files = local_files.map(|dir| manifest_root.join(dir));
+
+    let mut used = BTreeSet::new();
+    if let Some(patches) = &global.local_patches {
+        used.extend(patches.paths().into_iter().map(|dir| manifest_root.join(dir).clean()));
+    }
+    let mut index = None;
+    for location in local_locations(manifest) {
+        if let Source::Local { local } = &location.source {
+            let local_mods = match &local_mods {
+                None => manifest_root.to_path_buf(),
+                Some(dir) => dir.clone(),
+            };
+            if let Some(path) = local_mod_path(&local_mods, local, global.local_mods_index.unwrap_or(false), &mut index)? {
+                used.insert(path);
+            }
+        }
+    }
+    for module in &manifest.modules {
+        let origins = match module {
+            Module::Generated { gen } => gen.files.iter().collect(),
+            Module::File { file } => vec![&file.from],
+            Module::Mod { .. } => vec![],
+        };
+        for origin in origins {
+            if let FileModuleOrigin::Local { local, .. } = origin {
+                let local_files = local_files.clone().unwrap_or_else(|| manifest_root.to_path_buf());
+                used.insert(local_files.join(check_relative(local, "local")?).clean());
+            }
+        }
+    }
+
+    let mut unused = vec![];
+    for root in local_mods.iter().chain(local_files.iter()).map(|dir| dir.clean()).unique() {
+        if root.is_dir() {
+            unused_entries(&root, &used, &mut unused)?;
+        }
+    }
+    if !dry_run {
+        for path in &unused {
+            info!("removing unused local content {:?}", path);
+            let result = if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) };
+            if let Err(error) = result {
+                bail!("Could not remove unused local content {:?}\n -> {:?}", path, error);
+            }
+        }
+    }
+    Ok(unused)
+}
+
+/// The concrete locations of the modules and of the manifest `locations` (used or not).
+fn local_locations(manifest: &Manifest) -> Vec<&ConcreteLocation> {
+    let module_locations = manifest.modules.iter().filter_map(|module| match module {
+        Module::Mod { weidu_mod } => match &weidu_mod.location {
+            Some(Location::Concrete { concrete }) => Some(concrete),
+            _ => None,
+        },
+        Module::Generated { .. } | Module::File { .. } => None,
+    });
+    module_locations.chain(manifest.locations.entries.values()).collect()
+}
+
+/// Where a `local` source is found, as `ModuleDownload` resolves it; `None` if it doesn't exist.
+pub(crate) fn local_mod_path(local_mods: &Path, local: &str, use_index: bool, index: &mut Option<LocalModsIndex>) -> Result<Option<PathBuf>> {
+    let relative = check_relative(local, "local")?;
+    let direct_path = local_mods.join(&relative).clean();
+    if direct_path.exists() || relative.components().count() != 1 || !use_index {
+        return Ok(Some(direct_path));
+    }
+    let index = match index {
+        Some(index) => index,
+        None => index.insert(LocalModsIndex::build(local_mods)?),
+    };
+    Ok(index.find(&LwcString::new(local))?.map(|path| path.clean()))
+}
+
+pub(crate) fn check_relative(path: &str, property: &str) -> Result<PathBuf> {
+    let clean = PathBuf::from(path).clean();
+    if clean.is_absolute() || clean.starts_with("..") {
+        bail!("Invalid {} value {:?}\n -> must be relative and stay inside the manifest directory", property, path);
+    }
+    Ok(clean)
+}
+
+/// The manifest directory holds the manifest, the patches... that no `local` source names, it can't be pruned.
+fn check_prune_root(path: &str, property: &str) -> Result<PathBuf> {
+    let clean = check_relative(path, property)?;
+    if clean.as_os_str().is_empty() || clean == Path::new(".") {
+        bail!("Can't prune {} {:?}\n -> it is the manifest directory, use a subdirectory for the local content", property, path);
+    }
+    Ok(clean)
+}
+
+/// Adds to `unused` the entries of `dir` that are not used and don't contain anything used.
+fn unused_entries(dir: &Path, used: &BTreeSet<PathBuf>, unused: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Err(error) => bail!("Could not read local directory {:?}\n -> {:?}", dir, error),
+        Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()).sorted().collect::<Vec<_>>(),
+    };
+    for path in entries {
+        if used.contains(&path) {
+            continue;
+        }
+        if used.iter().any(|used_path| used_path.starts_with(&path)) {
+            unused_entries(&path, used, unused)?;
+        } else {
+            unused.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::module::manifest::Manifest;
+    use crate::test_utils::test_dir;
+
+    use super::prune_local_mods;
+
+    const MANIFEST: &str = r#"
+version: "1"
+global:
+  lang_dir: en_US
+  local_mods: mods
+  local_files: files
+  local_mods_index: true
+locations:
+  entries:
+    registry_mod:
+      local: registry_mod.zip
+modules:
+  - name: used
+    components: all
+    location:
+      local: used.zip
+  - name: some_npc
+    components: all
+    location:
+      local: some_npc
+  - name: my_mod
+    components: all
+    location:
+      local: unpacked
+  - gen_mod: tweaks
+    files:
+      - local: tweaks
+        glob: "*.itm"
+"#;
+
+    fn manifest_dir() -> tempfile::TempDir {
+        let temp = test_dir();
+        for file in ["mods/used.zip", "mods/unused.zip", "mods/registry_mod.zip", "mods/npcs/some_npc.zip",
+                        "mods/npcs/old_npc.zip", "mods/unpacked/my_mod/my_mod.tp2", "files/tweaks/sw1h01.itm",
+                        "files/old.txt", "files/old_dir/file.txt"] {
+            let path = temp.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        temp
+    }
+
+    fn relative(root: &Path, paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().map(|path| path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/")).collect()
+    }
+
+    #[test]
+    fn unreferenced_files_are_reported() {
+        let temp = manifest_dir();
+        let manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+
+        let unused = prune_local_mods(&manifest, temp.path(), true).unwrap();
+
+        assert_eq!(relative(temp.path(), &unused), vec!["mods/npcs/old_npc.zip", "mods/unused.zip", "files/old.txt", "files/old_dir"]);
+        assert!(unused.iter().all(|path| path.exists()));
+    }
+
+    #[test]
+    fn unreferenced_files_are_deleted() {
+        let temp = manifest_dir();
+        let manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+
+        let unused = prune_local_mods(&manifest, temp.path(), false).unwrap();
+
+        assert_eq!(unused.len(), 4);
+        assert!(unused.iter().all(|path| !path.exists()));
+        for kept in ["mods/used.zip", "mods/registry_mod.zip", "mods/npcs/some_npc.zip", "mods/unpacked/my_mod/my_mod.tp2",
+                        "files/tweaks/sw1h01.itm"] {
+            assert!(temp.path().join(kept).is_file(), "{} was removed", kept);
+        }
+    }
+
+    #[test]
+    fn nothing_pruned_without_local_dirs() {
+        let temp = manifest_dir();
+        let mut manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+        manifest.global.local_mods = None;
+        manifest.global.local_files = None;
+
+        assert!(prune_local_mods(&manifest, temp.path(), false).unwrap().is_empty());
+        assert!(temp.path().join("mods/unused.zip").exists());
+    }
+
+    #[test]
+    fn manifest_directory_is_not_pruned() {
+        let temp = manifest_dir();
+        std::fs::write(temp.path().join("manifest.yml"), MANIFEST).unwrap();
+        for root in [".", "./mods/.."] {
+            let mut manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+            manifest.global.local_mods = Some(root.to_string());
+
+            let error = prune_local_mods(&manifest, temp.path(), false).unwrap_err();
+
+            assert!(error.to_string().contains("manifest directory"), "{}", error);
+            assert!(temp.path().join("manifest.yml").is_file());
+            assert!(temp.path().join("mods/unused.zip").exists());
+        }
+    }
+}
@@ -0,0 +1,273 @@
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use log::debug;
+use reqwest::header::HeaderMap;
+
+use crate::download::{ByteRange, Downloader, RangedContent};
+
+/// End of central directory record (without its comment).
+const EOCD_SIZE: usize = 22;
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const CENTRAL_HEADER_SIZE: usize = 46;
+const CENTRAL_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+/// Enough to contain the end of central directory record whatever the length of the archive comment.
+const TAIL_PROBE_SIZE: u64 = (EOCD_SIZE + u16::MAX as usize) as u64;
+
+/// An entry of the central directory of a zip archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteZipEntry {
+    pub name: String,
+    pub compressed_size: u64,
+    pub size: u64,
+}
+
+/// Content of a zip archive, read from its central directory without downloading the whole file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteZip {
+    pub entries: Vec<RemoteZipEntry>,
+    /// Number of bytes downloaded to read the directory (the whole file if the server ignores ranges).
+    pub downloaded: u64,
+}
+
+impl RemoteZip {
+    /// Entry paths, as `Extractor::list_entries` gives them for a local archive.
+    pub fn entry_paths(&self) -> Vec<PathBuf> {
+        self.entries.iter().map(|entry| PathBuf::from(&entry.name)).collect()
+    }
+
+    /// Size of the content once extracted.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+}
+
+/// Reads the entries of the zip archive at `url` with ranged requests: the tail of the file first
+/// (end of central directory record), then the central directory if it was not in the tail.
+pub async fn list_remote_zip(downloader: &Downloader, url: &str, headers: &Option<HeaderMap>) -> Result<RemoteZip> {
+    let tail = downloader.download_range(url, ByteRange::Suffix(TAIL_PROBE_SIZE), headers).await?;
+    let mut downloaded = tail.content.len() as u64;
+    let directory = match find_central_directory(&tail.content) {
+        None => bail!("No zip central directory found at the end of {}\n -> not a zip archive?", url),
+        Some(directory) => directory?,
+    };
+    debug!("central directory of {} at {} ({} bytes, {} entries)", url, directory.offset, directory.size, directory.entries);
+    let entries = if directory.size == 0 {
+        if directory.entries > 0 {
+            bail!("Empty zip central directory in {}\n -> {} entries expected", url, directory.entries);
+        }
+        vec![]
+    } else if directory.offset >= tail.offset {
+        parse_central_directory(&tail, &directory)?
+    } else {
+        let range = ByteRange::Span { start: directory.offset, end: directory.offset + directory.size - 1 };
+        let content = downloader.download_range(url, range, headers).await?;
+        downloaded += content.content.len() as u64;
+        parse_central_directory(&content, &directory)?
+    };
+    Ok(RemoteZip { entries, downloaded })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CentralDirectory {
+    offset: u64,
+    size: u64,
+    entries: usize,
+}
+
+/// Looks for the end of central directory record, from the end of `data`.
+fn find_central_directory(data: &[u8]) -> Option<Result<CentralDirectory>> {
+    let last_start = data.len().checked_sub(EOCD_SIZE)?;
+    let start = (0..=last_start).rev().find(|index| data[*index..].starts_with(&EOCD_SIGNATURE))?;
+    let record = &data[start..];
+    let entries = read_u16(record, 10);
+    let size = read_u32(record, 12);
+    let offset = read_u32(record, 16);
+    if entries == u16::MAX || size == u32::MAX || offset == u32::MAX {
+        return Some(Err(anyhow::anyhow!("zip64 archives can't be listed remotely")));
+    }
+    Some(Ok(CentralDirectory { offset: offset as u64, size: size as u64, entries: entries as usize }))
+}
+
+/// Reads the central directory entries from `content` (which must contain the whole directory).
+fn parse_central_directory(content: &RangedContent, directory: &CentralDirectory) -> Result<Vec<RemoteZipEntry>> {
+    let start = directory.offset.checked_sub(content.offset).unwrap_or(u64::MAX) as usize;
+    let end = start.saturating_add(directory.size as usize);
+    let data = match content.content.get(start..end) {
+        None => bail!("Incomplete zip central directory (expected bytes {} to {})", directory.offset, directory.offset + directory.size),
+        Some(data) => data,
+    };
+    let mut entries = vec![];
+    let mut position = 0;
+    while entries.len() < directory.entries {
+        let header = match data.get(position..) {
+            None => bail!("Truncated zip central directory: entry {} of {} is past its end", entries.len() + 1, directory.entries),
+            Some(header) => header,
+        };
+        if header.len() < CENTRAL_HEADER_SIZE || !header.starts_with(&CENTRAL_HEADER_SIGNATURE) {
+            bail!("Invalid zip central directory entry at offset {}", directory.offset + position as u64);
+        }
+        let name_length = read_u16(header, 28) as usize;
+        let extra_length = read_u16(header, 30) as usize;
+        let comment_length = read_u16(header, 32) as usize;
+        let name = match header.get(CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_length) {
+            None => bail!("Truncated zip central directory entry at offset {}", directory.offset + position as u64),
+            Some(name) => String::from_utf8_lossy(name).to_string(),
+        };
+        entries.push(RemoteZipEntry {
+            name,
+            compressed_size: read_u32(header, 20) as u64,
+            size: read_u32(header, 24) as u64,
+        });
+        position += CENTRAL_HEADER_SIZE + name_length + extra_length + comment_length;
+    }
+    Ok(entries)
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use crate::download::{Downloader, RangedContent};
+    use crate::test_utils::server::{ok, response, TestServer};
+
+    use super::{find_central_directory, list_remote_zip, parse_central_directory};
+
+    /// A zip with one stored file of `padding` bytes (so that the directory is far from the start), a small one,
+    /// `tra_files` empty files and an archive comment of `comment_length` bytes.
+    fn zip_archive(padding: usize, tra_files: usize, comment_length: usize) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("my_mod/big.bam", stored).unwrap();
+        writer.write_all(&vec![7u8; padding]).unwrap();
+        writer.start_file("my_mod/setup-my_mod.tp2", stored).unwrap();
+        writer.write_all(b"BACKUP ~weidu_external/backup/my_mod~").unwrap();
+        for index in 0..tra_files {
+            writer.start_file(format!("my_mod/tra/lang_{}/setup.tra", index), stored).unwrap();
+        }
+        writer.set_comment("c".repeat(comment_length));
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Serves `archive` (honoring `Range` if `ranges` is set).
+    fn serve_archive(archive: Vec<u8>, ranges: bool) -> TestServer {
+        TestServer::start(move |request| {
+            let total = archive.len();
+            let (start, end) = match request.header("range").and_then(|range| range.strip_prefix("bytes=")) {
+                Some(suffix) if ranges && suffix.starts_with('-') =>
+                    (total.saturating_sub(suffix[1..].parse::<usize>().unwrap()), total - 1),
+                Some(span) if ranges => {
+                    let (start, end) = span.split_once('-').unwrap();
+                    (start.parse().unwrap(), end.parse().unwrap())
+                }
+                _ => (0, total - 1),
+            };
+            let body = &archive[start..=end];
+            if ranges {
+                response("206 Partial Content", &[("Content-Range", &format!("bytes {}-{}/{}", start, end, total))], body)
+            } else {
+                ok(body)
+            }
+        })
+    }
+
+    /// The `Range` headers of the received requests.
+    fn received_ranges(server: &TestServer) -> Vec<String> {
+        server.requests().iter().map(|request| request.header("range").unwrap_or_default().to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn list_entries_from_tail_bytes() {
+        let archive = zip_archive(1000, 0, 0);
+        let server = serve_archive(archive.clone(), true);
+        let url = server.url("/my_mod.zip");
+
+        let remote = list_remote_zip(&Downloader::new(), &url, &None).await.unwrap();
+
+        let names = remote.entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["my_mod/big.bam", "my_mod/setup-my_mod.tp2"]);
+        assert_eq!(remote.entries[0].size, 1000);
+        assert_eq!(remote.uncompressed_size(), 1000 + 37);
+        // the archive is smaller than the probe, everything came with the first request
+        assert_eq!(received_ranges(&server), vec!["bytes=-65557".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn central_directory_outside_of_the_tail() {
+        // the long comment pushes most of the directory out of the probed tail
+        let archive = zip_archive(200_000, 30, 65_000);
+        let server = serve_archive(archive.clone(), true);
+        let url = server.url("/my_mod.zip");
+
+        let remote = list_remote_zip(&Downloader::new(), &url, &None).await.unwrap();
+
+        assert_eq!(remote.entry_paths().len(), 32);
+        assert_eq!(remote.entry_paths()[31], std::path::PathBuf::from("my_mod/tra/lang_29/setup.tra"));
+        assert_eq!(remote.entries[0].compressed_size, 200_000);
+        assert!(remote.downloaded < archive.len() as u64 / 2, "{} bytes downloaded", remote.downloaded);
+        let received = received_ranges(&server);
+        assert_eq!(received.len(), 2);
+        let start = received[1].strip_prefix("bytes=").and_then(|span| span.split_once('-')).unwrap().0;
+        assert!(start.parse::<usize>().unwrap() > 200_000, "{:?}", received);
+    }
+
+    #[tokio::test]
+    async fn server_without_ranges_sends_the_whole_file() {
+        let archive = zip_archive(200_000, 0, 0);
+        let server = serve_archive(archive.clone(), false);
+        let url = server.url("/my_mod.zip");
+
+        let remote = list_remote_zip(&Downloader::new(), &url, &None).await.unwrap();
+
+        assert_eq!(remote.entries.len(), 2);
+        assert_eq!(remote.downloaded, archive.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn empty_central_directory_far_from_the_start() {
+        // an empty zip is only its end record, which says the (empty) directory is at offset 0
+        let mut archive = vec![0u8; 100_000];
+        archive.extend(ZipWriter::new(Cursor::new(vec![])).finish().unwrap().into_inner());
+        let server = serve_archive(archive.clone(), true);
+
+        let remote = list_remote_zip(&Downloader::new(), &server.url("/empty.zip"), &None).await.unwrap();
+
+        assert!(remote.entries.is_empty());
+        assert_eq!(received_ranges(&server).len(), 1);
+
+        // entry count of the end record
+        let count = archive.len() - 22 + 10;
+        archive[count..count + 2].copy_from_slice(&3u16.to_le_bytes());
+        let server = serve_archive(archive, true);
+
+        let error = list_remote_zip(&Downloader::new(), &server.url("/empty.zip"), &None).await.unwrap_err().to_string();
+
+        assert!(error.contains("Empty zip central directory"), "{}", error);
+    }
+
+    #[test]
+    fn lengths_past_the_directory_are_an_error() {
+        let mut archive = zip_archive(0, 2, 0);
+        let directory = find_central_directory(&archive).unwrap().unwrap();
+        // extra field length of the first entry, way past the end of the directory
+        let extra_length = directory.offset as usize + 30;
+        archive[extra_length..extra_length + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+        let content = RangedContent { offset: 0, content: archive, total_size: None, partial: false };
+
+        let error = parse_central_directory(&content, &directory).unwrap_err().to_string();
+
+        assert!(error.contains("Truncated zip central directory"), "{}", error);
+    }
+}
@@ -0,0 +1,204 @@
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::extractor_hint::find_in_path;
+use crate::lowercase::{lwc, LwcString};
+use crate::module::location::location::ConcreteLocation;
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::manifest_diff::effective_location;
+use crate::module::module::Module;
+
+/// An external program the installation needs.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tool {
+    /// A program that is run as configured (an `extractors` entry or a precopy command).
+    Command(String),
+    /// Archives with this extension need an external extractor but there is no `extractors` entry for it.
+    UnconfiguredExtractor(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequiredTool {
+    pub tool: Tool,
+    /// Names of the modules that need the tool, in manifest order.
+    pub needed_by: Vec<LwcString>,
+    /// Where the program was found in `PATH`, `None` if it's missing (always `None` for unconfigured extractors).
+    pub found: Option<PathBuf>,
+}
+
+/// The external programs needed to extract the module archives (by archive extension) and to run the
+/// precopy commands (for the current OS), with where they are found in `PATH`.<br>
+/// The archive name is known from the location, without downloading anything; precopy programs given with
+/// a relative path (`./fix.sh`) come with the mod and are not listed.
+pub fn required_tools(manifest: &Manifest, config: &Config) -> Vec<RequiredTool> {
+    required_tools_in(manifest, config, std::env::var_os("PATH").as_deref())
+}
+
+fn required_tools_in(manifest: &Manifest, config: &Config, path: Option<&OsStr>) -> Vec<RequiredTool> {
+    let mut tools = BTreeMap::<Tool, Vec<LwcString>>::new();
+    for module in &manifest.modules {
+        let weidu_mod = match module {
+            Module::Mod { weidu_mod } => weidu_mod,
+            Module::Generated { .. } | Module::File { .. } => continue,
+        };
+        let location = match effective_location(manifest, &weidu_mod.name, &weidu_mod.location) {
+            None => continue,
+            Some(location) => location,
+        };
+        let archive_tool = archive_extension(location, &weidu_mod.name)
+            .and_then(|extension| extractor_tool(&extension, config));
+        let precopy_tool = location.precopy.as_ref()
+            .and_then(|precopy| precopy.for_current_os().ok())
+            .map(|(command, _)| command)
+            .filter(|command| !is_relative_path(command))
+            .map(|command| Tool::Command(command.to_string()));
+        for tool in archive_tool.into_iter().chain(precopy_tool) {
+            let needed_by = tools.entry(tool).or_default();
+            if !needed_by.contains(&weidu_mod.name) {
+                needed_by.push(weidu_mod.name.clone());
+            }
+        }
+    }
+    tools.into_iter().map(|(tool, needed_by)| {
+        let found = match &tool {
+            Tool::Command(command) if Path::new(command).is_absolute() =>
+                Some(PathBuf::from(command)).filter(|command| command.is_file()),
+            Tool::Command(command) => find_in_path(command, path),
+            Tool::UnconfiguredExtractor(_) => None,
+        };
+        RequiredTool { tool, needed_by, found }
+    }).collect()
+}
+
+/// Lowercase extension of the archive, `None` for directories and names without extension.<br>
+/// For split archives (`my_mod.7z.001`), the extension of the whole archive.
+fn archive_extension(location: &ConcreteLocation, module_name: &LwcString) -> Option<String> {
+    let name = match &location.source {
+        Source::Local { local } => PathBuf::from(local),
+        Source::Absolute { path } => PathBuf::from(path),
+        source => source.save_name(module_name).ok()?,
+    };
+    let name = name.file_name()?.to_str()?.to_lowercase();
+    let name = name.strip_suffix(".001").unwrap_or(&name);
+    let extension = Path::new(name).extension()?.to_str()?;
+    let is_tar_gz = extension == "gz" && name.ends_with(".tar.gz");
+    Some(if is_tar_gz { "tgz".to_string() } else { extension.to_string() })
+}
+
+/// `None` when modda extracts the format itself.
+fn extractor_tool(extension: &str, config: &Config) -> Option<Tool> {
+    match extension {
+        "zip" | "iemod" | "tgz" => None,
+        "rar" if cfg!(feature = "rar") => None,
+        _ => match config.extractors.get(&lwc!(extension)) {
+            Some(extractor) => Some(Tool::Command(extractor.command.clone())),
+            None => Some(Tool::UnconfiguredExtractor(extension.to_string())),
+        },
+    }
+}
+
+fn is_relative_path(command: &str) -> bool {
+    let path = Path::new(command);
+    !path.is_absolute() && path.components().count() > 1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+
+    use crate::config::{Config, ExtractorCommand};
+    use crate::lowercase::{lwc, LwcString};
+    use crate::module::manifest::Manifest;
+    use crate::test_utils::test_dir;
+
+    use super::{required_tools_in, Tool};
+
+    const MANIFEST: &str = r#"
+version: "1"
+global:
+  lang_dir: en_US
+locations:
+  entries:
+    rar_mod:
+      http: https://example.com/files/Rar_Mod-v2.rar
+modules:
+  - name: seven_mod
+    components: all
+    location:
+      local: mods/seven_mod.7z
+  - name: rar_mod
+    components: all
+  - name: zipped
+    components: all
+    location:
+      local: mods/zipped.zip
+      precopy:
+        command: modda_test_fixer
+        args: [--all]
+  - name: bundled
+    components: all
+    location:
+      local: mods/bundled.7z
+      precopy:
+        command: ./fix.sh
+"#;
+
+    fn bin_dir() -> tempfile::TempDir {
+        let temp = test_dir();
+        let fixer = temp.path().join(if cfg!(windows) { "modda_test_fixer.exe" } else { "modda_test_fixer" });
+        std::fs::write(&fixer, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fixer, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        temp
+    }
+
+    fn names(names: &[&str]) -> Vec<LwcString> {
+        names.iter().map(|name| lwc!(name)).collect()
+    }
+
+    #[test]
+    fn extractors_and_precopy_commands() {
+        let bin = bin_dir();
+        let manifest: Manifest = serde_yaml::from_str(MANIFEST).unwrap();
+        let config = Config {
+            extractors: HashMap::from([(lwc!("7z"), ExtractorCommand {
+                command: "modda_test_7z".to_string(),
+                args: vec!["x".to_string()],
+                list_args: None,
+            })]),
+            ..Default::default()
+        };
+
+        let tools = required_tools_in(&manifest, &config, Some(&OsString::from(bin.path())));
+
+        let summary = tools.iter().map(|tool| (tool.tool.clone(), tool.needed_by.clone(), tool.found.is_some()))
+            .collect::<Vec<_>>();
+        let mut expected = vec![
+            (Tool::Command("modda_test_7z".to_string()), names(&["seven_mod", "bundled"]), false),
+            (Tool::Command("modda_test_fixer".to_string()), names(&["zipped"]), true),
+        ];
+        if !cfg!(feature = "rar") {
+            expected.push((Tool::UnconfiguredExtractor("rar".to_string()), names(&["rar_mod"]), false));
+        }
+        assert_eq!(summary, expected);
+    }
+
+    #[test]
+    fn nothing_needed_for_zip_archives() {
+        let manifest: Manifest = serde_yaml::from_str(&MANIFEST
+            .replace("seven_mod.7z", "seven_mod.zip")
+            .replace("Rar_Mod-v2.rar", "Rar_Mod-v2.tar.gz")
+            .replace("bundled.7z", "bundled.ZIP")
+            .replace("command: modda_test_fixer", "command: ./fixer")).unwrap();
+
+        assert_eq!(required_tools_in(&manifest, &Config::default(), None), vec![]);
+    }
+}
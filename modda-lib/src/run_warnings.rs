@@ -0,0 +1,166 @@
+
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+
+use log::warn;
+use nu_ansi_term::Color::Yellow;
+
+use crate::lowercase::LwcString;
+
+/// A warning that happened during a run (not an error: the run went on).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunWarning {
+    /// The module being processed, if any.
+    pub module: Option<LwcString>,
+    /// Which step produced the warning (`extract`, `copy`, `install`...).
+    pub context: String,
+    pub message: String,
+}
+
+impl Display for RunWarning {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match &self.module {
+            Some(module) => write!(formatter, "[{}] {}: {}", module, self.context, self.message),
+            None => write!(formatter, "{}: {}", self.context, self.message),
+        }
+    }
+}
+
+/// Collects the warnings of a run so that they can be shown together at the end.<br>
+/// Warnings are also logged when they are added.
+#[derive(Debug, Default)]
+pub struct WarningCollector {
+    warnings: Mutex<Vec<RunWarning>>,
+}
+
+impl WarningCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn warn(&self, module: Option<&LwcString>, context: &str, message: &str) {
+        let warning = RunWarning {
+            module: module.cloned(),
+            context: context.to_string(),
+            message: message.to_string(),
+        };
+        warn!("{}", warning);
+        self.warnings.lock().unwrap().push(warning);
+    }
+
+    pub fn warnings(&self) -> Vec<RunWarning> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.lock().unwrap().is_empty()
+    }
+
+    pub fn summary(&self) -> Option<String> {
+        let warnings = self.warnings.lock().unwrap();
+        if warnings.is_empty() {
+            return None;
+        }
+        let list = warnings.iter().map(|warning| format!("  - {}", warning)).collect::<Vec<_>>().join("\n");
+        Some(format!("{} warning(s) during this run:\n{}", warnings.len(), list))
+    }
+
+    /// Prints all warnings (if any).
+    pub fn report(&self) {
+        if let Some(summary) = self.summary() {
+            println!("{}", Yellow.bold().paint(summary));
+        }
+    }
+}
+
+/// Sends the warning to the collector if there is one, or only logs it.
+pub fn report_warning(collector: Option<&WarningCollector>, module: Option<&LwcString>, context: &str, message: &str) {
+    match collector {
+        Some(collector) => collector.warn(module, context, message),
+        None => warn!("{}", RunWarning { module: module.cloned(), context: context.to_string(), message: message.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::archive_extractor::Extractor;
+    use crate::archive_layout::Layout;
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::file_installer::FileInstaller;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::file_module_origin::FileModuleOrigin;
+    use crate::module::location::location::ConcreteLocation;
+    use crate::test_utils::test_dir;
+
+    use super::{RunWarning, WarningCollector};
+
+    #[test]
+    fn collect_warnings_from_several_stages() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let temp = test_dir();
+        let game_dir = CanonPath::new(temp.path().join("game")).unwrap();
+        std::fs::create_dir_all(game_dir.path()).unwrap();
+
+        let warnings = WarningCollector::new();
+        let config = Config::default();
+
+        // extraction: the layout doesn't strip the top directory of the archive, so nothing matches
+        let extractor = Extractor::new(&game_dir, &config).with_warnings(&warnings);
+        let location = ConcreteLocation {
+            layout: Some(Layout::with_tp2_default_and_strip(0)),
+            extract_location: Some(temp.path().join("extract").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        extractor.extract_files(&project.join("resources/test/archives/my_mod.zip"), &lwc!("my_mod"), &location).unwrap();
+
+        // copy: overwrites a file
+        let global = Global::default();
+        let installer = FileInstaller::new(&global, &game_dir, &game_dir, &config).with_warnings(&warnings);
+        let origin = FileModuleOrigin::Absolute {
+            absolute: project.join("resources/test/file_installer/some_dir").to_string_lossy().to_string(),
+            glob: None,
+            dir_copy: None,
+            literal: None,
+        };
+        let target = temp.path().join("data");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("a.txt"), "from another mod").unwrap();
+        installer.copy_from_origins(&lwc!("my_gen_mod"), &[&origin], &target, true).unwrap();
+
+        warnings.warn(None, "install", "something else");
+
+        let collected = warnings.warnings();
+        assert_eq!(
+            collected.iter().map(|warning| (warning.module.clone(), warning.context.as_str())).collect::<Vec<_>>(),
+            vec![
+                (Some(lwc!("my_mod")), "extract"),
+                (Some(lwc!("my_gen_mod")), "copy"),
+                (None, "install"),
+            ]
+        );
+        assert!(collected[0].message.contains("matched no file"));
+        assert!(collected[1].message.contains("a.txt"));
+
+        let summary = warnings.summary().unwrap();
+        assert!(summary.starts_with("3 warning(s)"));
+        for warning in &collected {
+            assert!(summary.contains(&warning.to_string()));
+        }
+    }
+
+    #[test]
+    fn no_summary_without_warnings() {
+        let warnings = WarningCollector::new();
+        assert!(warnings.is_empty());
+        assert_eq!(warnings.summary(), None);
+        assert_eq!(
+            RunWarning { module: Some(lwc!("my_mod")), context: "copy".to_string(), message: "oops".to_string() }.to_string(),
+            "[my_mod] copy: oops"
+        );
+    }
+}
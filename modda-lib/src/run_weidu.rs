@@ -1,4 +1,5 @@
 
+use std::path::Path;
 use std::process::{Command, Stdio};
 
 use anyhow::{bail, Result};
@@ -11,11 +12,14 @@ use crate::args::{Install, Reset};
 use crate::canon_path::CanonPath;
 use crate::global::Global;
 use crate::modda_context::WeiduContext;
-use crate::module::language::{LanguageOption, LanguageSelection, select_language};
+use crate::module::language::{fallback_language, LanguageOption, LanguageSelection, select_language};
 use crate::module::components::{Component, Components};
 use crate::lowercase::LwcString;
 use crate::module::weidu_mod::{WeiduMod, BareMod};
 use crate::run_result::RunResult;
+use crate::tp2_components::{read_tp2_components, resolve_component_names};
+use crate::weidu_output::{component_outcomes, parse_install_output, read_log_since, ComponentOutcome};
+use crate::weidu_version::{WeiduVersion, WeiduVersionReq};
 
 #[cfg(target_os="windows")]
 const WEIDU_BIN: &str = "weidu.exe";
@@ -30,32 +34,88 @@ pub fn run_weidu_install(tp2: &str, module: &WeiduMod, opts: &Install, global: &
         Ok(Selected(id)) => id,
         Ok(NoMatch(list)) if list.is_empty() => 0,
         Ok(NoPrefSet(available))
-        | Ok(NoMatch(available)) => handle_no_language_selected(available, module, global)?,
+        | Ok(NoMatch(available)) => fallback_language(&available, global.lang_fallback.unwrap_or_default(),
+                                                        &module.name, &global.lang_preferences)?,
         Err(err) => return Err(err),
     };
+    let lang_dir = global.lang_dir()?;
     match &module.components {
         Components::None => Ok(RunResult::Dry("Explicitly requested no components to be installed".to_string())),
         Components::Ask =>
-                run_weidu_install_interactive(tp2, module, opts, &global.game_language, weidu_context),
+                run_weidu_install_interactive(tp2, module, opts, lang_dir, weidu_context),
         Components::All =>
-                run_weidu_install_all(tp2, module, opts, &global.game_language, language_id, weidu_context),
+                run_weidu_install_all(tp2, module, opts, lang_dir, language_id, weidu_context),
         Components::List(comp) if comp.is_empty() =>
-                run_weidu_install_interactive(tp2, module, opts, &global.game_language, weidu_context),
-        Components::List(components) =>
-                run_weidu_install_auto(tp2, module, components, opts, &global.game_language, language_id, weidu_context),
+                run_weidu_install_interactive(tp2, module, opts, lang_dir, weidu_context),
+        Components::List(components) => {
+            let components = if components.iter().any(Component::is_named) {
+                let available = read_tp2_components(weidu_context.current_dir.path(), Path::new(tp2), language_id)?;
+                resolve_component_names(components, &available, &module.name)?
+            } else {
+                components.to_vec()
+            };
+            if opts.check_components {
+                check_components(tp2, &components, language_id, &module.name, weidu_context)?;
+            }
+            run_weidu_install_auto(tp2, module, &components, opts, lang_dir, language_id, weidu_context)
+        }
+        Components::File { file } => bail!("Components file {} for mod {} was not loaded", file, module.name),
     }
 }
 
-fn handle_no_language_selected(available: Vec<LanguageOption>, module: &WeiduMod, global: &Global) -> Result<u32> {
-    // may one day prompt user for selection and (if ok) same in the yaml file
-    bail!(
-        r#"No matching language found for module {} with language preferences {:?}
-        Available choices are {:?}
-        "#,
-        module.name, &global.lang_preferences, available);
+/// Installs the components; when weidu fails, reports which components failed and, with `component_retries`
+/// in the config, runs weidu again with only those.
+fn run_weidu_install_auto(tp2: &str, module: &WeiduMod, components: &[Component], opts: &Install,
+                    game_lang: &str, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
+    let retries = weidu_context.config.component_retries.unwrap_or(0);
+    let log_path = weidu_context.current_dir.path().join(format!("setup-{}.debug", module.name));
+    let mut pending = components.to_vec();
+    let mut attempt = 0;
+    loop {
+        let log_offset = std::fs::metadata(&log_path).map(|metadata| metadata.len()).unwrap_or(0);
+        let result = run_weidu_install_components(tp2, module, &pending, opts, game_lang, language_id, weidu_context)?;
+        if opts.dry_run || result.success() {
+            return Ok(result);
+        }
+        let failed = match failed_components(tp2, module, &pending, language_id, &log_path, log_offset, weidu_context) {
+            Err(error) => {
+                warn!("Could not read the component results of mod {}\n  {:?}", module.name, error);
+                return Ok(result);
+            }
+            Ok(failed) => failed,
+        };
+        if failed.is_empty() || attempt >= retries {
+            return Ok(result);
+        }
+        attempt += 1;
+        info!("installing failed components {:?} of mod {} again (retry {}/{})", failed, module.name, attempt, retries);
+        pending = failed;
+    }
 }
 
-fn run_weidu_install_auto(tp2: &str, module: &WeiduMod, components: &[Component], opts: &Install,
+/// Reads the results of the last weidu run from its log, reports them and returns the components that failed.
+fn failed_components(tp2: &str, module: &WeiduMod, requested: &[Component], language_id: u32, log_path: &Path,
+                        log_offset: u64, weidu_context: &WeiduContext) -> Result<Vec<Component>> {
+    let results = parse_install_output(&read_log_since(log_path, log_offset)?);
+    if results.is_empty() {
+        return Ok(vec![]);
+    }
+    let available = run_weidu_list_components(tp2, language_id, weidu_context)?;
+    let outcomes = component_outcomes(&results, &available);
+    let with_outcome = |expected: ComponentOutcome| outcomes.iter()
+        .filter(|(_, outcome)| **outcome == expected)
+        .map(|(number, _)| *number)
+        .collect::<Vec<_>>();
+    info!("component results of mod {}: installed {:?}, with warnings {:?}, failed {:?}", module.name,
+            with_outcome(ComponentOutcome::Installed), with_outcome(ComponentOutcome::InstalledWithWarnings),
+            with_outcome(ComponentOutcome::Failed));
+    Ok(requested.iter()
+        .filter(|component| component.index().map(|index| outcomes.get(&index) == Some(&ComponentOutcome::Failed)).unwrap_or(false))
+        .cloned()
+        .collect())
+}
+
+fn run_weidu_install_components(tp2: &str, module: &WeiduMod, components: &[Component], opts: &Install,
                     game_lang: &str, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
 
     let mut command = Command::new(weidu_command(weidu_context)?);
@@ -73,7 +133,12 @@ fn run_weidu_install_auto(tp2: &str, module: &WeiduMod, components: &[Component]
     ];
     // component list
     args.push("--force-install-list".to_owned());
-    args.extend(components.iter().map(|id| id.index().to_string()));
+    for component in components {
+        match component.index() {
+            Some(index) => args.push(index.to_string()),
+            None => bail!("Component {:?} of mod {} was not resolved", component, module.name),
+        }
+    }
 
     command.args(&args)
         .stdin(Stdio::inherit())
@@ -166,7 +231,12 @@ pub fn run_weidu_list_components(tp2: &str, lang_id: u32, weidu_context: &WeiduC
     ];
     command.args(&args);
     let output = command.output()?;
-    let output = String::from_utf8_lossy(&output.stdout);
+    parse_weidu_component_list(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads the output of `weidu --list-components-json` (the json list is on its own line,
+/// after the weidu messages).
+pub fn parse_weidu_component_list(output: &str) -> Result<Vec<WeiduComponent>> {
     for line in output.lines() {
         if !line.starts_with(r##"[{""##) {
             continue;
@@ -177,6 +247,31 @@ pub fn run_weidu_list_components(tp2: &str, lang_id: u32, weidu_context: &WeiduC
     bail!("weidu json output not parsable")
 }
 
+/// Component numbers requested but absent from the mod component list.
+pub fn unknown_components(requested: &[Component], available: &[WeiduComponent]) -> Vec<u32> {
+    requested.iter()
+        .filter_map(Component::index)
+        .filter(|index| !available.iter().any(|component| component.number == *index))
+        .collect()
+}
+
+/// Asks weidu for the components of the mod and fails if some requested components don't exist
+/// (or if weidu can't read the tp2).
+fn check_components(tp2: &str, components: &[Component], language_id: u32, mod_name: &LwcString,
+                    weidu_context: &WeiduContext) -> Result<()> {
+    let available = match run_weidu_list_components(tp2, language_id, weidu_context) {
+        Ok(available) => available,
+        Err(error) => bail!("Could not check the components of mod {} (tp2 {})\n -> {:?}", mod_name, tp2, error),
+    };
+    let unknown = unknown_components(components, &available);
+    if !unknown.is_empty() {
+        bail!("Mod {} has no component {:?}\n -> available components are {:?}", mod_name, unknown,
+                available.iter().map(|component| component.number).collect::<Vec<_>>());
+    }
+    debug!("components {:?} of mod {} exist", components, mod_name);
+    Ok(())
+}
+
 
 lazy_static! {
     static ref LANGUAGE_REGEX: Regex = Regex::new("^([0-9]*):(.*)$").unwrap();
@@ -230,10 +325,32 @@ pub fn check_weidu_exe(weidu_context: &WeiduContext) -> Result<()> {
     command
         .stdout(Stdio::null())
         .stderr(Stdio::null());
-    match command.output() {
-        Err(error) => bail!("weidu executable doesn't appear to work\n  {:?}", error),
-        _ => Ok(())
+    if let Err(error) = command.output() {
+        bail!("weidu executable doesn't appear to work\n  {:?}", error);
+    }
+    if let Some(requirement) = &weidu_context.config.weidu_version {
+        check_weidu_version(weidu_context, requirement, "`weidu_version` in the config")?;
+    }
+    Ok(())
+}
+
+/// Runs `weidu --version` and fails if the version doesn't satisfy `requirement`;
+/// `origin` tells where the requirement comes from (for the error message).
+pub fn check_weidu_version(weidu_context: &WeiduContext, requirement: &str, origin: &str) -> Result<WeiduVersion> {
+    let requirement_value = WeiduVersionReq::parse(requirement)?;
+    let weidu = weidu_command(weidu_context)?;
+    let output = match Command::new(&weidu).arg("--version").arg("--no-exit-pause").output() {
+        Err(error) => bail!("Could not run `{} --version`\n -> {:?}", weidu, error),
+        Ok(output) => output,
+    };
+    let version = WeiduVersion::from_version_output(&String::from_utf8_lossy(&output.stdout))?;
+    if !requirement_value.matches(&version) {
+        bail!("weidu version {} ({}) doesn't satisfy the required version `{}` ({})\n \
+                -> install a matching weidu version or set `weidu_path` to one",
+                version, weidu, requirement, origin);
     }
+    info!("weidu version {} satisfies `{}`", version, requirement);
+    Ok(version)
 }
 
 fn weidu_command(weidu_context: &WeiduContext) -> Result<String> {
@@ -313,11 +430,41 @@ pub fn run_weidu_uninstall(tp2: &str, module: &BareMod, opts: &Reset, weidu_cont
 
 #[cfg(test)]
 mod tests {
+    use crate::module::components::{Component, FullComponent};
     use crate::modda_context::WeiduContext;
     use crate::run_weidu::weidu_command;
     use crate::config::Config;
     use super::WEIDU_BIN;
 
+    const LIST_COMPONENTS_OUTPUT: &str = r#"[weidu] WeiDU version 24900
+[mymod/setup-mymod.tp2] parsed
+[{"index":0,"number":0,"forced":false,"name":"Core fixes","subgroup":null,"group":[]},{"index":1,"number":10,"forced":false,"name":"Better items","subgroup":"Items","group":["Items"]},{"index":2,"number":11,"forced":false,"name":"Best items","subgroup":"Items","group":["Items"]}]
+"#;
+
+    #[test]
+    fn parse_component_list_output() {
+        let components = super::parse_weidu_component_list(LIST_COMPONENTS_OUTPUT).unwrap();
+
+        assert_eq!(components.iter().map(|component| component.number).collect::<Vec<_>>(), vec![0, 10, 11]);
+        assert_eq!(components[1].name, "Better items");
+        assert_eq!(components[1].subgroup, Some("Items".to_string()));
+        assert!(super::parse_weidu_component_list("[weidu] ERROR: parsing setup-mymod.tp2").is_err());
+    }
+
+    #[test]
+    fn detect_unknown_components() {
+        let available = super::parse_weidu_component_list(LIST_COMPONENTS_OUTPUT).unwrap();
+
+        let requested = vec![
+            Component::Simple(0),
+            Component::Full(FullComponent { index: 10, component_name: "Better items".to_string() }),
+        ];
+        assert!(super::unknown_components(&requested, &available).is_empty());
+
+        let requested = vec![Component::Simple(1), Component::Simple(11), Component::Simple(30)];
+        assert_eq!(super::unknown_components(&requested, &available), vec![1, 30]);
+    }
+
     #[test]
     fn weidu_command_bin_present_in_current_dir_ignore_not_set() {
         // prepare game dir with weidu "binary" inside
@@ -0,0 +1,213 @@
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+/// An archive split in volumes (`my_mod.7z.001`, `my_mod.7z.002`...), each volume being the next part of the
+/// bytes of the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitArchive {
+    /// All the volumes, in order (the first one is the `.001` file).
+    pub volumes: Vec<PathBuf>,
+    /// Extension of the whole archive (`zip`, `7z`...).
+    pub extension: String,
+}
+
+/// If `archive` is the first volume of a split archive (`<name>.<extension>.001`), finds the other volumes
+/// next to it.<br>
+/// Fails if there is a gap in the volume numbers; a missing last volume can't be detected here
+/// (the extraction will fail on the truncated archive).
+pub fn find_split_archive(archive: &Path) -> Result<Option<SplitArchive>> {
+    let (stem, extension) = match split_first_volume(archive) {
+        None => return Ok(None),
+        Some(split) => split,
+    };
+    let dir = match archive.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Err(error) => bail!("Could not read the volumes of split archive {:?}\n -> {:?}", archive, error),
+        Ok(entries) => entries,
+    };
+    let numbers = entries.filter_map(Result::ok)
+        .filter_map(|entry| volume_number(&entry.file_name().to_string_lossy(), &stem))
+        .sorted()
+        .dedup()
+        .collect::<Vec<_>>();
+    let last = numbers.last().copied().unwrap_or(1);
+    let missing = (1..=last).filter(|number| !numbers.contains(number))
+        .map(|number| volume_name(&stem, number))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        bail!("Missing volumes of split archive {:?}\n -> {}", archive, missing.join(", "));
+    }
+    let volumes = (1..=last).map(|number| dir.join(volume_name(&stem, number))).collect();
+    Ok(Some(SplitArchive { volumes, extension }))
+}
+
+/// `(<name>.<extension>, extension)` for a `<name>.<extension>.001` file.
+fn split_first_volume(archive: &Path) -> Option<(String, String)> {
+    let name = archive.file_name()?.to_str()?;
+    let stem = name.strip_suffix(".001")?;
+    let extension = Path::new(stem).extension()?.to_str()?.to_lowercase();
+    Some((stem.to_string(), extension))
+}
+
+fn volume_number(file_name: &str, stem: &str) -> Option<u32> {
+    let suffix = file_name.strip_prefix(stem)?.strip_prefix('.')?;
+    if suffix.len() < 3 || !suffix.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    suffix.parse().ok().filter(|number| *number > 0)
+}
+
+pub(crate) fn volume_name(stem: &str, number: u32) -> String {
+    format!("{}.{:03}", stem, number)
+}
+
+/// Reads the volumes of a split archive as a single file.
+pub struct VolumeReader {
+    volumes: Vec<(PathBuf, u64)>,
+    /// Position in the whole archive.
+    position: u64,
+    /// Index in `volumes` and file of the volume that contains `position`, if it's open.
+    current: Option<(usize, File)>,
+}
+
+impl VolumeReader {
+    pub fn open(volumes: &[PathBuf]) -> Result<Self> {
+        let mut sized = vec![];
+        for volume in volumes {
+            match std::fs::metadata(volume) {
+                Err(error) => bail!("Could not read archive volume {:?}\n -> {:?}", volume, error),
+                Ok(metadata) => sized.push((volume.clone(), metadata.len())),
+            }
+        }
+        Ok(VolumeReader { volumes: sized, position: 0, current: None })
+    }
+
+    fn total_len(&self) -> u64 {
+        self.volumes.iter().map(|(_, len)| len).sum()
+    }
+
+    /// Index of the volume that contains `position` and the offset in this volume.
+    fn locate(&self, position: u64) -> Option<(usize, u64)> {
+        let mut start = 0;
+        for (index, (_, len)) in self.volumes.iter().enumerate() {
+            if position < start + len {
+                return Some((index, position - start));
+            }
+            start += len;
+        }
+        None
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (index, offset) = match self.locate(self.position) {
+            None => return Ok(0),
+            Some(located) => located,
+        };
+        let file = match &mut self.current {
+            Some((current, file)) if *current == index => file,
+            _ => {
+                let mut file = File::open(&self.volumes[index].0)?;
+                file.seek(SeekFrom::Start(offset))?;
+                &mut self.current.insert((index, file)).1
+            }
+        };
+        let available = self.volumes[index].1 - offset;
+        let wanted = buf.len().min(available as usize);
+        let count = file.read(&mut buf[..wanted])?;
+        if count == 0 && wanted > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "archive volume is shorter than expected"));
+        }
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.total_len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        let target = match target {
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the archive")),
+            Some(target) => target,
+        };
+        if target != self.position {
+            // the open volume is positioned again on the next read
+            self.current = None;
+            self.position = target;
+        }
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+    use std::path::PathBuf;
+
+    use crate::test_utils::test_dir;
+
+    use super::{find_split_archive, SplitArchive, VolumeReader};
+
+    fn archives() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/archives")
+    }
+
+    #[test]
+    fn find_two_volumes() {
+        let split = find_split_archive(&archives().join("my_mod_split.zip.001")).unwrap();
+
+        assert_eq!(split, Some(SplitArchive {
+            volumes: vec![archives().join("my_mod_split.zip.001"), archives().join("my_mod_split.zip.002")],
+            extension: "zip".to_string(),
+        }));
+    }
+
+    #[test]
+    fn not_a_split_archive() {
+        assert_eq!(find_split_archive(&archives().join("my_mod.zip")).unwrap(), None);
+        assert_eq!(find_split_archive(&archives().join("my_mod_split.zip.002")).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_volumes_are_listed() {
+        let temp = test_dir();
+        for volume in ["my_mod.7z.001", "my_mod.7z.003", "my_mod.7z.005", "other.7z.002"] {
+            std::fs::write(temp.path().join(volume), "").unwrap();
+        }
+
+        let error = find_split_archive(&temp.path().join("my_mod.7z.001")).unwrap_err().to_string();
+
+        assert!(error.contains("my_mod.7z.002, my_mod.7z.004"), "{}", error);
+        assert!(!error.contains("other"), "{}", error);
+    }
+
+    #[test]
+    fn read_volumes_as_one_file() {
+        let split = find_split_archive(&archives().join("my_mod_split.zip.001")).unwrap().unwrap();
+        let whole = std::fs::read(archives().join("my_mod.zip")).unwrap();
+        let mut reader = VolumeReader::open(&split.volumes).unwrap();
+
+        let mut content = vec![];
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, whole);
+
+        // across the volume boundary (the first volume has 300 bytes)
+        reader.seek(SeekFrom::End(-250)).unwrap();
+        let mut part = [0u8; 100];
+        reader.read_exact(&mut part).unwrap();
+        assert_eq!(&part[..], &whole[whole.len() - 250..whole.len() - 150]);
+    }
+}
@@ -0,0 +1,209 @@
+
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::args::Install;
+use crate::cache::Cache;
+use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::download::Downloader;
+use crate::lowercase::LwcString;
+use crate::module::file_mod::FileModule;
+use crate::module::gen_mod::GeneratedMod;
+use crate::module::location::github::GithubDescriptor;
+use crate::module::location::location::ConcreteLocation;
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::obtain::get_module::ModuleDownload;
+use crate::patch_source::{PatchDesc, PatchSource};
+
+/// Describes how a module of the manifest would be obtained and installed (`install --explain`).<br>
+/// Nothing is downloaded, extracted or installed: the plan only uses the manifest and the config.
+pub fn explain_module(manifest: &Manifest, module_name: &LwcString, opts: &Install, config: &Config,
+                        game_dir: &CanonPath, cache: &Cache) -> Result<String> {
+    let fragments = manifest.modules.iter().enumerate()
+        .filter(|(_, module)| module.get_name() == module_name)
+        .collect::<Vec<_>>();
+    let module = match fragments.first() {
+        None => bail!("Module {} is not in the manifest", module_name),
+        Some((_, module)) => *module,
+    };
+    let mut plan = String::new();
+    writeln!(plan, "module {} (fragments {})", module_name,
+                fragments.iter().map(|(index, _)| index + 1).join(", "))?;
+    writeln!(plan, "  target: {:?}", game_dir.join_path(module_name.as_ref()))?;
+    match module {
+        Module::Mod { weidu_mod } => {
+            let downloader = Downloader::new();
+            let module_download = ModuleDownload::new(config, &manifest.global, &manifest.locations, opts,
+                                                        &downloader, game_dir, cache);
+            let location = module_download.get_module_location(weidu_mod)?;
+            let archive = module_download.archive_path(location, module_name)?;
+            explain_location(&mut plan, location, module_name, &archive, config)?;
+        }
+        Module::Generated { gen } => explain_generated(&mut plan, gen)?,
+        Module::File { file } => explain_file(&mut plan, file, game_dir)?,
+    }
+    Ok(plan)
+}
+
+fn explain_location(plan: &mut String, location: &ConcreteLocation, module_name: &LwcString,
+                    archive: &std::path::Path, config: &Config) -> Result<()> {
+    match &location.source {
+        Source::Http(http) => writeln!(plan, "  source: {}", http.http)?,
+        Source::Github(github) => match (github.archive_url(), &github.descriptor) {
+            (Some(url), _) => writeln!(plan, "  source: {}", url)?,
+            (None, GithubDescriptor::Release { release, asset }) =>
+                writeln!(plan, "  source: asset {} of release {} in github repository {}/{} (URL obtained from the github API)",
+                            asset, release.as_deref().unwrap_or("latest"), github.github_user, github.repository)?,
+            (None, _) => writeln!(plan, "  source: github repository {}/{}", github.github_user, github.repository)?,
+        },
+        Source::Absolute { .. } | Source::Local { .. } => writeln!(plan, "  source: local files, read in place")?,
+    }
+    match &location.source {
+        Source::Http(_) | Source::Github(_) => writeln!(plan, "  cache path: {:?}", archive)?,
+        Source::Absolute { .. } | Source::Local { .. } => writeln!(plan, "  path: {:?}", archive)?,
+    }
+    match location.extract_location.as_ref().or(config.extract_location.as_ref()) {
+        None => writeln!(plan, "  extract location: system temporary directory")?,
+        Some(extract_location) => writeln!(plan, "  extract location: {}", extract_location)?,
+    }
+    if let Some(precopy) = &location.precopy {
+        let (command, args) = precopy.for_current_os()?;
+        writeln!(plan, "  precopy: {} {}", command, args.join(" "))?;
+    }
+    let layout = location.layout();
+    let globs = layout.to_glob(module_name, &location.source);
+    writeln!(plan, "  strip depth: {}", globs.strip)?;
+    if layout.auto_strip_single_root.unwrap_or(false) {
+        writeln!(plan, "  single root directory: stripped")?;
+    }
+    writeln!(plan, "  globs: {}", globs.patterns.join(", "))?;
+    if let Some(normalize) = &location.normalize {
+        if !normalize.line_endings.is_empty() {
+            writeln!(plan, "  normalize line endings: {}", normalize.line_endings.join(", "))?;
+        }
+        if !normalize.strip_bom.is_empty() {
+            writeln!(plan, "  strip UTF-8 BOM: {}", normalize.strip_bom.join(", "))?;
+        }
+        if !normalize.executable.is_empty() {
+            writeln!(plan, "  make executable: {}", normalize.executable.join(", "))?;
+        }
+    }
+    for (pattern, dest) in location.relocate.iter().flatten() {
+        writeln!(plan, "  relocate: {} to {}", pattern, dest)?;
+    }
+    for patch in location.patch.iter().chain(&location.patches) {
+        writeln!(plan, "  patch: {}", describe_patch(patch))?;
+    }
+    for replace in location.replace.iter().flatten() {
+        writeln!(plan, "  replace: {:?} with {:?} in {}", replace.replace, replace.with, replace.file_globs.join(", "))?;
+    }
+    Ok(())
+}
+
+fn explain_generated(plan: &mut String, gen: &GeneratedMod) -> Result<()> {
+    writeln!(plan, "  generated mod, component {}", gen.component.index)?;
+    for file in &gen.files {
+        writeln!(plan, "  files: {:?}", file)?;
+    }
+    Ok(())
+}
+
+fn explain_file(plan: &mut String, file: &FileModule, game_dir: &CanonPath) -> Result<()> {
+    writeln!(plan, "  file module, no weidu mod")?;
+    writeln!(plan, "  files: {:?}", file.from)?;
+    writeln!(plan, "  destination: {:?}", file.destination(game_dir)?)?;
+    if let Some(requires_file) = &file.requires_file {
+        writeln!(plan, "  only if present: {}", requires_file)?;
+    }
+    if let Some(forbids_file) = &file.forbids_file {
+        writeln!(plan, "  only if absent: {}", forbids_file)?;
+    }
+    Ok(())
+}
+
+fn describe_patch(patch: &PatchDesc) -> String {
+    match &patch.patch_source {
+        PatchSource::Inline { .. } => format!("inline ({:?})", patch.encoding),
+        PatchSource::Http { http } => format!("{} ({:?})", http, patch.encoding),
+        PatchSource::Relative { relative } => format!("{} ({:?})", relative, patch.encoding),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::test_utils::manifest;
+
+    use super::explain_module;
+
+    #[test]
+    fn explain_github_tag_module() {
+        let manifest = manifest(r#"
+modules:
+  - name: my_mod
+    components: [1]
+    location:
+      github_user: some_user
+      repository: my-mod
+      tag: v1.2
+      layout:
+        layout_type: single_dir_plus_tp2
+      replace:
+        - file_globs: ["*.tp2"]
+          replace: "VERSION ~1~"
+          with: "VERSION ~2~"
+"#);
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let config = Config { extract_location: Some("/extract".to_string()), ..Config::default() };
+
+        let plan = explain_module(&manifest, &lwc!("my_mod"), &Install::default(), &config, &game_dir, &cache).unwrap();
+
+        assert!(plan.contains("source: https://codeload.github.com/some_user/my-mod/zip/refs/tags/v1.2"), "{}", plan);
+        assert!(plan.contains(&format!("cache path: {:?}", PathBuf::from("/cache_path/github/some_user/my-mod/my_mod-v1.2.zip"))), "{}", plan);
+        assert!(plan.contains("extract location: /extract"), "{}", plan);
+        assert!(plan.contains("strip depth: 1"), "{}", plan);
+        assert!(plan.contains("globs: my_mod, setup-my_mod.tp2"), "{}", plan);
+        assert!(plan.contains("replace: \"VERSION ~1~\" with \"VERSION ~2~\" in *.tp2"), "{}", plan);
+    }
+
+    #[test]
+    fn explain_file_module() {
+        let manifest = manifest(r#"
+modules:
+  - file_mod: my_docs
+    from:
+      local: docs
+    to: Documentation
+    requires_file: chitin.key
+"#);
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+
+        let plan = explain_module(&manifest, &lwc!("my_docs"), &Install::default(), &Config::default(), &game_dir, &cache).unwrap();
+
+        assert!(plan.contains(&format!("destination: {:?}", game_dir.path().join("Documentation"))), "{}", plan);
+        assert!(plan.contains("only if present: chitin.key"), "{}", plan);
+    }
+
+    #[test]
+    fn explain_unknown_module() {
+        let manifest = manifest("modules: []\n");
+        let game_dir = CanonPath::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+
+        let error = explain_module(&manifest, &lwc!("nope"), &Install::default(), &Config::default(), &game_dir, &cache).unwrap_err();
+        assert!(error.to_string().contains("not in the manifest"));
+    }
+}
@@ -17,7 +17,7 @@ use crate::module::manifest_conditions::ManifestConditions;
 use crate::module::module::Module;
 use crate::module::weidu_mod::BareMod;
 use crate::unique_component::UniqueComponent;
-use crate::weidu_conf::read_weidu_conf_lang_dir;
+use crate::weidu_conf::{default_lang_preferences, read_weidu_conf_lang_dir};
 
 pub fn extract_bare_mods() -> Result<Vec<BareMod>> {
     let log_rows = parse_weidu_log(None)?;
@@ -67,23 +67,14 @@ pub fn generate_manifest(game_dir: &CanonPath, modules: Vec<Module>) -> Result<M
         manifest_conditions: ManifestConditions::default(),
         global: Global {
             game_language: lang_dir.clone(),
-            lang_preferences: default_lang_pref(&lang_dir),
+            lang_preferences: default_lang_preferences(&lang_dir),
             ..Default::default()
         },
         modules,
+        remove_modules: vec![],
     })
 }
 
-fn default_lang_pref(lang_dir: &str) -> Option<Vec<String>> {
-    match lang_dir {
-        "fr_FR" => Some(vec!["#rx#^fran[cç]ais".to_string(), "french".to_string()]),
-        "en_US" => Some(vec!["english".to_string(), "american english".to_string()]),
-        "es_ES" => Some(vec!["#rx#^espa[ñn]ol".to_string(), "spanish".to_string()]),
-        // some more...
-        _ => None,
-    }
-}
-
 fn bare_mod_from_log_row(row: &LogRow) -> BareMod {
     let components = vec![
         FullComponent {
@@ -1,11 +1,13 @@
 
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::io::BufWriter;
 use std::path::{PathBuf, Path};
+use std::time::Duration;
 
 use nu_ansi_term::Color::{Blue, Green, Red};
 use anyhow::{Result, anyhow, bail};
-use chrono::Local;
+use chrono::{DateTime, Local};
 use itertools::Itertools;
 use log::{debug, info, error, warn};
 
@@ -13,29 +15,75 @@ use crate::args::Install;
 use crate::cache::Cache;
 use crate::canon_path::CanonPath;
 use crate::module::components::{Components, Component, FullComponent};
-use crate::download::Downloader;
-use crate::file_installer::FileInstaller;
+use crate::download::{Downloader, ProxySetting, UrlRewrites, DEFAULT_DOWNLOAD_RATE_LIMIT_WAIT};
+use crate::file_installer::{FileInstaller, SYNC_DIR};
+use crate::install_journal::{journal_source, InstallJournal, JournalAction, JournalStatus};
+use crate::install_lock::{lock_written, modules_changed_since, read_lock, write_lock};
+use crate::jobs::{Jobs, Pool};
 use crate::lowercase::{lwc, LwcString};
 use crate::module::disable_condition::DisableOutCome;
+use crate::module::expected_files::check_expected_files;
 use crate::module::module::Module;
 use crate::module::weidu_mod::WeiduMod;
 use crate::obtain::get_module::ModuleDownload;
 use crate::post_install::PostInstallOutcome;
 use crate::log_parser::{check_install_complete, parse_weidu_log, LogRow};
 use crate::module::manifest::Manifest;
-use crate::process_weidu_mod::{process_generated_mod, process_weidu_mod, ProcessResult};
+use crate::process_weidu_mod::{process_file_module, process_generated_mod, process_weidu_mod, ProcessResult};
 use crate::config::Config;
 use crate::timeline::InstallTimeline;
+use crate::tp2::find_tp2;
 use crate::unique_component::UniqueComponent;
-use crate::weidu_conf::check_weidu_conf_lang;
-use crate::modda_context::ModdaContext;
+use crate::weidu_conf::{check_weidu_conf_lang, complete_lang_from_weidu_conf};
+use crate::modda_context::{ModdaContext, WeiduContext};
+use crate::run_weidu::check_weidu_version;
+use crate::run_warnings::WarningCollector;
 
+use super::explain::explain_module;
 use super::extract_manifest::extract_unique_components;
 
+/// Subdirectory of the archive cache where manifests obtained with `--manifest-url` are kept.
+const MANIFEST_CACHE_DIR: &str = "manifests";
+
 pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &Cache) -> Result<()> {
+    let warnings = WarningCollector::new();
+    let result = install_with_warnings(opts, settings, game_dir, cache, &warnings);
+    // shown even if the installation failed (the error comes after)
+    warnings.report();
+    result
+}
+
+/// Same as `install`, but the warnings of the run are left in `warnings` (not printed at the end).
+pub fn install_with_warnings(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &Cache,
+                                warnings: &WarningCollector) -> Result<()> {
 
-    let manifest = Manifest::assemble_from_path(&opts.manifest_path, &opts.get_manifest_root(game_dir))?;
-    check_weidu_conf_lang(game_dir, &manifest.global.game_language)?;
+    let fetched_opts;
+    let manifest_root = opts.get_manifest_root(game_dir)?;
+    let (mut manifest, opts) = match &opts.manifest_url {
+        None => (Manifest::assemble_from_path(&opts.manifest_path, &manifest_root, settings)?, opts),
+        Some(url) => {
+            let downloader = Downloader::new()
+                .with_proxy(ProxySetting::from_config(&settings.proxy))
+                .with_url_rewrites(UrlRewrites::new(settings.url_rewrites.as_deref().unwrap_or_default())?);
+            let (manifest, local_copy) = Manifest::assemble_from_url(url, &downloader, &cache.join(MANIFEST_CACHE_DIR),
+                                                                        &manifest_root, settings)?;
+            // from now on, the downloaded copy is used as the manifest file (for `--record`)
+            fetched_opts = Install { manifest_path: local_copy.to_string_lossy().to_string(), ..opts.clone() };
+            (manifest, &fetched_opts)
+        }
+    };
+    complete_lang_from_weidu_conf(&mut manifest.global, game_dir)?;
+    if let Some(module_name) = &opts.explain {
+        let plan = explain_module(&manifest, module_name, opts, settings, game_dir, cache)?;
+        println!("{}", plan);
+        return Ok(());
+    }
+    manifest.validate_local_paths(manifest_root.path())?;
+    check_weidu_conf_lang(game_dir, manifest.global.lang_dir()?)?;
+    if let Some(requirement) = &manifest.global.weidu_version {
+        let weidu_context = WeiduContext { config: settings, current_dir: game_dir };
+        check_weidu_version(&weidu_context, requirement, "`global.weidu_version` in the manifest")?;
+    }
     let modules = &manifest.modules;
 
     let log = if let Some(output) = &opts.output {
@@ -51,25 +99,91 @@ pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &
         None
     };
 
-    let modules = get_modules_range(&modules, opts)?;
+    let (first_index, modules) = get_modules_range(&modules, opts)?;
     if modules.is_empty() {
         info!("{}", Green.paint("Nothing to install with given range"));
         return Ok(())
     }
 
-    let downloader = Downloader::new();
+    let jobs = Jobs::resolve(opts.jobs, settings)?;
+    debug!("concurrency limits: {:?}", jobs);
+    let extract_pool = Pool::new(jobs.extract);
+    let glob_pool = Pool::new(jobs.glob);
+
+    let downloader = Downloader::new()
+        .with_max_rate_limit_wait(settings.download_rate_limit_max_wait
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_DOWNLOAD_RATE_LIMIT_WAIT))
+        .with_max_bytes_per_sec(settings.max_bytes_per_sec)
+        .with_proxy(ProxySetting::from_config(&settings.proxy))
+        .with_url_rewrites(UrlRewrites::new(settings.url_rewrites.as_deref().unwrap_or_default())?)
+        .with_external_downloader(settings.external_downloader.clone());
     let module_downloader = ModuleDownload::new(&settings, &manifest.global, &manifest.locations, &opts,
-                                                                        &downloader, &game_dir, cache);
-    let file_installer = FileInstaller::new(&manifest.global, &opts, &game_dir);
+                                                                        &downloader, &game_dir, cache)
+                                                                        .with_warnings(warnings)
+                                                                        .with_extract_pool(&extract_pool);
+    let file_installer = FileInstaller::new(&manifest.global, &manifest_root, &game_dir, settings)
+        .with_warnings(warnings)
+        .with_glob_pool(&glob_pool);
+    let sync_dir = game_dir.join_path(SYNC_DIR);
+    let file_installer = if opts.sync_files { file_installer.with_sync_dir(&sync_dir) } else { file_installer };
 
     let modda_context = ModdaContext { current_dir: game_dir, config: &settings, opts: &opts,
                                                     module_downloader: &module_downloader, file_installer: &file_installer,
-                                                    log: RefCell::from(log) };
+                                                    jobs, log: RefCell::from(log), warnings };
+
+    // nothing is installed with --dry-run, so nothing to record
+    let mut journal = if opts.dry_run { None } else { Some(InstallJournal::open(game_dir.path())?) };
+
+    // --since-lock doesn't allow a range, positions in the range are positions in the manifest
+    let since_lock = match &opts.since_lock {
+        None => None,
+        Some(lock_path) => Some(SinceLock {
+            changed: modules_changed_since(read_lock(Path::new(lock_path))?.as_ref(), &manifest),
+            written: lock_written(Path::new(lock_path))?,
+        }),
+    };
+
+    // with `jobs: 1`, each archive is downloaded when its module is installed
+    if !modda_context.jobs.is_serial() {
+        let to_download = modules.iter().enumerate()
+            .filter(|(index, module)| matches!(module_action(*index, index + first_index + 1, module, &since_lock, &journal, opts.force),
+                                                Some(JournalAction::Install | JournalAction::Resume)))
+            .filter_map(|(_, module)| match module {
+                Module::Mod { weidu_mod } => Some(weidu_mod),
+                Module::Generated { .. } | Module::File { .. } => None,
+            })
+            .filter(|weidu_mod| find_tp2(game_dir, &weidu_mod.name).is_err()
+                                || weidu_mod.clean_reinstall.or(manifest.global.clean_reinstall).unwrap_or(false))
+            .collect_vec();
+        module_downloader.prefetch(&to_download, modda_context.jobs.download);
+    }
 
     let mut timelines = vec![];
     for (index, module) in modules.iter().enumerate() {
-        let real_index = index + opts.from_index.unwrap_or(0) + 1;
-        info!("module {} - {}", real_index, module.describe());
+        let real_index = index + first_index + 1;
+        let journal_action = match module_action(index, real_index, module, &since_lock, &journal, opts.force) {
+            None => {
+                info!("module {} - {} didn't change since the lock, skipped", real_index, module.describe());
+                timelines.push(InstallTimeline::skipped(module.get_name().clone(), "unchanged since the lock"));
+                continue;
+            }
+            Some(action) => action,
+        };
+        match journal_action {
+            JournalAction::Skip => {
+                info!("module {} - {} was completed by a previous run, skipped (use --force to install it again)",
+                        real_index, module.describe());
+                timelines.push(InstallTimeline::skipped(module.get_name().clone(), "completed by a previous run"));
+                continue;
+            }
+            JournalAction::Resume => warn!("module {} - {} was interrupted in a previous run, installing it again",
+                                            real_index, module.describe()),
+            JournalAction::Install => {}
+        }
+        let start_message = module_start_message(real_index, module);
+        info!("{}", start_message);
+        modda_context.log(&start_message)?;
         debug!("{:?}", module);
 
         match check_safely_installable(module)? {
@@ -83,20 +197,24 @@ pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &
                 bail!("Aborting - proceeding with `install` is unsafe (could uninstall then install modules repeatedly)");
             }
         }
-        let process_result = match module.check_disabled(&opts.get_manifest_root(game_dir), &manifest.manifest_conditions) {
+        let process_result = match module.check_disabled(&manifest_root, &manifest.manifest_conditions) {
             Ok(DisableOutCome::No(reason)) => {
                 if let Some(reason) = reason {
                     info!("module {name} is not disabled - {reason}", name = module.get_name());
                 }
+                if let Some(journal) = &mut journal {
+                    journal.record(JournalStatus::Started, real_index, module.get_name(), &journal_source(&manifest, module))?;
+                }
                 match module {
                     Module::Mod { weidu_mod } =>
                         install_weidu(weidu_mod, &modda_context, &manifest, opts, index, real_index)?,
                     Module::Generated { gen } =>
                         process_generated_mod(gen, &modda_context, &manifest, real_index)?,
+                    Module::File { file } => process_file_module(file, &modda_context)?,
                 }
             }
             Ok(DisableOutCome::Yes(reason)) => {
-                info!("module {name} is disabled - {reason}", name = module.get_name());
+                warnings.warn(Some(module.get_name()), "disabled", &format!("module skipped - {reason}"));
                 ProcessResult {
                     stop: false,
                     timeline: InstallTimeline::new(lwc!(&format!("{} - disabled", module.get_name())), Local::now()),
@@ -121,6 +239,7 @@ pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &
         } else {
             match module.exec_post_install(&module.get_name()) {
                 PostInstallOutcome::Stop => {
+                    record_completed(&mut journal, real_index, module, &manifest)?;
                     info!("{}",  Blue.bold().paint(format!("Interruption requested for module {} - {}",
                                                             real_index, module.describe())));
                     return Ok(());
@@ -131,15 +250,61 @@ pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &
         // Now check we actually installed all requested components
         // if dry_run, nothing will have been installed at all so don't check
         if !opts.dry_run {
-            check_install_complete(&module)?
+            check_install_complete(&module)?;
+            check_expected_files(module, game_dir.path(), warnings)?;
         }
+        record_completed(&mut journal, real_index, module, &manifest)?;
     }
     info!("Installation done with no error");
+    if let (Some(lock_path), false) = (&opts.since_lock, opts.dry_run) {
+        write_lock(Path::new(lock_path), &manifest)?;
+        info!("lock {} updated", lock_path);
+    }
     timelines.push(InstallTimeline::new(lwc!("finished"), Local::now()));
     handle_timeline(opts.timeline, &timelines);
     Ok(())
 }
 
+/// Logged (and written to the `--output` log) when a module starts; has the module description if there is one.
+fn module_start_message(index: usize, module: &Module) -> String {
+    format!("module {} - {}", index, module.describe())
+}
+
+/// The lock of `--since-lock`, compared with the manifest.
+struct SinceLock {
+    /// Positions of the modules that changed since the lock.
+    changed: HashSet<usize>,
+    /// `None` when there was no lock yet.
+    written: Option<DateTime<Local>>,
+}
+
+/// What the run does with the module at `index` (in the range, `real_index` in the manifest), `None` when it didn't
+/// change since the lock of `--since-lock`.
+fn module_action(index: usize, real_index: usize, module: &Module, since_lock: &Option<SinceLock>,
+                    journal: &Option<InstallJournal>, force: bool) -> Option<JournalAction> {
+    if since_lock.as_ref().is_some_and(|since_lock| !since_lock.changed.contains(&index)) {
+        return None;
+    }
+    let journal = match journal {
+        None => return Some(JournalAction::Install),
+        Some(journal) => journal,
+    };
+    // a module that changed is installed again even if the journal has it, unless it was completed after
+    // the lock was written (by a run that stopped before the lock was updated)
+    let reinstall = since_lock.as_ref()
+        .is_some_and(|since_lock| !journal.completed_since(real_index, module.get_name(), since_lock.written));
+    Some(journal.action(real_index, module.get_name(), force || reinstall))
+}
+
+/// Only for modules that were actually processed (the journal has a `started` line for them).
+fn record_completed(journal: &mut Option<InstallJournal>, index: usize, module: &Module, manifest: &Manifest) -> Result<()> {
+    match journal {
+        Some(journal) if journal.last_status(index, module.get_name()) == Some(JournalStatus::Started) =>
+            journal.record(JournalStatus::Completed, index, module.get_name(), &journal_source(manifest, module)),
+        _ => Ok(()),
+    }
+}
+
 fn install_weidu(weidu_mod: &WeiduMod, modda_context: &ModdaContext, manifest: &Manifest,
                 opts: &Install, index: usize, real_index: usize) -> Result<ProcessResult> {
     let result = process_weidu_mod(weidu_mod, &modda_context, &manifest, real_index)?;
@@ -152,10 +317,23 @@ fn install_weidu(weidu_mod: &WeiduMod, modda_context: &ModdaContext, manifest: &
     Ok(result)
 }
 
-fn  get_modules_range<'a>(modules: &'a[Module], opts: &Install) -> Result<&'a [Module]> {
+/// Installs the module named `module_name` alone (like `--module`); fails if it is not in the manifest.
+pub fn install_module(module_name: &LwcString, opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &Cache) -> Result<()> {
+    let opts = Install { module: Some(module_name.clone()), ..opts.clone() };
+    install(&opts, settings, game_dir, cache)
+}
+
+/// The modules selected by the range options or `--module`, with the offset used for the module numbers
+/// (the 0-based position of the module for `--module`).
+fn  get_modules_range<'a>(modules: &'a[Module], opts: &Install) -> Result<(usize, &'a [Module])> {
+    if let Some(module_name) = &opts.module {
+        let position = named_module_position(modules, module_name)?;
+        return Ok((position, &modules[position..(position + 1)]));
+    }
+    let offset = opts.from_index.unwrap_or(0);
     let from_index = match opts.from_index {
         Some(from_index) => if from_index > modules.len() {
-            return Ok(&modules[0..0]);
+            return Ok((offset, &modules[0..0]));
         } else {
             from_index - 1
         }
@@ -163,7 +341,7 @@ fn  get_modules_range<'a>(modules: &'a[Module], opts: &Install) -> Result<&'a [M
     };
     let result = match (opts.to_index, opts.just_one, opts.count) {
         (Some(to_index), false, None) => if from_index > to_index {
-            return Ok(&modules[0..0]);
+            return Ok((offset, &modules[0..0]));
         } else if to_index > modules.len() {
             &modules[(from_index)..]
         } else {
@@ -179,7 +357,17 @@ fn  get_modules_range<'a>(modules: &'a[Module], opts: &Install) -> Result<&'a [M
         _ => bail!("incompatible arguments given"),
     };
     debug!("range: {:?}", result);
-    Ok(result)
+    Ok((offset, result))
+}
+
+fn named_module_position(modules: &[Module], module_name: &LwcString) -> Result<usize> {
+    let positions = modules.iter().positions(|module| module.get_name() == module_name).collect::<Vec<_>>();
+    match positions.as_slice() {
+        [] => bail!("Module {} is not in the manifest", module_name),
+        [position] => Ok(*position),
+        _ => bail!("Module {} is in the manifest {} times (modules {})\n -> use `--from-index <module> --just-one` to install one of them",
+                    module_name, positions.len(), positions.iter().map(|position| position + 1).join(", ")),
+    }
 }
 
 fn check_safely_installable(module: &Module) -> Result<SafetyResult> {
@@ -210,8 +398,9 @@ fn check_safely_installable(module: &Module) -> Result<SafetyResult> {
             }
         }
         Components::List(list) => {
-            let matches = list.iter().fold(vec![], |mut matches, current| {
-                let current = UniqueComponent { mod_key: module.get_name().to_owned(), index: current.index() };
+            // named components can't be checked before the mod tp2 is read
+            let matches = list.iter().filter_map(|current| current.index()).fold(vec![], |mut matches, index| {
+                let current = UniqueComponent { mod_key: module.get_name().to_owned(), index };
                 if installed.contains(&current) {
                     matches.push(current);
                     matches
@@ -225,6 +414,7 @@ fn check_safely_installable(module: &Module) -> Result<SafetyResult> {
                 Ok(SafetyResult::Safe)
             }
         }
+        Components::File { file } => bail!("Components file {} for mod {} was not loaded", file, module.get_name()),
     }
 }
 
@@ -256,12 +446,21 @@ fn record_selection(index: usize, module: &WeiduMod, output_file: &str, original
         Manifest::read_path_convert_comments(original_manifest_path)?
     };
 
-    let previous_mod = record_manifest.modules[..index].iter().rev().find(|item| match item.get_components() {
-        Components::List(_) => true,
-        Components::Ask => true,
-        Components::All => true,
-        Components::None => false,
-    });
+    // `index` is in install order, which is not the order in the file when modules have an `order`
+    let install_order = record_manifest.install_order();
+    let position = match install_order.get(index) {
+        None => bail!("No module at index {} in manifest {:?}", index, original_manifest_path),
+        Some(position) => *position,
+    };
+    let previous_mod = install_order[..index].iter().rev()
+            .map(|position| &record_manifest.modules[*position])
+            .find(|item| match item.get_components() {
+                Components::List(_) => true,
+                Components::Ask => true,
+                Components::All => true,
+                Components::File { .. } => true,
+                Components::None => false,
+            });
     debug!("record_selection- previous_mod={:?}", previous_mod);
 
     let selection_rows = match previous_mod {
@@ -273,11 +472,12 @@ fn record_selection(index: usize, module: &WeiduMod, output_file: &str, original
                 Components::Ask => bail!("components for previous mod fragment were not recorded"),
                 Components::All => bail!("components for previous mod fragment were not recorded"),
                 Components::None => bail!("search incorrectly returned a 'none' component list"),
+                Components::File { file } => bail!("components for previous mod fragment are in file {} and were not recorded", file),
             };
             let previous_name = previous.get_name();
             debug!("record_selection- previous_components={:?}, previous_name={}", previous_components, previous_name);
             let previous_match = log_rows.iter().enumerate().rev().find(|(_, row)| {
-                let result = previous_name == &row.module && previous_components.iter().any(|comp| comp.index() == row.component_index);
+                let result = previous_name == &row.module && previous_components.iter().any(|comp| comp.index() == Some(row.component_index));
                 debug!("{:?} ? {}", row, result);
                 result
             });
@@ -299,8 +499,8 @@ fn record_selection(index: usize, module: &WeiduMod, output_file: &str, original
         } else{
             Components::List(selection)
         };
-        debug!("replace {:?} at position {}", components, index);
-        record_manifest.modules[index] = Module::Mod { weidu_mod: WeiduMod {
+        debug!("replace {:?} at position {}", components, position);
+        record_manifest.modules[position] = Module::Mod { weidu_mod: WeiduMod {
             components,
             ..module.to_owned()
         } };
@@ -326,3 +526,188 @@ fn confirm_record(no_confirm_flag: bool, selection: &[&LogRow], module_name: &Lw
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{Duration, Local};
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::file_installer::SYNC_DIR;
+    use crate::install_journal::{InstallJournal, JournalAction, JournalStatus};
+    use crate::lowercase::lwc;
+    use crate::module::module::Module;
+    use crate::test_utils::test_dir;
+
+    use super::{get_modules_range, install, install_module, module_action, module_start_message, SinceLock};
+
+    /// A game directory with a manifest of file modules (no weidu needed to install them).
+    fn file_modules_game_dir() -> (tempfile::TempDir, CanonPath, Install) {
+        let temp_dir = test_dir();
+        let docs = temp_dir.path().join("files").join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("readme.txt"), "read me").unwrap();
+        let manifest_path = temp_dir.path().join("manifest.yml");
+        std::fs::write(&manifest_path, r#"
+version: "1"
+global:
+  lang_dir: fr_FR
+  local_files: files
+modules:
+  - file_mod: docs
+    from:
+      local: docs
+    to: Documentation
+  - file_mod: dlc_docs
+    from:
+      local: docs
+    to: dlc
+    requires_file: sod-dlc.zip
+"#).unwrap();
+        let game_dir = CanonPath::new(temp_dir.path()).unwrap();
+        let opts = Install { manifest_path: manifest_path.to_string_lossy().to_string(), ..Default::default() };
+        (temp_dir, game_dir, opts)
+    }
+
+    #[test]
+    fn install_file_modules() {
+        let (temp_dir, game_dir, opts) = file_modules_game_dir();
+        let cache = Cache::Path(temp_dir.path().join("cache"));
+
+        install(&opts, &Config::default(), &game_dir, &cache).unwrap();
+
+        assert_eq!(std::fs::read_to_string(game_dir.join_path("Documentation/readme.txt")).unwrap(), "read me");
+        assert!(!game_dir.join_path("dlc").exists());
+    }
+
+    #[test]
+    fn sync_files_only_copies_changed_files() {
+        let (temp_dir, game_dir, opts) = file_modules_game_dir();
+        let cache = Cache::Path(temp_dir.path().join("cache"));
+        let opts = Install { sync_files: true, force: true, ..opts };
+        let readme = game_dir.join_path("Documentation/readme.txt");
+
+        install(&opts, &Config::default(), &game_dir, &cache).unwrap();
+        std::fs::write(&readme, "edited in the game").unwrap();
+        install(&opts, &Config::default(), &game_dir, &cache).unwrap();
+
+        assert!(game_dir.join_path(SYNC_DIR).join("docs.stamp").is_file());
+        assert_eq!(std::fs::read_to_string(readme).unwrap(), "edited in the game");
+    }
+
+    #[test]
+    fn install_module_installs_only_the_named_module() {
+        let (temp_dir, game_dir, opts) = file_modules_game_dir();
+        let cache = Cache::Path(temp_dir.path().join("cache"));
+        std::fs::write(game_dir.join_path("sod-dlc.zip"), "").unwrap();
+
+        install_module(&lwc!("dlc_docs"), &opts, &Config::default(), &game_dir, &cache).unwrap();
+        let error = install_module(&lwc!("nope"), &opts, &Config::default(), &game_dir, &cache).unwrap_err();
+
+        assert!(game_dir.join_path("dlc/readme.txt").is_file());
+        assert!(!game_dir.join_path("Documentation").exists());
+        assert!(error.to_string().contains("nope"), "{}", error);
+    }
+
+    #[test]
+    fn dry_run_copies_no_file() {
+        let (temp_dir, game_dir, opts) = file_modules_game_dir();
+        let cache = Cache::Path(temp_dir.path().join("cache"));
+        let opts = Install { dry_run: true, ..opts };
+
+        install(&opts, &Config::default(), &game_dir, &cache).unwrap();
+
+        assert!(!game_dir.join_path("Documentation").exists());
+    }
+
+    fn modules() -> Vec<Module> {
+        serde_yaml::from_str("
+            - name: dlcmerger
+              components: [1]
+            - name: bg1npc
+              components: [0]
+            - name: tweaks
+              components: [10]
+            - name: bg1npc
+              components: [20]
+        ").unwrap()
+    }
+
+    #[test]
+    fn start_message_has_the_description() {
+        let module: Module = serde_yaml::from_str("
+            name: bg1npc
+            components: [1, 2]
+            description: NPC project, before the tweaks
+        ").unwrap();
+
+        assert_eq!(module_start_message(4, &module), "module 4 - bg1npc (NPC project, before the tweaks)");
+    }
+
+    #[test]
+    fn start_message_without_description() {
+        let module: Module = serde_yaml::from_str("
+            name: bg1npc
+            components: [1, 2]
+        ").unwrap();
+
+        assert_eq!(module_start_message(4, &module), "module 4 - bg1npc");
+    }
+
+    #[test]
+    fn install_one_module_by_name() {
+        let modules = modules();
+        let opts = Install { module: Some(lwc!("Tweaks")), ..Default::default() };
+
+        let (first_index, selected) = get_modules_range(&modules, &opts).unwrap();
+
+        assert_eq!(first_index, 2);
+        assert_eq!(selected, &modules[2..3]);
+    }
+
+    #[test]
+    fn install_unknown_module_name() {
+        let opts = Install { module: Some(lwc!("unknown")), ..Default::default() };
+
+        let error = get_modules_range(&modules(), &opts).unwrap_err().to_string();
+
+        assert_eq!(error, "Module unknown is not in the manifest");
+    }
+
+    #[test]
+    fn install_module_name_found_twice() {
+        let opts = Install { module: Some(lwc!("bg1npc")), ..Default::default() };
+
+        let error = get_modules_range(&modules(), &opts).unwrap_err().to_string();
+
+        assert!(error.contains("2 times (modules 2, 4)"), "{}", error);
+    }
+
+    #[test]
+    fn changed_module_completed_after_the_lock_is_not_installed_again() {
+        let game_dir = test_dir();
+        let modules = modules();
+        let mut journal = InstallJournal::open(game_dir.path()).unwrap();
+        journal.record(JournalStatus::Started, 2, &lwc!("bg1npc"), "files").unwrap();
+        journal.record(JournalStatus::Completed, 2, &lwc!("bg1npc"), "files").unwrap();
+        let journal = Some(journal);
+        let since_lock = |written| Some(SinceLock { changed: HashSet::from([1, 2]), written });
+
+        // the run that completed bg1npc stopped before writing the lock
+        let before = Some(Local::now() - Duration::hours(1));
+        assert_eq!(module_action(0, 1, &modules[0], &since_lock(before), &journal, false), None);
+        assert_eq!(module_action(1, 2, &modules[1], &since_lock(before), &journal, false), Some(JournalAction::Skip));
+        assert_eq!(module_action(2, 3, &modules[2], &since_lock(before), &journal, false), Some(JournalAction::Install));
+        assert_eq!(module_action(1, 2, &modules[1], &since_lock(None), &journal, false), Some(JournalAction::Skip));
+        assert_eq!(module_action(1, 2, &modules[1], &since_lock(before), &journal, true), Some(JournalAction::Install));
+
+        // bg1npc changed since the lock written after its installation
+        let after = Some(Local::now() + Duration::hours(1));
+        assert_eq!(module_action(1, 2, &modules[1], &since_lock(after), &journal, false), Some(JournalAction::Install));
+        assert_eq!(module_action(1, 2, &modules[1], &None, &journal, false), Some(JournalAction::Skip));
+    }
+}
@@ -1,6 +1,8 @@
 
-use crate::args::Invalidate;
-use crate::cache::Cache;
+use crate::args::{manifest_root_from_path, Invalidate};
+use crate::cache::{archive_metadata_path, Cache};
+use crate::canon_path::CanonPath;
+use crate::config::Config;
 use crate::lowercase::lwc;
 use crate::module::location::location::{ConcreteLocation, Location};
 use crate::module::location::source::Source;
@@ -11,8 +13,9 @@ use crate::module::weidu_mod::WeiduMod;
 use anyhow::{bail, Result};
 
 
-pub fn invalidate(params: &Invalidate, cache: &Cache) -> Result<()> {
-    let manifest = match Manifest::read_path(&params.manifest_path) {
+pub fn invalidate(params: &Invalidate, current_dir: &CanonPath, cache: &Cache, config: &Config) -> Result<()> {
+    let manifest_root = manifest_root_from_path(&params.manifest_path, current_dir);
+    let manifest = match Manifest::assemble_from_path(&params.manifest_path, &manifest_root, config) {
         Ok(manifest) => manifest,
         Err(error) => bail!("Could not read manifest\n -> {:?}", error),
     };
@@ -24,34 +27,38 @@ pub fn invalidate(params: &Invalidate, cache: &Cache) -> Result<()> {
                 Module::Mod { weidu_mod } => match &weidu_mod.location {
                     None => {} // continue to search a mod with same name and a location location
                     Some(Location::Concrete { concrete }) => {
-                        clear_mod_archive(concrete, &weidu_mod, cache)?;
+                        clear_mod_archive(concrete, &weidu_mod, cache, config)?;
                         return Ok(()) // only once per name
                     }
                     Some(Location::Ref { r#ref }) => {
                         match manifest.locations.find(r#ref) {
                             None => {} // continue to search a mod with same name and a location location
                             Some(location) => {
-                                clear_mod_archive(location, &weidu_mod, cache)?;
+                                clear_mod_archive(location, &weidu_mod, cache, config)?;
                                 return Ok(()) // only once per name
                             }
                         }
                     }
                 }
-                Module::Generated { .. } => return Ok(()), // generated, not downloaded
+                Module::Generated { .. } | Module::File { .. } => return Ok(()), // generated or files, not downloaded
             }
         }
     }
     bail!("Module {} not found or location not provided", mod_name);
 }
 
-fn clear_mod_archive(location: &ConcreteLocation, module :&WeiduMod, cache: &Cache) -> Result<()> {
+fn clear_mod_archive(location: &ConcreteLocation, module :&WeiduMod, cache: &Cache, config: &Config) -> Result<()> {
     match location.source {
         Source::Local {..} | Source::Absolute{..} => bail!("Can't invalidate mods with absolute or local sources"),
         _ => {}
     }
-    let dest = cache.join(location.source.save_subdir()?);
-    let save_name = location.source.save_name(&module.name)?;
-    let archive_path = dest.join(save_name);
+    let archive_path = cache.path_for(&location.source, &module.name, config.cache_shard_depth.unwrap_or(0))?;
+    let metadata_path = archive_metadata_path(&archive_path);
+    if metadata_path.exists() {
+        if let Err(error) = std::fs::remove_file(&metadata_path) {
+            bail!("Could not remove archive metadata {:?}\n -> {:?}", metadata_path, error);
+        }
+    }
     if archive_path.exists() {
         match std::fs::remove_file(&archive_path) {
             Ok(_) => Ok(()),
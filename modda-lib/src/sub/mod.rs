@@ -1,5 +1,7 @@
 
 pub mod append_mod;
+pub mod explain;
 pub mod extract_manifest;
 pub mod install;
 pub mod invalidate;
+pub mod verify;
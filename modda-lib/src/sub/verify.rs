@@ -0,0 +1,213 @@
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use log::info;
+use nu_ansi_term::Color::{Green, Red};
+
+use crate::args::{manifest_root_from_path, Verify};
+use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::file_installer::FileInstaller;
+use crate::lowercase::LwcString;
+use crate::module::disable_condition::DisableOutCome;
+use crate::module::file_mod::FileModule;
+use crate::module::gen_mod::GeneratedMod;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::tp2::find_tp2;
+
+/// Result of the verification of a single module.
+#[derive(Debug, PartialEq)]
+pub struct ModuleCheck {
+    pub name: LwcString,
+    /// Paths (relative to the game directory) that should be present but were not found.
+    pub missing: Vec<PathBuf>,
+}
+
+impl ModuleCheck {
+    pub fn is_complete(&self) -> bool { self.missing.is_empty() }
+}
+
+pub fn verify_install(params: &Verify, game_dir: &CanonPath, config: &Config) -> Result<()> {
+    let manifest_root = manifest_root_from_path(&params.manifest_path, game_dir);
+    let manifest = Manifest::assemble_from_path(&params.manifest_path, &manifest_root, config)?;
+    let file_installer = FileInstaller::new(&manifest.global, &manifest_root, game_dir, config);
+
+    let mut incomplete = 0;
+    for module in &manifest.modules {
+        if let DisableOutCome::Yes(reason) = module.check_disabled(&manifest_root, &manifest.manifest_conditions)? {
+            info!("module {name} is disabled, not checked - {reason}", name = module.get_name());
+            continue;
+        }
+        let check = verify_module(module, game_dir, &file_installer)?;
+        if check.is_complete() {
+            info!("{}", Green.paint(format!("module {} - ok", module.describe())));
+        } else {
+            incomplete += 1;
+            info!("{}", Red.paint(format!("module {} - missing:\n  - {}", module.describe(),
+                    check.missing.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>().join("\n  - "))));
+        }
+    }
+    if incomplete > 0 {
+        bail!("{} module(s) are not completely installed", incomplete);
+    }
+    Ok(())
+}
+
+/// Checks that what the module puts in the game directory is present.
+/// - for weidu mods, only the mod tp2 (in the mod folder or the game directory) is checked
+/// - for generated mods, the tp2 and each file copied from the `files` origins are checked
+/// - for file modules, each file copied from `from` is checked (unless the module's file conditions skip it)
+pub fn verify_module(module: &Module, game_dir: &CanonPath, file_installer: &FileInstaller) -> Result<ModuleCheck> {
+    let missing = match module {
+        Module::Mod { weidu_mod } => match find_tp2(game_dir, &weidu_mod.name) {
+            Ok(_) => vec![],
+            Err(_) => vec![PathBuf::from(weidu_mod.name.as_ref())],
+        }
+        Module::Generated { gen } => verify_generated_mod(gen, game_dir, file_installer)?,
+        Module::File { file } => verify_file_module(file, game_dir, file_installer)?,
+    };
+    Ok(ModuleCheck { name: module.get_name().clone(), missing })
+}
+
+fn verify_generated_mod(gen: &GeneratedMod, game_dir: &CanonPath, file_installer: &FileInstaller) -> Result<Vec<PathBuf>> {
+    let mod_dir = PathBuf::from(gen.gen_mod.as_ref());
+    let data_dir = mod_dir.join("data");
+    let mut expected = vec![mod_dir.join(format!("{}.tp2", gen.gen_mod))];
+    expected.extend(file_installer.expected_destinations(&gen.files.iter().collect::<Vec<_>>(), &data_dir)?);
+
+    Ok(expected.into_iter().filter(|path| !game_dir.join_path(path).exists()).collect())
+}
+
+/// Missing files are relative to the game directory, unless the module copies them outside of it.
+fn verify_file_module(file: &FileModule, game_dir: &CanonPath, file_installer: &FileInstaller) -> Result<Vec<PathBuf>> {
+    if let Some(reason) = file.skip_reason(game_dir.path())? {
+        info!("module {} copies no files - {}", file.file_mod, reason);
+        return Ok(vec![]);
+    }
+    let expected = file_installer.expected_destinations(&[&file.from], &file.destination(game_dir)?)?;
+    Ok(expected.into_iter()
+        .filter(|path| !path.exists())
+        .map(|path| path.strip_prefix(game_dir.path()).map(Path::to_path_buf).unwrap_or(path))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::file_installer::FileInstaller;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::file_mod::FileModule;
+    use crate::module::file_module_origin::FileModuleOrigin;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::test_utils::test_dir;
+
+    use super::verify_module;
+
+    fn generated_mod() -> GeneratedMod {
+        GeneratedMod {
+            gen_mod: lwc!("my_gen"),
+            files: vec![FileModuleOrigin::Absolute {
+                absolute: format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test/file_installer/some_dir"),
+                glob: None,
+                dir_copy: None,
+                literal: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// A game directory and what the `FileInstaller` borrows.
+    struct VerifySetup {
+        _temp_dir: tempfile::TempDir,
+        game_dir: CanonPath,
+        global: Global,
+        config: Config,
+    }
+
+    impl VerifySetup {
+        fn new() -> Self {
+            let temp_dir = test_dir();
+            let game_dir = CanonPath::new(temp_dir.path()).unwrap();
+            Self { _temp_dir: temp_dir, game_dir, global: Global::default(), config: Config::default() }
+        }
+
+        fn installer(&self) -> FileInstaller<'_> {
+            FileInstaller::new(&self.global, &self.game_dir, &self.game_dir, &self.config)
+        }
+    }
+
+    /// Does what the installation of the generated mod would do (without running weidu).
+    fn install_generated_mod(gen: &GeneratedMod, game_dir: &CanonPath, file_installer: &FileInstaller) {
+        let mod_dir = game_dir.join_path(gen.gen_mod.as_ref());
+        file_installer.copy_from_origins(&gen.gen_mod, &gen.files.iter().collect::<Vec<_>>(), &mod_dir.join("data"), false).unwrap();
+        std::fs::write(mod_dir.join(format!("{}.tp2", gen.gen_mod)), "").unwrap();
+    }
+
+    #[test]
+    fn verify_complete_install() {
+        let setup = VerifySetup::new();
+        let game_dir = &setup.game_dir;
+        let file_installer = setup.installer();
+
+        let gen = generated_mod();
+        install_generated_mod(&gen, game_dir, &file_installer);
+        std::fs::create_dir_all(game_dir.join_path("my_mod")).unwrap();
+        std::fs::write(game_dir.join_path("my_mod").join("setup-my_mod.tp2"), "").unwrap();
+
+        let gen_check = verify_module(&Module::Generated { gen }, game_dir, &file_installer).unwrap();
+        let mod_check = verify_module(&Module::Mod { weidu_mod: WeiduMod { name: lwc!("my_mod"), ..Default::default() } },
+                                        game_dir, &file_installer).unwrap();
+
+        assert!(gen_check.is_complete(), "missing {:?}", gen_check.missing);
+        assert!(mod_check.is_complete(), "missing {:?}", mod_check.missing);
+    }
+
+    #[test]
+    fn verify_tampered_install() {
+        let setup = VerifySetup::new();
+        let game_dir = &setup.game_dir;
+        let file_installer = setup.installer();
+
+        let gen = generated_mod();
+        install_generated_mod(&gen, game_dir, &file_installer);
+        std::fs::remove_file(game_dir.join_path("my_gen/data/sub/b.txt")).unwrap();
+
+        let gen_check = verify_module(&Module::Generated { gen }, game_dir, &file_installer).unwrap();
+        let mod_check = verify_module(&Module::Mod { weidu_mod: WeiduMod { name: lwc!("my_mod"), ..Default::default() } },
+                                        game_dir, &file_installer).unwrap();
+
+        assert_eq!(gen_check.missing, vec![PathBuf::from("my_gen/data/sub/b.txt")]);
+        assert_eq!(mod_check.missing, vec![PathBuf::from("my_mod")]);
+    }
+
+    #[test]
+    fn verify_file_module() {
+        let setup = VerifySetup::new();
+        let game_dir = &setup.game_dir;
+        let file_installer = setup.installer();
+
+        let file = FileModule {
+            file_mod: lwc!("my_files"),
+            from: generated_mod().files.remove(0),
+            to: "override".to_string(),
+            ..Default::default()
+        };
+        file_installer.copy_file_module(&file).unwrap();
+        std::fs::remove_file(game_dir.join_path("override/sub/b.txt")).unwrap();
+
+        let check = verify_module(&Module::File { file: file.clone() }, game_dir, &file_installer).unwrap();
+        let skipped = FileModule { requires_file: Some("chitin.key".to_string()), ..file };
+        let skipped_check = verify_module(&Module::File { file: skipped }, game_dir, &file_installer).unwrap();
+
+        assert_eq!(check.missing, vec![PathBuf::from("override/sub/b.txt")]);
+        assert!(skipped_check.is_complete(), "missing {:?}", skipped_check.missing);
+    }
+}
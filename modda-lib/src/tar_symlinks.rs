@@ -0,0 +1,203 @@
+
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// What to do with the symlink entries of tar archives; a symlink pointing outside of the extracted archive
+/// is always refused (except with `Skip`).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TarSymlinks {
+    /// Default value, the symlink is created if its target is inside the archive
+    #[default]
+    RecreateIfSafe,
+    /// A copy of the target (file or directory) is made in place of the symlink, works where symlinks can't be created
+    Copy,
+    /// Symlinks are not extracted
+    Skip,
+}
+
+/// Extracts the entries like `tar::Archive::unpack`, but the symlinks are handled according to `policy`,
+/// once all the other entries are extracted (a target can come after the symlink in the archive).
+pub fn unpack_tar<R: Read>(archive: &mut tar::Archive<R>, dest: &Path, policy: TarSymlinks) -> Result<()> {
+    std::fs::create_dir_all(dest)?;
+    let dest = &dest.canonicalize()?;
+    // directories last, like `unpack`, so that their permissions don't prevent the extraction of their content
+    let mut directories = vec![];
+    let mut symlinks = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => directories.push(entry),
+            tar::EntryType::Symlink => match entry.link_name()? {
+                None => bail!("Symlink {:?} without target in tar archive", entry.path()?),
+                Some(target) => symlinks.push((entry.path()?.into_owned(), target.into_owned())),
+            },
+            _ => { entry.unpack_in(dest)?; }
+        }
+    }
+    directories.sort_by(|left, right| right.path_bytes().cmp(&left.path_bytes()));
+    for mut directory in directories {
+        directory.unpack_in(dest)?;
+    }
+    for (link, target) in symlinks {
+        if let Err(error) = extract_symlink(dest, &link, &target, policy) {
+            bail!("Could not extract symlink {:?} -> {:?}\n -> {:?}", link, target, error);
+        }
+    }
+    Ok(())
+}
+
+fn extract_symlink(dest: &Path, link: &Path, target: &Path, policy: TarSymlinks) -> Result<()> {
+    if policy == TarSymlinks::Skip {
+        info!("skipped symlink {:?} -> {:?}", link, target);
+        return Ok(());
+    }
+    let (link, resolved) = match (inside_path(link), resolve_target(link, target)) {
+        (Some(link), Some(resolved)) => (dest.join(link), dest.join(resolved)),
+        _ => bail!("the symlink points outside of the archive (set `tar_symlinks: skip` to ignore it)"),
+    };
+    // the target can go through other symlinks of the archive
+    if let Ok(canonical) = resolved.canonicalize() {
+        if !canonical.starts_with(dest) {
+            bail!("the symlink points outside of the archive through {:?} (set `tar_symlinks: skip` to ignore it)", canonical);
+        }
+    }
+    if let Some(parent) = link.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match policy {
+        TarSymlinks::RecreateIfSafe => create_symlink(target, &resolved, &link)?,
+        TarSymlinks::Copy if resolved.is_dir() => {
+            let options = fs_extra::dir::CopyOptions { content_only: true, ..Default::default() };
+            std::fs::create_dir_all(&link)?;
+            fs_extra::dir::copy(&resolved, &link, &options)?;
+        }
+        TarSymlinks::Copy if resolved.is_file() => { std::fs::copy(&resolved, &link)?; }
+        TarSymlinks::Copy => bail!("the target is not in the archive, nothing to copy"),
+        TarSymlinks::Skip => {}
+    }
+    Ok(())
+}
+
+/// `path` without `.` and `..`, `None` if it goes above the base directory or is absolute.
+fn inside_path(path: &Path) -> Option<PathBuf> {
+    let mut inside = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => inside.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => if !inside.pop() { return None },
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(inside)
+}
+
+/// Path (relative to the archive root) of the target of the symlink at `link`.
+fn resolve_target(link: &Path, target: &Path) -> Option<PathBuf> {
+    if target.has_root() {
+        return None;
+    }
+    inside_path(&link.parent().unwrap_or(Path::new("")).join(target))
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, _resolved: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, resolved: &Path, link: &Path) -> io::Result<()> {
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::test_utils::test_dir;
+
+    use super::{unpack_tar, TarSymlinks};
+
+    /// A tar with `my_mod/tra/english.tra` and symlinks given as (path, target).
+    fn tar_with_symlinks(symlinks: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (link, target) in symlinks {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, link, target).unwrap();
+        }
+        let content = "@1 = ~Hello~\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "my_mod/tra/english.tra", content.as_bytes()).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn unpack(content: &[u8], dest: &Path, policy: TarSymlinks) -> anyhow::Result<()> {
+        unpack_tar(&mut tar::Archive::new(content), dest, policy)
+    }
+
+    const SAFE: (&str, &str) = ("my_mod/tra/american.tra", "english.tra");
+    const SAFE_DIR: (&str, &str) = ("my_mod/lang", "tra");
+    const ESCAPING: (&str, &str) = ("my_mod/tra/passwd", "../../../../../../etc/passwd");
+
+    #[cfg(unix)]
+    #[test]
+    fn recreate_safe_symlinks() {
+        let temp = test_dir();
+        let dest = temp.path().join("safe");
+
+        unpack(&tar_with_symlinks(&[SAFE, SAFE_DIR]), &dest, TarSymlinks::RecreateIfSafe).unwrap();
+
+        let link = dest.join("my_mod/tra/american.tra");
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(link).unwrap(), "@1 = ~Hello~\n");
+        assert_eq!(std::fs::read_to_string(dest.join("my_mod/lang/english.tra")).unwrap(), "@1 = ~Hello~\n");
+
+        let dest = temp.path().join("escaping");
+        let error = format!("{:?}", unpack(&tar_with_symlinks(&[SAFE, ESCAPING]), &dest, TarSymlinks::RecreateIfSafe).unwrap_err());
+        assert!(error.contains("points outside of the archive"), "{}", error);
+        assert!(dest.join("my_mod/tra/passwd").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn copy_symlink_targets() {
+        let temp = test_dir();
+        let dest = temp.path().join("safe");
+
+        unpack(&tar_with_symlinks(&[SAFE, SAFE_DIR]), &dest, TarSymlinks::Copy).unwrap();
+
+        let link = dest.join("my_mod/tra/american.tra");
+        assert!(link.symlink_metadata().unwrap().file_type().is_file());
+        assert_eq!(std::fs::read_to_string(link).unwrap(), "@1 = ~Hello~\n");
+        assert!(dest.join("my_mod/lang").symlink_metadata().unwrap().file_type().is_dir());
+        assert_eq!(std::fs::read_to_string(dest.join("my_mod/lang/english.tra")).unwrap(), "@1 = ~Hello~\n");
+
+        let dest = temp.path().join("escaping");
+        let error = format!("{:?}", unpack(&tar_with_symlinks(&[ESCAPING]), &dest, TarSymlinks::Copy).unwrap_err());
+        assert!(error.contains("points outside of the archive"), "{}", error);
+        assert!(!dest.join("my_mod/tra/passwd").exists());
+    }
+
+    #[test]
+    fn skip_symlinks() {
+        let temp = test_dir();
+
+        unpack(&tar_with_symlinks(&[SAFE, ESCAPING]), temp.path(), TarSymlinks::Skip).unwrap();
+
+        assert!(temp.path().join("my_mod/tra/english.tra").is_file());
+        assert!(temp.path().join("my_mod/tra/american.tra").symlink_metadata().is_err());
+        assert!(temp.path().join("my_mod/tra/passwd").symlink_metadata().is_err());
+    }
+}
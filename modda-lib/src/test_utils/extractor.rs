@@ -0,0 +1,45 @@
+
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+
+use crate::archive_extractor::Extractor;
+use crate::archive_layout::Layout;
+use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::module::location::location::ConcreteLocation;
+
+use super::test_dir;
+
+/// A temporary directory with an empty `game` directory, mods are extracted through `extract`.
+pub struct ExtractorFixture {
+    pub temp: TempDir,
+    pub game_dir: CanonPath,
+    pub config: Config,
+}
+
+pub fn extractor_fixture(config: Config) -> ExtractorFixture {
+    let temp = test_dir();
+    let game_dir = CanonPath::new(temp.path().join("game")).unwrap();
+    std::fs::create_dir_all(game_dir.path()).unwrap();
+    ExtractorFixture { temp, game_dir, config }
+}
+
+impl ExtractorFixture {
+    pub fn extractor(&self) -> Extractor<'_> {
+        Extractor::new(&self.game_dir, &self.config)
+    }
+
+    pub fn extract_location(&self) -> PathBuf {
+        self.temp.path().join("extract")
+    }
+
+    /// Location with `layout`, extracted in `extract_location()`.
+    pub fn location(&self, layout: Layout) -> ConcreteLocation {
+        ConcreteLocation {
+            layout: Some(layout),
+            extract_location: Some(self.extract_location().to_string_lossy().to_string()),
+            ..Default::default()
+        }
+    }
+}
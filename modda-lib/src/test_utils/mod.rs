@@ -0,0 +1,20 @@
+
+use std::path::PathBuf;
+
+use crate::module::manifest::Manifest;
+
+pub mod extractor;
+pub mod server;
+
+/// Manifest with `content` (top-level properties after `global`, usually `modules`).
+pub fn manifest(content: &str) -> Manifest {
+    let yaml = format!("version: \"1\"\nglobal:\n  lang_dir: fr_FR\n{}", content);
+    serde_yaml::from_str(&yaml).unwrap()
+}
+
+/// A new temporary directory in `target/test_data`, deleted when dropped.
+pub fn test_dir() -> tempfile::TempDir {
+    let test_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("test_data");
+    std::fs::create_dir_all(&test_path).unwrap();
+    tempfile::tempdir_in(&test_path).unwrap()
+}
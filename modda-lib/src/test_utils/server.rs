@@ -0,0 +1,162 @@
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A request received by a [`TestServer`].
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// What follows the method in the request line (a path, or a full URL for proxied requests).
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|(header, _)| *header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+type Respond = dyn Fn(&Request) -> Vec<u8> + Send + Sync;
+
+/// Local HTTP server for the tests.
+/// Each connection carries one request, answered with the (raw) response built by the `respond` callback.
+/// Received requests are recorded before the response is sent.
+pub struct TestServer {
+    address: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    pub fn start<F>(respond: F) -> Self where F: Fn(&Request) -> Vec<u8> + Send + Sync + 'static {
+        Self::start_with_delay(Duration::ZERO, respond)
+    }
+
+    /// Waits `delay` before answering each request, so that concurrent requests overlap.
+    pub fn start_with_delay<F>(delay: Duration, respond: F) -> Self where F: Fn(&Request) -> Vec<u8> + Send + Sync + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = TestServer {
+            address: format!("http://{}", listener.local_addr().unwrap()),
+            requests: Arc::new(Mutex::new(vec![])),
+            max_in_flight: Arc::new(AtomicUsize::new(0)),
+        };
+        let respond: Arc<Respond> = Arc::new(respond);
+        let requests = server.requests.clone();
+        let max_in_flight = server.max_in_flight.clone();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let (respond, requests, in_flight, max_in_flight) =
+                    (respond.clone(), requests.clone(), in_flight.clone(), max_in_flight.clone());
+                std::thread::spawn(move || {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    answer(stream, delay, respond.as_ref(), &requests);
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        server
+    }
+
+    /// Answers the n-th request with the n-th response, with a `404` once they are exhausted.
+    pub fn scripted(responses: Vec<Vec<u8>>) -> Self {
+        let next = AtomicUsize::new(0);
+        Self::start(move |_| {
+            responses.get(next.fetch_add(1, Ordering::SeqCst)).cloned().unwrap_or_else(not_found)
+        })
+    }
+
+    /// Serves `pages` by path, `404` for the others.
+    pub fn pages(pages: Vec<(&'static str, &'static [u8])>) -> Self {
+        Self::start(move |request| {
+            match pages.iter().find(|(page, _)| *page == request.path) {
+                None => not_found(),
+                Some((_, body)) => ok(body),
+            }
+        })
+    }
+
+    /// `http://127.0.0.1:<port>`
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.address, path)
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.requests().into_iter().map(|request| request.path).collect()
+    }
+
+    /// Highest number of requests that were being answered at the same time.
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+fn answer(stream: TcpStream, delay: Duration, respond: &Respond, requests: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let Some(request) = read_request(&mut reader) else { return };
+    requests.lock().unwrap().push(request.clone());
+    std::thread::sleep(delay);
+    let mut stream = stream;
+    // the client may have given up (timeouts, rate-limit tests)
+    let _ = stream.write_all(&respond(&request));
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line == "\r\n" { break; }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length = headers.iter().find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request { method, path, headers, body })
+}
+
+/// A `200` response with `body`.
+pub fn ok(body: &[u8]) -> Vec<u8> {
+    response("200 OK", &[], body)
+}
+
+pub fn not_found() -> Vec<u8> {
+    response("404 Not Found", &[], b"")
+}
+
+/// A raw response with the given status and extra headers; `Content-Length` and `Connection: close` are added.
+pub fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", body.len()));
+    let mut response = response.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
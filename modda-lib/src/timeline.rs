@@ -1,12 +1,16 @@
 
 use chrono::{DateTime, Local, Duration};
 
+use crate::archive_extractor::ExtractionStats;
+use crate::iemod::IemodMetadata;
 use crate::lowercase::LwcString;
 
 
 #[derive(Default, Debug, Clone)]
 pub struct InstallTimeline {
     pub name: LwcString,
+    /// `description` of the module, shown after its name in the summary.
+    pub description: Option<String>,
     pub start: DateTime<Local>,
     pub start_download: Option<DateTime<Local>>,
     pub downloaded: Option<DateTime<Local>>,
@@ -15,6 +19,9 @@ pub struct InstallTimeline {
     pub configured: Option<DateTime<Local>>,
     pub start_install: Option<DateTime<Local>>,
     pub installed: Option<DateTime<Local>>,
+    pub extraction: Option<ExtractionStats>,
+    /// Why the module was not installed by this run; shown instead of the durations.
+    pub skipped: Option<String>,
 }
 
 impl InstallTimeline {
@@ -26,16 +33,39 @@ impl InstallTimeline {
         }
     }
 
+    pub fn skipped(name: LwcString, reason: &str) -> Self {
+        InstallTimeline {
+            skipped: Some(reason.to_string()),
+            ..InstallTimeline::new(name, Local::now())
+        }
+    }
+
+    pub fn with_description(self, description: Option<String>) -> Self {
+        Self { description, ..self }
+    }
+
     pub fn complete(&mut self, setup: SetupTimeline) {
+        if self.description.is_none() {
+            self.description = setup.extraction.as_ref()
+                .and_then(|extraction| extraction.iemod.as_ref())
+                .and_then(IemodMetadata::module_description);
+        }
         self.start_download = Some(setup.start);
         self.downloaded = setup.downloaded;
         self.copied = setup.copied;
         self.patched = setup.patched;
         self.configured = setup.configured;
+        self.extraction = setup.extraction;
     }
 
     pub fn short(&self) -> String {
         let mut result = format!("({}) {}" , self.start.format("%H:%M:%S"), self.name);
+        if let Some(description) = &self.description {
+            result += &format!(" ({})", description);
+        }
+        if let Some(reason) = &self.skipped {
+            return result + &format!(" skipped: {}", reason);
+        }
             result +=" download: ";
         if let (Some(start), Some(end)) = (self.start_download, self.downloaded) {
             result+= &format_duration(end - start).to_string();
@@ -45,6 +75,9 @@ impl InstallTimeline {
             result +=" extract: ";
         if let (Some(downloaded), Some(copied)) = (self.downloaded, self.copied) {
             result+= &format_duration(copied - downloaded).to_string();
+            if let Some(stats) = &self.extraction {
+                result += &format!(" ({:?}, {} files, {} bytes)", stats.method, stats.entries, stats.bytes);
+            }
         } else {
             result += "-"
         }
@@ -77,4 +110,58 @@ pub struct SetupTimeline {
     pub patched: Option<DateTime<Local>>,
     pub replaced: Option<DateTime<Local>>,
     pub configured: Option<DateTime<Local>>,
+    pub extraction: Option<ExtractionStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Local, TimeZone};
+
+    use crate::archive_extractor::{ExtractionMethod, ExtractionStats};
+    use crate::iemod::IemodMetadata;
+    use crate::lowercase::lwc;
+
+    use super::{InstallTimeline, SetupTimeline};
+
+    #[test]
+    fn summary_has_the_description() {
+        let start = Local.with_ymd_and_hms(2024, 5, 1, 10, 20, 30).unwrap();
+        let timeline = InstallTimeline::new(lwc!("bg1npc"), start)
+            .with_description(Some("NPC project".to_string()));
+
+        assert_eq!(timeline.short(), "(10:20:30) bg1npc (NPC project) download: - extract: - prepare: - install: -");
+    }
+
+    #[test]
+    fn iemod_description_is_used_when_the_manifest_has_none() {
+        let start = Local.with_ymd_and_hms(2024, 5, 1, 10, 20, 30).unwrap();
+        let extraction = ExtractionStats {
+            method: ExtractionMethod::Zip,
+            entries: 3,
+            bytes: 97,
+            duration: Duration::from_secs(1),
+            iemod: Some(IemodMetadata { name: Some("My Mod".to_string()), ..Default::default() }),
+        };
+        let setup = SetupTimeline { start, extraction: Some(extraction), ..Default::default() };
+
+        let mut timeline = InstallTimeline::new(lwc!("my_mod"), start);
+        timeline.complete(setup.clone());
+        assert_eq!(timeline.description.as_deref(), Some("My Mod"));
+
+        let mut timeline = InstallTimeline::new(lwc!("my_mod"), start).with_description(Some("mine".to_string()));
+        timeline.complete(setup);
+        assert_eq!(timeline.description.as_deref(), Some("mine"));
+    }
+
+    #[test]
+    fn summary_of_skipped_module() {
+        let timeline = InstallTimeline {
+            start: Local.with_ymd_and_hms(2024, 5, 1, 10, 20, 30).unwrap(),
+            ..InstallTimeline::skipped(lwc!("bg1npc"), "unchanged since the lock")
+        };
+
+        assert_eq!(timeline.short(), "(10:20:30) bg1npc skipped: unchanged since the lock");
+    }
 }
@@ -0,0 +1,250 @@
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use log::{debug, warn};
+
+use crate::lowercase::LwcString;
+use crate::module::components::{Component, FullComponent};
+
+/// A component as declared in a tp2 file (`BEGIN` + optional `DESIGNATED`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tp2Component {
+    /// Component number (the one given to weidu)
+    pub number: u32,
+    /// Component name in the selected language; `None` if it could not be resolved.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// `~...~`, `"..."`, `%...%` or `~~~~~...~~~~~` string, without the delimiters
+    Str(String),
+    Word(String),
+}
+
+fn tokenize(content: &str) -> Vec<Token> {
+    let chars = content.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < chars.len() {
+        let current = chars[pos];
+        let next = chars.get(pos + 1).cloned();
+        if current.is_whitespace() {
+            pos += 1;
+        } else if current == '/' && next == Some('/') {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+            }
+        } else if current == '/' && next == Some('*') {
+            pos += 2;
+            while pos < chars.len() && !(chars[pos] == '*' && chars.get(pos + 1) == Some(&'/')) {
+                pos += 1;
+            }
+            pos += 2;
+        } else if current == '~' || current == '"' || current == '%' {
+            let delimiter = if chars[pos..].starts_with(&['~'; 5]) { vec!['~'; 5] } else { vec![current] };
+            pos += delimiter.len();
+            let start = pos;
+            while pos < chars.len() && !chars[pos..].starts_with(&delimiter) {
+                pos += 1;
+            }
+            tokens.push(Token::Str(chars[start..pos.min(chars.len())].iter().collect()));
+            pos += delimiter.len();
+        } else if current == '=' {
+            tokens.push(Token::Word("=".to_string()));
+            pos += 1;
+        } else {
+            let start = pos;
+            while pos < chars.len() && !chars[pos].is_whitespace() && !['~', '"', '=', '%'].contains(&chars[pos]) {
+                pos += 1;
+            }
+            tokens.push(Token::Word(chars[start..pos].iter().collect()));
+        }
+    }
+    tokens
+}
+
+/// Reads the `@123 = ~text~` entries of a `.tra` file.
+fn read_tra(path: &Path) -> Result<HashMap<String, String>> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(error) => bail!("Could not read tra file {:?}\n -> {:?}", path, error),
+    };
+    let tokens = tokenize(&String::from_utf8_lossy(&content));
+    let mut result = HashMap::new();
+    for window in tokens.windows(3) {
+        if let [Token::Word(reference), Token::Word(equal), Token::Str(text)] = window {
+            if reference.starts_with('@') && equal == "=" {
+                result.insert(reference.to_owned(), text.to_owned());
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Reads the component declarations of a tp2 file.<br>
+/// `game_dir` is where the paths in the tp2 (tra files) are relative to, `language_index` is
+/// the index of the `LANGUAGE` declaration (as given to weidu with `--language`).
+pub fn read_tp2_components(game_dir: &Path, tp2: &Path, language_index: u32) -> Result<Vec<Tp2Component>> {
+    let tp2_path = game_dir.join(tp2);
+    let content = match std::fs::read(&tp2_path) {
+        Ok(content) => content,
+        Err(error) => bail!("Could not read tp2 file {:?}\n -> {:?}", tp2_path, error),
+    };
+    let tokens = tokenize(&String::from_utf8_lossy(&content));
+    let mod_folder = tp2.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .map(|parent| parent.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut languages = vec![];
+    let mut components: Vec<(u32, &Token)> = vec![];
+    let mut next_number = 0;
+    let mut pos = 0;
+    while pos < tokens.len() {
+        match &tokens[pos] {
+            Token::Word(word) if word == "LANGUAGE" => {
+                // LANGUAGE ~name~ ~directory~ ~tra file~...
+                let strings = tokens[pos + 1..].iter()
+                    .take_while(|token| matches!(token, Token::Str(_)))
+                    .filter_map(|token| match token { Token::Str(value) => Some(value.to_owned()), _ => None })
+                    .collect::<Vec<_>>();
+                pos += strings.len() + 1;
+                languages.push(strings.into_iter().skip(2).collect::<Vec<_>>());
+            }
+            Token::Word(word) if word == "BEGIN" => {
+                // other BEGINs open blocks and are not followed by a string or a tra reference
+                let name = match tokens.get(pos + 1) {
+                    Some(name @ Token::Str(_)) => Some(name),
+                    Some(name @ Token::Word(reference)) if reference.starts_with('@') => Some(name),
+                    _ => None,
+                };
+                if let Some(name) = name {
+                    components.push((next_number, name));
+                    next_number += 1;
+                }
+                pos += 1;
+            }
+            Token::Word(word) if word == "DESIGNATED" => {
+                let number = match tokens.get(pos + 1) {
+                    Some(Token::Str(value)) | Some(Token::Word(value)) => value.trim().parse::<u32>().ok(),
+                    None => None,
+                };
+                match (number, components.last_mut()) {
+                    (Some(number), Some(last)) => {
+                        last.0 = number;
+                        next_number = number + 1;
+                    }
+                    _ => warn!("Invalid DESIGNATED in {:?}", tp2_path),
+                }
+                pos += 2;
+            }
+            _ => pos += 1,
+        }
+    }
+
+    let mut translations = HashMap::new();
+    if let Some(tra_files) = languages.get(language_index as usize) {
+        for tra_file in tra_files {
+            let tra_file = tra_file.replace("%MOD_FOLDER%", &mod_folder);
+            translations.extend(read_tra(&game_dir.join(tra_file))?);
+        }
+    }
+    debug!("tp2 {:?} has components {:?}", tp2_path, components);
+    Ok(components.into_iter().map(|(number, name)| Tp2Component {
+        number,
+        name: match name {
+            Token::Str(text) => Some(text.to_owned()),
+            Token::Word(reference) => translations.get(reference).cloned(),
+        },
+    }).collect())
+}
+
+/// Replaces the named components with the components of the tp2 with the same name (case-insensitive).<br>
+/// Components with an index are kept as they are.
+pub fn resolve_component_names(components: &[Component], available: &[Tp2Component], mod_name: &LwcString) -> Result<Vec<Component>> {
+    let mut result = vec![];
+    for component in components {
+        let name = match component {
+            Component::Named(name) => name,
+            other => {
+                result.push(other.clone());
+                continue;
+            }
+        };
+        let expected = name.trim().to_lowercase();
+        let matches = available.iter()
+            .filter(|candidate| candidate.name.as_ref().map(|value| value.trim().to_lowercase() == expected).unwrap_or(false))
+            .collect::<Vec<_>>();
+        match matches.as_slice() {
+            [] => bail!("Unknown component name {:?} for mod {}", name, mod_name),
+            [single] => result.push(Component::Full(FullComponent { index: single.number, component_name: name.to_owned() })),
+            several => bail!("Ambiguous component name {:?} for mod {} (components {:?})", name, mod_name,
+                                several.iter().map(|candidate| candidate.number).collect::<Vec<_>>()),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, FullComponent};
+
+    use super::{read_tp2_components, resolve_component_names, Tp2Component};
+
+    fn fixture_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/tp2_components")
+    }
+
+    fn component(number: u32, name: &str) -> Tp2Component {
+        Tp2Component { number, name: Some(name.to_string()) }
+    }
+
+    #[test]
+    fn read_components_in_selected_language() {
+        let tp2 = Path::new("mymod/setup-mymod.tp2");
+        let english = read_tp2_components(&fixture_dir(), tp2, 0).unwrap();
+        assert_eq!(english, vec![
+            component(0, "Core fixes"),
+            component(10, "Better items"),
+            component(11, "Faster travel"),
+            component(20, "Untranslated component"),
+            component(21, "Same name"),
+            component(22, "Same name"),
+        ]);
+
+        let french = read_tp2_components(&fixture_dir(), tp2, 1).unwrap();
+        assert_eq!(french[1], component(10, "Meilleurs objets"));
+    }
+
+    #[test]
+    fn map_component_names_to_indexes() {
+        let available = read_tp2_components(&fixture_dir(), Path::new("mymod/setup-mymod.tp2"), 0).unwrap();
+        let requested = vec![
+            Component::Simple(0),
+            Component::Named("faster travel".to_string()),
+            Component::Full(FullComponent { index: 10, component_name: "Faster travel".to_string() }),
+            Component::Named("Untranslated component".to_string()),
+        ];
+        let resolved = resolve_component_names(&requested, &available, &lwc!("mymod")).unwrap();
+        assert_eq!(resolved, vec![
+            Component::Simple(0),
+            Component::Full(FullComponent { index: 11, component_name: "faster travel".to_string() }),
+            // the index is used, not the name
+            Component::Full(FullComponent { index: 10, component_name: "Faster travel".to_string() }),
+            Component::Full(FullComponent { index: 20, component_name: "Untranslated component".to_string() }),
+        ]);
+    }
+
+    #[test]
+    fn reject_unknown_or_ambiguous_names() {
+        let available = read_tp2_components(&fixture_dir(), Path::new("mymod/setup-mymod.tp2"), 0).unwrap();
+        resolve_component_names(&[Component::Named("Nope".to_string())], &available, &lwc!("mymod")).unwrap_err();
+        resolve_component_names(&[Component::Named("Same name".to_string())], &available, &lwc!("mymod")).unwrap_err();
+    }
+}
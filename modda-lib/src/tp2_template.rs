@@ -5,6 +5,7 @@ use handlebars::Handlebars;
 use serde_json::json;
 
 use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 
 use crate::canon_path::CanonPath;
 use crate::module::gen_mod::GeneratedMod;
@@ -17,14 +18,15 @@ const TP2_TEMPLATE: &str ="
 BACKUP ~weidu_external/backup/{{mod_name}}~
 AUTHOR ~generated by modda~
 {{description}}
-BEGIN ~{{component_name}}~
+{{{always}}}BEGIN ~{{component_name}}~
 DESIGNATED ~{{index}}~
 
-COPY ~{{mod_name}}/data~ ~override~
-
+{{{copy}}}{{{at_exit}}}
 ";
 
-pub fn generate_tp2(gen: &GeneratedMod, date: DateTime<Utc>) -> Result<String> {
+/// `files` are the files staged in the `data` directory of the generated mod (relative to it),
+/// each one gets its own `COPY` to `override`.
+pub fn generate_tp2(gen: &GeneratedMod, files: &[PathBuf], date: DateTime<Utc>) -> Result<String> {
     let registry = Handlebars::new();
 
     let comp_name = match &gen.component.name {
@@ -39,17 +41,58 @@ pub fn generate_tp2(gen: &GeneratedMod, date: DateTime<Utc>) -> Result<String> {
             "mod_name": &gen.gen_mod,
             "component_name": comp_name,
             "index": gen.component.index,
+            "copy": copy_lines(&gen.gen_mod.to_string(), files)?,
             "description": match &gen.description {
                 Some(desc) => format!("\n// {desc}"),
                 None => "".to_string(),
             },
+            // raw snippets, inserted unescaped (triple-stash in the template)
+            "always": match &gen.always {
+                Some(always) => format!("ALWAYS\n{}\nEND\n", always.trim_end()),
+                None => "".to_string(),
+            },
+            "at_exit": match &gen.at_exit {
+                Some(at_exit) => format!("{}\n", at_exit.trim_end()),
+                None => "".to_string(),
+            },
         })
     )?;
     Ok(result)
 }
 
-pub fn create_tp2(gen: &GeneratedMod, target: &CanonPath) -> Result<()> {
-    let content = match generate_tp2(gen, Utc::now()) {
+/// One `COPY` per staged file; files in sub-directories of `data` still go to `override` itself
+/// (`override` has no sub-directories).
+fn copy_lines(mod_name: &str, files: &[PathBuf]) -> Result<String> {
+    let mut lines = String::new();
+    for file in files {
+        let source = format!("{}/data/{}", mod_name, weidu_path(file)?);
+        lines.push_str(&format!("COPY {} ~override~\n", weidu_string(&source)?));
+    }
+    Ok(lines)
+}
+
+/// Relative path with `/` separators (accepted by weidu on all platforms).
+fn weidu_path(path: &Path) -> Result<String> {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            _ => bail!("Invalid staged file path {:?} for generated mod\n -> must be relative to the data directory", path),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// Quotes a weidu string with the first delimiter that doesn't appear in it.
+fn weidu_string(value: &str) -> Result<String> {
+    match ['~', '"', '%'].into_iter().find(|delimiter| !value.contains(*delimiter)) {
+        Some(delimiter) => Ok(format!("{delimiter}{value}{delimiter}")),
+        None => bail!("Can't use {:?} as a weidu string\n -> it contains all the string delimiters (~ \" %)", value),
+    }
+}
+
+pub fn create_tp2(gen: &GeneratedMod, files: &[PathBuf], target: &CanonPath) -> Result<()> {
+    let content = match generate_tp2(gen, files, Utc::now()) {
         Err(err) => bail!("Could not generate tp2 file from template\n  {}", err),
         Ok(content) => content,
     };
@@ -70,10 +113,18 @@ pub fn create_tp2(gen: &GeneratedMod, target: &CanonPath) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+    use crate::test_utils::test_dir;
+
     use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::file_installer::FileInstaller;
+    use crate::global::Global;
     use crate::lowercase::lwc;
-    use crate::module::file_module_origin::FileModuleOrigin;
+    use crate::module::file_module_origin::{DirCopy, FileModuleOrigin};
     use crate::module::gen_mod::{GenModComponent, GeneratedMod};
     use crate::tp2_template::generate_tp2;
 
@@ -83,14 +134,12 @@ mod tests {
         let gen_mod = GeneratedMod {
             gen_mod: lwc!("ccc"),
             files: vec![
-                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
+                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None, dir_copy: None, literal: None },
             ],
-            description: None,
             component: GenModComponent { index: 0, name: Some("my component".to_string()) },
-            post_install: None,
             ignore_warnings: true,
-            allow_overwrite: true,
-            disabled_if: None
+            allow_overwrite: Some(true),
+            ..Default::default()
         };
         let date_time = DateTime::from_naive_utc_and_offset(
             NaiveDateTime::new(
@@ -100,7 +149,7 @@ mod tests {
             Utc
         );
         assert_eq!(
-            generate_tp2(&gen_mod,date_time).unwrap(),
+            generate_tp2(&gen_mod, &[PathBuf::from("a.txt"), PathBuf::from("sub/b.txt")], date_time).unwrap(),
             r#"
 /*
  * TP2 generated by modda
@@ -112,7 +161,8 @@ AUTHOR ~generated by modda~
 BEGIN ~my component~
 DESIGNATED ~0~
 
-COPY ~ccc/data~ ~override~
+COPY ~ccc/data/a.txt~ ~override~
+COPY ~ccc/data/sub/b.txt~ ~override~
 
 "#
         )
@@ -123,14 +173,13 @@ COPY ~ccc/data~ ~override~
         let gen_mod = GeneratedMod {
             gen_mod: lwc!("ccc"),
             files: vec![
-                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
+                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None, dir_copy: None, literal: None },
             ],
             description: Some("Very detailed description".to_string()),
             component: GenModComponent { index: 0, name: Some("my component".to_string()) },
-            post_install: None,
             ignore_warnings: true,
-            allow_overwrite: true,
-            disabled_if: None,
+            allow_overwrite: Some(true),
+            ..Default::default()
         };
         let date_time = DateTime::from_naive_utc_and_offset(
             NaiveDateTime::new(
@@ -140,7 +189,7 @@ COPY ~ccc/data~ ~override~
             Utc
         );
         assert_eq!(
-            generate_tp2(&gen_mod,date_time).unwrap(),
+            generate_tp2(&gen_mod, &[PathBuf::from("a.txt"), PathBuf::from("sub/b.txt")], date_time).unwrap(),
             r#"
 /*
  * TP2 generated by modda
@@ -153,9 +202,91 @@ AUTHOR ~generated by modda~
 BEGIN ~my component~
 DESIGNATED ~0~
 
-COPY ~ccc/data~ ~override~
+COPY ~ccc/data/a.txt~ ~override~
+COPY ~ccc/data/sub/b.txt~ ~override~
+
+"#
+        )
+    }
+
+    #[test]
+    fn generate_tp2_with_always_and_at_exit() {
+        let gen_mod = GeneratedMod {
+            gen_mod: lwc!("ccc"),
+            files: vec![
+                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None, dir_copy: None, literal: None },
+            ],
+            component: GenModComponent { index: 0, name: Some("my component".to_string()) },
+            ignore_warnings: true,
+            allow_overwrite: Some(true),
+            always: Some("OUTER_SET x = 1\nOUTER_SPRINT s ~<a & b>~\n".to_string()),
+            at_exit: Some("AT_EXIT ~cleanup.sh~".to_string()),
+            ..Default::default()
+        };
+        let date_time = DateTime::from_naive_utc_and_offset(
+            NaiveDateTime::new(
+                NaiveDate::from_ymd_opt(2024, 05, 18).unwrap(),
+                NaiveTime::from_hms_opt(12, 13, 14).unwrap(),
+            ),
+            Utc
+        );
+        assert_eq!(
+            generate_tp2(&gen_mod, &[PathBuf::from("a.txt"), PathBuf::from("sub/b.txt")], date_time).unwrap(),
+            r#"
+/*
+ * TP2 generated by modda
+ * 2024-05-18 12:13:14 UTC
+*/
+BACKUP ~weidu_external/backup/ccc~
+AUTHOR ~generated by modda~
+
+ALWAYS
+OUTER_SET x = 1
+OUTER_SPRINT s ~<a & b>~
+END
+BEGIN ~my component~
+DESIGNATED ~0~
+
+COPY ~ccc/data/a.txt~ ~override~
+COPY ~ccc/data/sub/b.txt~ ~override~
+AT_EXIT ~cleanup.sh~
 
 "#
         )
     }
+
+    #[test]
+    fn generated_copy_lines_match_declared_files() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let temp = test_dir();
+        let data_dir = temp.path().join("ccc").join("data");
+        let fixture = project.join("resources/test/file_installer/some_dir");
+        let files = vec![
+            // only `a.txt` of this directory
+            FileModuleOrigin::Absolute { absolute: fixture.to_string_lossy().to_string(), glob: Some("a.*".to_string()), dir_copy: None, literal: None },
+            FileModuleOrigin::Absolute { absolute: fixture.join("sub").to_string_lossy().to_string(), glob: None, dir_copy: Some(DirCopy::Directory), literal: None },
+        ];
+        let gen_mod = GeneratedMod { gen_mod: lwc!("ccc"), files, ..Default::default() };
+
+        let global = Global::default();
+        let game_dir = CanonPath::new(temp.path()).unwrap();
+        let config = Config::default();
+        let installer = FileInstaller::new(&global, &game_dir, &game_dir, &config);
+        let origins = gen_mod.files.iter().collect::<Vec<_>>();
+        let staged = installer.staged_files(&origins, &data_dir).unwrap();
+        installer.copy_from_origins(&gen_mod.gen_mod, &origins, &data_dir, false).unwrap();
+
+        let tp2 = generate_tp2(&gen_mod, &staged, Utc::now()).unwrap();
+        let copy_lines = tp2.lines().filter(|line| line.starts_with("COPY")).collect::<Vec<_>>();
+        assert_eq!(copy_lines, vec![
+            "COPY ~ccc/data/a.txt~ ~override~",
+            "COPY ~ccc/data/sub/b.txt~ ~override~",
+        ]);
+        // every copied file is staged, and nothing else is
+        for line in &copy_lines {
+            let source = line.split('~').nth(1).unwrap().strip_prefix("ccc/data/").unwrap();
+            assert!(data_dir.join(source).is_file(), "{}", source);
+        }
+        assert!(!data_dir.join("b.txt").exists());
+    }
 }
@@ -1,4 +1,6 @@
 
 pub mod bufread_raw;
 pub mod pathext;
+pub mod permissions;
 pub mod read_all;
+pub mod sorted_map;
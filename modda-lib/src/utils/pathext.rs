@@ -11,3 +11,13 @@ pub fn append_extension(ext: impl AsRef<OsStr>, path: &Path) -> PathBuf {
     os_string.push(ext.as_ref());
     os_string.into()
 }
+
+/// Whether `path` is hidden (dot-prefixed) or inside a hidden directory, only looking
+/// at the components below `base`.
+pub fn is_hidden_under(path: &Path, base: &Path) -> bool {
+    path.strip_prefix(base).unwrap_or(path)
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.')
+                            && component != std::path::Component::CurDir
+                            && component != std::path::Component::ParentDir)
+}
@@ -0,0 +1,122 @@
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+/// Makes the permissions of `path` (recursively if it's a directory) consistent:
+/// - on Unix, directories get `0755` and files `0644` (`0755` if they had any execute bit,
+///   as some mods ship helper programs)
+/// - on Windows, the read-only flag is cleared.
+pub fn normalize_permissions(path: &Path) -> Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(error) => bail!("Could not read metadata of {:?}\n -> {:?}", path, error),
+    };
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    // directories must be writable/traversable before we look inside
+    set_normalized(path, &metadata)?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            normalize_permissions(&entry?.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_normalized(path: &Path, metadata: &std::fs::Metadata) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = if metadata.is_dir() || metadata.permissions().mode() & 0o111 != 0 { 0o755 } else { 0o644 };
+    if let Err(error) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+        bail!("Could not set permissions of {:?}\n -> {:?}", path, error);
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn set_normalized(path: &Path, metadata: &std::fs::Metadata) -> Result<()> {
+    let mut permissions = metadata.permissions();
+    if permissions.readonly() {
+        permissions.set_readonly(false);
+        if let Err(error) = std::fs::set_permissions(path, permissions) {
+            bail!("Could not set permissions of {:?}\n -> {:?}", path, error);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the execute bits (for those who can read the file) on Unix; does nothing on Windows.
+#[cfg(unix)]
+pub fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.permissions().mode(),
+        Err(error) => bail!("Could not read metadata of {:?}\n -> {:?}", path, error),
+    };
+    let executable = mode | ((mode & 0o444) >> 2);
+    if executable != mode {
+        if let Err(error) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(executable)) {
+            bail!("Could not set permissions of {:?}\n -> {:?}", path, error);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn set_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::test_utils::test_dir;
+
+    use super::normalize_permissions;
+
+    #[cfg(unix)]
+    #[test]
+    fn normalize_unix_modes() {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = test_dir();
+        let mod_dir = temp.path().join("my_mod");
+        std::fs::create_dir_all(mod_dir.join("sub")).unwrap();
+        std::fs::write(mod_dir.join("my_mod.tp2"), "").unwrap();
+        std::fs::write(mod_dir.join("sub").join("tool"), "").unwrap();
+        std::fs::set_permissions(mod_dir.join("my_mod.tp2"), Permissions::from_mode(0o600)).unwrap();
+        std::fs::set_permissions(mod_dir.join("sub").join("tool"), Permissions::from_mode(0o700)).unwrap();
+        std::fs::set_permissions(mod_dir.join("sub"), Permissions::from_mode(0o700)).unwrap();
+
+        normalize_permissions(&mod_dir).unwrap();
+
+        let mode = |path: PathBuf| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(mod_dir.clone()), 0o755);
+        assert_eq!(mode(mod_dir.join("my_mod.tp2")), 0o644);
+        assert_eq!(mode(mod_dir.join("sub")), 0o755);
+        assert_eq!(mode(mod_dir.join("sub").join("tool")), 0o755);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn normalize_clears_readonly() {
+        let temp = test_dir();
+        let mod_dir = temp.path().join("my_mod");
+        std::fs::create_dir_all(&mod_dir).unwrap();
+        let file = mod_dir.join("my_mod.tp2");
+        std::fs::write(&file, "").unwrap();
+        let mut permissions = std::fs::metadata(&file).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&file, permissions).unwrap();
+
+        normalize_permissions(&mod_dir).unwrap();
+
+        assert!(!std::fs::metadata(&file).unwrap().permissions().readonly());
+    }
+}
@@ -0,0 +1,19 @@
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+/// Serializes a `HashMap` with its keys in alphabetical order (for `serialize_with`),
+/// so that writing the same content twice gives the same text.
+pub fn serialize_sorted<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+        where K: Serialize + Display, V: Serialize, S: Serializer {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by_cached_key(|(key, _)| key.to_string());
+    let mut serialized = serializer.serialize_map(Some(entries.len()))?;
+    for (key, value) in entries {
+        serialized.serialize_entry(key, value)?;
+    }
+    serialized.end()
+}
@@ -1,17 +1,17 @@
 use std::io::{BufRead, BufReader};
-use std::path::Path;
 use anyhow::{Result, anyhow, bail};
 use lazy_static::lazy_static;
 use regex::Regex;
 
 use crate::canon_path::CanonPath;
+use crate::global::Global;
 
 lazy_static! {
     static ref LANG_DIR_REGEX: Regex = Regex::new(r##"(?i)lang_dir(\s)+=(\s)+([a-z_]+)"##).unwrap();
 }
 
 pub fn check_weidu_conf_lang(game_dir: &CanonPath, lang: &str) -> Result<()> {
-    if !Path::new("weidu.conf").exists() {
+    if !game_dir.join_path("weidu.conf").exists() {
         return Ok(())
     }
     let file = match std::fs::File::open(game_dir.join_path("weidu.conf")) {
@@ -38,7 +38,7 @@ pub fn check_weidu_conf_lang(game_dir: &CanonPath, lang: &str) -> Result<()> {
 }
 
 pub fn read_weidu_conf_lang_dir(game_dir: &CanonPath) -> Result<Option<String>> {
-    if !Path::new("weidu.conf").exists() {
+    if !game_dir.join_path("weidu.conf").exists() {
         return Ok(None)
     }
     let file = match std::fs::File::open(game_dir.join_path("weidu.conf")) {
@@ -57,3 +57,98 @@ pub fn read_weidu_conf_lang_dir(game_dir: &CanonPath) -> Result<Option<String>>
     }
     Ok(None)
 }
+
+/// Fills the `lang_dir` and `lang_preferences` missing from the manifest with the game `weidu.conf` when
+/// `lang_from_weidu_conf` is set; the values in the manifest always win.<br>
+/// Fails if `lang_dir` is still unknown.
+pub fn complete_lang_from_weidu_conf(global: &mut Global, game_dir: &CanonPath) -> Result<()> {
+    if global.lang_from_weidu_conf.unwrap_or(false) && global.game_language.is_empty() {
+        match read_weidu_conf_lang_dir(game_dir)? {
+            None => bail!("`lang_from_weidu_conf` is set but there is no `lang_dir` in weidu.conf\n -> {:?}",
+                            game_dir.join_path("weidu.conf")),
+            Some(lang_dir) => global.game_language = lang_dir,
+        }
+        if global.lang_preferences.is_none() {
+            global.lang_preferences = default_lang_preferences(&global.game_language);
+        }
+    }
+    global.lang_dir()?;
+    Ok(())
+}
+
+/// The usual names of the language of a `lang_dir`, for a limited set of languages.
+pub fn default_lang_preferences(lang_dir: &str) -> Option<Vec<String>> {
+    match lang_dir.to_lowercase().as_str() {
+        "fr_fr" => Some(vec!["#rx#^fran[cç]ais".to_string(), "french".to_string()]),
+        "en_us" => Some(vec!["english".to_string(), "american english".to_string()]),
+        "es_es" => Some(vec!["#rx#^espa[ñn]ol".to_string(), "spanish".to_string()]),
+        // some more...
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::canon_path::CanonPath;
+    use crate::global::Global;
+    use crate::test_utils::test_dir;
+
+    use super::complete_lang_from_weidu_conf;
+
+    const WEIDU_CONF: &str = "lang_dir = fr_fr\r\n";
+
+    fn test_game_dir(weidu_conf: Option<&str>) -> (tempfile::TempDir, CanonPath) {
+        let temp = test_dir();
+        if let Some(content) = weidu_conf {
+            std::fs::write(temp.path().join("weidu.conf"), content).unwrap();
+        }
+        let game_dir = CanonPath::new(temp.path()).unwrap();
+        (temp, game_dir)
+    }
+
+    #[test]
+    fn lang_read_from_weidu_conf() {
+        let (_temp, game_dir) = test_game_dir(Some(WEIDU_CONF));
+        let mut global = Global { lang_from_weidu_conf: Some(true), ..Default::default() };
+
+        complete_lang_from_weidu_conf(&mut global, &game_dir).unwrap();
+
+        assert_eq!(global.game_language, "fr_fr");
+        assert_eq!(global.lang_preferences, Some(vec!["#rx#^fran[cç]ais".to_string(), "french".to_string()]));
+    }
+
+    #[test]
+    fn manifest_lang_wins_over_weidu_conf() {
+        let (_temp, game_dir) = test_game_dir(Some(WEIDU_CONF));
+        let mut global = Global {
+            game_language: "en_US".to_string(),
+            lang_from_weidu_conf: Some(true),
+            ..Default::default()
+        };
+        complete_lang_from_weidu_conf(&mut global, &game_dir).unwrap();
+        assert_eq!(global.game_language, "en_US");
+        assert_eq!(global.lang_preferences, None);
+
+        let mut global = Global {
+            lang_from_weidu_conf: Some(true),
+            lang_preferences: Some(vec!["english".to_string()]),
+            ..Default::default()
+        };
+        complete_lang_from_weidu_conf(&mut global, &game_dir).unwrap();
+        assert_eq!(global.game_language, "fr_fr");
+        assert_eq!(global.lang_preferences, Some(vec!["english".to_string()]));
+    }
+
+    #[test]
+    fn missing_lang_dir() {
+        let (_temp, game_dir) = test_game_dir(Some(WEIDU_CONF));
+        let error = complete_lang_from_weidu_conf(&mut Global::default(), &game_dir).unwrap_err().to_string();
+        assert!(error.contains("Missing `lang_dir`"), "{}", error);
+
+        let (_temp, game_dir) = test_game_dir(None);
+        let mut global = Global { lang_from_weidu_conf: Some(true), ..Default::default() };
+        let error = complete_lang_from_weidu_conf(&mut global, &game_dir).unwrap_err().to_string();
+        assert!(error.contains("no `lang_dir` in weidu.conf"), "{}", error);
+    }
+}
@@ -0,0 +1,161 @@
+
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use log::debug;
+
+use crate::run_weidu::WeiduComponent;
+
+/// How weidu reported the installation of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentOutcome {
+    Installed,
+    InstalledWithWarnings,
+    Failed,
+}
+
+/// Result line of a component in the weidu output (`SUCCESSFULLY INSTALLED <name>`...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentResult {
+    /// Component name as weidu prints it (`<group> -> <name>` for subcomponents).
+    pub name: String,
+    pub outcome: ComponentOutcome,
+}
+
+const RESULT_PREFIXES: &[(&str, ComponentOutcome)] = &[
+    ("SUCCESSFULLY INSTALLED", ComponentOutcome::Installed),
+    ("INSTALLED WITH WARNINGS", ComponentOutcome::InstalledWithWarnings),
+    ("NOT INSTALLED DUE TO ERRORS", ComponentOutcome::Failed),
+];
+
+/// Reads the per-component results from the output of a weidu installation (the `--log` file has the same lines),
+/// in installation order.
+pub fn parse_install_output(output: &str) -> Vec<ComponentResult> {
+    output.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            RESULT_PREFIXES.iter().find_map(|(prefix, outcome)| {
+                line.strip_prefix(prefix).map(|name| ComponentResult { name: name.trim().to_string(), outcome: *outcome })
+            })
+        })
+        .filter(|result| !result.name.is_empty())
+        .collect()
+}
+
+/// Associates the results with the component numbers, using the names of the mod components
+/// (results with names that are not in `available` are ignored).<br>
+/// If a component was installed more than once, the last result wins.
+pub fn component_outcomes(results: &[ComponentResult], available: &[WeiduComponent]) -> BTreeMap<u32, ComponentOutcome> {
+    let mut outcomes = BTreeMap::new();
+    for result in results {
+        match available.iter().find(|component| same_component(&result.name, component)) {
+            None => debug!("component {:?} of the weidu output not found in the component list", result.name),
+            Some(component) => { outcomes.insert(component.number, result.outcome); }
+        }
+    }
+    outcomes
+}
+
+fn same_component(printed: &str, component: &WeiduComponent) -> bool {
+    let name = component.name.trim();
+    if printed.eq_ignore_ascii_case(name) {
+        return true;
+    }
+    match &component.subgroup {
+        None => false,
+        Some(subgroup) => printed.eq_ignore_ascii_case(&format!("{} -> {}", subgroup.trim(), name)),
+    }
+}
+
+/// What was written to the weidu `--log` file after `offset` (its size before a run, weidu appends to it).
+pub fn read_log_since(path: &Path, offset: u64) -> Result<String> {
+    let mut file = match std::fs::File::open(path) {
+        Err(error) => bail!("Could not open weidu log {:?}\n -> {:?}", path, error),
+        Ok(file) => file,
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut content = vec![];
+    file.read_to_end(&mut content)?;
+    Ok(String::from_utf8_lossy(&content).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    use crate::run_weidu::parse_weidu_component_list;
+    use crate::test_utils::test_dir;
+
+    use super::{component_outcomes, parse_install_output, read_log_since, ComponentOutcome, ComponentResult};
+
+    const INSTALL_OUTPUT: &str = r#"[weidu] WeiDU version 24900
+Using Language [English]
+
+Installing [Core fixes] [v1.0]
+Copying and patching 3 files ...
+
+SUCCESSFULLY INSTALLED      Core fixes
+
+Installing [Items -> Better items] [v1.0]
+ERROR: COPY ~mymod/itm/sw1h01.itm~ ~override~ failed: file not found
+Stopping installation because of error.
+
+ERROR Installing [Items -> Better items], rolling back to previous state
+Will uninstall    0 files for [MYMOD/SETUP-MYMOD.TP2] component 10.
+Uninstalled       0 files for [MYMOD/SETUP-MYMOD.TP2] component 10.
+ERROR: Failure("resource [sw1h01.itm] not found")
+PLEASE email the file SETUP-MYMOD.DEBUG to the mod author
+
+NOT INSTALLED DUE TO ERRORS Items -> Better items
+
+Installing [Extra spells] [v1.0]
+WARNING: no translation for @12
+
+INSTALLED WITH WARNINGS     Extra spells
+"#;
+
+    const COMPONENTS: &str = r#"[{"index":0,"number":0,"forced":false,"name":"Core fixes","subgroup":null,"group":[]},{"index":1,"number":10,"forced":false,"name":"Better items","subgroup":"Items","group":["Items"]},{"index":2,"number":11,"forced":false,"name":"Best items","subgroup":"Items","group":["Items"]},{"index":3,"number":20,"forced":false,"name":"Extra spells","subgroup":null,"group":[]}]"#;
+
+    #[test]
+    fn parse_component_results() {
+        let results = parse_install_output(INSTALL_OUTPUT);
+
+        assert_eq!(results, vec![
+            ComponentResult { name: "Core fixes".to_string(), outcome: ComponentOutcome::Installed },
+            ComponentResult { name: "Items -> Better items".to_string(), outcome: ComponentOutcome::Failed },
+            ComponentResult { name: "Extra spells".to_string(), outcome: ComponentOutcome::InstalledWithWarnings },
+        ]);
+    }
+
+    #[test]
+    fn outcomes_by_component_number() {
+        let available = parse_weidu_component_list(COMPONENTS).unwrap();
+        let mut results = parse_install_output(INSTALL_OUTPUT);
+        results.push(ComponentResult { name: "Not in the tp2".to_string(), outcome: ComponentOutcome::Failed });
+
+        let outcomes = component_outcomes(&results, &available);
+
+        assert_eq!(outcomes, BTreeMap::from([
+            (0, ComponentOutcome::Installed),
+            (10, ComponentOutcome::Failed),
+            (20, ComponentOutcome::InstalledWithWarnings),
+        ]));
+    }
+
+    #[test]
+    fn read_appended_log() {
+        let temp = test_dir();
+        let log = temp.path().join("setup-mymod.debug");
+        std::fs::write(&log, "NOT INSTALLED DUE TO ERRORS Core fixes\n").unwrap();
+        let offset = std::fs::metadata(&log).unwrap().len();
+        std::fs::OpenOptions::new().append(true).open(&log).unwrap()
+            .write_all(b"SUCCESSFULLY INSTALLED      Core fixes\n").unwrap();
+
+        let results = parse_install_output(&read_log_since(&log, offset).unwrap());
+
+        assert_eq!(results, vec![ComponentResult { name: "Core fixes".to_string(), outcome: ComponentOutcome::Installed }]);
+    }
+}
@@ -0,0 +1,137 @@
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+use anyhow::{bail, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// A weidu version; `weidu --version` prints `WeiDU version 24900` for 249.00.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WeiduVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Display for WeiduVersion {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+lazy_static! {
+    static ref VERSION_OUTPUT_REGEX: Regex = Regex::new(r"(?i)weidu version\s+v?([0-9]+(?:\.[0-9]+)?)").unwrap();
+}
+
+impl WeiduVersion {
+    /// Reads a version written as in the weidu output (`24900`), or as usually written (`249`, `249.00`, `v249`).
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().trim_start_matches(['v', 'V']);
+        let (major, minor) = match value.split_once('.') {
+            Some((major, minor)) => (major.parse::<u32>(), minor.parse::<u32>()),
+            // the weidu output has the minor version in the last 2 digits
+            None if value.len() > 3 => (value.parse::<u32>().map(|value| value / 100), value.parse::<u32>().map(|value| value % 100)),
+            None => (value.parse::<u32>(), Ok(0)),
+        };
+        match (major, minor) {
+            (Ok(major), Ok(minor)) => Ok(WeiduVersion { major, minor }),
+            _ => bail!("Invalid weidu version {:?}\n -> expected something like `249`, `249.00` or `24900`", value),
+        }
+    }
+
+    /// Finds the version in the output of `weidu --version`.
+    pub fn from_version_output(output: &str) -> Result<Self> {
+        match VERSION_OUTPUT_REGEX.captures(output).and_then(|captures| captures.get(1)) {
+            None => bail!("Could not find the weidu version in the output of `weidu --version`\n -> {:?}", output.trim()),
+            Some(version) => Self::parse(version.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+/// A required weidu version: comparisons separated by commas, all must be satisfied
+/// (`249`, `>=247`, `>=247, <250`). A version without operator must match exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeiduVersionReq {
+    comparisons: Vec<(Operator, WeiduVersion)>,
+}
+
+impl WeiduVersionReq {
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut comparisons = vec![];
+        for part in value.split(',').map(str::trim) {
+            let (operator, version) = [(">=", Operator::GreaterOrEqual), ("<=", Operator::LessOrEqual),
+                                        (">", Operator::Greater), ("<", Operator::Less), ("=", Operator::Equal)]
+                .into_iter()
+                .find_map(|(prefix, operator)| part.strip_prefix(prefix).map(|version| (operator, version)))
+                .unwrap_or((Operator::Equal, part));
+            match WeiduVersion::parse(version) {
+                Ok(version) => comparisons.push((operator, version)),
+                Err(error) => bail!("Invalid weidu version requirement {:?}\n -> {}", value, error),
+            }
+        }
+        Ok(WeiduVersionReq { comparisons })
+    }
+
+    pub fn matches(&self, version: &WeiduVersion) -> bool {
+        self.comparisons.iter().all(|(operator, required)| {
+            let ordering = version.cmp(required);
+            match operator {
+                Operator::Equal => ordering == Ordering::Equal,
+                Operator::Greater => ordering == Ordering::Greater,
+                Operator::GreaterOrEqual => ordering != Ordering::Less,
+                Operator::Less => ordering == Ordering::Less,
+                Operator::LessOrEqual => ordering != Ordering::Greater,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WeiduVersion, WeiduVersionReq};
+
+    fn version(major: u32, minor: u32) -> WeiduVersion {
+        WeiduVersion { major, minor }
+    }
+
+    #[test]
+    fn parse_version_output() {
+        assert_eq!(WeiduVersion::from_version_output("[weidu] WeiDU version 24900\n").unwrap(), version(249, 0));
+        assert_eq!(WeiduVersion::from_version_output("[./weidu] WeiDU version 24601\n").unwrap(), version(246, 1));
+        assert_eq!(WeiduVersion::from_version_output("[C:\\BG2\\weidu.exe] WeiDU version 23600\r\n").unwrap(), version(236, 0));
+        assert!(WeiduVersion::from_version_output("command not found").is_err());
+    }
+
+    #[test]
+    fn parse_written_versions() {
+        assert_eq!(WeiduVersion::parse("249").unwrap(), version(249, 0));
+        assert_eq!(WeiduVersion::parse("v249.01").unwrap(), version(249, 1));
+        assert_eq!(WeiduVersion::parse("24900").unwrap(), version(249, 0));
+        assert!(WeiduVersion::parse("latest").is_err());
+        assert_eq!(version(246, 1).to_string(), "246.01");
+    }
+
+    #[test]
+    fn requirement_is_satisfied_or_violated() {
+        let installed = WeiduVersion::from_version_output("[weidu] WeiDU version 24900").unwrap();
+
+        assert!(WeiduVersionReq::parse("249").unwrap().matches(&installed));
+        assert!(WeiduVersionReq::parse("=24900").unwrap().matches(&installed));
+        assert!(WeiduVersionReq::parse(">=247, <250").unwrap().matches(&installed));
+        assert!(WeiduVersionReq::parse("<=249").unwrap().matches(&installed));
+
+        assert!(!WeiduVersionReq::parse("246").unwrap().matches(&installed));
+        assert!(!WeiduVersionReq::parse(">249").unwrap().matches(&installed));
+        assert!(!WeiduVersionReq::parse(">=247, <249").unwrap().matches(&installed));
+        assert!(WeiduVersionReq::parse(">= soon").is_err());
+    }
+}